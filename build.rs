@@ -0,0 +1,206 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const USER_TARGET: &str = "riscv32imac-unknown-none-elf";
+/// Address the kernel loads user images at, see `Memory::USER_START`
+const USER_START: u32 = 0xC000_0000;
+/// Most memory a program can span from `USER_START`, see `Memory::IMAGE_MAX_SIZE`
+const USER_MEMORY_MAX: u64 = 0x1000_0000;
+/// Start of the header of every program image, see `process::IMAGE_MAGIC`
+const IMAGE_MAGIC: &[u8; 4] = b"PPIM";
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    // Only the kernel binary is linked with the kernel's script, user programs in the
+    // workspace bring their own
+    println!(
+        "cargo:rustc-link-arg-bins=-T{}",
+        manifest_dir.join("linker.ld").display()
+    );
+    println!("cargo:rerun-if-changed=linker.ld");
+
+    build_programs(&manifest_dir.join("user"), &out_dir);
+}
+
+/// Builds the workspace in `user/programs` and writes `programs.rs`, which lists a flat
+/// image of every program for `kernel::programs`, and `initramfs.cpio`, which holds every
+/// program in `/bin` and the one named `init` as `/init`
+fn build_programs(user_dir: &Path, out_dir: &Path) {
+    let programs_dir = user_dir.join("programs");
+    let target_dir = out_dir.join("user");
+    println!("cargo:rerun-if-changed={}", user_dir.join("runtime").display());
+    println!("cargo:rerun-if-changed={}", programs_dir.display());
+
+    // The flags cargo passes to this script are meant for the kernel
+    let output = Command::new(env::var("CARGO").unwrap())
+        .current_dir(&programs_dir)
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .env_remove("RUSTFLAGS")
+        .args(["build", "--release", "--target", USER_TARGET, "--target-dir"])
+        .arg(&target_dir)
+        .output()
+        .expect("failed to run cargo for the user programs");
+    if !output.status.success() {
+        panic!(
+            "building the user programs failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let mut names = vec![];
+    for entry in fs::read_dir(&programs_dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.join("Cargo.toml").is_file() {
+            names.push(path.file_name().unwrap().to_str().unwrap().to_owned());
+        }
+    }
+    names.sort();
+
+    let mut registry = String::new();
+    let mut archive = CpioWriter::default();
+    archive.directory("bin");
+    registry.push_str("pub static PROGRAMS: &[Program] = &[\n");
+    for name in names {
+        let elf_path = target_dir.join(USER_TARGET).join("release").join(&name);
+        let elf = fs::read(&elf_path)
+            .unwrap_or_else(|e| panic!("failed to read {}: {e}", elf_path.display()));
+        let image = flatten(&name, &elf);
+        if name == "init" {
+            archive.file("init", &image);
+        }
+        archive.file(&format!("bin/{name}"), &image);
+
+        let image_path = out_dir.join(format!("{name}.bin"));
+        fs::write(&image_path, image).unwrap();
+        registry.push_str(&format!(
+            "    Program {{ name: {name:?}, image: include_bytes!({:?}) }},\n",
+            image_path.display().to_string()
+        ));
+    }
+    registry.push_str("];\n");
+    fs::write(out_dir.join("programs.rs"), registry).unwrap();
+    fs::write(out_dir.join("initramfs.cpio"), archive.finish()).unwrap();
+}
+
+/// Writes a cpio archive in the "newc" format `kernel::initramfs` reads
+#[derive(Default)]
+struct CpioWriter {
+    data: Vec<u8>,
+    next_inode: u32,
+}
+
+impl CpioWriter {
+    fn directory(&mut self, name: &str) {
+        self.entry(name, 0o040_755, &[]);
+    }
+
+    fn file(&mut self, name: &str, contents: &[u8]) {
+        self.entry(name, 0o100_755, contents);
+    }
+
+    fn entry(&mut self, name: &str, mode: u32, contents: &[u8]) {
+        self.next_inode += 1;
+        let fields = [
+            self.next_inode,
+            mode,
+            0, // uid
+            0, // gid
+            1, // nlink
+            0, // mtime
+            contents.len() as u32,
+            0, // devmajor
+            0, // devminor
+            0, // rdevmajor
+            0, // rdevminor
+            name.len() as u32 + 1,
+            0, // check
+        ];
+        self.data.extend_from_slice(b"070701");
+        for field in fields {
+            self.data.extend_from_slice(format!("{field:08x}").as_bytes());
+        }
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.push(0);
+        self.pad();
+        self.data.extend_from_slice(contents);
+        self.pad();
+    }
+
+    fn pad(&mut self) {
+        while !self.data.len().is_multiple_of(4) {
+            self.data.push(0);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.entry("TRAILER!!!", 0, &[]);
+        self.data
+    }
+}
+
+/// Lays out the loadable segments of a 32-bit ELF file the way they are placed in memory,
+/// starting at `USER_START`, behind a header with the size of the memory the segments span.
+/// Memory past the file contents of a segment, like .bss and the stack, is not stored
+fn flatten(name: &str, elf: &[u8]) -> Vec<u8> {
+    const PT_LOAD: u32 = 1;
+    let u16_at = |offset: usize| u16::from_le_bytes(elf[offset..offset + 2].try_into().unwrap());
+    let u32_at = |offset: usize| u32::from_le_bytes(elf[offset..offset + 4].try_into().unwrap());
+
+    assert!(
+        elf.starts_with(b"\x7fELF\x01\x01"),
+        "{name} is not a little endian 32-bit ELF file"
+    );
+    let phoff = u32_at(0x1c) as usize;
+    let phentsize = u16_at(0x2a) as usize;
+    let phnum = u16_at(0x2c) as usize;
+
+    let mut image = vec![];
+    let mut memory_size = 0;
+    for i in 0..phnum {
+        let header = phoff + i * phentsize;
+        let (kind, offset, vaddr, filesz, memsz) = (
+            u32_at(header),
+            u32_at(header + 4) as usize,
+            u32_at(header + 8),
+            u32_at(header + 16) as usize,
+            u32_at(header + 20),
+        );
+        if kind != PT_LOAD || memsz == 0 {
+            continue;
+        }
+        assert!(
+            vaddr >= USER_START,
+            "{name} has a segment below {USER_START:#x}, is it linked with pippopp-user.ld?"
+        );
+        let end = (vaddr - USER_START) as u64 + memsz as u64;
+        assert!(
+            end <= USER_MEMORY_MAX,
+            "{name} spans {end:#x} bytes of memory, a process has {USER_MEMORY_MAX:#x}"
+        );
+        memory_size = memory_size.max(end);
+        if filesz == 0 {
+            continue;
+        }
+
+        let start = (vaddr - USER_START) as usize;
+        if image.len() < start + filesz {
+            image.resize(start + filesz, 0);
+        }
+        image[start..start + filesz].copy_from_slice(&elf[offset..offset + filesz]);
+    }
+    assert_eq!(
+        u32_at(0x18),
+        USER_START,
+        "{name} does not start at {USER_START:#x}"
+    );
+
+    let mut file = IMAGE_MAGIC.to_vec();
+    file.extend_from_slice(&(memory_size as u32).to_le_bytes());
+    file.extend_from_slice(&image);
+    file
+}
@@ -105,6 +105,7 @@ impl Sepc {
 pub enum Scause {
     Ecall,
     SegFault,
+    IllegalInstruction,
     Interrupt(u32),
     Unknown(u32),
 }
@@ -122,7 +123,9 @@ impl Scause {
             // Exception
             match scause {
                 0x8 => Scause::Ecall,
-                0xd => Scause::SegFault,
+                0x2 => Scause::IllegalInstruction,
+                // Instruction, load and store access and page faults
+                0x1 | 0x5 | 0x7 | 0xc | 0xd | 0xf => Scause::SegFault,
                 _ => Scause::Unknown(scause),
             }
         }
@@ -3,7 +3,7 @@ use core::arch::naked_asm;
 use crate::{
     arch::riscv::{
         dispatch::riscv32im::DispatcherRiscv32im, frame::riscv32im::FrameRiscv32im,
-        page_table::satp_sv32::SatpSv32Table1,
+        page_table::satp_sv32::SatpSv32Table1, timer::ClockRiscv32im,
     },
     kernel::environment::{Dispatch, Environment},
 };
@@ -15,6 +15,7 @@ impl Environment for EnvironmentRiscv32im {
     type PageTable = SatpSv32Table1;
     type Frame = FrameRiscv32im;
     type Dispatch = DispatcherRiscv32im;
+    type Clock = ClockRiscv32im;
 
     fn wfi_program() -> &'static [u8] {
         const WFI: u32 = 0x0000006f;
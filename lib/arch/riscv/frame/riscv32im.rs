@@ -51,17 +51,42 @@ impl Frame for FrameRiscv32im {
         self.pc = pc as u32;
     }
 
-    fn set_success(&mut self, args: (Option<usize>, Option<usize>, Option<usize>)) {
-        self.a0 = args.0.map_or(self.a0, |v| v as u32);
-        self.a1 = args.1.map_or(self.a1, |v| v as u32);
-        self.a2 = args.2.map_or(self.a2, |v| v as u32); 
+    fn restart_syscall(&mut self) {
+        // The trap handler stepped past the ecall, which is always 4 bytes
+        self.pc -= 4;
+    }
+
+    fn stack_pointer(&self) -> usize {
+        self.sp as usize
+    }
+
+    fn set_stack_pointer(&mut self, sp: usize) {
+        self.sp = sp as u32;
+    }
+
+    fn set_return_address(&mut self, ra: usize) {
+        self.ra = ra as u32;
+    }
+
+    fn set_thread_pointer(&mut self, tp: usize) {
+        self.tp = tp as u32;
+    }
+
+    fn set_argument(&mut self, arg: usize) {
+        self.a0 = arg as u32;
+    }
+
+    // Syscalls return their value in a0 and a secondary value in a1. a7, which held the
+    // syscall number, is 0 on success and 1 on failure, in which case a0 holds the errno
+    fn set_success(&mut self, value: usize, secondary: usize) {
+        self.a0 = value as u32;
+        self.a1 = secondary as u32;
         self.a7 = 0;
     }
 
-    fn set_error(&mut self, args: (Option<usize>, Option<usize>, Option<usize>)) {
-        self.a0 = args.0.map_or(self.a0, |v| v as u32);
-        self.a1 = args.1.map_or(self.a1, |v| v as u32);
-        self.a2 = args.2.map_or(self.a2, |v| v as u32); 
+    fn set_error(&mut self, errno: usize) {
+        self.a0 = errno as u32;
+        self.a1 = 0;
         self.a7 = 1;
     }
 
@@ -1,6 +1,6 @@
 extern crate alloc;
 
-use crate::arch::riscv::csr::Satp;
+use crate::arch::riscv::{csr::Satp, sbi};
 use crate::kernel::environment::PageTable;
 use crate::{kernel::environment::Mode, utils::is_aligned};
 use alloc::alloc::{alloc, dealloc, Layout};
@@ -129,6 +129,14 @@ impl PageTable for SatpSv32Table1 {
             start += SATP_PAGE_SIZE;
         }
 
+        for (base, size) in crate::kernel::mem::device_memory() {
+            let mut page = base as u32 & !(SATP_PAGE_SIZE - 1);
+            while page < (base + size) as u32 {
+                table1.map(page, page, PAGE_R | PAGE_W | PAGE_V);
+                page += SATP_PAGE_SIZE;
+            }
+        }
+
         table1
     }
 
@@ -157,6 +165,10 @@ impl PageTable for SatpSv32Table1 {
         }
     }
 
+    fn flush_all_cores(virt: usize, size: usize) {
+        sbi::rfence::remote_sfence_vma_all(virt, size).expect("Remote fence failed");
+    }
+
     fn is_active(pt: &Self) -> bool {
         let satp = Satp::load().as_usize() as u32;
         satp == pt.as_satp()
@@ -188,4 +200,39 @@ impl PageTable for SatpSv32Table1 {
 
         SatpSv32Table1::map(self, virt as u32, phys as u32, flags);
     }
+
+    fn unmap(&mut self, virt: usize) {
+        let vpn1 = (virt >> 22) & 0x3ff;
+        let entry1 = unsafe { *self.0.add(vpn1) };
+        if entry1 & PAGE_V == 0 || entry1 & (PAGE_R | PAGE_W | PAGE_X) != 0 {
+            return;
+        }
+
+        let table0 = ((entry1 >> 10) << 12) as *mut u32;
+        let vpn0 = (virt >> 12) & 0x3ff;
+        unsafe {
+            table0.add(vpn0).write(0);
+            core::arch::asm!("sfence.vma {virt}, zero", virt = in(reg) virt, options(nostack));
+        }
+    }
+
+    fn translate(&self, virt: usize) -> Option<usize> {
+        let vpn1 = (virt >> 22) & 0x3ff;
+        let entry1 = unsafe { *self.0.add(vpn1) };
+        if entry1 & PAGE_V == 0 {
+            return None;
+        }
+        // A leaf in the first level maps a whole 4 MiB megapage
+        if entry1 & (PAGE_R | PAGE_W | PAGE_X) != 0 {
+            return Some((((entry1 >> 20) << 22) as usize) | (virt & 0x3f_ffff));
+        }
+
+        let table0 = ((entry1 >> 10) << 12) as *const u32;
+        let vpn0 = (virt >> 12) & 0x3ff;
+        let entry0 = unsafe { *table0.add(vpn0) };
+        if entry0 & PAGE_V == 0 {
+            return None;
+        }
+        Some((((entry0 >> 10) << 12) as usize) | (virt & (SATP_PAGE_SIZE as usize - 1)))
+    }
 }
@@ -38,14 +38,18 @@ pub unsafe fn write(s: &[u8]) -> SbiResult {
 }
 
 
-unsafe fn read() -> SbiResult {
-    // Need to rework this function, because i dont want it to work like this hmm lol
+/// Returns the next byte received by the debug console, or None if nothing is pending.
+/// The buffer lives on the kernel stack, which is identity mapped, so its address is physical
+pub fn read_byte() -> Option<u8> {
     let mut buf = [0u8; 1];
-    let num_bytes = 1;
     let base_addr = buf.as_mut_ptr() as u64;
     let base_addr_lo = base_addr as u32;
     let base_addr_hi = (base_addr >> 32) as u32;
-    sbi_debug_console_read(num_bytes, base_addr_lo, base_addr_hi).into_result()
+    let read = unsafe { sbi_debug_console_read(1, base_addr_lo, base_addr_hi).into_result() };
+    match read {
+        Ok(1) => Some(buf[0]),
+        _ => None,
+    }
 }
 
 pub struct SbiWriter;
@@ -2,6 +2,9 @@ use core::arch::naked_asm;
 
 use crate::arch::riscv::sbi::{SbiResult, SbiRet};
 
+/// State `status` reports for a hart that can be started with `start`
+pub const HART_STOPPED: u32 = 1;
+
 #[unsafe(naked)]
 extern "C" fn sbi_harth_start(hartid: u32, start_addr: u32, opaque: u32) -> SbiRet {
     naked_asm!(
@@ -40,4 +40,5 @@ pub type SbiResult = Result<u32, SbiError>;
 pub mod harth;
 pub mod base;
 pub mod debug_console;
+pub mod rfence;
 pub mod timer;
@@ -0,0 +1,29 @@
+use core::arch::naked_asm;
+
+use crate::arch::riscv::sbi::{SbiResult, SbiRet};
+
+/// Hart mask base that selects every hart, the mask itself is ignored
+const ALL_HARTS: u32 = u32::MAX;
+
+#[unsafe(naked)]
+extern "C" fn sbi_remote_sfence_vma(
+    hart_mask: u32,
+    hart_mask_base: u32,
+    start_addr: u32,
+    size: u32,
+) -> SbiRet {
+    naked_asm!(
+        "
+        li a7, 0x52464E43
+        li a6, 1
+        ecall
+        ret
+        "
+    );
+}
+
+/// Flushes the translations of `size` bytes from `start` on from the TLB of every hart.
+/// Returns once all of them have done so
+pub fn remote_sfence_vma_all(start: usize, size: usize) -> SbiResult {
+    unsafe { sbi_remote_sfence_vma(0, ALL_HARTS, start as u32, size as u32).into_result() }
+}
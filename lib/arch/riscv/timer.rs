@@ -1,23 +1,35 @@
-use crate::arch::riscv::sbi;
+use crate::{arch::riscv::sbi, kernel::environment::Clock};
 
-pub fn init() {
-    schedule();
-}
+#[derive(Debug)]
+pub struct ClockRiscv32im {}
+
+impl Clock for ClockRiscv32im {
+    fn now() -> u64 {
+        loop {
+            let datel: u32;
+            let dateh: u32;
+            let dateh_check: u32;
+            unsafe {
+                core::arch::asm!(
+                    "
+                    rdtimeh {dateh}
+                    rdtime {datel}
+                    rdtimeh {dateh_check}
+                    ",
+                    datel = out(reg) datel,
+                    dateh = out(reg) dateh,
+                    dateh_check = out(reg) dateh_check,
+                    options(nostack, preserves_flags)
+                );
+            }
+            // The low word can wrap between the two reads, so retry until the high word is stable
+            if dateh == dateh_check {
+                return ((dateh as u64) << 32) | (datel as u64);
+            }
+        }
+    }
 
-pub fn schedule() {
-    let datel: u32;
-    let dateh: u32;
-    unsafe {
-        core::arch::asm!(
-            "
-            rdtime {datel}
-            rdtimeh {dateh}
-            ",
-            datel = out(reg) datel,
-            dateh = out(reg) dateh,
-            options(nostack, preserves_flags)
-        );
+    fn set_deadline(deadline: u64) {
+        sbi::timer::set_timer(deadline).unwrap();
     }
-    let date = ((dateh as u64) << 32) | (datel as u64);
-    sbi::timer::set_timer(date + 1000).unwrap();
 }
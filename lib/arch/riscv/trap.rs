@@ -6,7 +6,6 @@ use core::sync::atomic::Ordering::SeqCst;
 use crate::arch::riscv::csr::{Scause, Sepc, Sscratch, Sstatus};
 use crate::arch::riscv::environment::riscv32im::EnvironmentRiscv32im;
 use crate::arch::riscv::frame::riscv32im::FrameRiscv32im;
-use crate::arch::riscv::{kernel, timer};
 use crate::kernel::environment::Frame;
 use crate::kernel::trap::{TrapCtx, TrapReason};
 use crate::kernel::Kernel;
@@ -24,7 +23,11 @@ pub extern "C" fn waiting_trap_entry(kernel: *const Kernel<EnvironmentRiscv32im>
     match scause {
         Scause::Interrupt(code) => match code {
             5 => {
-                timer::schedule();
+                kernel.run_timers();
+                kernel.switch_from_waiting();
+            }
+            9 => {
+                crate::drivers::irq::handle(kernel.core);
                 kernel.switch_from_waiting();
             }
             _ => {
@@ -70,9 +73,11 @@ pub extern "C" fn trap_entry(
     if let Scause::Interrupt(code) = scause {
         match code {
             5 => {
-                timer::schedule();
                 kernel.trap(frame, TrapReason::Timer);
             }
+            9 => {
+                kernel.trap(frame, TrapReason::External);
+            }
             _ => {
                 panic!("Unknown interrupt code: {}", code);
             }
@@ -87,9 +92,14 @@ pub extern "C" fn trap_entry(
                 frame,
                 TrapReason::SysCall(
                     frame.a7 as usize,
-                    frame.a0 as usize,
-                    frame.a1 as usize,
-                    frame.a2 as usize,
+                    [
+                        frame.a0 as usize,
+                        frame.a1 as usize,
+                        frame.a2 as usize,
+                        frame.a3 as usize,
+                        frame.a4 as usize,
+                        frame.a5 as usize,
+                    ],
                 ),
             );
         }
@@ -108,6 +118,14 @@ pub extern "C" fn trap_entry(
                 },
             );
         }
+        Scause::IllegalInstruction => {
+            kernel.trap(
+                frame,
+                TrapReason::IllegalInstruction {
+                    pc_addr: frame.pc as usize,
+                },
+            );
+        }
         Scause::Interrupt(_) => {
             unreachable!("Handle above");
         }
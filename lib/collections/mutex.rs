@@ -39,6 +39,10 @@ impl<T> Mutex<T> {
         }
     }
 
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
     pub fn is_locked(&self) -> bool {
         self.__is_locked()
     }
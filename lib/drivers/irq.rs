@@ -0,0 +1,56 @@
+//! Handlers for device interrupts, delivered through the interrupt controller.
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, sync::Arc};
+
+use crate::{arch::riscv::csr::Sstatus, collections::mutex::Mutex, drivers::plic};
+
+/// Runs in the trap handler with interrupts off, so it must not block
+pub type Handler = Arc<dyn Fn() + Send + Sync>;
+
+static HANDLERS: Mutex<BTreeMap<u32, Handler>> = Mutex::new(BTreeMap::new());
+
+/// Calls `handler` whenever interrupt `source` fires
+pub fn register(source: u32, handler: Handler) {
+    without_interrupts(|| HANDLERS.lock().insert(source, handler));
+    plic::enable(source);
+}
+
+/// Whether the interrupt controller can deliver interrupt `source`. Drivers of devices whose
+/// interrupts can not be delivered have to poll them
+pub fn is_deliverable(source: u32) -> bool {
+    plic::handles(source)
+}
+
+/// Runs the handlers of the device interrupts pending for `hart`
+pub fn handle(hart: usize) {
+    while let Some(source) = plic::claim(hart) {
+        let handler = HANDLERS.lock().get(&source).cloned();
+        match handler {
+            Some(handler) => handler(),
+            None => {
+                println!("irq: no handler for interrupt {}", source);
+            }
+        }
+        plic::complete(hart, source);
+    }
+}
+
+/// Runs `f` with interrupts off on this hart. Locks an interrupt handler takes are held this
+/// way, so the handler can not spin on a lock its own hart holds
+pub fn without_interrupts<T>(f: impl FnOnce() -> T) -> T {
+    let mut sstatus = Sstatus::load();
+    let enabled = sstatus.SIE;
+    sstatus.SIE = false;
+    sstatus.store();
+
+    let value = f();
+
+    if enabled {
+        let mut sstatus = Sstatus::load();
+        sstatus.SIE = true;
+        sstatus.store();
+    }
+    value
+}
@@ -1,2 +1,3 @@
+pub mod irq;
+pub mod plic;
 pub mod virtio;
-
@@ -0,0 +1,132 @@
+//! Platform-level interrupt controller, which routes the interrupts of devices to the harts.
+
+extern crate alloc;
+
+use core::ptr::{read_volatile, write_volatile};
+
+use alloc::vec::Vec;
+
+use crate::{
+    collections::mutex::Mutex,
+    drivers::irq::without_interrupts,
+    kernel::{fdt::Fdt, mem},
+};
+
+const PRIORITY: usize = 0x0000;
+const ENABLE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const THRESHOLD: usize = 0x0;
+const CLAIM: usize = 0x4;
+
+/// Priority devices are given, anything above the threshold of 0 is delivered
+const DEVICE_PRIORITY: u32 = 1;
+
+static PLIC: Mutex<Option<Plic>> = Mutex::new(None);
+
+#[derive(Debug)]
+struct Plic {
+    base: usize,
+    sources: u32,
+    /// Context delivering supervisor interrupts, by hart id
+    contexts: Vec<(usize, usize)>,
+}
+
+impl Plic {
+    fn context(&self, hart: usize) -> Option<usize> {
+        self.contexts
+            .iter()
+            .find(|&&(id, _)| id == hart)
+            .map(|&(_, context)| context)
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        // SAFETY: `init` found the registers in the device tree
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        // SAFETY: `init` found the registers in the device tree
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+}
+
+/// Sets up the interrupt controller described by `fdt` for every hart. Returns false if
+/// the platform has none, so devices can only be polled
+pub fn init(fdt: &Fdt) -> bool {
+    let Some(controller) = fdt.interrupt_controller() else {
+        return false;
+    };
+    let contexts: Vec<(usize, usize)> = fdt
+        .harts()
+        .filter_map(|hart| Some((hart, fdt.supervisor_context(hart)?)))
+        .collect();
+
+    // The priorities, then the enable bits and the threshold and claim registers of the
+    // contexts in use
+    mem::map_device(controller.base + PRIORITY, 4 * (controller.sources as usize + 1));
+    for &(_, context) in &contexts {
+        mem::map_device(controller.base + ENABLE + context * ENABLE_STRIDE, ENABLE_STRIDE);
+        mem::map_device(controller.base + CONTEXT + context * CONTEXT_STRIDE, CONTEXT_STRIDE);
+    }
+
+    let plic = Plic {
+        base: controller.base,
+        sources: controller.sources,
+        contexts,
+    };
+    for &(_, context) in &plic.contexts {
+        plic.write(CONTEXT + context * CONTEXT_STRIDE + THRESHOLD, 0);
+    }
+    without_interrupts(|| *PLIC.lock() = Some(plic));
+    true
+}
+
+/// Whether there is an interrupt controller with a source `source`
+pub fn handles(source: u32) -> bool {
+    without_interrupts(|| {
+        PLIC.lock()
+            .as_ref()
+            .is_some_and(|plic| source != 0 && source <= plic.sources)
+    })
+}
+
+/// Lets interrupt `source` through to every hart
+pub fn enable(source: u32) {
+    without_interrupts(|| {
+        let plic = PLIC.lock();
+        let Some(plic) = plic.as_ref() else {
+            return;
+        };
+        if source == 0 || source > plic.sources {
+            return;
+        }
+        plic.write(PRIORITY + 4 * source as usize, DEVICE_PRIORITY);
+        for &(_, context) in &plic.contexts {
+            let offset = ENABLE + context * ENABLE_STRIDE + 4 * (source as usize / 32);
+            plic.write(offset, plic.read(offset) | 1 << (source % 32));
+        }
+    });
+}
+
+/// Takes the highest priority interrupt pending for `hart`, which has to be completed once
+/// it is handled
+pub(super) fn claim(hart: usize) -> Option<u32> {
+    let plic = PLIC.lock();
+    let plic = plic.as_ref()?;
+    let context = plic.context(hart)?;
+    match plic.read(CONTEXT + context * CONTEXT_STRIDE + CLAIM) {
+        0 => None,
+        source => Some(source),
+    }
+}
+
+pub(super) fn complete(hart: usize, source: u32) {
+    let plic = PLIC.lock();
+    if let Some(plic) = plic.as_ref() {
+        if let Some(context) = plic.context(hart) {
+            plic.write(CONTEXT + context * CONTEXT_STRIDE + CLAIM, source);
+        }
+    }
+}
@@ -0,0 +1,461 @@
+//! Driver for virtio block devices.
+//!
+//! Every read, write and flush is a `Request` future. Any number of them can be in flight,
+//! as many as the request queue has descriptors for. The interrupt handler collects finished
+//! ones from the used ring and wakes whoever waits for them. Disks are registered with the
+//! block layer as `vda`, `vdb` and so on.
+
+extern crate alloc;
+
+use core::{
+    future::Future,
+    mem::size_of,
+    pin::Pin,
+    ptr::{addr_of, read_volatile},
+    task::{Context, Poll, Waker},
+};
+
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    collections::mutex::Mutex,
+    drivers::{
+        irq::{self, without_interrupts},
+        virtio::{Buffer, Driver, Transport, VirtQueue, VirtioError, DEVICE_ID_BLOCK},
+    },
+    kernel::block::{self, BlockDevice, BlockError, BlockFuture, Completion, FlushFuture},
+};
+
+pub const SECTOR_SIZE: usize = 512;
+
+/// The device only allows reads
+const F_RO: u64 = 1 << 5;
+/// The device caches writes until it is sent a flush
+const F_FLUSH: u64 = 1 << 9;
+
+const T_IN: u32 = 0;
+const T_OUT: u32 = 1;
+const T_FLUSH: u32 = 4;
+
+const S_OK: u8 = 0;
+const S_UNSUPP: u8 = 2;
+
+/// Entries asked for in the request queue, the device may offer fewer
+const QUEUE_SIZE: u16 = 128;
+
+pub const DRIVER: Driver = Driver {
+    name: "virtio-blk",
+    device_id: DEVICE_ID_BLOCK,
+    probe,
+};
+
+/// Number of disks found so far, which picks the name of the next one
+static DISKS: Mutex<usize> = Mutex::new(0);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct RequestHeader {
+    kind: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// A request by the id of its first descriptor
+#[derive(Debug)]
+enum Slot {
+    Free,
+    InFlight {
+        buffer: Box<[u8]>,
+        waker: Option<Waker>,
+    },
+    Done {
+        buffer: Box<[u8]>,
+        result: Result<(), BlockError>,
+    },
+    /// Its `Request` was dropped, the buffer is kept until the device is done with it
+    #[allow(dead_code)]
+    Abandoned { buffer: Box<[u8]> },
+}
+
+#[derive(Debug)]
+struct Inner {
+    transport: Transport,
+    queue: VirtQueue,
+    /// Header and status of each request, by its id. The device reads and writes them, so
+    /// they never move
+    headers: Box<[RequestHeader]>,
+    statuses: Box<[u8]>,
+    slots: Vec<Slot>,
+    /// Requests waiting for free descriptors
+    space_waiters: Vec<Waker>,
+}
+
+#[derive(Debug)]
+struct Device {
+    inner: Mutex<Inner>,
+    /// Size of the disk in sectors
+    capacity: u64,
+    read_only: bool,
+    /// Writes only reach stable storage after a flush
+    write_back: bool,
+    /// No interrupt reaches the driver, so waiters poll the used ring themselves
+    polled: bool,
+}
+
+/// A virtio block device, addressed in sectors
+#[derive(Debug, Clone)]
+pub struct VirtioBlk {
+    name: String,
+    device: Arc<Device>,
+}
+
+fn probe(mut transport: Transport) -> Result<(), VirtioError> {
+    let features = transport.init(F_RO | F_FLUSH)?;
+    let queue = match transport.setup_queue(0, QUEUE_SIZE) {
+        Ok(queue) => queue,
+        Err(err) => {
+            transport.fail();
+            return Err(err);
+        }
+    };
+    transport.driver_ok();
+
+    let capacity: u64 = transport.read_config(0);
+    let read_only = features & F_RO != 0;
+    let write_back = features & F_FLUSH != 0;
+    let interrupt = transport.interrupt();
+    let base = transport.base();
+    let size = queue.size() as usize;
+    let inner = Inner {
+        transport,
+        queue,
+        headers: alloc::vec![
+            RequestHeader {
+                kind: 0,
+                reserved: 0,
+                sector: 0,
+            };
+            size
+        ]
+        .into_boxed_slice(),
+        statuses: alloc::vec![0; size].into_boxed_slice(),
+        slots: (0..size).map(|_| Slot::Free).collect(),
+        space_waiters: Vec::new(),
+    };
+    let polled = !interrupt.is_some_and(irq::is_deliverable);
+    let device = Arc::new(Device {
+        inner: Mutex::new(inner),
+        capacity,
+        read_only,
+        write_back,
+        polled,
+    });
+    if let (Some(interrupt), false) = (interrupt, polled) {
+        let handler = device.clone();
+        irq::register(interrupt, Arc::new(move || handler.handle_interrupt()));
+    }
+
+    let name = {
+        let mut disks = DISKS.lock();
+        *disks += 1;
+        disk_name(*disks - 1)
+    };
+    println!(
+        "virtio-blk: {} at {:#x}{}",
+        name,
+        base,
+        if polled { ", polled" } else { "" }
+    );
+    block::register(Arc::new(VirtioBlk { name, device }));
+    Ok(())
+}
+
+/// `vda` to `vdz`, then `vdaa` and so on
+fn disk_name(index: usize) -> String {
+    let mut suffix = Vec::new();
+    let mut index = index + 1;
+    while index > 0 {
+        index -= 1;
+        suffix.push(b'a' + (index % 26) as u8);
+        index /= 26;
+    }
+    suffix.reverse();
+    format!("vd{}", core::str::from_utf8(&suffix).unwrap_or("?"))
+}
+
+impl VirtioBlk {
+    /// Size of the disk in sectors
+    pub fn capacity(&self) -> u64 {
+        self.device.capacity
+    }
+
+    /// Reads the sectors starting at `sector` into `buffer`, as many as it holds. The
+    /// request hands the buffer back once it is done
+    pub fn read_sectors(&self, sector: u64, buffer: Box<[u8]>) -> Request {
+        Request::new(self.device.clone(), T_IN, sector, buffer)
+    }
+
+    /// Writes `buffer` to the sectors starting at `sector`
+    pub fn write_sectors(&self, sector: u64, buffer: Box<[u8]>) -> Request {
+        Request::new(self.device.clone(), T_OUT, sector, buffer)
+    }
+
+    /// Makes the device write out the writes it cached. Without write-back caching there is
+    /// nothing to do
+    pub fn flush_cache(&self) -> Option<Request> {
+        self.device
+            .write_back
+            .then(|| Request::new(self.device.clone(), T_FLUSH, 0, Box::new([])))
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.device.capacity
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.device.read_only
+    }
+
+    fn read(&self, block: u64, buffer: Box<[u8]>) -> BlockFuture {
+        Box::pin(self.read_sectors(block, buffer))
+    }
+
+    fn write(&self, block: u64, buffer: Box<[u8]>) -> BlockFuture {
+        Box::pin(self.write_sectors(block, buffer))
+    }
+
+    fn flush(&self) -> FlushFuture {
+        match self.flush_cache() {
+            Some(request) => Box::pin(async move { request.await.1 }),
+            None => Box::pin(core::future::ready(Ok(()))),
+        }
+    }
+}
+
+impl Device {
+    fn check(&self, kind: u32, sector: u64, len: usize) -> Result<(), BlockError> {
+        if kind == T_FLUSH {
+            return Ok(());
+        }
+        if len == 0 || !len.is_multiple_of(SECTOR_SIZE) || len > u32::MAX as usize {
+            return Err(BlockError::BadLength);
+        }
+        let end = sector.checked_add((len / SECTOR_SIZE) as u64);
+        if end.is_none_or(|end| end > self.capacity) {
+            return Err(BlockError::OutOfRange);
+        }
+        if kind == T_OUT && self.read_only {
+            return Err(BlockError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    fn handle_interrupt(&self) {
+        let mut wakers = Vec::new();
+        {
+            let mut inner = self.inner.lock();
+            inner.transport.ack_interrupt();
+            inner.collect(&mut wakers);
+        }
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+impl Inner {
+    /// Puts a request in the queue and tells the device. Hands the buffer back if the queue
+    /// has no room for it
+    fn submit(
+        &mut self,
+        kind: u32,
+        sector: u64,
+        buffer: Box<[u8]>,
+        waker: &Waker,
+    ) -> Result<u16, Box<[u8]>> {
+        let Some(id) = self.queue.next_id() else {
+            return Err(buffer);
+        };
+        self.headers[id as usize] = RequestHeader {
+            kind,
+            reserved: 0,
+            sector,
+        };
+        // Anything but S_OK, so a request the device never touched does not pass
+        self.statuses[id as usize] = u8::MAX;
+
+        let header = Buffer {
+            addr: addr_of!(self.headers[id as usize]) as usize,
+            len: size_of::<RequestHeader>() as u32,
+            device_writable: false,
+        };
+        let data = Buffer {
+            addr: buffer.as_ptr() as usize,
+            len: buffer.len() as u32,
+            device_writable: kind == T_IN,
+        };
+        let status = Buffer {
+            addr: addr_of!(self.statuses[id as usize]) as usize,
+            len: 1,
+            device_writable: true,
+        };
+        // A flush moves no data
+        let added = if buffer.is_empty() {
+            self.queue.add(&[header, status])
+        } else {
+            self.queue.add(&[header, data, status])
+        };
+        match added {
+            Ok(added) => {
+                debug_assert_eq!(added, id);
+                self.slots[id as usize] = Slot::InFlight {
+                    buffer,
+                    waker: Some(waker.clone()),
+                };
+                self.transport.notify(self.queue.index());
+                Ok(id)
+            }
+            Err(_) => Err(buffer),
+        }
+    }
+
+    /// Moves the requests the device finished out of the used ring, and adds the wakers of
+    /// those waiting for them to `wakers`
+    fn collect(&mut self, wakers: &mut Vec<Waker>) {
+        let mut freed = false;
+        while let Some((id, _)) = self.queue.pop_used() {
+            freed = true;
+            let id = id as usize;
+            // SAFETY: The device wrote the status before it put the request in the used ring
+            let result = match unsafe { read_volatile(&self.statuses[id]) } {
+                S_OK => Ok(()),
+                S_UNSUPP => Err(BlockError::Unsupported),
+                // S_IOERR, or a status the device never wrote
+                _ => Err(BlockError::Io),
+            };
+            // A request nobody waits for anymore is just freed
+            if let Slot::InFlight { buffer, waker } =
+                core::mem::replace(&mut self.slots[id], Slot::Free)
+            {
+                self.slots[id] = Slot::Done { buffer, result };
+                wakers.extend(waker);
+            }
+        }
+        if freed {
+            wakers.append(&mut self.space_waiters);
+        }
+    }
+}
+
+#[derive(Debug)]
+enum RequestState {
+    Unsubmitted(Box<[u8]>),
+    Submitted(u16),
+    Finished,
+}
+
+/// A read, write or flush, which resolves to its buffer and whether it succeeded
+#[derive(Debug)]
+pub struct Request {
+    device: Arc<Device>,
+    kind: u32,
+    sector: u64,
+    state: RequestState,
+}
+
+impl Request {
+    fn new(device: Arc<Device>, kind: u32, sector: u64, buffer: Box<[u8]>) -> Self {
+        Self {
+            device,
+            kind,
+            sector,
+            state: RequestState::Unsubmitted(buffer),
+        }
+    }
+
+    fn poll_locked(
+        &mut self,
+        inner: &mut Inner,
+        cx: &mut Context<'_>,
+    ) -> Poll<Completion> {
+        match core::mem::replace(&mut self.state, RequestState::Finished) {
+            RequestState::Unsubmitted(buffer) => {
+                if let Err(err) = self.device.check(self.kind, self.sector, buffer.len()) {
+                    return Poll::Ready((buffer, Err(err)));
+                }
+                match inner.submit(self.kind, self.sector, buffer, cx.waker()) {
+                    Ok(id) => self.state = RequestState::Submitted(id),
+                    Err(buffer) => {
+                        self.state = RequestState::Unsubmitted(buffer);
+                        inner.space_waiters.push(cx.waker().clone());
+                    }
+                }
+                Poll::Pending
+            }
+            RequestState::Submitted(id) => {
+                let slot = &mut inner.slots[id as usize];
+                match core::mem::replace(slot, Slot::Free) {
+                    Slot::Done { buffer, result } => Poll::Ready((buffer, result)),
+                    Slot::InFlight { buffer, .. } => {
+                        *slot = Slot::InFlight {
+                            buffer,
+                            waker: Some(cx.waker().clone()),
+                        };
+                        self.state = RequestState::Submitted(id);
+                        Poll::Pending
+                    }
+                    _ => unreachable!("request {} lost its slot", id),
+                }
+            }
+            RequestState::Finished => panic!("virtio-blk: request polled after it finished"),
+        }
+    }
+}
+
+impl Future for Request {
+    type Output = Completion;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let device = this.device.clone();
+        without_interrupts(|| {
+            let mut wakers = Vec::new();
+            let poll = {
+                let mut inner = device.inner.lock();
+                // Completions may be waiting here already, and are the only way to see
+                // them without an interrupt
+                inner.collect(&mut wakers);
+                this.poll_locked(&mut inner, cx)
+            };
+            wakers.into_iter().for_each(Waker::wake);
+            if poll.is_pending() && device.polled {
+                cx.waker().wake_by_ref();
+            }
+            poll
+        })
+    }
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        let RequestState::Submitted(id) = self.state else {
+            return;
+        };
+        without_interrupts(|| {
+            let mut inner = self.device.inner.lock();
+            let slot = &mut inner.slots[id as usize];
+            *slot = match core::mem::replace(slot, Slot::Free) {
+                Slot::InFlight { buffer, .. } => Slot::Abandoned { buffer },
+                _ => Slot::Free,
+            };
+        });
+    }
+}
@@ -0,0 +1,255 @@
+//! The virtio-mmio register interface, in both its legacy (version 1) and modern (version 2)
+//! layout.
+
+use core::ptr::{read_volatile, write_volatile};
+
+use crate::drivers::virtio::{queue::VirtQueue, VirtioError};
+
+const MAGIC: u32 = 0x7472_6976; // "virt"
+
+const REG_MAGIC: usize = 0x000;
+const REG_VERSION: usize = 0x004;
+const REG_DEVICE_ID: usize = 0x008;
+const REG_VENDOR_ID: usize = 0x00c;
+const REG_DEVICE_FEATURES: usize = 0x010;
+const REG_DEVICE_FEATURES_SEL: usize = 0x014;
+const REG_DRIVER_FEATURES: usize = 0x020;
+const REG_DRIVER_FEATURES_SEL: usize = 0x024;
+/// Legacy only
+const REG_GUEST_PAGE_SIZE: usize = 0x028;
+const REG_QUEUE_SEL: usize = 0x030;
+const REG_QUEUE_NUM_MAX: usize = 0x034;
+const REG_QUEUE_NUM: usize = 0x038;
+/// Legacy only
+const REG_QUEUE_ALIGN: usize = 0x03c;
+/// Legacy only
+const REG_QUEUE_PFN: usize = 0x040;
+/// Modern only
+const REG_QUEUE_READY: usize = 0x044;
+const REG_QUEUE_NOTIFY: usize = 0x050;
+const REG_INTERRUPT_STATUS: usize = 0x060;
+const REG_INTERRUPT_ACK: usize = 0x064;
+const REG_STATUS: usize = 0x070;
+const REG_QUEUE_DESC_LOW: usize = 0x080;
+const REG_QUEUE_DESC_HIGH: usize = 0x084;
+const REG_QUEUE_DRIVER_LOW: usize = 0x090;
+const REG_QUEUE_DRIVER_HIGH: usize = 0x094;
+const REG_QUEUE_DEVICE_LOW: usize = 0x0a0;
+const REG_QUEUE_DEVICE_HIGH: usize = 0x0a4;
+/// Modern only
+const REG_CONFIG_GENERATION: usize = 0x0fc;
+const REG_CONFIG: usize = 0x100;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+const STATUS_FAILED: u32 = 128;
+
+/// The device follows the virtio 1.0 specification. Required from modern devices
+pub const F_VERSION_1: u64 = 1 << 32;
+
+/// Page size told to legacy devices, which address queues by page number
+const LEGACY_PAGE_SIZE: usize = 4096;
+
+/// One virtio-mmio slot with a device behind it
+#[derive(Debug)]
+pub struct Transport {
+    base: usize,
+    version: u32,
+    device_id: u32,
+    vendor_id: u32,
+    interrupt: Option<u32>,
+}
+
+impl Transport {
+    /// Identifies the device in the slot at `base`. Returns None for slots QEMU keeps free for
+    /// devices that are not attached
+    ///
+    /// # Safety
+    /// `base` must be the address of a virtio-mmio register block that nothing else uses
+    pub unsafe fn probe(base: usize, interrupt: Option<u32>) -> Result<Option<Self>, VirtioError> {
+        let mut transport = Self {
+            base,
+            version: 0,
+            device_id: 0,
+            vendor_id: 0,
+            interrupt,
+        };
+        if transport.read(REG_MAGIC) != MAGIC {
+            return Err(VirtioError::BadMagic);
+        }
+        transport.version = transport.read(REG_VERSION);
+        if !matches!(transport.version, 1 | 2) {
+            return Err(VirtioError::UnsupportedVersion(transport.version));
+        }
+        transport.device_id = transport.read(REG_DEVICE_ID);
+        if transport.device_id == 0 {
+            return Ok(None);
+        }
+        transport.vendor_id = transport.read(REG_VENDOR_ID);
+        Ok(Some(transport))
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// 1 for legacy devices, 2 for modern ones
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.version == 1
+    }
+
+    pub fn device_id(&self) -> u32 {
+        self.device_id
+    }
+
+    pub fn vendor_id(&self) -> u32 {
+        self.vendor_id
+    }
+
+    /// Interrupt source of the device at the interrupt controller
+    pub fn interrupt(&self) -> Option<u32> {
+        self.interrupt
+    }
+
+    /// Resets the device and negotiates features, accepting those of `supported` the device
+    /// offers. Returns the accepted features. Queues are set up next, followed by `driver_ok`
+    pub fn init(&mut self, supported: u64) -> Result<u64, VirtioError> {
+        self.write(REG_STATUS, 0);
+        self.set_status(STATUS_ACKNOWLEDGE);
+        self.set_status(STATUS_DRIVER);
+
+        let offered = self.device_features();
+        let mut accepted = offered & supported;
+        if !self.is_legacy() {
+            if offered & F_VERSION_1 == 0 {
+                self.fail();
+                return Err(VirtioError::FeaturesRejected);
+            }
+            accepted |= F_VERSION_1;
+        }
+        self.write(REG_DRIVER_FEATURES_SEL, 0);
+        self.write(REG_DRIVER_FEATURES, accepted as u32);
+        self.write(REG_DRIVER_FEATURES_SEL, 1);
+        self.write(REG_DRIVER_FEATURES, (accepted >> 32) as u32);
+
+        if self.is_legacy() {
+            // Legacy devices have no FEATURES_OK handshake, but need the page size queues
+            // are addressed in
+            self.write(REG_GUEST_PAGE_SIZE, LEGACY_PAGE_SIZE as u32);
+        } else {
+            self.set_status(STATUS_FEATURES_OK);
+            if self.read(REG_STATUS) & STATUS_FEATURES_OK == 0 {
+                self.fail();
+                return Err(VirtioError::FeaturesRejected);
+            }
+        }
+        Ok(accepted)
+    }
+
+    fn device_features(&mut self) -> u64 {
+        self.write(REG_DEVICE_FEATURES_SEL, 0);
+        let low = self.read(REG_DEVICE_FEATURES) as u64;
+        self.write(REG_DEVICE_FEATURES_SEL, 1);
+        let high = self.read(REG_DEVICE_FEATURES) as u64;
+        (high << 32) | low
+    }
+
+    /// Sets up queue `index` with as many entries as the device allows, but at most `max_size`
+    pub fn setup_queue(&mut self, index: u16, max_size: u16) -> Result<VirtQueue, VirtioError> {
+        self.write(REG_QUEUE_SEL, index as u32);
+        let in_use = if self.is_legacy() {
+            self.read(REG_QUEUE_PFN) != 0
+        } else {
+            self.read(REG_QUEUE_READY) != 0
+        };
+        let device_max = self.read(REG_QUEUE_NUM_MAX).min(u16::MAX as u32) as u16;
+        if in_use || device_max == 0 {
+            return Err(VirtioError::QueueUnavailable(index));
+        }
+
+        // Split queues of legacy devices must be a power of two long
+        let size = device_max.min(max_size.max(1));
+        let size = 1 << (u16::BITS - 1 - size.leading_zeros());
+        let queue = VirtQueue::new(index, size);
+        self.write(REG_QUEUE_NUM, size as u32);
+
+        if self.is_legacy() {
+            self.write(REG_QUEUE_ALIGN, LEGACY_PAGE_SIZE as u32);
+            self.write(REG_QUEUE_PFN, (queue.descriptor_address() / LEGACY_PAGE_SIZE) as u32);
+        } else {
+            let address = |address: usize| (address as u64 as u32, (address as u64 >> 32) as u32);
+            let (low, high) = address(queue.descriptor_address());
+            self.write(REG_QUEUE_DESC_LOW, low);
+            self.write(REG_QUEUE_DESC_HIGH, high);
+            let (low, high) = address(queue.driver_address());
+            self.write(REG_QUEUE_DRIVER_LOW, low);
+            self.write(REG_QUEUE_DRIVER_HIGH, high);
+            let (low, high) = address(queue.device_address());
+            self.write(REG_QUEUE_DEVICE_LOW, low);
+            self.write(REG_QUEUE_DEVICE_HIGH, high);
+            self.write(REG_QUEUE_READY, 1);
+        }
+        Ok(queue)
+    }
+
+    /// Tells the device the driver is set up, after which it starts processing queues
+    pub fn driver_ok(&mut self) {
+        self.set_status(STATUS_DRIVER_OK);
+    }
+
+    /// Tells the device the driver gave up on it
+    pub fn fail(&mut self) {
+        self.set_status(STATUS_FAILED);
+    }
+
+    /// Tells the device new buffers are available in queue `index`
+    pub fn notify(&self, index: u16) {
+        self.write(REG_QUEUE_NOTIFY, index as u32);
+    }
+
+    /// Acknowledges pending interrupts and returns them. Bit 0 means a queue was used, bit 1
+    /// that the configuration changed
+    pub fn ack_interrupt(&self) -> u32 {
+        let status = self.read(REG_INTERRUPT_STATUS);
+        self.write(REG_INTERRUPT_ACK, status);
+        status
+    }
+
+    /// Reads a field of the device specific configuration at `offset`
+    pub fn read_config<T: Copy>(&self, offset: usize) -> T {
+        let ptr = (self.base + REG_CONFIG + offset) as *const T;
+        if self.is_legacy() {
+            return unsafe { read_volatile(ptr) };
+        }
+        // Fields wider than a register may change while they are read, which the generation
+        // counter reveals
+        loop {
+            let generation = self.read(REG_CONFIG_GENERATION);
+            let value = unsafe { read_volatile(ptr) };
+            if self.read(REG_CONFIG_GENERATION) == generation {
+                return value;
+            }
+        }
+    }
+
+    fn set_status(&mut self, status: u32) {
+        let current = self.read(REG_STATUS);
+        self.write(REG_STATUS, current | status);
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        // SAFETY: `probe` requires the base to be a register block of a virtio-mmio device
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        // SAFETY: `probe` requires the base to be a register block of a virtio-mmio device
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+}
@@ -0,0 +1,234 @@
+//! Split virtqueues, the rings buffers are exchanged with a device through.
+//!
+//! The descriptor table, the available ring and the used ring are laid out in one block the
+//! way legacy devices expect them, which modern devices accept as well. The block comes from
+//! the kernel heap, which is mapped in every address space, so completions can be collected
+//! from any interrupt.
+
+extern crate alloc;
+
+use core::{
+    mem::size_of,
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{fence, Ordering},
+};
+
+use alloc::alloc::{alloc_zeroed, Layout};
+
+use crate::utils::align_up;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+/// Alignment of the used ring for legacy devices
+const USED_ALIGN: usize = 4096;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// A piece of memory handed to the device, by its physical address
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer {
+    pub addr: usize,
+    pub len: u32,
+    /// The device writes the buffer instead of reading it
+    pub device_writable: bool,
+}
+
+/// Not enough free descriptors for the buffers of a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFull;
+
+#[derive(Debug)]
+pub struct VirtQueue {
+    index: u16,
+    size: u16,
+    descriptors: *mut Descriptor,
+    /// flags, idx, ring[size], used_event
+    avail: *mut u16,
+    /// flags, idx, ring[size] of `UsedElem`, avail_event
+    used: *mut u16,
+    /// First descriptor of the chain of free ones, linked through `next`
+    free_head: u16,
+    free_count: u16,
+    /// Index into the used ring up to which completions were collected
+    last_used: u16,
+}
+
+// SAFETY: The rings are only changed through `&mut self` and by the device
+unsafe impl Send for VirtQueue {}
+unsafe impl Sync for VirtQueue {}
+
+impl VirtQueue {
+    pub(super) fn new(index: u16, size: u16) -> Self {
+        let (avail_offset, used_offset, total) = Self::layout(size);
+        let layout = Layout::from_size_align(total, USED_ALIGN).unwrap();
+        // SAFETY: Layout is non zero. The rings live as long as the device, so they are
+        // never freed
+        let memory = unsafe { alloc_zeroed(layout) };
+        if memory.is_null() {
+            panic!("Failed to allocate a virtqueue");
+        }
+
+        let descriptors = memory as *mut Descriptor;
+        for i in 0..size {
+            // SAFETY: The table has `size` entries
+            unsafe { (*descriptors.add(i as usize)).next = i.wrapping_add(1) };
+        }
+        Self {
+            index,
+            size,
+            descriptors,
+            avail: unsafe { memory.add(avail_offset) } as *mut u16,
+            used: unsafe { memory.add(used_offset) } as *mut u16,
+            free_head: 0,
+            free_count: size,
+            last_used: 0,
+        }
+    }
+
+    /// Offsets of the available and used ring and the size of the whole block
+    fn layout(size: u16) -> (usize, usize, usize) {
+        let size = size as usize;
+        let avail_offset = size_of::<Descriptor>() * size;
+        let avail_size = size_of::<u16>() * (3 + size);
+        let used_offset = align_up(avail_offset + avail_size, USED_ALIGN);
+        let used_size = size_of::<u16>() * 3 + size_of::<UsedElem>() * size;
+        (avail_offset, used_offset, used_offset + used_size)
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// How many descriptors are free, a request takes one per buffer
+    pub fn free_descriptors(&self) -> u16 {
+        self.free_count
+    }
+
+    pub(super) fn descriptor_address(&self) -> usize {
+        self.descriptors as usize
+    }
+
+    pub(super) fn driver_address(&self) -> usize {
+        self.avail as usize
+    }
+
+    pub(super) fn device_address(&self) -> usize {
+        self.used as usize
+    }
+
+    /// Id the next request `add` makes available gets, None if no descriptor is free
+    pub fn next_id(&self) -> Option<u16> {
+        (self.free_count > 0).then_some(self.free_head)
+    }
+
+    /// Chains `buffers` into one request and makes it available to the device. Returns the
+    /// id of the request, which `pop_used` hands back once the device is done with it. The
+    /// device still has to be notified
+    pub fn add(&mut self, buffers: &[Buffer]) -> Result<u16, QueueFull> {
+        if buffers.is_empty() || buffers.len() > self.free_count as usize {
+            return Err(QueueFull);
+        }
+
+        let head = self.free_head;
+        for (i, buffer) in buffers.iter().enumerate() {
+            let id = self.free_head;
+            let descriptor = self.descriptor(id);
+            self.free_head = descriptor.next;
+            let mut flags = if buffer.device_writable { DESC_F_WRITE } else { 0 };
+            if i + 1 < buffers.len() {
+                flags |= DESC_F_NEXT;
+            }
+            self.set_descriptor(
+                id,
+                Descriptor {
+                    addr: buffer.addr as u64,
+                    len: buffer.len,
+                    flags,
+                    next: self.free_head,
+                },
+            );
+        }
+        self.free_count -= buffers.len() as u16;
+
+        // SAFETY: The ring has `size` entries behind its two header fields
+        unsafe {
+            let index = read_volatile(self.avail.add(1));
+            write_volatile(self.avail.add(2 + (index % self.size) as usize), head);
+            // The entry has to be visible before the device sees the new index
+            fence(Ordering::SeqCst);
+            write_volatile(self.avail.add(1), index.wrapping_add(1));
+            fence(Ordering::SeqCst);
+        }
+        Ok(head)
+    }
+
+    /// Takes the next request the device finished from the used ring and frees its
+    /// descriptors. Returns its id and how many bytes the device wrote
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        // SAFETY: The ring has `size` entries behind its two header fields
+        let element = unsafe {
+            if read_volatile(self.used.add(1)) == self.last_used {
+                return None;
+            }
+            // Entries are only read after the index that announced them
+            fence(Ordering::SeqCst);
+            let ring = self.used.add(2) as *const UsedElem;
+            read_volatile(ring.add((self.last_used % self.size) as usize))
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+
+        let head = element.id as u16;
+        self.free_chain(head);
+        Some((head, element.len))
+    }
+
+    fn free_chain(&mut self, head: u16) {
+        let mut id = head;
+        loop {
+            let descriptor = self.descriptor(id);
+            self.free_count += 1;
+            if descriptor.flags & DESC_F_NEXT == 0 {
+                // Put the chain in front of the free ones
+                self.set_descriptor(
+                    id,
+                    Descriptor {
+                        next: self.free_head,
+                        ..descriptor
+                    },
+                );
+                break;
+            }
+            id = descriptor.next;
+        }
+        self.free_head = head;
+    }
+
+    fn descriptor(&self, id: u16) -> Descriptor {
+        // SAFETY: Ids handed out are below `size`
+        unsafe { read_volatile(self.descriptors.add(id as usize)) }
+    }
+
+    fn set_descriptor(&mut self, id: u16, descriptor: Descriptor) {
+        // SAFETY: Ids handed out are below `size`
+        unsafe { write_volatile(self.descriptors.add(id as usize), descriptor) }
+    }
+}
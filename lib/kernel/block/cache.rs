@@ -0,0 +1,273 @@
+//! Cache of device blocks that filesystems read and write through.
+//!
+//! `get` hands out a shared `BlockBuffer` per (device, block), reading it on a miss. Writes
+//! only mark a buffer dirty; it reaches the device when `sync` runs or when the buffer is
+//! evicted to make room for another block. Buffers nobody holds a handle to are evicted least
+//! recently used first, and their memory is reused for the next block.
+
+extern crate alloc;
+
+use core::{
+    future::poll_fn,
+    task::{Poll, Waker},
+};
+
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec, vec::Vec};
+
+use crate::{
+    collections::mutex::Mutex,
+    drivers::irq::without_interrupts,
+    kernel::block::{BlockDevice, BlockError},
+};
+
+/// Number of buffers the cache keeps before it evicts, it only goes beyond it while every
+/// buffer is in use
+const CAPACITY: usize = 256;
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache {
+    buffers: BTreeMap::new(),
+    clock: 0,
+});
+
+/// A device by the address of its object, which the cache keeps alive as long as it holds
+/// buffers of it
+type Key = (usize, u64);
+
+#[derive(Debug)]
+struct Cached {
+    buffer: Arc<BlockBuffer>,
+    last_used: u64,
+}
+
+#[derive(Debug)]
+struct Cache {
+    buffers: BTreeMap<Key, Cached>,
+    /// Counts the lookups, stamps the buffers with their last one
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct BufferState {
+    /// Out with the device while a read or write is in flight
+    data: Option<Box<[u8]>>,
+    /// The data holds the contents of the block
+    valid: bool,
+    dirty: bool,
+    /// Wait for the data to come back from the device
+    waiters: Vec<Waker>,
+}
+
+/// One block of a device, shared by everyone who holds it
+#[derive(Debug)]
+pub struct BlockBuffer {
+    device: Arc<dyn BlockDevice>,
+    block: u64,
+    state: Mutex<BufferState>,
+}
+
+fn device_id(device: &Arc<dyn BlockDevice>) -> usize {
+    Arc::as_ptr(device) as *const () as usize
+}
+
+/// Returns the buffer of `block` of `device`, read from the device unless it is cached
+pub async fn get(
+    device: &Arc<dyn BlockDevice>,
+    block: u64,
+) -> Result<Arc<BlockBuffer>, BlockError> {
+    if block >= device.block_count() {
+        return Err(BlockError::OutOfRange);
+    }
+    let key = (device_id(device), block);
+    let buffer = loop {
+        let victim = {
+            let mut cache = CACHE.lock();
+            cache.clock += 1;
+            let clock = cache.clock;
+            if let Some(cached) = cache.buffers.get_mut(&key) {
+                cached.last_used = clock;
+                break cached.buffer.clone();
+            }
+            match cache.victim() {
+                // Written back first, and looked up again as another task may have taken
+                // it or the block in the meantime
+                Some(victim) if cache.buffers[&victim].buffer.is_dirty() => {
+                    cache.buffers[&victim].buffer.clone()
+                }
+                victim => {
+                    let data = victim
+                        .and_then(|victim| cache.buffers.remove(&victim))
+                        .and_then(|cached| Arc::into_inner(cached.buffer))
+                        .and_then(|buffer| buffer.state.into_inner().data)
+                        .filter(|data| data.len() == device.block_size())
+                        .unwrap_or_else(|| vec![0; device.block_size()].into_boxed_slice());
+                    let buffer = Arc::new(BlockBuffer::new(device.clone(), block, data));
+                    cache.buffers.insert(
+                        key,
+                        Cached {
+                            buffer: buffer.clone(),
+                            last_used: clock,
+                        },
+                    );
+                    break buffer;
+                }
+            }
+        };
+        victim.write_back().await?;
+    };
+    buffer.load().await?;
+    Ok(buffer)
+}
+
+/// Writes every dirty buffer back and flushes the devices they belong to
+pub async fn sync() -> Result<(), BlockError> {
+    sync_buffers(|_| true).await
+}
+
+/// Writes the dirty buffers of `device` back and flushes it
+pub async fn sync_device(device: &Arc<dyn BlockDevice>) -> Result<(), BlockError> {
+    let device = device_id(device);
+    sync_buffers(|(buffer_device, _)| buffer_device == device).await
+}
+
+async fn sync_buffers(filter: impl Fn(Key) -> bool) -> Result<(), BlockError> {
+    let dirty: Vec<Arc<BlockBuffer>> = CACHE
+        .lock()
+        .buffers
+        .iter()
+        .filter(|&(&key, cached)| filter(key) && cached.buffer.is_dirty())
+        .map(|(_, cached)| cached.buffer.clone())
+        .collect();
+
+    let mut result = Ok(());
+    let mut devices: BTreeMap<usize, Arc<dyn BlockDevice>> = BTreeMap::new();
+    for buffer in dirty {
+        if let Err(err) = buffer.write_back().await {
+            result = result.and(Err(err));
+        }
+        devices
+            .entry(device_id(&buffer.device))
+            .or_insert_with(|| buffer.device.clone());
+    }
+    for device in devices.values() {
+        if let Err(err) = device.flush().await {
+            result = result.and(Err(err));
+        }
+    }
+    result
+}
+
+impl Cache {
+    /// The least recently used buffer nobody holds a handle to and the device is not busy with,
+    /// if the cache is full
+    fn victim(&self) -> Option<Key> {
+        if self.buffers.len() < CAPACITY {
+            return None;
+        }
+        self.buffers
+            .iter()
+            .filter(|(_, cached)| Arc::strong_count(&cached.buffer) == 1)
+            .filter(|(_, cached)| cached.buffer.state.lock().data.is_some())
+            .min_by_key(|(_, cached)| cached.last_used)
+            .map(|(&key, _)| key)
+    }
+}
+
+impl BlockBuffer {
+    fn new(device: Arc<dyn BlockDevice>, block: u64, data: Box<[u8]>) -> Self {
+        Self {
+            device,
+            block,
+            state: Mutex::new(BufferState {
+                data: Some(data),
+                valid: false,
+                dirty: false,
+                waiters: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn device(&self) -> &Arc<dyn BlockDevice> {
+        &self.device
+    }
+
+    pub fn block(&self) -> u64 {
+        self.block
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.state.lock().dirty
+    }
+
+    /// Runs `f` on the contents of the block
+    pub async fn read<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        self.with_data(|state| f(state.data.as_deref().unwrap()))
+            .await
+    }
+
+    /// Runs `f` on the contents of the block and marks it dirty, it is written back on the
+    /// next `sync` or when it is evicted
+    pub async fn write<R>(&self, f: impl FnOnce(&mut [u8]) -> R) -> R {
+        self.with_data(|state| {
+            state.dirty = true;
+            f(state.data.as_deref_mut().unwrap())
+        })
+        .await
+    }
+
+    /// Writes the block to the device if it is dirty
+    pub async fn write_back(&self) -> Result<(), BlockError> {
+        let Some(data) = self.take_data(|state| state.dirty).await else {
+            return Ok(());
+        };
+        let (data, result) = self.device.write(self.block, data).await;
+        self.put_data(data, |state| {
+            // Nobody could write to it while the device had it
+            state.dirty = result.is_err();
+        });
+        result
+    }
+
+    /// Reads the block from the device unless it was already
+    async fn load(&self) -> Result<(), BlockError> {
+        let Some(data) = self.take_data(|state| !state.valid).await else {
+            return Ok(());
+        };
+        let (data, result) = self.device.read(self.block, data).await;
+        self.put_data(data, |state| state.valid = result.is_ok());
+        result
+    }
+
+    /// Waits until the data is back from the device and runs `f` on the state
+    async fn with_data<R>(&self, f: impl FnOnce(&mut BufferState) -> R) -> R {
+        let mut f = Some(f);
+        poll_fn(|cx| {
+            let mut state = self.state.lock();
+            if state.data.is_none() {
+                state.waiters.push(cx.waker().clone());
+                return Poll::Pending;
+            }
+            Poll::Ready(f.take().unwrap()(&mut state))
+        })
+        .await
+    }
+
+    /// Takes the data out to hand it to the device, if `needs_io` says the state needs it
+    async fn take_data(&self, needs_io: impl Fn(&BufferState) -> bool) -> Option<Box<[u8]>> {
+        self.with_data(|state| match needs_io(state) {
+            true => state.data.take(),
+            false => None,
+        })
+        .await
+    }
+
+    /// Puts the data back once the device is done with it, and wakes those waiting for it
+    fn put_data(&self, data: Box<[u8]>, update: impl FnOnce(&mut BufferState)) {
+        let waiters = {
+            let mut state = self.state.lock();
+            state.data = Some(data);
+            update(&mut state);
+            core::mem::take(&mut state.waiters)
+        };
+        without_interrupts(|| waiters.into_iter().for_each(Waker::wake));
+    }
+}
@@ -0,0 +1,125 @@
+//! Block devices, the storage filesystems and partition tables are read from.
+//!
+//! Drivers implement `BlockDevice` and `register` their devices under a name, e.g. `vda`.
+//! Reads and writes are futures, which kernel code waits for with `Kernel::block_on`.
+//! Filesystems go through the buffer `cache` rather than to the devices directly.
+
+extern crate alloc;
+
+use core::{
+    fmt::Debug,
+    future::Future,
+    pin::{pin, Pin},
+    task::{Context, Poll, Waker},
+};
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+
+use crate::collections::mutex::Mutex;
+
+pub mod cache;
+pub mod partition;
+pub mod ramdisk;
+
+pub use ramdisk::RamDisk;
+
+static DEVICES: Mutex<Vec<Arc<dyn BlockDevice>>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    /// The request reaches past the last block
+    OutOfRange,
+    ReadOnly,
+    /// The buffer is empty or not a whole number of blocks long
+    BadLength,
+    /// The device failed to carry out the request
+    Io,
+    /// The device does not support the request
+    Unsupported,
+}
+
+/// The buffer of a finished read or write, and whether it succeeded
+pub type Completion = (Box<[u8]>, Result<(), BlockError>);
+/// Resolves to the buffer of a read or write, and whether it succeeded
+pub type BlockFuture = Pin<Box<dyn Future<Output = Completion> + Send>>;
+pub type FlushFuture = Pin<Box<dyn Future<Output = Result<(), BlockError>> + Send>>;
+
+pub trait BlockDevice: Send + Sync + Debug {
+    /// Name the device is registered under
+    fn name(&self) -> &str;
+
+    /// Size of a block in bytes
+    fn block_size(&self) -> usize;
+
+    /// Size of the device in blocks
+    fn block_count(&self) -> u64;
+
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    /// Reads the blocks starting at `block` into `buffer`, as many as it holds
+    fn read(&self, block: u64, buffer: Box<[u8]>) -> BlockFuture;
+
+    /// Writes `buffer` to the blocks starting at `block`
+    fn write(&self, block: u64, buffer: Box<[u8]>) -> BlockFuture;
+
+    /// Resolves once everything written so far is on stable storage
+    fn flush(&self) -> FlushFuture;
+
+    /// Checks a request for `len` bytes starting at `block` against the size of the device
+    fn check_range(&self, block: u64, len: usize) -> Result<(), BlockError> {
+        let block_size = self.block_size();
+        if len == 0 || !len.is_multiple_of(block_size) {
+            return Err(BlockError::BadLength);
+        }
+        let end = block.checked_add((len / block_size) as u64);
+        if end.is_none_or(|end| end > self.block_count()) {
+            return Err(BlockError::OutOfRange);
+        }
+        Ok(())
+    }
+}
+
+/// Polls `future` until it is ready. For boot, before there are tasks to park, tasks wait
+/// with `Kernel::block_on`
+pub fn poll_to_completion<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Makes `device` available under its name
+pub fn register(device: Arc<dyn BlockDevice>) {
+    println!(
+        "block: {}: {} blocks of {} bytes{}",
+        device.name(),
+        device.block_count(),
+        device.block_size(),
+        if device.is_read_only() {
+            ", read only"
+        } else {
+            ""
+        }
+    );
+    DEVICES.lock().push(device);
+}
+
+/// Returns the device registered as `name`
+pub fn find(name: &str) -> Option<Arc<dyn BlockDevice>> {
+    DEVICES
+        .lock()
+        .iter()
+        .find(|device| device.name() == name)
+        .cloned()
+}
+
+/// Every registered device, in the order they were registered
+pub fn devices() -> Vec<Arc<dyn BlockDevice>> {
+    DEVICES.lock().clone()
+}
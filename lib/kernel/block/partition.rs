@@ -0,0 +1,316 @@
+//! Partitions of a disk, found in its MBR or GPT partition table.
+//!
+//! Each partition is a `BlockDevice` of its own, named after the disk and its number like
+//! `vda1`. Its blocks are translated to blocks of the disk and may not reach past its end.
+
+extern crate alloc;
+
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec, vec::Vec};
+
+use crate::kernel::block::{self, BlockDevice, BlockError, BlockFuture, FlushFuture};
+
+/// Smallest block the tables fit in, the MBR and the GPT header take one block each
+const MIN_BLOCK_SIZE: usize = 512;
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_SIGNATURE_OFFSET: usize = 510;
+const MBR_ENTRIES_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+/// Partition types of extended partitions, which hold a chain of logical ones
+const MBR_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+/// Partition type of the single entry covering a GPT disk
+const MBR_PROTECTIVE: u8 = 0xee;
+/// Logical partitions followed before the chain is taken to be broken
+const MAX_LOGICAL: usize = 64;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+/// Entries read before the table is taken to be broken
+const GPT_MAX_ENTRIES: u32 = 1024;
+/// Largest entry array read, 1024 entries of the usual 128 bytes
+const GPT_MAX_TABLE_SIZE: usize = 128 * 1024;
+
+/// Why the partition table of a disk could not be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanError {
+    /// Reading the table from the disk failed
+    Device(BlockError),
+    /// The MBR is protective but there is no GPT header behind it
+    MissingGpt,
+    /// The GPT header describes an entry array that can not be right
+    BadGptEntries,
+    /// Blocks of the device are too small to hold a boot record
+    BlockSizeTooSmall,
+}
+
+impl From<BlockError> for ScanError {
+    fn from(error: BlockError) -> Self {
+        ScanError::Device(error)
+    }
+}
+
+/// A range of blocks of another device
+#[derive(Debug)]
+pub struct Partition {
+    name: String,
+    device: Arc<dyn BlockDevice>,
+    /// First block on the device
+    start: u64,
+    /// Size in blocks
+    count: u64,
+    /// Number in the partition table, starting at 1
+    number: usize,
+}
+
+impl Partition {
+    pub fn device(&self) -> &Arc<dyn BlockDevice> {
+        &self.device
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn number(&self) -> usize {
+        self.number
+    }
+}
+
+impl BlockDevice for Partition {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.count
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.device.is_read_only()
+    }
+
+    fn read(&self, block: u64, buffer: Box<[u8]>) -> BlockFuture {
+        match self.check_range(block, buffer.len()) {
+            Ok(()) => self.device.read(self.start + block, buffer),
+            Err(err) => Box::pin(core::future::ready((buffer, Err(err)))),
+        }
+    }
+
+    fn write(&self, block: u64, buffer: Box<[u8]>) -> BlockFuture {
+        match self.check_range(block, buffer.len()) {
+            Ok(()) => self.device.write(self.start + block, buffer),
+            Err(err) => Box::pin(core::future::ready((buffer, Err(err)))),
+        }
+    }
+
+    fn flush(&self) -> FlushFuture {
+        self.device.flush()
+    }
+}
+
+/// A partition as the table describes it, in blocks of the disk
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    number: usize,
+    start: u64,
+    count: u64,
+}
+
+/// Reads the partition table of `device`. A disk without one has no partitions
+pub async fn scan(device: &Arc<dyn BlockDevice>) -> Result<Vec<Partition>, ScanError> {
+    if device.block_size() < MIN_BLOCK_SIZE {
+        return Err(ScanError::BlockSizeTooSmall);
+    }
+    let mbr = read_blocks(device, 0, 1).await?;
+    if mbr[MBR_SIGNATURE_OFFSET..MBR_SIGNATURE_OFFSET + 2] != MBR_SIGNATURE {
+        return Ok(Vec::new());
+    }
+    // A boot sector of a filesystem ends with the signature too, but has code where the
+    // entries are, which rarely passes as boot flags
+    if mbr_slots(&mbr, 4).any(|slot| slot.boot_flag & 0x7f != 0) {
+        return Ok(Vec::new());
+    }
+    let entries = if mbr_slots(&mbr, 4).any(|slot| slot.kind == MBR_PROTECTIVE) {
+        gpt_entries(device).await?
+    } else {
+        mbr_partitions(device, &mbr).await?
+    };
+
+    let device_count = device.block_count();
+    let partitions = entries
+        .into_iter()
+        .filter(|entry| {
+            let fits = entry.count > 0
+                && entry
+                    .start
+                    .checked_add(entry.count)
+                    .is_some_and(|end| end <= device_count);
+            if !fits {
+                println!(
+                    "{}: partition {} reaches past the end of the disk",
+                    device.name(),
+                    entry.number
+                );
+            }
+            fits
+        })
+        .map(|entry| Partition {
+            name: partition_name(device.name(), entry.number),
+            device: device.clone(),
+            start: entry.start,
+            count: entry.count,
+            number: entry.number,
+        })
+        .collect();
+    Ok(partitions)
+}
+
+/// Registers the partitions of every registered disk as block devices of their own
+pub async fn register_all() {
+    for device in block::devices() {
+        match scan(&device).await {
+            Ok(partitions) => partitions
+                .into_iter()
+                .for_each(|partition| block::register(Arc::new(partition))),
+            Err(err) => {
+                println!("{}: partition table: {:?}", device.name(), err);
+            }
+        }
+    }
+}
+
+/// `vda` and 1 make `vda1`, a name ending in a digit gets a `p` in between like `ram0p1`
+fn partition_name(device: &str, number: usize) -> String {
+    match device.ends_with(|c: char| c.is_ascii_digit()) {
+        true => format!("{}p{}", device, number),
+        false => format!("{}{}", device, number),
+    }
+}
+
+async fn read_blocks(
+    device: &Arc<dyn BlockDevice>,
+    block: u64,
+    count: usize,
+) -> Result<Box<[u8]>, BlockError> {
+    let buffer = vec![0; device.block_size() * count].into_boxed_slice();
+    let (buffer, result) = device.read(block, buffer).await;
+    result.map(|()| buffer)
+}
+
+/// An entry of an MBR or an extended boot record, of type 0 if it is unused
+#[derive(Debug, Clone, Copy)]
+struct MbrSlot {
+    boot_flag: u8,
+    kind: u8,
+    start: u64,
+    count: u64,
+}
+
+/// The first `count` entries of a boot record
+fn mbr_slots(record: &[u8], count: usize) -> impl Iterator<Item = MbrSlot> + '_ {
+    record[MBR_ENTRIES_OFFSET..MBR_ENTRIES_OFFSET + count * MBR_ENTRY_SIZE]
+        .chunks_exact(MBR_ENTRY_SIZE)
+        .map(|entry| MbrSlot {
+            boot_flag: entry[0],
+            kind: entry[4],
+            start: le32(&entry[8..]) as u64,
+            count: le32(&entry[12..]) as u64,
+        })
+}
+
+/// Primary partitions are numbered 1 to 4 by their slot, logical ones from 5 on
+async fn mbr_partitions(
+    device: &Arc<dyn BlockDevice>,
+    mbr: &[u8],
+) -> Result<Vec<Entry>, BlockError> {
+    let mut entries = Vec::new();
+    let mut extended = None;
+    for (index, slot) in mbr_slots(mbr, 4).enumerate() {
+        match slot.kind {
+            0 => {}
+            kind if MBR_EXTENDED.contains(&kind) => extended = extended.or(Some(slot.start)),
+            _ => entries.push(Entry {
+                number: index + 1,
+                start: slot.start,
+                count: slot.count,
+            }),
+        }
+    }
+
+    // Each extended boot record holds a logical partition, relative to itself, and the
+    // next record, relative to the extended partition
+    let Some(extended) = extended else {
+        return Ok(entries);
+    };
+    let mut record = extended;
+    for number in 5..5 + MAX_LOGICAL {
+        let ebr = read_blocks(device, record, 1).await?;
+        if ebr[MBR_SIGNATURE_OFFSET..MBR_SIGNATURE_OFFSET + 2] != MBR_SIGNATURE {
+            break;
+        }
+        let mut slots = mbr_slots(&ebr, 2);
+        if let Some(logical) = slots.next().filter(|slot| slot.kind != 0) {
+            entries.push(Entry {
+                number,
+                start: record + logical.start,
+                count: logical.count,
+            });
+        }
+        match slots.next() {
+            Some(next) if next.kind != 0 && next.start != 0 => record = extended + next.start,
+            _ => break,
+        }
+    }
+    Ok(entries)
+}
+
+/// Partitions of the GPT, numbered by their slot in the entry array starting at 1
+async fn gpt_entries(device: &Arc<dyn BlockDevice>) -> Result<Vec<Entry>, ScanError> {
+    let header = read_blocks(device, 1, 1).await?;
+    if &header[0..8] != GPT_SIGNATURE {
+        return Err(ScanError::MissingGpt);
+    }
+    let entries_start = le64(&header[72..]);
+    let entry_count = le32(&header[80..]).min(GPT_MAX_ENTRIES) as usize;
+    let entry_size = le32(&header[84..]) as usize;
+    let block_size = device.block_size();
+    // Entries are 128 bytes times a power of two, so they never straddle a block
+    if entry_size < 128 || !entry_size.is_power_of_two() || entry_size > block_size {
+        return Err(ScanError::BadGptEntries);
+    }
+    let table_size = entry_count
+        .checked_mul(entry_size)
+        .filter(|&size| size <= GPT_MAX_TABLE_SIZE)
+        .ok_or(ScanError::BadGptEntries)?;
+
+    let blocks = table_size.div_ceil(block_size);
+    let table = read_blocks(device, entries_start, blocks).await?;
+    let entries = table
+        .chunks_exact(entry_size)
+        .take(entry_count)
+        .enumerate()
+        // An entry of type zero is unused
+        .filter(|(_, entry)| entry[0..16].iter().any(|&b| b != 0))
+        .filter_map(|(slot, entry)| {
+            // The last block is inclusive, an entry ending on the last possible block can
+            // not be right
+            let (first, last) = (le64(&entry[32..]), le64(&entry[40..]));
+            Some(Entry {
+                number: slot + 1,
+                start: first,
+                count: last.checked_add(1)?.saturating_sub(first),
+            })
+        })
+        .collect();
+    Ok(entries)
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn le64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
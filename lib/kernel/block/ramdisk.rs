@@ -0,0 +1,97 @@
+//! A block device backed by kernel memory.
+
+extern crate alloc;
+
+use alloc::{boxed::Box, string::String, vec};
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::block::{BlockDevice, BlockError, BlockFuture, FlushFuture},
+};
+
+#[derive(Debug)]
+pub struct RamDisk {
+    name: String,
+    block_size: usize,
+    data: Mutex<Box<[u8]>>,
+    read_only: bool,
+}
+
+impl RamDisk {
+    /// Creates a zeroed disk of `block_count` blocks
+    pub fn new(name: &str, block_size: usize, block_count: usize) -> Self {
+        Self::from_image(
+            name,
+            block_size,
+            vec![0; block_size * block_count].into_boxed_slice(),
+        )
+    }
+
+    /// Creates a disk holding `image`, cut off after its last whole block
+    pub fn from_image(name: &str, block_size: usize, mut image: Box<[u8]>) -> Self {
+        let len = image.len() / block_size * block_size;
+        if len != image.len() {
+            image = image[..len].into();
+        }
+        Self {
+            name: name.into(),
+            block_size,
+            data: Mutex::new(image),
+            read_only: false,
+        }
+    }
+
+    /// Makes writes fail with `BlockError::ReadOnly`
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    fn copy(&self, block: u64, buffer: &mut [u8], write: bool) -> Result<(), BlockError> {
+        if write && self.read_only {
+            return Err(BlockError::ReadOnly);
+        }
+        self.check_range(block, buffer.len())?;
+        let start = block as usize * self.block_size;
+        let mut data = self.data.lock();
+        let data = &mut data[start..start + buffer.len()];
+        if write {
+            data.copy_from_slice(buffer);
+        } else {
+            buffer.copy_from_slice(data);
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.data.lock().len() / self.block_size) as u64
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn read(&self, block: u64, mut buffer: Box<[u8]>) -> BlockFuture {
+        let result = self.copy(block, &mut buffer, false);
+        Box::pin(core::future::ready((buffer, result)))
+    }
+
+    fn write(&self, block: u64, mut buffer: Box<[u8]>) -> BlockFuture {
+        let result = self.copy(block, &mut buffer, true);
+        Box::pin(core::future::ready((buffer, result)))
+    }
+
+    fn flush(&self) -> FlushFuture {
+        Box::pin(core::future::ready(Ok(())))
+    }
+}
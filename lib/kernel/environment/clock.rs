@@ -0,0 +1,8 @@
+pub trait Clock {
+    /// Returns the current value of the monotonic tick counter
+    fn now() -> u64;
+
+    /// Requests a timer interrupt on the current core once the tick counter reaches `deadline`.
+    /// Replaces any previously requested deadline on this core
+    fn set_deadline(deadline: u64);
+}
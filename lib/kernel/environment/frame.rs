@@ -3,7 +3,22 @@ pub trait Frame: Clone {
     fn set_is_user_mode(&mut self, is_user_mode: bool);
     fn is_user_mode(&self) -> bool;
     fn set_pc(&mut self, pc: usize);
-    fn set_error(&mut self, args: (Option<usize>, Option<usize>, Option<usize>));
-    fn set_success(&mut self, args: (Option<usize>, Option<usize>, Option<usize>));
+    /// Moves the pc of a frame saved by a syscall trap back onto the syscall instruction, so the
+    /// syscall runs again when the frame is dispatched
+    fn restart_syscall(&mut self);
+    fn stack_pointer(&self) -> usize;
+    fn set_stack_pointer(&mut self, sp: usize);
+    /// Sets the register the function the frame starts in returns to
+    fn set_return_address(&mut self, ra: usize);
+    /// Sets the register user space uses to find its thread local storage
+    fn set_thread_pointer(&mut self, tp: usize);
+    /// Sets the first argument register, read by the function the frame starts in
+    fn set_argument(&mut self, arg: usize);
+    /// Writes the result of a successful syscall, `value` to the first return register and
+    /// `secondary` to the second, and clears the error flag
+    fn set_success(&mut self, value: usize, secondary: usize);
+    /// Writes the error code of a failed syscall to the first return register and raises the
+    /// error flag
+    fn set_error(&mut self, errno: usize);
 }
 
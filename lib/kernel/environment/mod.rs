@@ -1,3 +1,4 @@
+mod clock;
 mod dispatch;
 mod page_table;
 mod frame;
@@ -5,13 +6,15 @@ mod frame;
 
 use core::fmt::Debug;
 
+pub use clock::*;
 pub use dispatch::*;
 pub use page_table::*;
 pub use frame::*;
 
-pub trait Environment: Sized + Debug {
+pub trait Environment: Sized + Debug + 'static {
     type PageTable: PageTable + Debug;
     type Frame: Frame + Clone + Debug;
     type Dispatch: Dispatch<Self::Frame>;
+    type Clock: Clock;
     fn wfi_program() -> &'static [u8];
 }
@@ -10,7 +10,8 @@ bitflags! {
 }
 
 pub trait PageTable {
-    /// Returns a new page table that is mapped to the core kernel memory
+    /// Returns a new page table that is mapped to the core kernel memory and the device
+    /// registers added with `mem::map_device`
     /// Does not need to be mapped to general user pages
     fn new_kernel_mapped() -> Self;
 
@@ -19,8 +20,18 @@ pub trait PageTable {
     /// SAFETY: This will move around pointers, so any living pointers needs to still be valid
     unsafe fn deactivate();
 
+    /// Flushes the mappings of `size` bytes from `virt` on from the TLB of every core, for
+    /// pages unmapped while other cores may run threads of the same process
+    fn flush_all_cores(virt: usize, size: usize);
+
     fn is_active(pt: &Self) -> bool;
     fn is_deactivated() -> bool;
 
     fn map(&mut self, virt: usize, phys: usize, mode: Mode);
+
+    /// Removes the mapping of the page at `virt` and flushes it from the TLB of this core
+    fn unmap(&mut self, virt: usize);
+
+    /// Returns the physical address `virt` is mapped to, or None if it is not mapped
+    fn translate(&self, virt: usize) -> Option<usize>;
 }
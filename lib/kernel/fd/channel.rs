@@ -0,0 +1,244 @@
+extern crate alloc;
+
+use alloc::{collections::VecDeque, sync::Arc};
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::{
+        environment::{Environment, Frame},
+        fd::{File, FileError, FileRef},
+        scheduler::{CancelWait, Scheduler, Task, Wait, WaitOn, WaitQueue, WaitTicket},
+        trap::syscall::Errno,
+    },
+};
+
+/// Bytes of payload in every message
+pub const MESSAGE_SIZE: usize = 64;
+
+/// Messages an asynchronous channel buffers in each direction before senders block
+pub const CHANNEL_CAPACITY: usize = 16;
+
+/// Layout of a message in user memory
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct UserMessage {
+    pub data: [u8; MESSAGE_SIZE],
+    /// Descriptor sent along with the message, -1 for none. The receiver gets its own
+    /// descriptor for the same object, the sender keeps the one it sent
+    pub handle: i32,
+}
+
+impl UserMessage {
+    pub const NO_HANDLE: i32 = -1;
+}
+
+/// Why a send failed, with the synchronous sender handed back
+pub type SendError<ENV> = (FileError, Option<Task<ENV>>);
+
+pub struct Message<ENV: Environment> {
+    pub data: [u8; MESSAGE_SIZE],
+    pub handle: Option<FileRef<ENV>>,
+}
+
+struct QueuedMessage<ENV: Environment> {
+    message: Message<ENV>,
+    /// Sender of a synchronous channel, blocked until the message is received, with the
+    /// ticket of its wait
+    sender: Option<(WaitTicket, Task<ENV>)>,
+}
+
+/// Messages travelling in one direction of a channel
+pub struct ChannelQueue<ENV: Environment> {
+    messages: VecDeque<QueuedMessage<ENV>>,
+    /// Tasks blocked in `channel_recv`
+    receivers: WaitQueue<ENV>,
+    /// Tasks blocked in `channel_send` on a full asynchronous channel
+    senders: WaitQueue<ENV>,
+    /// Cleared once either endpoint is closed
+    open: bool,
+}
+
+impl<ENV: Environment> core::fmt::Debug for ChannelQueue<ENV> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "ChannelQueue {{ ... }}")
+    }
+}
+
+impl<ENV: Environment> CancelWait<ENV> for Mutex<ChannelQueue<ENV>> {
+    fn cancel_wait(&self, ticket: WaitTicket) -> Option<Task<ENV>> {
+        let mut queue = self.lock();
+        if let Some(task) = queue.receivers.remove(ticket) {
+            return Some(task);
+        }
+        if let Some(task) = queue.senders.remove(ticket) {
+            return Some(task);
+        }
+        // An interrupted synchronous sender leaves its message queued
+        queue.messages.iter_mut().find_map(|queued| {
+            let (_, task) = queued.sender.take_if(|(sent, _)| *sent == ticket)?;
+            Some(task)
+        })
+    }
+}
+
+/// One end of a bidirectional message channel, created in pairs by `channel_create`
+#[derive(Debug)]
+pub struct ChannelEndpoint<ENV: Environment> {
+    /// Messages sent to this endpoint
+    incoming: Arc<Mutex<ChannelQueue<ENV>>>,
+    /// Messages sent by this endpoint
+    outgoing: Arc<Mutex<ChannelQueue<ENV>>>,
+    /// Senders block until their message has been received
+    synchronous: bool,
+    scheduler: Arc<Mutex<Scheduler<ENV>>>,
+}
+
+impl<ENV: Environment> ChannelEndpoint<ENV> {
+    /// Creates a channel and returns both of its endpoints
+    pub fn pair(
+        synchronous: bool,
+        scheduler: Arc<Mutex<Scheduler<ENV>>>,
+    ) -> (ChannelEndpoint<ENV>, ChannelEndpoint<ENV>) {
+        let queue = || {
+            Arc::new(Mutex::new(ChannelQueue {
+                messages: VecDeque::new(),
+                receivers: WaitQueue::new(),
+                senders: WaitQueue::new(),
+                open: true,
+            }))
+        };
+        let a_to_b = queue();
+        let b_to_a = queue();
+        (
+            ChannelEndpoint {
+                incoming: b_to_a.clone(),
+                outgoing: a_to_b.clone(),
+                synchronous,
+                scheduler: scheduler.clone(),
+            },
+            ChannelEndpoint {
+                incoming: a_to_b,
+                outgoing: b_to_a,
+                synchronous,
+                scheduler,
+            },
+        )
+    }
+
+    pub fn is_synchronous(&self) -> bool {
+        self.synchronous
+    }
+
+    /// Queues a message for the other endpoint. On a synchronous channel `sender` is the
+    /// blocked sending task, woken by the receiver, and the wait it is parked in is returned.
+    /// On failure the task is handed back, `FileError::WouldBlock` means an asynchronous
+    /// channel is full
+    pub fn send(
+        &self,
+        message: Message<ENV>,
+        sender: Option<Task<ENV>>,
+    ) -> Result<Option<Wait<ENV>>, SendError<ENV>> {
+        let mut queue = self.outgoing.lock();
+        if !queue.open {
+            return Err((FileError::BrokenPipe, sender));
+        }
+        if !self.synchronous && queue.messages.len() >= CHANNEL_CAPACITY {
+            return Err((FileError::WouldBlock, sender));
+        }
+
+        let sender = sender.map(|task| (WaitTicket::allocate(), task));
+        let wait = sender.as_ref().map(|(ticket, _)| Wait {
+            on: WaitOn::Object(self.outgoing.clone()),
+            ticket: *ticket,
+            restarts: false,
+        });
+        queue.messages.push_back(QueuedMessage { message, sender });
+        queue.receivers.wake_one(&mut self.scheduler.lock(), |_| {});
+        Ok(wait)
+    }
+
+    /// Parks `task` until a full asynchronous channel has room again
+    pub fn wait_sendable(&self, task: Task<ENV>) -> Result<Wait<ENV>, Task<ENV>> {
+        let mut queue = self.outgoing.lock();
+        if !queue.open || queue.messages.len() < CHANNEL_CAPACITY {
+            return Err(task);
+        }
+        Ok(Wait {
+            ticket: queue.senders.push(task),
+            on: WaitOn::Object(self.outgoing.clone()),
+            restarts: true,
+        })
+    }
+
+    /// Takes the oldest message sent to this endpoint. `FileError::WouldBlock` means there is
+    /// none yet, `FileError::BrokenPipe` that there never will be
+    pub fn recv(&self) -> Result<Message<ENV>, FileError> {
+        let mut queue = self.incoming.lock();
+        let Some(queued) = queue.messages.pop_front() else {
+            if queue.open {
+                return Err(FileError::WouldBlock);
+            }
+            return Err(FileError::BrokenPipe);
+        };
+
+        let mut scheduler = self.scheduler.lock();
+        if let Some((_, mut sender)) = queued.sender {
+            sender.frame.set_success(0, 0);
+            scheduler.add_task(sender);
+        }
+        // Blocked senders restart their syscall and find the free slot
+        queue.senders.wake_one(&mut scheduler, |_| {});
+        Ok(queued.message)
+    }
+
+    /// Parks `task` until a message arrives. The ticket of the returned wait cancels it when
+    /// it times out
+    pub fn wait_receivable(&self, task: Task<ENV>) -> Result<Wait<ENV>, Task<ENV>> {
+        let mut queue = self.incoming.lock();
+        if !queue.open || !queue.messages.is_empty() {
+            return Err(task);
+        }
+        Ok(Wait {
+            ticket: queue.receivers.push(task),
+            on: WaitOn::Object(self.incoming.clone()),
+            restarts: true,
+        })
+    }
+
+    /// The queue receivers of this endpoint block on, for timing out their wait
+    pub fn receive_queue(&self) -> Arc<dyn CancelWait<ENV>> {
+        self.incoming.clone()
+    }
+}
+
+impl<ENV: Environment> File<ENV> for ChannelEndpoint<ENV> {
+    fn as_channel(&self) -> Option<&ChannelEndpoint<ENV>> {
+        Some(self)
+    }
+}
+
+impl<ENV: Environment> Drop for ChannelEndpoint<ENV> {
+    fn drop(&mut self) {
+        // Messages nobody will receive are dropped and their synchronous senders fail
+        let undelivered = {
+            let mut incoming = self.incoming.lock();
+            incoming.open = false;
+            let mut scheduler = self.scheduler.lock();
+            incoming.senders.wake_all(&mut scheduler, |_| {});
+            core::mem::take(&mut incoming.messages)
+        };
+        for queued in undelivered {
+            if let Some((_, mut sender)) = queued.sender {
+                sender.frame.set_error(Errno::EPIPE.as_usize());
+                self.scheduler.lock().add_task(sender);
+            }
+        }
+
+        // The peer sees the end of the channel once it has drained what is left
+        let mut outgoing = self.outgoing.lock();
+        outgoing.open = false;
+        let mut scheduler = self.scheduler.lock();
+        outgoing.receivers.wake_all(&mut scheduler, |_| {});
+        outgoing.senders.wake_all(&mut scheduler, |_| {});
+    }
+}
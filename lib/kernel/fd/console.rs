@@ -0,0 +1,63 @@
+use crate::{
+    arch::riscv::sbi,
+    kernel::{
+        environment::Environment,
+        fd::{File, FileError},
+        mem::UserPages,
+    },
+};
+
+/// Bytes handed to the SBI in one call when writing to the console
+const WRITE_CHUNK_SIZE: usize = UserPages::PAGE_SIZE;
+
+/// The SBI debug console, what stdin, stdout and stderr of a new process refer to
+#[derive(Debug)]
+pub struct Console {}
+
+impl Console {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<ENV: Environment> File<ENV> for Console {
+    /// Returns the bytes already received, zero if there are none
+    fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            match sbi::debug_console::read_byte() {
+                Some(b) => *byte = b,
+                None => return Ok(i),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, FileError> {
+        // The SBI takes a physical address, so user data is copied to a page, which is identity
+        // mapped, and written with one call per chunk instead of one per character
+        let mut page = UserPages::try_zeroed(1).ok_or(FileError::OutOfMemory)?;
+        // SAFETY: The page belongs to the buffer alone and outlives the slice
+        let chunk = unsafe { core::slice::from_raw_parts_mut(page.start_mut(), WRITE_CHUNK_SIZE) };
+        let mut total = 0;
+        for part in buf.chunks(WRITE_CHUNK_SIZE) {
+            chunk[..part.len()].copy_from_slice(part);
+            let mut written = 0;
+            while written < part.len() {
+                // SAFETY: The debug console extension is what the kernel prints through as well
+                match unsafe { sbi::debug_console::write(&chunk[written..part.len()]) } {
+                    Ok(n) if n > 0 => written += n as usize,
+                    _ if total + written > 0 => return Ok(total + written),
+                    _ => return Err(FileError::Io),
+                }
+            }
+            total += written;
+        }
+        Ok(total)
+    }
+}
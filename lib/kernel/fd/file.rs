@@ -0,0 +1,76 @@
+use core::fmt::Debug;
+
+use crate::kernel::{
+    environment::Environment,
+    fd::{ChannelEndpoint, SharedMemory},
+    scheduler::{Task, Wait},
+    vfs::OpenFile,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileError {
+    /// The descriptor is not open
+    BadDescriptor,
+    /// The object does not support the operation, e.g. writing to a read only file
+    NotSupported,
+    /// A user pointer is outside of the process memory
+    BadAddress,
+    /// The process has run out of descriptors
+    TooManyFiles,
+    /// The device behind the object failed
+    Io,
+    /// The operation would have to wait, the syscall layer parks the task with
+    /// `File::wait_readable` or `File::wait_writable` and restarts the syscall once it is woken
+    WouldBlock,
+    /// Talking to a pipe or channel whose other end has been closed
+    BrokenPipe,
+    /// A blocking call reached its deadline
+    TimedOut,
+    /// There is not enough free memory for the object
+    OutOfMemory,
+    /// An argument is out of range, e.g. the size of a shared memory region
+    InvalidArgument,
+}
+
+/// A kernel object a descriptor can refer to, such as the console, a file or a pipe.
+/// Objects are reference counted and shared by every descriptor pointing at them, across
+/// `dup` and `fork`, and are dropped when the last one is closed
+pub trait File<ENV: Environment>: Debug {
+    /// Reads up to `buf.len()` bytes, returns the amount read
+    fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+        Err(FileError::NotSupported)
+    }
+
+    /// Writes up to `buf.len()` bytes, returns the amount written
+    fn write(&self, _buf: &[u8]) -> Result<usize, FileError> {
+        Err(FileError::NotSupported)
+    }
+
+    /// Parks `task` until a read that failed with `FileError::WouldBlock` may succeed.
+    /// Hands the task back if that is already the case, so a wake up can not be missed
+    fn wait_readable(&self, task: Task<ENV>) -> Result<Wait<ENV>, Task<ENV>> {
+        Err(task)
+    }
+
+    /// Parks `task` until a write that failed with `FileError::WouldBlock` may succeed.
+    /// Hands the task back if that is already the case, so a wake up can not be missed
+    fn wait_writable(&self, task: Task<ENV>) -> Result<Wait<ENV>, Task<ENV>> {
+        Err(task)
+    }
+
+    /// Returns the object as a channel endpoint if it is one
+    fn as_channel(&self) -> Option<&ChannelEndpoint<ENV>> {
+        None
+    }
+
+    /// Returns the object as a shared memory region if it is one
+    fn as_shared_memory(&self) -> Option<&SharedMemory> {
+        None
+    }
+
+    /// Returns the object as a file of the filesystem if it is one. Its operations may wait
+    /// for a device, so they are futures instead of the methods above
+    fn as_open_file(&self) -> Option<&OpenFile> {
+        None
+    }
+}
@@ -0,0 +1,15 @@
+//! Per-process descriptor tables and the kernel objects descriptors refer to.
+
+mod channel;
+mod console;
+mod file;
+mod pipe;
+mod shared_memory;
+mod table;
+
+pub use channel::*;
+pub use console::*;
+pub use file::*;
+pub use pipe::*;
+pub use shared_memory::*;
+pub use table::*;
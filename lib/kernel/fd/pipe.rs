@@ -0,0 +1,195 @@
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use crate::{
+    collections::{mutex::Mutex, ring_buffer::RingBuffer},
+    kernel::{
+        environment::Environment,
+        fd::{File, FileError},
+        scheduler::{CancelWait, Scheduler, Task, Wait, WaitOn, WaitQueue, WaitTicket},
+    },
+};
+
+/// Bytes a pipe holds before writers block
+pub const PIPE_CAPACITY: usize = 4096;
+
+struct PipeState<ENV: Environment> {
+    buffer: RingBuffer<u8>,
+    readers: usize,
+    writers: usize,
+    /// Readers waiting for data, or for the last writer to close
+    blocked_readers: WaitQueue<ENV>,
+    /// Writers waiting for room, or for the last reader to close
+    blocked_writers: WaitQueue<ENV>,
+}
+
+/// Anonymous pipe shared by a `PipeReader` and a `PipeWriter`
+pub struct Pipe<ENV: Environment> {
+    state: Mutex<PipeState<ENV>>,
+    /// Blocked tasks are handed back to the scheduler when the pipe wakes them
+    scheduler: Arc<Mutex<Scheduler<ENV>>>,
+}
+
+impl<ENV: Environment> core::fmt::Debug for Pipe<ENV> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Pipe {{ ... }}")
+    }
+}
+
+impl<ENV: Environment> Pipe<ENV> {
+    /// Creates a pipe and returns its read and write end
+    pub fn pair(scheduler: Arc<Mutex<Scheduler<ENV>>>) -> (PipeReader<ENV>, PipeWriter<ENV>) {
+        let pipe = Arc::new(Pipe {
+            state: Mutex::new(PipeState {
+                // The ring buffer keeps one slot free to tell full from empty
+                buffer: RingBuffer::new(PIPE_CAPACITY + 1),
+                readers: 1,
+                writers: 1,
+                blocked_readers: WaitQueue::new(),
+                blocked_writers: WaitQueue::new(),
+            }),
+            scheduler,
+        });
+        (
+            PipeReader { pipe: pipe.clone() },
+            PipeWriter { pipe },
+        )
+    }
+
+    /// Woken tasks restart the syscall they blocked in, so they see the new state of the pipe
+    fn wake_all(&self, queue: &mut WaitQueue<ENV>) {
+        if !queue.is_empty() {
+            queue.wake_all(&mut self.scheduler.lock(), |_| {});
+        }
+    }
+}
+
+impl<ENV: Environment> CancelWait<ENV> for Pipe<ENV> {
+    fn cancel_wait(&self, ticket: WaitTicket) -> Option<Task<ENV>> {
+        let mut state = self.state.lock();
+        let state = &mut *state;
+        state
+            .blocked_readers
+            .remove(ticket)
+            .or_else(|| state.blocked_writers.remove(ticket))
+    }
+}
+
+/// Read end of a pipe, reads block until data arrives and return zero once every write end
+/// has been closed
+#[derive(Debug)]
+pub struct PipeReader<ENV: Environment> {
+    pipe: Arc<Pipe<ENV>>,
+}
+
+/// Write end of a pipe, writes block while the pipe is full and fail with
+/// `FileError::BrokenPipe` once every read end has been closed
+#[derive(Debug)]
+pub struct PipeWriter<ENV: Environment> {
+    pipe: Arc<Pipe<ENV>>,
+}
+
+impl<ENV: Environment> File<ENV> for PipeReader<ENV> {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut state = self.pipe.state.lock();
+        let mut count = 0;
+        while count < buf.len() {
+            match state.buffer.pop() {
+                Ok(byte) => {
+                    buf[count] = byte;
+                    count += 1;
+                }
+                Err(()) => break,
+            }
+        }
+
+        if count > 0 {
+            let state = &mut *state;
+            self.pipe.wake_all(&mut state.blocked_writers);
+            Ok(count)
+        } else if state.writers == 0 {
+            Ok(0)
+        } else {
+            Err(FileError::WouldBlock)
+        }
+    }
+
+    fn wait_readable(&self, task: Task<ENV>) -> Result<Wait<ENV>, Task<ENV>> {
+        let mut state = self.pipe.state.lock();
+        if !state.buffer.is_empty() || state.writers == 0 {
+            return Err(task);
+        }
+        Ok(Wait {
+            ticket: state.blocked_readers.push(task),
+            on: WaitOn::Object(self.pipe.clone()),
+            restarts: true,
+        })
+    }
+}
+
+impl<ENV: Environment> File<ENV> for PipeWriter<ENV> {
+    fn write(&self, buf: &[u8]) -> Result<usize, FileError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut state = self.pipe.state.lock();
+        if state.readers == 0 {
+            return Err(FileError::BrokenPipe);
+        }
+
+        let mut count = 0;
+        while count < buf.len() && state.buffer.push(buf[count]).is_ok() {
+            count += 1;
+        }
+
+        if count > 0 {
+            let state = &mut *state;
+            self.pipe.wake_all(&mut state.blocked_readers);
+            Ok(count)
+        } else {
+            Err(FileError::WouldBlock)
+        }
+    }
+
+    fn wait_writable(&self, task: Task<ENV>) -> Result<Wait<ENV>, Task<ENV>> {
+        let mut state = self.pipe.state.lock();
+        if !state.buffer.is_full() || state.readers == 0 {
+            return Err(task);
+        }
+        Ok(Wait {
+            ticket: state.blocked_writers.push(task),
+            on: WaitOn::Object(self.pipe.clone()),
+            restarts: true,
+        })
+    }
+}
+
+impl<ENV: Environment> Drop for PipeReader<ENV> {
+    fn drop(&mut self) {
+        let mut state = self.pipe.state.lock();
+        state.readers -= 1;
+        if state.readers == 0 {
+            // Blocked writers restart and fail with a broken pipe
+            let state = &mut *state;
+            self.pipe.wake_all(&mut state.blocked_writers);
+        }
+    }
+}
+
+impl<ENV: Environment> Drop for PipeWriter<ENV> {
+    fn drop(&mut self) {
+        let mut state = self.pipe.state.lock();
+        state.writers -= 1;
+        if state.writers == 0 {
+            // Blocked readers restart and see the end of the stream
+            let state = &mut *state;
+            self.pipe.wake_all(&mut state.blocked_readers);
+        }
+    }
+}
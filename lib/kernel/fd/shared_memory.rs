@@ -0,0 +1,45 @@
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use crate::kernel::{
+    environment::Environment,
+    fd::{File, FileError},
+    mem::UserPages,
+};
+
+/// Largest region `shm_create` hands out
+pub const SHARED_MEMORY_MAX_SIZE: usize = 16 * 1024 * 1024;
+
+/// Zeroed pages that several processes can map into their memory with `shm_map`. The
+/// descriptor and every mapping hold a reference to the pages, so they are freed once the
+/// descriptor is closed everywhere and the region is unmapped from every process
+#[derive(Debug)]
+pub struct SharedMemory {
+    pages: Arc<UserPages>,
+}
+
+impl SharedMemory {
+    /// Allocates a region of at least `size` bytes, rounded up to whole pages
+    pub fn new(size: usize) -> Result<Self, FileError> {
+        if size == 0 || size > SHARED_MEMORY_MAX_SIZE {
+            return Err(FileError::InvalidArgument);
+        }
+        let count = size.div_ceil(UserPages::PAGE_SIZE);
+        let pages = UserPages::try_zeroed(count).ok_or(FileError::OutOfMemory)?;
+        Ok(SharedMemory {
+            pages: Arc::new(pages),
+        })
+    }
+
+    /// The backing pages, shared with every mapping of the region
+    pub fn pages(&self) -> Arc<UserPages> {
+        self.pages.clone()
+    }
+}
+
+impl<ENV: Environment> File<ENV> for SharedMemory {
+    fn as_shared_memory(&self) -> Option<&SharedMemory> {
+        Some(self)
+    }
+}
@@ -0,0 +1,108 @@
+extern crate alloc;
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::kernel::{
+    environment::Environment,
+    fd::{Console, File, FileError},
+};
+
+pub const STDIN: usize = 0;
+pub const STDOUT: usize = 1;
+pub const STDERR: usize = 2;
+
+/// A reference to an open kernel object
+pub type FileRef<ENV> = Arc<dyn File<ENV>>;
+
+/// Open descriptors of a process, a descriptor is an index into the table
+#[derive(Debug)]
+pub struct FileTable<ENV: Environment> {
+    files: Vec<Option<FileRef<ENV>>>,
+}
+
+impl<ENV: Environment> FileTable<ENV> {
+    pub const MAX_FILES: usize = 256;
+
+    pub fn new() -> Self {
+        Self { files: Vec::new() }
+    }
+
+    /// Returns a table with stdin, stdout and stderr open on the console
+    pub fn with_stdio() -> Self {
+        let console: FileRef<ENV> = Arc::new(Console::new());
+        let mut table = Self::new();
+        for fd in [STDIN, STDOUT, STDERR] {
+            table.files.push(Some(console.clone()));
+            debug_assert_eq!(table.files.len() - 1, fd);
+        }
+        table
+    }
+
+    pub fn get(&self, fd: usize) -> Result<FileRef<ENV>, FileError> {
+        self.files
+            .get(fd)
+            .and_then(|f| f.clone())
+            .ok_or(FileError::BadDescriptor)
+    }
+
+    /// Returns true if opening another descriptor would fail
+    pub fn is_full(&self) -> bool {
+        self.files.len() >= Self::MAX_FILES && self.files.iter().all(|f| f.is_some())
+    }
+
+    /// Opens `file` on the lowest free descriptor
+    pub fn insert(&mut self, file: FileRef<ENV>) -> Result<usize, FileError> {
+        if let Some(fd) = self.files.iter().position(|f| f.is_none()) {
+            self.files[fd] = Some(file);
+            return Ok(fd);
+        }
+        if self.files.len() >= Self::MAX_FILES {
+            return Err(FileError::TooManyFiles);
+        }
+        self.files.push(Some(file));
+        Ok(self.files.len() - 1)
+    }
+
+    pub fn close(&mut self, fd: usize) -> Result<(), FileError> {
+        let file = self.files.get_mut(fd).and_then(|f| f.take());
+        file.map(|_| ()).ok_or(FileError::BadDescriptor)
+    }
+
+    /// Opens the object behind `fd` on the lowest free descriptor
+    pub fn dup(&mut self, fd: usize) -> Result<usize, FileError> {
+        let file = self.get(fd)?;
+        self.insert(file)
+    }
+
+    /// Makes `new_fd` refer to the object behind `old_fd` and returns whatever `new_fd` referred
+    /// to, so the caller can drop it once no locks are held
+    pub fn dup2(
+        &mut self,
+        old_fd: usize,
+        new_fd: usize,
+    ) -> Result<Option<FileRef<ENV>>, FileError> {
+        let file = self.get(old_fd)?;
+        if new_fd >= Self::MAX_FILES {
+            return Err(FileError::BadDescriptor);
+        }
+        if new_fd >= self.files.len() {
+            self.files.resize(new_fd + 1, None);
+        }
+        Ok(self.files[new_fd].replace(file))
+    }
+}
+
+/// Forked processes share every open object with their parent
+impl<ENV: Environment> Clone for FileTable<ENV> {
+    fn clone(&self) -> Self {
+        Self {
+            files: self.files.clone(),
+        }
+    }
+}
+
+impl<ENV: Environment> Default for FileTable<ENV> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
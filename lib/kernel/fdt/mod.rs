@@ -0,0 +1,202 @@
+//! Reader for the flattened device tree (DTB) the firmware hands to the kernel at boot.
+//!
+//! Nothing here allocates, so the tree can be read before the kernel heap is set up.
+//! `Node` walks the tree, `platform` builds typed views of the devices on top of it.
+
+mod node;
+mod platform;
+
+pub use node::*;
+pub use platform::*;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_HEADER_SIZE: usize = 40;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdtError {
+    BadMagic,
+    Truncated,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fdt<'a> {
+    data: &'a [u8],
+    structure: &'a [u8],
+    strings: &'a [u8],
+    reservations: &'a [u8],
+}
+
+/// One token of the structure block, together with the offset of the token after it
+#[derive(Debug, Clone, Copy)]
+enum Token<'a> {
+    BeginNode(&'a str),
+    EndNode,
+    Prop(&'a str, &'a [u8]),
+    Nop,
+    End,
+}
+
+impl<'a> Fdt<'a> {
+    /// Parses the device tree blob starting at `ptr`
+    ///
+    /// # Safety
+    /// - The caller must ensure that `ptr` points to a device tree blob
+    /// - The caller must ensure that the blob stays valid and unmodified for `'a`
+    pub unsafe fn from_ptr(ptr: *const u8) -> Result<Self, FdtError> {
+        let header = unsafe { core::slice::from_raw_parts(ptr, FDT_HEADER_SIZE) };
+        if be32(header, 0) != Some(FDT_MAGIC) {
+            return Err(FdtError::BadMagic);
+        }
+        let total_size = be32(header, 4).ok_or(FdtError::Truncated)? as usize;
+        let data = unsafe { core::slice::from_raw_parts(ptr, total_size) };
+        Self::from_slice(data)
+    }
+
+    pub fn from_slice(data: &'a [u8]) -> Result<Self, FdtError> {
+        if be32(data, 0) != Some(FDT_MAGIC) {
+            return Err(FdtError::BadMagic);
+        }
+
+        let header = |offset| be32(data, offset).ok_or(FdtError::Truncated);
+        let structure_offset = header(8)? as usize;
+        let strings_offset = header(12)? as usize;
+        let reservations_offset = header(16)? as usize;
+        let strings_size = header(32)? as usize;
+        let structure_size = header(36)? as usize;
+
+        let structure = data
+            .get(structure_offset..structure_offset + structure_size)
+            .ok_or(FdtError::Truncated)?;
+        let strings = data
+            .get(strings_offset..strings_offset + strings_size)
+            .ok_or(FdtError::Truncated)?;
+        let reservations = data.get(reservations_offset..).ok_or(FdtError::Truncated)?;
+
+        Ok(Fdt {
+            data,
+            structure,
+            strings,
+            reservations,
+        })
+    }
+
+    /// The blob itself, which has to stay reserved for as long as the tree is read
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The root node `/`
+    pub fn root(&self) -> Option<Node<'a>> {
+        match self.token(0)? {
+            (Token::BeginNode(name), offset) => Some(Node::new(*self, name, offset, Cells::ROOT)),
+            _ => None,
+        }
+    }
+
+    /// Returns the node at `path`, e.g. `/cpus/cpu@0`. A path component without a unit
+    /// address matches a node name with one, so `/memory` finds `/memory@80000000`
+    pub fn find_node(&self, path: &str) -> Option<Node<'a>> {
+        let mut node = self.root()?;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            node = node
+                .children()
+                .find(|child| node_name_matches(child.name(), component))?;
+        }
+        Some(node)
+    }
+
+    /// Returns the raw value of property `name` on the node at `path`
+    pub fn property(&self, path: &str, name: &str) -> Option<&'a [u8]> {
+        self.find_node(path)?.property(name)
+    }
+
+    /// Every node of the tree, parents before their children
+    pub fn nodes(&self) -> Nodes<'a> {
+        Nodes::new(*self)
+    }
+
+    /// Nodes whose `compatible` property lists `compatible`
+    pub fn compatible_nodes<'b>(
+        &self,
+        compatible: &'b str,
+    ) -> impl Iterator<Item = Node<'a>> + 'b
+    where
+        'a: 'b,
+    {
+        self.nodes()
+            .filter(move |node| node.is_compatible(compatible))
+    }
+
+    /// Ranges of memory from the reservation block of the blob, as (address, size)
+    pub fn reservations(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        self.reservations
+            .chunks_exact(16)
+            .map(|entry| (be64(entry, 0).unwrap(), be64(entry, 8).unwrap()))
+            .take_while(|&(address, size)| address != 0 || size != 0)
+    }
+
+    fn token(&self, offset: usize) -> Option<(Token<'a>, usize)> {
+        let token = be32(self.structure, offset)?;
+        let offset = offset + 4;
+        Some(match token {
+            FDT_BEGIN_NODE => {
+                let name = cstr(self.structure, offset)?;
+                (Token::BeginNode(name), align4(offset + name.len() + 1))
+            }
+            FDT_END_NODE => (Token::EndNode, offset),
+            FDT_PROP => {
+                let len = be32(self.structure, offset)? as usize;
+                let name_offset = be32(self.structure, offset + 4)? as usize;
+                let value = self.structure.get(offset + 8..offset + 8 + len)?;
+                let name = cstr(self.strings, name_offset)?;
+                (Token::Prop(name, value), align4(offset + 8 + len))
+            }
+            FDT_NOP => (Token::Nop, offset),
+            FDT_END => (Token::End, offset),
+            // A token this reader does not understand
+            _ => return None,
+        })
+    }
+}
+
+/// Reads a property value made of one or two big endian cells as a single number
+pub fn read_cells(value: &[u8]) -> Option<u64> {
+    match value.len() {
+        4 => be32(value, 0).map(|v| v as u64),
+        8 => be64(value, 0),
+        _ => None,
+    }
+}
+
+fn node_name_matches(node_name: &str, component: &str) -> bool {
+    if component.contains('@') {
+        node_name == component
+    } else {
+        node_name.split('@').next() == Some(component)
+    }
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn be64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(((be32(data, offset)? as u64) << 32) | be32(data, offset + 4)? as u64)
+}
+
+fn cstr(data: &[u8], offset: usize) -> Option<&str> {
+    let rest = data.get(offset..)?;
+    let len = rest.iter().position(|&b| b == 0)?;
+    core::str::from_utf8(&rest[..len]).ok()
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
@@ -0,0 +1,239 @@
+use crate::kernel::fdt::{be32, cstr, Fdt, Token};
+
+/// How deeply nested nodes `Nodes` follows, deeper nodes are skipped
+const MAX_DEPTH: usize = 16;
+
+/// `#address-cells` and `#size-cells` of a node, which give the layout of `reg` in its children
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cells {
+    pub address: u32,
+    pub size: u32,
+}
+
+impl Cells {
+    /// Values the specification defaults to when a node leaves the properties out
+    pub const DEFAULT: Cells = Cells {
+        address: 2,
+        size: 1,
+    };
+    /// The root node has no parent, so this never lays out a `reg`
+    pub(super) const ROOT: Cells = Cells::DEFAULT;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Node<'a> {
+    fdt: Fdt<'a>,
+    name: &'a str,
+    /// Offset of the first token inside the node
+    offset: usize,
+    /// Cells of the parent, which lay out `reg` of this node
+    parent_cells: Cells,
+}
+
+impl<'a> Node<'a> {
+    pub(super) fn new(fdt: Fdt<'a>, name: &'a str, offset: usize, parent_cells: Cells) -> Self {
+        Self {
+            fdt,
+            name,
+            offset,
+            parent_cells,
+        }
+    }
+
+    /// Name with its unit address, e.g. `memory@80000000`. The root node has an empty name
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn properties(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> + 'a {
+        let fdt = self.fdt;
+        let mut offset = self.offset;
+        core::iter::from_fn(move || loop {
+            let (token, next) = fdt.token(offset)?;
+            offset = next;
+            match token {
+                Token::Prop(name, value) => return Some((name, value)),
+                Token::Nop => {}
+                // Properties come before the children of a node
+                _ => return None,
+            }
+        })
+    }
+
+    pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+        self.properties()
+            .find(|&(property, _)| property == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn u32_property(&self, name: &str) -> Option<u32> {
+        let value = self.property(name)?;
+        if value.len() != 4 {
+            return None;
+        }
+        be32(value, 0)
+    }
+
+    /// Reads a string property, without its terminating NUL
+    pub fn str_property(&self, name: &str) -> Option<&'a str> {
+        cstr(self.property(name)?, 0)
+    }
+
+    /// Entries of the `compatible` property, most specific first
+    pub fn compatible(&self) -> impl Iterator<Item = &'a str> + 'a {
+        string_list(self.property("compatible").unwrap_or(&[]))
+    }
+
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.compatible().any(|c| c == compatible)
+    }
+
+    /// False for nodes whose `status` marks the device as not present or not usable
+    pub fn is_enabled(&self) -> bool {
+        matches!(self.str_property("status"), None | Some("okay") | Some("ok"))
+    }
+
+    pub fn phandle(&self) -> Option<u32> {
+        self.u32_property("phandle")
+    }
+
+    /// Cells of this node, which lay out `reg` of its children
+    pub fn cells(&self) -> Cells {
+        Cells {
+            address: self
+                .u32_property("#address-cells")
+                .unwrap_or(Cells::DEFAULT.address),
+            size: self
+                .u32_property("#size-cells")
+                .unwrap_or(Cells::DEFAULT.size),
+        }
+    }
+
+    /// Address ranges of the `reg` property as (address, size), in the address space of the
+    /// parent bus
+    pub fn reg(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let cells = self.parent_cells;
+        let entry = (cells.address + cells.size) as usize * 4;
+        let value = self.property("reg").unwrap_or(&[]);
+        value
+            .chunks_exact(entry.max(4))
+            .filter(move |_| entry > 0)
+            .filter_map(move |entry| {
+                let (address, size) = entry.split_at(cells.address as usize * 4);
+                Some((read_number(address)?, read_number(size)?))
+            })
+    }
+
+    /// Cells of the `interrupts` property, one per interrupt for controllers like the PLIC
+    /// that use a single cell
+    pub fn interrupts(&self) -> impl Iterator<Item = u32> + 'a {
+        self.property("interrupts")
+            .unwrap_or(&[])
+            .chunks_exact(4)
+            .map(|cell| u32::from_be_bytes([cell[0], cell[1], cell[2], cell[3]]))
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = Node<'a>> + 'a {
+        let fdt = self.fdt;
+        let cells = self.cells();
+        let mut offset = self.offset;
+        core::iter::from_fn(move || {
+            let mut depth = 0;
+            loop {
+                let (token, next) = fdt.token(offset)?;
+                offset = next;
+                match token {
+                    Token::BeginNode(name) if depth == 0 => {
+                        let child = Node::new(fdt, name, next, cells);
+                        // Continue behind the subtree of the child
+                        depth = 1;
+                        loop {
+                            let (token, next) = fdt.token(offset)?;
+                            offset = next;
+                            match token {
+                                Token::BeginNode(_) => depth += 1,
+                                Token::EndNode => depth -= 1,
+                                Token::End => return None,
+                                _ => {}
+                            }
+                            if depth == 0 {
+                                return Some(child);
+                            }
+                        }
+                    }
+                    Token::EndNode | Token::End => return None,
+                    _ => {}
+                }
+            }
+        })
+    }
+}
+
+/// Iterator over every node of a tree, see `Fdt::nodes`
+pub struct Nodes<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+    depth: usize,
+    /// Cells of the nodes currently open, indexed by depth
+    cells: [Cells; MAX_DEPTH + 1],
+    done: bool,
+}
+
+impl<'a> Nodes<'a> {
+    pub(super) fn new(fdt: Fdt<'a>) -> Self {
+        Self {
+            fdt,
+            offset: 0,
+            depth: 0,
+            cells: [Cells::ROOT; MAX_DEPTH + 1],
+            done: false,
+        }
+    }
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Node<'a>> {
+        while !self.done {
+            let Some((token, next)) = self.fdt.token(self.offset) else {
+                self.done = true;
+                break;
+            };
+            self.offset = next;
+            match token {
+                Token::BeginNode(name) => {
+                    let depth = self.depth;
+                    self.depth += 1;
+                    if depth >= MAX_DEPTH {
+                        continue;
+                    }
+                    let node = Node::new(self.fdt, name, next, self.cells[depth]);
+                    self.cells[depth + 1] = node.cells();
+                    return Some(node);
+                }
+                Token::EndNode => self.depth = self.depth.saturating_sub(1),
+                Token::End => self.done = true,
+                Token::Prop(..) | Token::Nop => {}
+            }
+        }
+        None
+    }
+}
+
+fn string_list(value: &[u8]) -> impl Iterator<Item = &str> {
+    value
+        .split(|&b| b == 0)
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| core::str::from_utf8(s).ok())
+}
+
+/// Reads a number of zero to two cells
+fn read_number(cells: &[u8]) -> Option<u64> {
+    match cells.len() {
+        0 => Some(0),
+        4 => be32(cells, 0).map(|v| v as u64),
+        8 => Some(((be32(cells, 0)? as u64) << 32) | be32(cells, 4)? as u64),
+        _ => None,
+    }
+}
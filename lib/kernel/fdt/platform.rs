@@ -0,0 +1,138 @@
+use crate::kernel::fdt::{read_cells, Fdt, Node};
+
+/// Compatible strings of the platform-level interrupt controller
+const PLIC_COMPATIBLE: [&str; 2] = ["riscv,plic0", "sifive,plic-1.0.0"];
+const VIRTIO_MMIO_COMPATIBLE: &str = "virtio,mmio";
+/// Interrupt number of supervisor external interrupts at the interrupt controller of a hart
+const IRQ_SUPERVISOR_EXTERNAL: u32 = 9;
+
+/// A range of physical memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub start: u64,
+    pub size: u64,
+}
+
+impl MemoryRegion {
+    pub fn end(&self) -> u64 {
+        self.start.saturating_add(self.size)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptController {
+    pub base: usize,
+    pub size: usize,
+    /// Number of interrupt sources, source 0 is reserved
+    pub sources: u32,
+    pub phandle: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtioMmioDevice {
+    pub base: usize,
+    pub size: usize,
+    /// Interrupt source at the interrupt controller
+    pub interrupt: Option<u32>,
+}
+
+impl<'a> Fdt<'a> {
+    /// Returns the frequency in Hz of the `time` CSR, shared by all harts
+    pub fn timebase_frequency(&self) -> Option<u64> {
+        read_cells(self.property("/cpus", "timebase-frequency")?)
+    }
+
+    /// Returns the physical address range of the initrd the boot loader placed in memory
+    pub fn initrd(&self) -> Option<(usize, usize)> {
+        let start = read_cells(self.property("/chosen", "linux,initrd-start")?)?;
+        let end = read_cells(self.property("/chosen", "linux,initrd-end")?)?;
+        (start < end).then_some((start as usize, end as usize))
+    }
+
+    /// Physical memory of the machine, from the nodes with `device_type = "memory"`
+    pub fn memory_regions(&self) -> impl Iterator<Item = MemoryRegion> + 'a {
+        self.nodes()
+            .filter(|node| node.str_property("device_type") == Some("memory"))
+            .filter(Node::is_enabled)
+            .flat_map(|node| node.reg())
+            .filter(|&(_, size)| size > 0)
+            .map(|(start, size)| MemoryRegion { start, size })
+    }
+
+    /// Memory the kernel must not hand out: the reservation block of the blob and the
+    /// children of `/reserved-memory`, e.g. the region of the SBI firmware
+    pub fn reserved_regions(&self) -> impl Iterator<Item = MemoryRegion> + 'a {
+        let reserved_memory = self.find_node("/reserved-memory");
+        self.reservations()
+            .chain(
+                reserved_memory
+                    .into_iter()
+                    .flat_map(|node| node.children())
+                    .flat_map(|node| node.reg()),
+            )
+            .filter(|&(_, size)| size > 0)
+            .map(|(start, size)| MemoryRegion { start, size })
+    }
+
+    /// Ids of the harts that are available to the kernel
+    pub fn harts(&self) -> impl Iterator<Item = usize> + 'a {
+        self.find_node("/cpus")
+            .into_iter()
+            .flat_map(|cpus| cpus.children())
+            .filter(|node| node.str_property("device_type") == Some("cpu"))
+            .filter(Node::is_enabled)
+            .filter_map(|node| node.reg().next())
+            .map(|(id, _)| id as usize)
+    }
+
+    /// The platform-level interrupt controller routing device interrupts to the harts
+    pub fn interrupt_controller(&self) -> Option<InterruptController> {
+        let node = self.plic()?;
+        let (base, size) = node.reg().next()?;
+        Some(InterruptController {
+            base: base as usize,
+            size: size as usize,
+            sources: node.u32_property("riscv,ndev").unwrap_or(0),
+            phandle: node.phandle(),
+        })
+    }
+
+    /// Index of the interrupt controller context that raises supervisor external interrupts
+    /// on `hart`
+    pub fn supervisor_context(&self, hart: usize) -> Option<usize> {
+        let plic = self.plic()?;
+        let cpus = self.find_node("/cpus")?;
+        // Pairs of the phandle of a hart's interrupt controller and the interrupt raised
+        // there, one per context
+        plic.property("interrupts-extended")?
+            .chunks_exact(8)
+            .position(|context| {
+                let phandle = u32::from_be_bytes([context[0], context[1], context[2], context[3]]);
+                let irq = u32::from_be_bytes([context[4], context[5], context[6], context[7]]);
+                irq == IRQ_SUPERVISOR_EXTERNAL
+                    && cpus.children().any(|cpu| {
+                        cpu.reg().next().map(|(id, _)| id as usize) == Some(hart)
+                            && cpu.children().any(|intc| intc.phandle() == Some(phandle))
+                    })
+            })
+    }
+
+    fn plic(&self) -> Option<Node<'a>> {
+        self.nodes()
+            .find(|node| PLIC_COMPATIBLE.iter().any(|c| node.is_compatible(c)))
+    }
+
+    /// Every virtio-mmio transport slot, whether a device sits behind it or not
+    pub fn virtio_mmio_devices(&self) -> impl Iterator<Item = VirtioMmioDevice> + 'a {
+        self.compatible_nodes(VIRTIO_MMIO_COMPATIBLE)
+            .filter(Node::is_enabled)
+            .filter_map(|node| {
+                let (base, size) = node.reg().next()?;
+                Some(VirtioMmioDevice {
+                    base: base as usize,
+                    size: size as usize,
+                    interrupt: node.interrupts().next(),
+                })
+            })
+    }
+}
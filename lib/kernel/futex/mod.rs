@@ -0,0 +1,56 @@
+//! Wait queues keyed by the physical address of a user space word, the kernel half of
+//! user space mutexes and condition variables.
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+
+use crate::kernel::{
+    environment::Environment,
+    scheduler::{Task, WaitQueue, WaitTicket},
+};
+
+/// Tasks blocked in `futex_wait`. Keys are physical addresses, so threads of one process and
+/// processes sharing memory meet on the same queue whatever address they mapped the word at
+#[derive(Debug)]
+pub struct FutexTable<ENV: Environment> {
+    queues: BTreeMap<usize, WaitQueue<ENV>>,
+}
+
+impl<ENV: Environment> FutexTable<ENV> {
+    pub const fn new() -> Self {
+        Self {
+            queues: BTreeMap::new(),
+        }
+    }
+
+    pub fn wait(&mut self, key: usize, task: Task<ENV>) -> WaitTicket {
+        self.queues.entry(key).or_default().push(task)
+    }
+
+    /// Removes the longest waiting task on `key`
+    pub fn pop(&mut self, key: usize) -> Option<Task<ENV>> {
+        let queue = self.queues.get_mut(&key)?;
+        let task = queue.pop();
+        if queue.is_empty() {
+            self.queues.remove(&key);
+        }
+        task
+    }
+
+    /// Removes a task whose wait timed out, returns None if it was already woken
+    pub fn remove(&mut self, key: usize, ticket: WaitTicket) -> Option<Task<ENV>> {
+        let queue = self.queues.get_mut(&key)?;
+        let task = queue.remove(ticket);
+        if queue.is_empty() {
+            self.queues.remove(&key);
+        }
+        task
+    }
+}
+
+impl<ENV: Environment> Default for FutexTable<ENV> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,104 @@
+//! Reader for cpio archives in the "newc" format, as produced by `cpio -H newc`.
+
+const MAGIC: &[u8] = b"070701";
+/// Same layout as `MAGIC`, with a checksum this reader does not verify
+const MAGIC_CRC: &[u8] = b"070702";
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+pub const MODE_TYPE_MASK: u32 = 0o170_000;
+pub const MODE_DIRECTORY: u32 = 0o040_000;
+pub const MODE_REGULAR: u32 = 0o100_000;
+pub const MODE_SYMLINK: u32 = 0o120_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpioError {
+    BadMagic,
+    BadHeader,
+    Truncated,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry<'a> {
+    /// Path as stored in the archive, usually relative like `bin/init`
+    pub name: &'a str,
+    pub mode: u32,
+    pub data: &'a [u8],
+}
+
+/// Iterates the entries of an archive up to its trailer
+pub struct Entries<'a> {
+    archive: &'a [u8],
+    offset: usize,
+    done: bool,
+}
+
+pub fn entries(archive: &[u8]) -> Entries<'_> {
+    Entries {
+        archive,
+        offset: 0,
+        done: false,
+    }
+}
+
+impl<'a> Entries<'a> {
+    fn parse(&mut self) -> Result<Option<Entry<'a>>, CpioError> {
+        let header = self
+            .archive
+            .get(self.offset..self.offset + HEADER_SIZE)
+            .ok_or(CpioError::Truncated)?;
+        if &header[..6] != MAGIC && &header[..6] != MAGIC_CRC {
+            return Err(CpioError::BadMagic);
+        }
+        // Thirteen fields of eight hex digits follow the magic
+        let field = |index: usize| hex(&header[6 + index * 8..14 + index * 8]);
+        let mode = field(1)?;
+        let file_size = field(6)? as usize;
+        let name_size = field(11)? as usize;
+
+        let name_start = self.offset + HEADER_SIZE;
+        let name = self
+            .archive
+            .get(name_start..name_start + name_size)
+            .ok_or(CpioError::Truncated)?;
+        // The size counts the terminating NUL
+        let name = name.strip_suffix(&[0]).ok_or(CpioError::BadHeader)?;
+        let name = core::str::from_utf8(name).map_err(|_| CpioError::BadHeader)?;
+
+        let data_start = align4(name_start + name_size);
+        let data = self
+            .archive
+            .get(data_start..data_start + file_size)
+            .ok_or(CpioError::Truncated)?;
+        self.offset = align4(data_start + file_size);
+
+        if name == TRAILER {
+            return Ok(None);
+        }
+        Ok(Some(Entry { name, mode, data }))
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<Entry<'a>, CpioError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = self.parse();
+        if !matches!(entry, Ok(Some(_))) {
+            self.done = true;
+        }
+        entry.transpose()
+    }
+}
+
+fn hex(digits: &[u8]) -> Result<u32, CpioError> {
+    let digits = core::str::from_utf8(digits).map_err(|_| CpioError::BadHeader)?;
+    u32::from_str_radix(digits, 16).map_err(|_| CpioError::BadHeader)
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
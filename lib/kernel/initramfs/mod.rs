@@ -0,0 +1,148 @@
+//! Initial RAM filesystem, unpacked at boot from a cpio archive.
+//!
+//! The archive is either passed by the boot loader, e.g. with QEMU's `-initrd`, and found
+//! through the device tree, or the one the build script packs from `user/programs`. Its
+//! contents are copied into kernel memory, so the boot code hands the pages of an archive
+//! passed by the boot loader to the page allocator once it is unpacked.
+
+extern crate alloc;
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+pub mod cpio;
+
+pub use cpio::CpioError;
+
+/// Archive built from `user/programs`, holding `/init` and every program in `/bin`
+pub static EMBEDDED: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/initramfs.cpio"));
+
+/// How many symbolic links are followed when resolving a path
+const MAX_LINKS: usize = 8;
+
+#[derive(Debug)]
+pub enum Node {
+    File(Vec<u8>),
+    Directory,
+    Symlink(String),
+}
+
+/// Tree of files keyed by their absolute path, e.g. `/bin/init`
+#[derive(Debug)]
+pub struct Initramfs {
+    nodes: BTreeMap<String, Node>,
+}
+
+impl Initramfs {
+    pub fn unpack(archive: &[u8]) -> Result<Self, CpioError> {
+        let mut nodes = BTreeMap::new();
+        nodes.insert("/".to_string(), Node::Directory);
+
+        for entry in cpio::entries(archive) {
+            let entry = entry?;
+            let path = normalize(entry.name);
+            if path == "/" {
+                continue;
+            }
+            let node = match entry.mode & cpio::MODE_TYPE_MASK {
+                cpio::MODE_DIRECTORY => Node::Directory,
+                cpio::MODE_REGULAR => Node::File(entry.data.to_vec()),
+                cpio::MODE_SYMLINK => {
+                    let target = core::str::from_utf8(entry.data)
+                        .map_err(|_| CpioError::BadHeader)?;
+                    Node::Symlink(target.to_string())
+                }
+                // Devices, FIFOs and sockets have no meaning without a driver behind them
+                _ => continue,
+            };
+
+            // Archives may leave out the directories a file is in
+            let mut parent = path.as_str();
+            while let Some(end) = parent.rfind('/') {
+                parent = &parent[..end.max(1)];
+                nodes.entry(parent.to_string()).or_insert(Node::Directory);
+                if parent == "/" {
+                    break;
+                }
+            }
+            nodes.insert(path, node);
+        }
+
+        Ok(Self { nodes })
+    }
+
+    /// Every node by its absolute path, parents before their children
+    pub fn into_nodes(self) -> impl Iterator<Item = (String, Node)> {
+        self.nodes.into_iter()
+    }
+
+    /// Returns the node at `path`, following symbolic links
+    pub fn lookup(&self, path: &str) -> Option<&Node> {
+        let mut path = normalize(path);
+        for _ in 0..=MAX_LINKS {
+            match self.nodes.get(&path)? {
+                Node::Symlink(target) if target.starts_with('/') => path = normalize(target),
+                Node::Symlink(target) => {
+                    let parent = &path[..path.rfind('/').unwrap_or(0)];
+                    path = normalize(&alloc::format!("{parent}/{target}"));
+                }
+                node => return Some(node),
+            }
+        }
+        None
+    }
+
+    /// Returns the contents of the file at `path`
+    pub fn read(&self, path: &str) -> Option<&[u8]> {
+        match self.lookup(path)? {
+            Node::File(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Returns the names of the entries of the directory at `path`
+    pub fn read_dir<'a>(&'a self, path: &str) -> Option<impl Iterator<Item = &'a str> + 'a> {
+        let Node::Directory = self.lookup(path)? else {
+            return None;
+        };
+        let mut prefix = normalize(path);
+        if prefix != "/" {
+            prefix.push('/');
+        }
+        let len = prefix.len();
+        // Paths sort by their parent directory first, so the children follow the prefix
+        Some(
+            self.nodes
+                .range(prefix.clone()..)
+                .take_while(move |(path, _)| path.starts_with(prefix.as_str()))
+                .map(move |(path, _)| &path[len..])
+                .filter(|name| !name.is_empty() && !name.contains('/')),
+        )
+    }
+}
+
+/// Makes `path` absolute and resolves `.` and `..`, so `./bin/../init` becomes `/init`
+fn normalize(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    let mut normalized = String::new();
+    for component in &components {
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
@@ -1,14 +1,168 @@
 use core::alloc::{GlobalAlloc, Layout};
 use core::arch::asm;
-use core::sync::atomic::AtomicPtr;
-use core::sync::atomic::Ordering::SeqCst;
+use core::ptr::null_mut;
 
+use crate::arch::riscv::csr::Sstatus;
+use crate::collections::mutex::Mutex;
 use crate::utils::align_up;
 
-static HEAP_START: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
-static HEAP_END: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
+use super::page_allocator::{try_alloc_pages, PAGE_SIZE};
 
-/// SAFETY: Caller must ensure that this function is called only once
+/// Pages the heap grows by at least once the region the linker reserved for it is used up
+const GROW_PAGES: usize = 16;
+
+/// A free block of the heap, its header is stored at the start of the block itself
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+const BLOCK_ALIGN: usize = core::mem::align_of::<FreeBlock>();
+/// Smallest block the heap hands out, so every block can hold a header once it is freed
+const MIN_BLOCK: usize = core::mem::size_of::<FreeBlock>();
+
+/// Free blocks of the heap in a list sorted by address, so freed blocks are joined to their
+/// free neighbours
+struct Heap {
+    head: *mut FreeBlock,
+}
+
+// SAFETY: The blocks belong to the heap alone, which is only reached through its lock
+unsafe impl Send for Heap {}
+unsafe impl Sync for Heap {}
+
+static HEAP: Mutex<Heap> = Mutex::new(Heap { head: null_mut() });
+
+impl Heap {
+    /// Bytes a block for `layout` takes, the same when it is allocated and when it is freed
+    fn block_size(layout: Layout) -> usize {
+        layout.size().max(MIN_BLOCK).next_multiple_of(BLOCK_ALIGN)
+    }
+
+    /// Cuts a block for `layout` out of the first free block it fits in, null if none does
+    ///
+    /// # Safety
+    /// The free blocks must be valid
+    unsafe fn take(&mut self, layout: Layout) -> *mut u8 {
+        let size = Self::block_size(layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+        let mut link: *mut *mut FreeBlock = &mut self.head;
+        // SAFETY: The list only holds valid blocks, which nothing else refers to
+        unsafe {
+            while !(*link).is_null() {
+                let block = *link;
+                let block_start = block as usize;
+                let block_end = block_start + (*block).size;
+                let mut start = align_up(block_start, align);
+                // What is left in front of the block has to hold a header as well
+                if start != block_start && start - block_start < MIN_BLOCK {
+                    start = align_up(block_start + MIN_BLOCK, align);
+                }
+                // The same goes for what is left behind it
+                let fits = start.checked_add(size).filter(|&end| {
+                    end == block_end || (end < block_end && block_end - end >= MIN_BLOCK)
+                });
+                let Some(end) = fits else {
+                    link = &mut (*block).next;
+                    continue;
+                };
+
+                let next = (*block).next;
+                let behind = if end < block_end {
+                    let behind = end as *mut FreeBlock;
+                    behind.write(FreeBlock {
+                        size: block_end - end,
+                        next,
+                    });
+                    behind
+                } else {
+                    next
+                };
+                if start > block_start {
+                    (*block).size = start - block_start;
+                    (*block).next = behind;
+                } else {
+                    *link = behind;
+                }
+                return start as *mut u8;
+            }
+        }
+        null_mut()
+    }
+
+    /// Adds the `size` bytes at `ptr` to the free blocks, joined to those right before and
+    /// after it
+    ///
+    /// # Safety
+    /// The memory must be unused, aligned to `BLOCK_ALIGN` and at least `MIN_BLOCK` bytes
+    unsafe fn give(&mut self, ptr: *mut u8, size: usize) {
+        let start = ptr as usize;
+        let mut prev: *mut FreeBlock = null_mut();
+        let mut next = self.head;
+        // SAFETY: The list only holds valid blocks and the caller hands over the memory
+        unsafe {
+            while !next.is_null() && (next as usize) < start {
+                prev = next;
+                next = (*next).next;
+            }
+
+            let block = ptr as *mut FreeBlock;
+            block.write(FreeBlock { size, next });
+            if !next.is_null() && start + size == next as usize {
+                (*block).size += (*next).size;
+                (*block).next = (*next).next;
+            }
+
+            if prev.is_null() {
+                self.head = block;
+            } else if prev as usize + (*prev).size == start {
+                (*prev).size += (*block).size;
+                (*prev).next = (*block).next;
+            } else {
+                (*prev).next = block;
+            }
+        }
+    }
+
+    /// Adds pages from the page allocator that fit a block for `layout`, false if there are
+    /// none. They stay with the heap once freed
+    fn grow(&mut self, layout: Layout) -> bool {
+        // Room to align the block and to leave a free block in front of it
+        let needed = Self::block_size(layout)
+            .checked_add(layout.align())
+            .and_then(|needed| needed.checked_add(MIN_BLOCK));
+        let Some(needed) = needed else {
+            return false;
+        };
+        let pages = needed.div_ceil(PAGE_SIZE).max(GROW_PAGES);
+        let Some(ptr) = try_alloc_pages(pages) else {
+            return false;
+        };
+        // SAFETY: The pages were just allocated and are aligned to a page
+        unsafe { self.give(ptr, pages * PAGE_SIZE) };
+        true
+    }
+}
+
+/// Runs `f` with interrupts off, as trap handlers may allocate while the code they
+/// interrupted holds the heap lock
+fn without_interrupts<T>(f: impl FnOnce() -> T) -> T {
+    let mut sstatus = Sstatus::load();
+    let enabled = sstatus.SIE;
+    sstatus.SIE = false;
+    sstatus.store();
+    let result = f();
+    if enabled {
+        sstatus.SIE = true;
+        sstatus.store();
+    }
+    result
+}
+
+/// Hands the region the linker reserved for the heap to it
+///
+/// # Safety
+/// Caller must ensure that this function is called only once
 pub(super) unsafe fn init() {
     let heap_start: *mut u8;
     let heap_end: *mut u8;
@@ -22,8 +176,12 @@ pub(super) unsafe fn init() {
             out(reg) heap_end
         );
     }
-    HEAP_START.store(heap_start, SeqCst);
-    HEAP_END.store(heap_end, SeqCst);
+    let start = align_up(heap_start as usize, BLOCK_ALIGN);
+    let end = heap_end as usize & !(BLOCK_ALIGN - 1);
+    if end >= start + MIN_BLOCK {
+        // SAFETY: Nothing but the heap uses the region
+        without_interrupts(|| unsafe { HEAP.lock().give(start as *mut u8, end - start) });
+    }
 }
 
 pub struct KernelAllocator {}
@@ -36,30 +194,25 @@ impl KernelAllocator {
 
 unsafe impl GlobalAlloc for KernelAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        loop {
-            let heap_start = HEAP_START.load(SeqCst);
-            let ptr = align_up(heap_start as usize, layout.align()) as *mut u8;
-            let size = layout.size();
-            let end = ptr.wrapping_add(size);
-
-            if end > HEAP_END.load(SeqCst) {
-                panic!("out of memeory")
-            }
-
-            if HEAP_START
-                .compare_exchange(heap_start, end, SeqCst, SeqCst)
-                .is_ok()
-            {
-                if ptr.is_null() {
-                    panic!("Couldnt allocate");
-                }
-                unsafe { core::ptr::write_bytes(ptr, 0, size) };
+        let ptr = without_interrupts(|| {
+            let mut heap = HEAP.lock();
+            // SAFETY: The heap only holds blocks given to it by `init`, `grow` and `dealloc`
+            let ptr = unsafe { heap.take(layout) };
+            if !ptr.is_null() || !heap.grow(layout) {
                 return ptr;
             }
+            // SAFETY: See above
+            unsafe { heap.take(layout) }
+        });
+        if !ptr.is_null() {
+            unsafe { core::ptr::write_bytes(ptr, 0, layout.size()) };
         }
+        ptr
     }
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        // TODO: Implement a real allocator
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let size = Heap::block_size(layout);
+        // SAFETY: The block came from `alloc` with the same layout, so it is as large
+        without_interrupts(|| unsafe { HEAP.lock().give(ptr, size) });
     }
 }
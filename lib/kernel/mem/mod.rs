@@ -5,14 +5,72 @@ mod kernel_allocator;
 mod pages;
 pub use pages::*;
 
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::fdt::{Fdt, MemoryRegion},
+};
+
+/// Device registers every address space maps for the kernel, as (address, size)
+static DEVICE_MEMORY: Mutex<Vec<(usize, usize)>> = Mutex::new(Vec::new());
+
 #[global_allocator]
 pub static mut ALLOCATOR: kernel_allocator::KernelAllocator = kernel_allocator::KernelAllocator::new();
 
-/// SAFETY: Caller ensures this function is only called once
-pub unsafe fn init() {
+/// Sets up the kernel heap and hands the memory `fdt` describes to the page allocator. The
+/// blob itself and the initrd it points to are kept out of the page heap
+///
+/// # Safety
+/// Caller ensures this function is only called once
+pub unsafe fn init(fdt: Option<&Fdt>) {
+    let memory = || fdt.into_iter().flat_map(|fdt| fdt.memory_regions());
+    let blob = fdt.map(|fdt| MemoryRegion {
+        start: fdt.as_bytes().as_ptr() as u64,
+        size: fdt.as_bytes().len() as u64,
+    });
+    let initrd = fdt.and_then(|fdt| fdt.initrd()).map(|(start, end)| MemoryRegion {
+        start: start as u64,
+        size: (end - start) as u64,
+    });
+    let reserved = || {
+        fdt.into_iter()
+            .flat_map(|fdt| fdt.reserved_regions())
+            .chain(blob)
+            .chain(initrd)
+    };
+
     // SAFETY: Since this function can only be called once both of our init calls are safe
     unsafe {
-        page_allocator::init();
+        page_allocator::init(memory, reserved);
         kernel_allocator::init();
     }
 }
+
+/// Gives the whole pages inside `start..end`, which `init` kept out of the page allocator, to
+/// it once they are no longer needed, like the initrd after it has been unpacked
+///
+/// # Safety
+/// Caller ensures nothing refers to the memory anymore
+pub unsafe fn release(start: usize, end: usize) {
+    let start = start.next_multiple_of(page_allocator::PAGE_SIZE);
+    let end = end & !(page_allocator::PAGE_SIZE - 1);
+    if start < end {
+        let pages = (end - start) / page_allocator::PAGE_SIZE;
+        // SAFETY: The pages are not handed out, `init` reserved them
+        unsafe { page_allocator::dealloc_pages(start as *mut u8, pages) };
+    }
+}
+
+/// Maps the device registers at `base` into every address space created from now on, so
+/// drivers can reach them from traps taken in any process
+pub fn map_device(base: usize, size: usize) {
+    DEVICE_MEMORY.lock().push((base, size));
+}
+
+/// Device registers mapped with `map_device`, as (address, size)
+pub fn device_memory() -> Vec<(usize, usize)> {
+    DEVICE_MEMORY.lock().clone()
+}
@@ -1,54 +1,262 @@
-use core::{
-    arch::asm,
-    sync::atomic::{AtomicPtr, Ordering::SeqCst},
-};
+use core::arch::asm;
+
+use crate::{collections::mutex::Mutex, kernel::fdt::MemoryRegion};
 
 pub(super) const PAGE_SIZE: usize = 4096;
-const PAGE_COUNT: usize = 16 * 1024;
+/// Memory assumed to follow the kernel image when the firmware reports none
+const FALLBACK_SIZE: usize = 64 * 1024 * 1024;
+/// Pages one word of the bitmap stands for
+const WORD_BITS: usize = usize::BITS as usize;
+
+#[derive(Debug, Clone, Copy)]
+struct Range {
+    start: usize,
+    end: usize,
+}
 
-static HEAP_START: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
-static HEAP_END: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
+impl Range {
+    /// Converts `region` to whole pages, dropping what lies outside the address space
+    fn pages(region: &MemoryRegion) -> Option<Self> {
+        let limit = usize::MAX as u64 + 1;
+        let start = region.start.min(limit).next_multiple_of(PAGE_SIZE as u64);
+        let end = region.end().min(limit) & !(PAGE_SIZE as u64 - 1);
+        // The end of the address space does not fit in a usize, so the last page is left out
+        let end = end.min(limit - PAGE_SIZE as u64);
+        (start < end).then_some(Range {
+            start: start as usize,
+            end: end as usize,
+        })
+    }
+
+    /// Widens `region` to whole pages, unlike free memory which is narrowed
+    fn covering(region: &MemoryRegion) -> Option<Self> {
+        let start = region.start & !(PAGE_SIZE as u64 - 1);
+        let end = region.end().next_multiple_of(PAGE_SIZE as u64);
+        (start < usize::MAX as u64).then_some(Range {
+            start: start as usize,
+            end: end.min(usize::MAX as u64) as usize,
+        })
+    }
+
+    fn overlaps(&self, other: &Range) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+/// Physical memory as one bit per page, set for pages that are handed out or unusable. It
+/// spans from the lowest to the highest page of memory, so holes between memory regions cost
+/// a bit per page as well
+#[derive(Debug)]
+struct PageBitmap {
+    /// Address of the page the first bit stands for
+    base: usize,
+    /// How many pages the bitmap covers
+    pages: usize,
+    words: &'static mut [usize],
+    /// How many pages are free
+    free: usize,
+    /// No page below this one is free, so searches start here
+    hint: usize,
+}
 
-/// SAFETY: Caller must ensure that this function is called only once
-pub(super) unsafe fn init() {
-    let heap_start: *mut u8;
+impl PageBitmap {
+    fn is_used(&self, page: usize) -> bool {
+        self.words[page / WORD_BITS] & (1 << (page % WORD_BITS)) != 0
+    }
+
+    /// Marks the pages of `range` the bitmap covers as used or free
+    fn mark(&mut self, range: Range, used: bool) {
+        let end = self.base + self.pages * PAGE_SIZE;
+        let first = (range.start.clamp(self.base, end) - self.base) / PAGE_SIZE;
+        let last = (range.end.clamp(self.base, end) - self.base).div_ceil(PAGE_SIZE);
+        for page in first..last {
+            if self.is_used(page) == used {
+                continue;
+            }
+            self.words[page / WORD_BITS] ^= 1 << (page % WORD_BITS);
+            if used {
+                self.free -= 1;
+            } else {
+                self.free += 1;
+            }
+        }
+
+        if !used {
+            self.hint = self.hint.min(first);
+        } else if (first..last).contains(&self.hint) {
+            self.hint = last;
+        }
+    }
+
+    /// Returns the first page of the lowest `count` free pages in a row
+    fn find(&self, count: usize) -> Option<usize> {
+        let mut run = 0;
+        let mut page = self.hint;
+        while page < self.pages {
+            // Words without a free page are skipped at once
+            if page.is_multiple_of(WORD_BITS) && self.words[page / WORD_BITS] == usize::MAX {
+                run = 0;
+                page += WORD_BITS;
+                continue;
+            }
+            if self.is_used(page) {
+                run = 0;
+            } else {
+                run += 1;
+                if run == count {
+                    return Some(page + 1 - count);
+                }
+            }
+            page += 1;
+        }
+        None
+    }
+}
+
+static FREE: Mutex<PageBitmap> = Mutex::new(PageBitmap {
+    base: 0,
+    pages: 0,
+    words: &mut [],
+    free: 0,
+    hint: 0,
+});
+
+/// Hands the physical memory `memory` returns to the allocator, except for the kernel image
+/// and the regions `reserved` returns. Both are walked several times, so they are passed as
+/// functions creating the iterators
+///
+/// # Safety
+/// Caller must ensure that this function is called only once, and that `reserved` covers
+/// everything in `memory` that is in use
+pub(super) unsafe fn init<M, R>(memory: impl Fn() -> M, reserved: impl Fn() -> R)
+where
+    M: Iterator<Item = MemoryRegion>,
+    R: Iterator<Item = MemoryRegion>,
+{
+    let kernel_begin: usize;
+    let kernel_end: usize;
     unsafe {
         asm!(
-            "la {}, __heap_begin",
-            out(reg) heap_start
+            "la {}, __kernel_begin",
+            "la {}, __kernel_end",
+            out(reg) kernel_begin,
+            out(reg) kernel_end,
         );
     }
-    assert_eq!((heap_start as usize) % PAGE_SIZE, 0);
-    HEAP_START.store(heap_start, SeqCst);
-    HEAP_END.store(
-        heap_start.wrapping_add(PAGE_SIZE * PAGE_COUNT),
-        SeqCst,
-    );
+    let kernel = Range {
+        start: kernel_begin & !(PAGE_SIZE - 1),
+        end: kernel_end.next_multiple_of(PAGE_SIZE),
+    };
+    let fallback = Range {
+        start: kernel.end,
+        end: kernel.end + FALLBACK_SIZE,
+    };
+
+    let reported = memory().any(|region| Range::pages(&region).is_some());
+    let free = || {
+        memory()
+            .filter_map(|region| Range::pages(&region))
+            .chain((!reported).then_some(fallback))
+    };
+    let used = || {
+        reserved()
+            .filter_map(|region| Range::covering(&region))
+            .chain([kernel])
+    };
+
+    let base = free().map(|range| range.start).min().unwrap_or(fallback.start);
+    let end = free().map(|range| range.end).max().unwrap_or(fallback.end);
+    let pages = (end - base) / PAGE_SIZE;
+    let words = pages.div_ceil(WORD_BITS);
+    let size = (words * core::mem::size_of::<usize>()).next_multiple_of(PAGE_SIZE);
+
+    // The bitmap goes into the first gap that fits it. Gaps start where a memory region starts
+    // or where something in use ends
+    let spot = free()
+        .find_map(|range| {
+            core::iter::once(range.start)
+                .chain(used().map(|used| used.end))
+                .filter_map(|start| Some(Range { start, end: start.checked_add(size)? }))
+                .find(|spot| {
+                    range.start <= spot.start
+                        && spot.end <= range.end
+                        && !used().any(|used| used.overlaps(spot))
+                })
+        })
+        .expect("No free memory for the page bitmap");
+
+    // SAFETY: The spot is free memory inside of `memory`, which nothing else uses
+    let words = unsafe { core::slice::from_raw_parts_mut(spot.start as *mut usize, words) };
+    // Bits past the last page stay set, so those pages are never handed out
+    words.fill(usize::MAX);
+
+    let mut bitmap = FREE.lock();
+    *bitmap = PageBitmap {
+        base,
+        pages,
+        words,
+        free: 0,
+        hint: 0,
+    };
+    for range in free() {
+        bitmap.mark(range, false);
+    }
+    for range in used() {
+        bitmap.mark(range, true);
+    }
+    bitmap.mark(spot, true);
 }
 
+/// Returns how many bytes are left to allocate pages from
+pub fn available() -> usize {
+    FREE.lock().free * PAGE_SIZE
+}
+
+/// Allocates `pages` zeroed pages in a row, panics if there are not enough free pages in a row
 pub fn alloc_pages(pages: usize) -> *mut u8 {
-    loop {
-        let heap_start = HEAP_START.load(SeqCst);
-        if heap_start.is_null() {
-            panic!("Heap not initialized");
-        }
-        let ptr = heap_start;
-        let size = PAGE_SIZE * pages;
-        let end = ptr.wrapping_add(size);
-        if end >= HEAP_END.load(SeqCst) {
-            panic!("Out of memory: page heap exhausted");
-        }
-        if HEAP_START
-            .compare_exchange(heap_start, end, SeqCst, SeqCst)
-            .is_ok()
-        {
-            unsafe { core::ptr::write_bytes(ptr, 0, size) };
-            return ptr;
-        }
+    try_alloc_pages(pages)
+        .unwrap_or_else(|| panic!("Out of memory: no {} free pages in a row", pages))
+}
+
+/// Allocates `pages` zeroed pages in a row, None if there are not enough free pages in a row
+pub fn try_alloc_pages(pages: usize) -> Option<*mut u8> {
+    let size = PAGE_SIZE.checked_mul(pages)?;
+    if pages == 0 {
+        // Nothing is handed out, so any aligned address will do
+        return Some(core::ptr::without_provenance_mut(PAGE_SIZE));
     }
+    let ptr = {
+        let mut free = FREE.lock();
+        let start = free.base + free.find(pages)? * PAGE_SIZE;
+        free.mark(
+            Range {
+                start,
+                end: start + size,
+            },
+            true,
+        );
+        start as *mut u8
+    };
+    unsafe { core::ptr::write_bytes(ptr, 0, size) };
+    Some(ptr)
 }
 
-/// SAFETY: The caller must ensure that the they deallocate the correct number of pages
+/// Gives `pages` pages starting at `ptr` back to the allocator
+///
+/// # Safety
+/// The caller must ensure that the pages came from `alloc_pages` or `try_alloc_pages`
+/// with the same count, or were kept out of the allocator by `init`, and that they are not
+/// used or freed again afterwards
 pub unsafe fn dealloc_pages(ptr: *mut u8, pages: usize) {
-    // TODO: Implement a real allocator
+    if pages == 0 {
+        return;
+    }
+    let start = ptr as usize;
+    FREE.lock().mark(
+        Range {
+            start,
+            end: start + PAGE_SIZE * pages,
+        },
+        false,
+    );
 }
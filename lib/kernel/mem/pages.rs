@@ -1,6 +1,6 @@
 use core::cell::UnsafeCell;
 
-use super::page_allocator::{alloc_pages, dealloc_pages, PAGE_SIZE};
+use super::page_allocator::{alloc_pages, dealloc_pages, try_alloc_pages, PAGE_SIZE};
 
 /// Represents a block of pages allocated in user memeory space
 #[derive(Debug)]
@@ -9,6 +9,11 @@ pub struct UserPages {
     count: usize,
 }
 
+// SAFETY: The pages belong to the block alone and are only written through `&mut self` or by
+// user code through mappings of them
+unsafe impl Send for UserPages {}
+unsafe impl Sync for UserPages {}
+
 impl UserPages {
     pub const PAGE_SIZE: usize = PAGE_SIZE;
 
@@ -54,6 +59,14 @@ impl UserPages {
         Self { ptr, count }
     }
 
+    /// Creates a new block of pages initialized to zero, None if there is not enough free
+    /// memory for it
+    pub fn try_zeroed(count: usize) -> Option<Self> {
+        // The allocator hands out pages zeroed already
+        let ptr = try_alloc_pages(count)?;
+        Some(Self { ptr, count })
+    }
+
     fn ptr_as_mut(&mut self) -> *mut u8 {
         // SAFETY: Since we borrow self as &mut we have exclusive access to this memory region
         unsafe { self.ptr as *mut u8 }
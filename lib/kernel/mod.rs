@@ -3,7 +3,10 @@ extern crate alloc;
 use core::{
     arch::asm,
     cell::{Cell, RefCell, UnsafeCell},
+    future::Future,
+    pin::pin,
     sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
 };
 
 use alloc::{
@@ -12,20 +15,30 @@ use alloc::{
     sync::Arc,
 };
 
+pub mod block;
 pub mod environment;
+pub mod fd;
+pub mod fdt;
+pub mod futex;
+pub mod initramfs;
 pub mod mem;
 pub mod process;
+pub mod programs;
 pub mod scheduler;
+pub mod signal;
+pub mod time;
 pub mod trap;
+pub mod vfs;
 
 use crate::{
     arch::riscv::{csr::Sstatus, trap::trap_set_kernel},
     collections::mutex::Mutex,
     kernel::{
-        environment::{Dispatch, DispatchLevel, Environment, Frame, PageTable},
+        environment::{Clock, Dispatch, DispatchLevel, Environment, Frame, PageTable},
         mem::UserPages,
         process::{Process, ProcessId, ProcessState},
-        scheduler::{Pin, Scheduler, Stack, Task, TaskId},
+        scheduler::{Pin, Scheduler, Stack, Task, TaskId, TaskWaker},
+        time::{nanos_to_ticks, TimerEvent, TimerQueue, SCHEDULER_TICK_NANOS},
     },
 };
 
@@ -37,10 +50,16 @@ pub struct Kernel<ENV: Environment> {
     pub stack: UnsafeCell<*mut u8>,
     pub waiting: Cell<bool>,
 
-    pub waiting_stack: *mut u8,
+    pub waiting_stack: Stack,
     pub current_running: RefCell<Option<Task<ENV>>>,
     pub scheduler: Arc<Mutex<Scheduler<ENV>>>,
+    pub timers: RefCell<TimerQueue<ENV>>,
     pub core: usize,
+    /// Where `block_on` parks the running task on its next switch
+    parking: RefCell<Option<Arc<TaskWaker<ENV>>>>,
+    /// Stack of the task that last exited on this core. The core still runs on it until it
+    /// switches away, so it is only freed once the next task exits here
+    reaped: RefCell<Option<Stack>>,
 }
 
 impl<ENV: Environment> Kernel<ENV> {
@@ -50,9 +69,12 @@ impl<ENV: Environment> Kernel<ENV> {
             scratch: UnsafeCell::new([0; 8]),
             stack: UnsafeCell::new(core::ptr::null_mut()),
             waiting: Cell::new(true),
-            waiting_stack: Stack::new().get_base() as *mut u8,
+            waiting_stack: Stack::new(),
             current_running: RefCell::new(None),
             scheduler,
+            timers: RefCell::new(TimerQueue::new()),
+            parking: RefCell::new(None),
+            reaped: RefCell::new(None),
         }
     }
 
@@ -60,7 +82,7 @@ impl<ENV: Environment> Kernel<ENV> {
 
     pub fn wfi(&self) -> ! {
         self.waiting.replace(true);
-        unsafe { *self.stack.get() = self.waiting_stack };
+        unsafe { *self.stack.get() = self.waiting_stack.get_base().cast_mut() };
         ENV::Dispatch::activate_irq();
         unsafe {
             asm!("wfi", options(noreturn));
@@ -68,33 +90,64 @@ impl<ENV: Environment> Kernel<ENV> {
     }
 
     pub fn switch_from_waiting(&self) -> ! {
+        self.schedule_next();
+    }
+
+    pub fn context_switch(&self, old_frame: &ENV::Frame) -> ! {
         ENV::Dispatch::deactivate_irq();
-        let new_frame = {
-            let (running_task, frame) = {
-                let mut scheduler = self.scheduler.lock();
-                let new_task = match scheduler.next_task(self.core) {
-                    Some(task) => task,
-                    None => {
-                        drop(scheduler);
-                        self.wfi();
-                    }
-                };
-                let new_process = new_task.process.try_lock().unwrap();
-                let frame = new_task.frame.clone();
-
-                // Activate the page table of the running process
-                unsafe {
-                    ENV::PageTable::activate(&new_process.memory.page_table);
-                    self.stack.get().write(new_task.stack.get_base() as *mut u8);
+        if let Some(mut task) = self.current_running.borrow_mut().take() {
+            task.frame = old_frame.clone();
+            match self.parking.borrow_mut().take() {
+                Some(waker) => waker.park(task),
+                None => self.scheduler.lock().add_task(task),
+            }
+        }
+        self.schedule_next();
+    }
+
+    /// Removes the running task from this core so it can be parked on a wait queue or a timer.
+    /// The task continues from `frame` once it is woken
+    pub fn take_current(&self, frame: &ENV::Frame) -> Task<ENV> {
+        ENV::Dispatch::deactivate_irq();
+        let mut task = self
+            .current_running
+            .borrow_mut()
+            .take()
+            .expect("No task running on this core");
+        task.frame = frame.clone();
+        task
+    }
+
+    /// Dispatches the next runnable task without putting the current one back on the run queue,
+    /// waits for an interrupt if nothing is runnable
+    pub fn schedule_next(&self) -> ! {
+        ENV::Dispatch::deactivate_irq();
+        let mut new_frame = {
+            let mut scheduler = self.scheduler.lock();
+            let new_task = match scheduler.next_task(self.core) {
+                Some(task) => task,
+                None => {
+                    drop(scheduler);
+                    self.wfi();
                 }
-                drop(new_process);
-                (new_task, frame)
             };
+            drop(scheduler);
+
+            let new_process = new_task.process.lock();
+            let frame = new_task.frame.clone();
 
-            self.current_running.replace(Some(running_task));
+            // Activate the page table of the running process
+            unsafe {
+                ENV::PageTable::activate(&new_process.memory.page_table);
+                self.stack.get().write(new_task.stack.get_base() as *mut u8);
+            }
+            drop(new_process);
+
+            self.current_running.replace(Some(new_task));
             self.waiting.replace(false);
             frame
         };
+        self.deliver_signals(&mut new_frame);
         unsafe {
             // TODO: Include this in the frame instead of hard coded here
             let mut sstatus = Sstatus::load();
@@ -104,50 +157,64 @@ impl<ENV: Environment> Kernel<ENV> {
         }
     }
 
-    pub fn context_switch(&self, old_frame: &ENV::Frame) -> ! {
+    /// Returns to the task running on this core. Trap handlers may have switched address space
+    /// or kernel stack, so both are restored before dispatching. Pending signals are acted on
+    /// on the way back to user mode
+    pub fn resume(&self, frame: &ENV::Frame) -> ! {
         ENV::Dispatch::deactivate_irq();
-        let new_frame = {
-            let (running_task, frame) = {
-                let mut scheduler = self.scheduler.lock();
-                let mut running_task = self.current_running.borrow_mut();
-                match running_task.take() {
-                    Some(mut task) => {
-                        task.frame = old_frame.clone();
-                        scheduler.add_task(task);
-                    }
-                    None => {}
-                };
-
-                let new_task = match scheduler.next_task(self.core) {
-                    Some(task) => task,
-                    None => {
-                        drop(running_task);
-                        drop(scheduler);
-                        self.wfi();
-                    }
-                };
-                let new_process = new_task.process.try_lock().unwrap();
-                let frame = new_task.frame.clone();
-
-                // Activate the page table of the running process
-                unsafe {
-                    ENV::PageTable::activate(&new_process.memory.page_table);
-                    self.stack.get().write(new_task.stack.get_base() as *mut u8);
-                }
-                drop(new_process);
-                (new_task, frame)
-            };
+        let mut frame = frame.clone();
+        {
+            let running_task = self.current_running.borrow();
+            let running_task = running_task.as_ref().unwrap();
+            let running_process = running_task.process.lock();
+            unsafe {
+                ENV::PageTable::activate(&running_process.memory.page_table);
+                self.stack
+                    .get()
+                    .write(running_task.stack.get_base().cast_mut());
+            }
+        }
 
-            self.current_running.replace(Some(running_task));
-            frame
-        };
+        self.deliver_signals(&mut frame);
         unsafe {
-            // TODO: Include this in the frame instead of hard coded here
-            let mut sstatus = Sstatus::load();
-            sstatus.SPIE = false;
-            sstatus.store();
-            ENV::Dispatch::dispatch(&new_frame);
+            ENV::Dispatch::dispatch(&frame);
+        }
+    }
+
+    /// Ends the running task as if it called `thread_exit` with `code` at `frame`, tearing down
+    /// its process when it is the last thread
+    pub fn exit_current(&self, frame: &ENV::Frame, code: usize) -> ! {
+        let task = self.take_current(frame);
+        let (last, files) = {
+            let mut process = task.process.lock();
+            let last = process.exit_thread(task.id, code, &mut self.scheduler.lock());
+            // Closing may wake tasks blocked on the other end of a pipe, which takes the
+            // scheduler lock, so the table is dropped once no locks are held
+            let files = last.then(|| core::mem::take(&mut process.files));
+            (last, files)
+        };
+        drop(files);
+
+        if last {
+            // The address space may be freed together with the task below
+            unsafe { ENV::PageTable::deactivate() };
         }
+        let Task { stack, .. } = task;
+        // This core switched away from the stack of the last task that exited here
+        self.reaped.replace(Some(stack));
+        self.schedule_next();
+    }
+
+    /// Runs `f` with the process of the running task locked. Threads of one process can run on
+    /// the same core, so interrupts stay off while the lock is held to keep a preempting sibling
+    /// from spinning on it forever
+    pub fn with_process<T, F: FnOnce(&mut Process<ENV>) -> T>(&self, f: F) -> T {
+        ENV::Dispatch::irq_lock(|| {
+            let running_task = self.current_running.borrow();
+            let running_task = running_task.as_ref().expect("No task running on this core");
+            let mut process = running_task.process.lock();
+            f(&mut process)
+        })
     }
 
     pub fn kernel_yield(&self) {
@@ -160,6 +227,28 @@ impl<ENV: Environment> Kernel<ENV> {
         ENV::Dispatch::kernel_yield();
     }
 
+    /// Runs `future` to completion from kernel code. The running task sleeps while the future
+    /// is pending, until whatever completes it wakes it. Without a running task, e.g. during
+    /// boot, the future is polled until it is ready
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let mut future = pin!(future);
+        let task_waker = Arc::new(TaskWaker::new(self.scheduler.clone()));
+        let waker = Waker::from(task_waker.clone());
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            if self.current_running.borrow().is_none() {
+                core::hint::spin_loop();
+                continue;
+            }
+            ENV::Dispatch::deactivate_irq();
+            self.parking.replace(Some(task_waker.clone()));
+            self.kernel_yield();
+        }
+    }
+
     pub fn start(&self) -> ! {
         use crate::kernel::environment::Dispatch;
         use crate::kernel::environment::PageTable;
@@ -167,6 +256,11 @@ impl<ENV: Environment> Kernel<ENV> {
 
         println!("Kernel starting...");
 
+        self.add_timer(
+            ENV::Clock::now().saturating_add(nanos_to_ticks(SCHEDULER_TICK_NANOS)),
+            TimerEvent::SchedulerTick,
+        );
+
         let frame = {
             let mut scheduler = self.scheduler.lock();
             let task = match scheduler.next_task(self.core) {
@@ -179,7 +273,7 @@ impl<ENV: Environment> Kernel<ENV> {
             };
 
             {
-                let process = task.process.try_lock().unwrap();
+                let process = task.process.lock();
                 unsafe {
                     ENV::PageTable::activate(&process.memory.page_table);
                     self.stack.get().write(task.stack.get_base() as *mut u8);
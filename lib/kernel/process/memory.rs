@@ -1,5 +1,6 @@
 extern crate alloc;
 
+use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
 
@@ -8,16 +9,37 @@ use crate::kernel::environment::Mode;
 use crate::kernel::environment::PageTable;
 use crate::kernel::mem::UserPages;
 
+/// Shared memory region mapped into a process by `shm_map`
+#[derive(Debug)]
+pub struct SharedMapping {
+    pub start: usize,
+    pub pages: Arc<UserPages>,
+}
+
+impl SharedMapping {
+    pub fn end(&self) -> usize {
+        self.start + self.pages.size()
+    }
+}
+
 #[derive(Debug)]
 pub struct Memory<ENV: Environment> {
     pub page_table: ENV::PageTable,
     pub pages: Vec<UserPages>,
     pub user_start: usize,
     pub user_end: usize,
+    /// Regions shared with other processes, all inside the shared window
+    pub shared: Vec<SharedMapping>,
 }
 
 impl<ENV: Environment> Memory<ENV> {
     const USER_START: usize = 0xC000_0000;
+    /// Window `shm_map` places shared regions in, leaving the memory below it for the
+    /// process image and its heap to grow into
+    const SHARED_START: usize = 0xD000_0000;
+    const SHARED_END: usize = 0xE000_0000;
+    /// Most memory a program image can ask for, it has to end below the shared window
+    pub const IMAGE_MAX_SIZE: usize = Self::SHARED_START - Self::USER_START;
 
     pub fn new() -> Self {
         Memory {
@@ -25,12 +47,26 @@ impl<ENV: Environment> Memory<ENV> {
             pages: vec![],
             user_start: Self::USER_START,
             user_end: Self::USER_START,
+            shared: vec![],
         }
     }
 
+    /// Returns true if the `len` bytes starting at `ptr` are all user memory
+    pub fn contains(&self, ptr: usize, len: usize) -> bool {
+        let Some(end) = ptr.checked_add(len) else {
+            return false;
+        };
+        if ptr >= self.user_start && end <= self.user_end {
+            return true;
+        }
+        self.shared
+            .iter()
+            .any(|mapping| ptr >= mapping.start && end <= mapping.end())
+    }
+
     /// Safety: The caller must ensure that no other references to the slice exist
     pub unsafe fn slice(&self, ptr: *mut u8, len: usize) -> Result<&[u8], ()> {
-        if (ptr as usize) < self.user_start || (ptr as usize) + len > self.user_end {
+        if !self.contains(ptr as usize, len) {
             return Err(());
         }
 
@@ -42,7 +78,7 @@ impl<ENV: Environment> Memory<ENV> {
 
     /// Safety: The caller must ensure that no other references to the slice exist
     pub unsafe fn slice_mut(&mut self, ptr: *mut u8, len: usize) -> Result<&mut [u8], ()> {
-        if (ptr as usize) < self.user_start || (ptr as usize) + len > self.user_end {
+        if !self.contains(ptr as usize, len) {
             return Err(());
         }
 
@@ -52,6 +88,23 @@ impl<ENV: Environment> Memory<ENV> {
         };
     }
 
+    /// Copies a value out of user memory
+    ///
+    /// # Safety
+    /// The caller must ensure that the page table of this memory is active
+    pub unsafe fn read<T: Copy>(&self, ptr: *const T) -> Result<T, ()> {
+        let bytes = unsafe { self.slice(ptr as *mut u8, core::mem::size_of::<T>())? };
+        Ok(unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+    }
+
+    /// Returns the physical address behind a user address
+    pub fn physical_address(&self, ptr: usize) -> Result<usize, ()> {
+        if !self.contains(ptr, 1) {
+            return Err(());
+        }
+        self.page_table.translate(ptr).ok_or(())
+    }
+
     pub fn grow(&mut self, pages: UserPages) {
         for (i, page) in pages.iter().enumerate() {
             self.page_table.map(
@@ -63,6 +116,95 @@ impl<ENV: Environment> Memory<ENV> {
         }
         self.pages.push(pages);
     }
+
+    /// Grows the memory by `increment` bytes rounded up to whole pages of zeroes, without
+    /// running into the shared window. Returns the previous end of the memory
+    pub fn sbrk(&mut self, increment: usize) -> Result<usize, ()> {
+        let end = self.user_end;
+        let pages = increment.div_ceil(UserPages::PAGE_SIZE);
+        if pages == 0 {
+            return Ok(end);
+        }
+        pages
+            .checked_mul(UserPages::PAGE_SIZE)
+            .and_then(|size| end.checked_add(size))
+            .filter(|&new_end| new_end <= Self::SHARED_START)
+            .ok_or(())?;
+
+        self.grow(UserPages::try_zeroed(pages).ok_or(())?);
+        Ok(end)
+    }
+
+    /// Maps a shared region at `at`, or at the lowest free address of the shared window if
+    /// `at` is None. Returns the address the region starts at
+    pub fn map_shared(&mut self, pages: Arc<UserPages>, at: Option<usize>) -> Result<usize, ()> {
+        let size = pages.size();
+        let start = match at {
+            Some(start) => {
+                let free = start.is_multiple_of(UserPages::PAGE_SIZE)
+                    && start >= Self::SHARED_START
+                    && start.checked_add(size).is_some_and(|end| end <= Self::SHARED_END)
+                    && !self.overlaps_shared(start, start + size);
+                if !free {
+                    return Err(());
+                }
+                start
+            }
+            None => self.find_shared_gap(size).ok_or(())?,
+        };
+
+        for (i, page) in pages.iter().enumerate() {
+            self.page_table.map(
+                start + UserPages::PAGE_SIZE * i,
+                page as usize,
+                Mode::WRITE | Mode::READ | Mode::USER,
+            );
+        }
+        self.shared.push(SharedMapping { start, pages });
+        Ok(start)
+    }
+
+    /// Unmaps the shared region starting at `start`. The pages are freed once no other
+    /// process or descriptor holds on to them
+    pub fn unmap_shared(&mut self, start: usize) -> Result<(), ()> {
+        let index = self
+            .shared
+            .iter()
+            .position(|mapping| mapping.start == start)
+            .ok_or(())?;
+        let mapping = self.shared.swap_remove(index);
+        for i in 0..mapping.pages.len() {
+            self.page_table.unmap(start + UserPages::PAGE_SIZE * i);
+        }
+        // Other cores may run threads of the process, none of them may reach the pages once
+        // they can be freed
+        ENV::PageTable::flush_all_cores(start, mapping.pages.size());
+        Ok(())
+    }
+
+    fn overlaps_shared(&self, start: usize, end: usize) -> bool {
+        self.shared
+            .iter()
+            .any(|mapping| start < mapping.end() && mapping.start < end)
+    }
+
+    fn find_shared_gap(&self, size: usize) -> Option<usize> {
+        let mut start = Self::SHARED_START;
+        loop {
+            let end = start.checked_add(size).filter(|&end| end <= Self::SHARED_END)?;
+            // Skip past the first mapping in the way and try again behind it
+            match self
+                .shared
+                .iter()
+                .filter(|mapping| start < mapping.end() && mapping.start < end)
+                .map(|mapping| mapping.end())
+                .max()
+            {
+                Some(next) => start = next,
+                None => return Some(start),
+            }
+        }
+    }
 }
 
 impl<ENV: Environment> Clone for Memory<ENV> {
@@ -92,11 +234,28 @@ impl<ENV: Environment> Clone for Memory<ENV> {
             );
         }
 
+        // Shared regions stay shared with the child
+        let mut shared = Vec::with_capacity(self.shared.len());
+        for mapping in self.shared.iter() {
+            for (i, page) in mapping.pages.iter().enumerate() {
+                table.map(
+                    mapping.start + UserPages::PAGE_SIZE * i,
+                    page as usize,
+                    Mode::WRITE | Mode::READ | Mode::USER,
+                );
+            }
+            shared.push(SharedMapping {
+                start: mapping.start,
+                pages: mapping.pages.clone(),
+            });
+        }
+
         Memory {
             pages: vec![pages],
             page_table: table,
             user_start: self.user_start,
             user_end: self.user_end,
+            shared,
         }
     }
 }
@@ -131,6 +290,7 @@ impl<ENV: Environment> From<&[u8]> for Memory<ENV> {
             pages: vec![pages],
             user_start: Self::USER_START,
             user_end: user_end,
+            shared: vec![],
         }
     }
 }
@@ -1,6 +1,9 @@
 mod process;
 mod memory;
+mod thread;
 
+pub use process::ImageError;
 pub use process::Process;
 pub use process::ProcessId;
 pub use process::ProcessState;
+pub use thread::Thread;
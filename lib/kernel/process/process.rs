@@ -1,21 +1,54 @@
 extern crate alloc;
 
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use alloc::rc::Rc;
+use alloc::{collections::BTreeMap, rc::Rc, sync::Arc, vec::Vec};
 
 use crate::{
     collections::mutex::Mutex,
     kernel::{
         environment::{Environment, Frame},
-        process::memory::Memory,
+        fd::FileTable,
+        mem::UserPages,
+        process::{memory::Memory, Thread},
+        scheduler::{Scheduler, TaskId},
+        signal::SignalState,
+        time::ProcessTimer,
+        vfs::Dentry,
     },
 };
 
+/// Program images start with this and the size of the memory the program spans from the
+/// start of user memory as a little endian u32, followed by the contents of that memory up
+/// to the end of its last initialized byte. The build script writes the header
+pub const IMAGE_MAGIC: [u8; 4] = *b"PPIM";
+const IMAGE_HEADER_SIZE: usize = 8;
+
+/// Why a program image could not be loaded
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageError {
+    /// The header is missing or asks for less memory than the image holds, or for more than
+    /// a process has
+    Invalid,
+    /// There is not enough free memory for the program
+    OutOfMemory,
+}
+
+/// Ids below this are handed out by hand, like the one of init
+static NEXT_PROCESS_ID: AtomicUsize = AtomicUsize::new(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ProcessId(usize);
 
 impl ProcessId {
+    /// The first user process, which adopts the children of processes that exit
+    pub const INIT: ProcessId = ProcessId(1);
+
+    /// Returns an id no other process has been given
+    pub fn allocate() -> Self {
+        ProcessId(NEXT_PROCESS_ID.fetch_add(1, Ordering::SeqCst))
+    }
+
     pub fn from(id: usize) -> Self {
         ProcessId(id)
     }
@@ -42,6 +75,14 @@ pub struct Process<ENV: Environment> {
     pub id: ProcessId,
     pub state: Mutex<ProcessState>,
     pub memory: Rc<Memory<ENV>>,
+    pub files: FileTable<ENV>,
+    /// Timers created with `timer_create`, indexed by the id handed to user space
+    pub timers: Vec<Option<Arc<Mutex<ProcessTimer<ENV>>>>>,
+    /// Every task running in this process, plus exited ones that have not been joined yet
+    pub threads: BTreeMap<TaskId, Thread<ENV>>,
+    pub signals: SignalState<ENV>,
+    /// Where relative paths start, the root of the filesystem for None
+    pub cwd: Option<Arc<Dentry>>,
 }
 
 #[derive(Debug, Clone)]
@@ -59,15 +100,95 @@ impl<ENV: Environment> Process<ENV> {
             id: id,
             state: Mutex::new(ProcessState::Idle),
             memory: memory,
+            files: FileTable::with_stdio(),
+            timers: Vec::new(),
+            threads: BTreeMap::new(),
+            signals: SignalState::new(),
+            cwd: None,
         }
     }
 
+    /// Creates a process running the program `image`, with zeroed memory behind its contents
+    /// up to the size the header asks for, which holds .bss and the stack
+    pub fn from_image(id: ProcessId, image: &[u8]) -> Result<Self, ImageError> {
+        let (header, contents) = image
+            .split_at_checked(IMAGE_HEADER_SIZE)
+            .ok_or(ImageError::Invalid)?;
+        let (magic, size) = header.split_at(IMAGE_MAGIC.len());
+        let size = u32::from_le_bytes(size.try_into().expect("header size")) as usize;
+        if magic != IMAGE_MAGIC || size < contents.len() || size > Memory::<ENV>::IMAGE_MAX_SIZE
+        {
+            return Err(ImageError::Invalid);
+        }
+
+        let mut process = Self::from_slice(id, contents);
+        let extra = size.div_ceil(UserPages::PAGE_SIZE)
+            - contents.len().div_ceil(UserPages::PAGE_SIZE);
+        if extra > 0 {
+            let pages = UserPages::try_zeroed(extra).ok_or(ImageError::OutOfMemory)?;
+            process.memory_mut().grow(pages);
+        }
+        Ok(process)
+    }
+
     pub fn fork(&self, id: ProcessId) -> Process<ENV> {
         Process {
             id: id,
             state: Mutex::new(ProcessState::Idle),
             memory: Rc::new(self.memory.as_ref().clone()),
+            files: self.files.clone(),
+            timers: Vec::new(),
+            threads: BTreeMap::new(),
+            signals: self.signals.forked(),
+            cwd: self.cwd.clone(),
+        }
+    }
+
+    /// The memory of the process is never shared with another one, so it can always be
+    /// borrowed mutably through the process
+    pub fn memory_mut(&mut self) -> &mut Memory<ENV> {
+        Rc::get_mut(&mut self.memory).expect("process memory is owned by its process")
+    }
+
+    pub fn timer(&self, id: usize) -> Option<Arc<Mutex<ProcessTimer<ENV>>>> {
+        self.timers.get(id)?.clone()
+    }
+
+    /// Registers a task that runs in this process
+    pub fn add_thread(&mut self, id: TaskId) {
+        self.threads.insert(id, Thread::new());
+    }
+
+    /// Amount of threads that have not exited yet
+    pub fn live_threads(&self) -> usize {
+        self.threads.values().filter(|t| !t.has_exited()).count()
+    }
+
+    /// Marks a thread as exited and hands `code` to every task joining it. When the last thread
+    /// exits the process is torn down and true is returned
+    pub fn exit_thread(&mut self, id: TaskId, code: usize, scheduler: &mut Scheduler<ENV>) -> bool {
+        if let Some(thread) = self.threads.get_mut(&id) {
+            thread.exit_code = Some(code);
+            let joined = thread.joiners.wake_all(scheduler, |task| {
+                task.frame.set_success(code, 0);
+            });
+            if joined > 0 {
+                self.threads.remove(&id);
+            }
+        }
+
+        if self.live_threads() > 0 {
+            return false;
+        }
+
+        *self.state.lock() = ProcessState::Exited;
+        for timer in self.timers.drain(..).flatten() {
+            timer.lock().disarm(scheduler);
         }
+        self.threads.clear();
+        // The last thread to exit decides the exit code of the process
+        scheduler.exit_process(self.id, code);
+        true
     }
 
     pub fn new_wfi() -> Self {
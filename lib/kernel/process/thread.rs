@@ -0,0 +1,38 @@
+use crate::kernel::{
+    environment::Environment,
+    scheduler::{Wait, WaitQueue},
+};
+
+/// Join state of one task in a process
+#[derive(Debug)]
+pub struct Thread<ENV: Environment> {
+    /// Set once the thread has called `thread_exit`, the entry is kept until it is joined
+    pub exit_code: Option<usize>,
+    /// Tasks blocked in `thread_join` on this thread
+    pub joiners: WaitQueue<ENV>,
+    /// Signals this thread does not act on, one bit per signal number
+    pub blocked: u32,
+    /// The wait the thread is parked in, so a signal can interrupt it
+    pub wait: Option<Wait<ENV>>,
+}
+
+impl<ENV: Environment> Thread<ENV> {
+    pub fn new() -> Self {
+        Self {
+            exit_code: None,
+            joiners: WaitQueue::new(),
+            blocked: 0,
+            wait: None,
+        }
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.exit_code.is_some()
+    }
+}
+
+impl<ENV: Environment> Default for Thread<ENV> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
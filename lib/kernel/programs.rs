@@ -0,0 +1,17 @@
+//! User programs embedded in the kernel image.
+//!
+//! Every package in `user/programs` is built by the build script and registered here under
+//! its package name. Processes are started from the same images in the initramfs.
+
+/// Image of a user program as `Process::from_image` loads it, at the start of user memory
+#[derive(Debug)]
+pub struct Program {
+    pub name: &'static str,
+    pub image: &'static [u8],
+}
+
+include!(concat!(env!("OUT_DIR"), "/programs.rs"));
+
+pub fn all() -> &'static [Program] {
+    PROGRAMS
+}
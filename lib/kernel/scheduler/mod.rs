@@ -1,12 +1,15 @@
 extern crate alloc;
 
-use alloc::{collections::LinkedList, rc::Rc, sync::Arc};
+use alloc::{
+    collections::{BTreeMap, LinkedList},
+    sync::Arc,
+};
 
 use crate::{
     collections::mutex::Mutex,
     kernel::{
         environment::{Environment, Frame},
-        mem::UserPages,
+        futex::FutexTable,
         process::{Process, ProcessId},
     },
 };
@@ -17,33 +20,63 @@ pub use task::*;
 mod stack;
 pub use stack::*;
 
+mod wait_queue;
+pub use wait_queue::*;
+
+mod waker;
+pub use waker::*;
+
+mod process_table;
+use process_table::ProcessEntry;
+pub use process_table::NoChild;
+
 #[derive(Debug)]
 pub struct Scheduler<ENV: Environment> {
     tasks: LinkedList<Task<ENV>>,
+    /// Kept next to the run queue so waking a futex waiter only takes one lock
+    pub futexes: FutexTable<ENV>,
+    /// Tasks in `sleep`, taken back out by their timer or by a signal
+    pub sleepers: WaitQueue<ENV>,
+    /// Every process that has not been reaped yet, see `process_table`
+    processes: BTreeMap<ProcessId, ProcessEntry<ENV>>,
 }
 
 impl<ENV: Environment> Scheduler<ENV> {
     pub fn new() -> Self {
         Self {
             tasks: LinkedList::new(),
+            futexes: FutexTable::new(),
+            sleepers: WaitQueue::new(),
+            processes: BTreeMap::new(),
         }
     }
 
     pub fn new_test_task(&mut self, id: usize, data: &[u8]) {
-        let mut process = Process::from_slice(ProcessId::from(id), data);
-
-        Rc::get_mut(&mut process.memory)
-            .unwrap()
-            .grow(UserPages::zeroed(40));
+        let process =
+            Process::from_image(ProcessId::from(id), data).expect("Invalid program image");
+        self.add_process(process, None, Stack::new());
+    }
 
+    /// Registers `process` as a child of `parent` and starts its main thread at the start of
+    /// its memory, on the kernel stack `stack`
+    pub fn add_process(
+        &mut self,
+        mut process: Process<ENV>,
+        parent: Option<ProcessId>,
+        stack: Stack,
+    ) {
         let frame = ENV::Frame::empty(process.memory.user_start);
+        let task_id = TaskId::allocate();
+        process.add_thread(task_id);
 
+        let process = Arc::new(Mutex::new(process));
+        self.register_process(&process, parent);
         self.add_task(Task {
-            id: TaskId::new(id),
+            id: task_id,
             frame: frame,
             pin: Pin::Unpinned,
-            stack: Stack::new(),
-            process: Arc::new(Mutex::new(process)),
+            stack,
+            process,
         });
     }
 
@@ -0,0 +1,142 @@
+//! Processes by id and who their parents are.
+//!
+//! An exited process stays in the table as a zombie holding its exit code until its parent
+//! reaps it with `wait`. Children of an exiting process are adopted by init, which reaps
+//! them in its place.
+
+extern crate alloc;
+
+use alloc::sync::{Arc, Weak};
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::{
+        environment::Environment,
+        process::{Process, ProcessId},
+        scheduler::{Scheduler, Task, WaitQueue, WaitTicket},
+    },
+};
+
+#[derive(Debug)]
+pub(super) struct ProcessEntry<ENV: Environment> {
+    process: Weak<Mutex<Process<ENV>>>,
+    parent: Option<ProcessId>,
+    /// Set once the last thread of the process exited
+    exit_code: Option<usize>,
+    /// Tasks of the process blocked in `wait` until one of its children exits
+    child_waiters: WaitQueue<ENV>,
+}
+
+/// `reap_child` was asked for a child the process does not have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoChild;
+
+impl<ENV: Environment> Scheduler<ENV> {
+    /// Makes `process` findable by its id and a child of `parent`, which reaps it once it
+    /// exits. A process without a parent is forgotten as soon as it exits
+    pub fn register_process(
+        &mut self,
+        process: &Arc<Mutex<Process<ENV>>>,
+        parent: Option<ProcessId>,
+    ) {
+        let id = process.lock().id;
+        self.processes.insert(
+            id,
+            ProcessEntry {
+                process: Arc::downgrade(process),
+                parent,
+                exit_code: None,
+                child_waiters: WaitQueue::new(),
+            },
+        );
+    }
+
+    /// Returns the process `id` if it is still running
+    pub fn process(&self, id: ProcessId) -> Option<Arc<Mutex<Process<ENV>>>> {
+        self.processes.get(&id)?.process.upgrade()
+    }
+
+    /// Records that the last thread of process `id` exited with `code`
+    pub fn exit_process(&mut self, id: ProcessId, code: usize) {
+        if id == ProcessId::INIT {
+            panic!("init exited with code {}", code);
+        }
+
+        let mut adopted_zombie = false;
+        for entry in self.processes.values_mut() {
+            if entry.parent == Some(id) {
+                entry.parent = Some(ProcessId::INIT);
+                adopted_zombie |= entry.exit_code.is_some();
+            }
+        }
+        if adopted_zombie {
+            self.wake_child_waiters(ProcessId::INIT);
+        }
+
+        let Some(entry) = self.processes.get_mut(&id) else {
+            return;
+        };
+        entry.exit_code = Some(code);
+        let parent = entry.parent;
+        match parent {
+            Some(parent) if self.processes.contains_key(&parent) => {
+                self.wake_child_waiters(parent)
+            }
+            _ => {
+                self.processes.remove(&id);
+            }
+        }
+    }
+
+    /// Removes an exited child of `parent` from the table and returns its id and exit code.
+    /// `child` picks a specific child, None takes any. Returns None while the children are
+    /// all still running
+    pub fn reap_child(
+        &mut self,
+        parent: ProcessId,
+        child: Option<ProcessId>,
+    ) -> Result<Option<(ProcessId, usize)>, NoChild> {
+        let mut children = self
+            .processes
+            .iter()
+            .filter(|(id, entry)| {
+                entry.parent == Some(parent) && child.is_none_or(|child| child == **id)
+            })
+            .peekable();
+        if children.peek().is_none() {
+            return Err(NoChild);
+        }
+
+        let exited = children.find_map(|(id, entry)| Some((*id, entry.exit_code?)));
+        if let Some((id, _)) = exited {
+            self.processes.remove(&id);
+        }
+        Ok(exited)
+    }
+
+    /// Parks `task` of process `parent` until one of the children of the process exits.
+    /// Hands the task back if the process is not in the table
+    pub fn wait_for_child(
+        &mut self,
+        parent: ProcessId,
+        task: Task<ENV>,
+    ) -> Result<WaitTicket, Task<ENV>> {
+        match self.processes.get_mut(&parent) {
+            Some(entry) => Ok(entry.child_waiters.push(task)),
+            None => Err(task),
+        }
+    }
+
+    /// Removes a task of `parent` that started waiting for a child with `ticket`, None if
+    /// it was already woken
+    pub fn cancel_child_wait(&mut self, parent: ProcessId, ticket: WaitTicket) -> Option<Task<ENV>> {
+        self.processes.get_mut(&parent)?.child_waiters.remove(ticket)
+    }
+
+    fn wake_child_waiters(&mut self, id: ProcessId) {
+        if let Some(entry) = self.processes.get_mut(&id) {
+            let mut waiters = core::mem::take(&mut entry.child_waiters);
+            waiters.wake_all(self, |_| {});
+        }
+    }
+}
@@ -1,7 +1,7 @@
-extern crate alloc;
-
-use alloc::alloc::{alloc, dealloc, Layout};
-use alloc::boxed::Box;
+use crate::kernel::mem::{
+    page_allocator::{dealloc_pages, try_alloc_pages},
+    UserPages,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum GrowDirection {
@@ -9,26 +9,21 @@ enum GrowDirection {
     Up,
 }
 
+/// A kernel stack, taken from the page allocator and given back to it when dropped
 #[derive(Debug)]
 pub struct Stack(*const u8);
 
 impl Stack {
-    const SIZE: usize = 8 * 1024;
+    const PAGES: usize = 2;
+    const SIZE: usize = Self::PAGES * UserPages::PAGE_SIZE;
 
     pub fn new() -> Self {
-        let data = unsafe {
-            let layout = Layout::from_size_align(Self::SIZE, Self::alignment()).unwrap();
-            alloc(layout)
-        };
-        if data.is_null() {
-            panic!("Failed to allocate stack memory");
-        }
-        Stack(data)
+        Self::try_new().expect("Failed to allocate stack memory")
     }
 
-    #[cfg(target_arch = "riscv32")]
-    fn alignment() -> usize {
-        4
+    /// Allocates a stack, None if there is not enough free memory for it
+    pub fn try_new() -> Option<Self> {
+        try_alloc_pages(Self::PAGES).map(|data| Stack(data))
     }
 
     #[cfg(target_arch = "riscv32")]
@@ -48,3 +43,11 @@ impl Stack {
         }
     }
 }
+
+impl Drop for Stack {
+    fn drop(&mut self) {
+        // SAFETY: The pages came from `try_alloc_pages` with the same count and the stack is
+        // only dropped once nothing runs on it anymore
+        unsafe { dealloc_pages(self.0.cast_mut(), Self::PAGES) };
+    }
+}
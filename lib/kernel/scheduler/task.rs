@@ -1,5 +1,7 @@
 extern crate alloc;
 
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
 use alloc::sync::Arc;
 
 use crate::{
@@ -11,7 +13,9 @@ use crate::{
     },
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+static NEXT_TASK_ID: AtomicUsize = AtomicUsize::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TaskId(usize);
 
 impl From<usize> for TaskId {
@@ -25,6 +29,11 @@ impl TaskId {
         TaskId(id)
     }
 
+    /// Returns an id no other task has been given
+    pub fn allocate() -> Self {
+        TaskId(NEXT_TASK_ID.fetch_add(1, SeqCst))
+    }
+
     pub fn id(&self) -> usize {
         self.0
     }
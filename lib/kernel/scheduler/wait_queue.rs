@@ -0,0 +1,124 @@
+extern crate alloc;
+
+use core::{
+    fmt::Debug,
+    sync::atomic::{AtomicUsize, Ordering::SeqCst},
+};
+
+use alloc::{collections::VecDeque, sync::Arc};
+
+use crate::kernel::{
+    environment::Environment,
+    process::ProcessId,
+    scheduler::{Scheduler, Task, TaskId},
+};
+
+static NEXT_WAIT_TICKET: AtomicUsize = AtomicUsize::new(1);
+
+/// Identifies one wait of one task, used to pull the task back out of a queue when a wait times
+/// out. Never reused, so a stale timeout can not cancel a later wait of the same task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WaitTicket(usize);
+
+impl WaitTicket {
+    /// Returns a ticket no other wait has been given. Tickets only grow
+    pub fn allocate() -> Self {
+        WaitTicket(NEXT_WAIT_TICKET.fetch_add(1, SeqCst))
+    }
+}
+
+/// A kernel object a timer can pull a waiting task out of when the wait times out
+pub trait CancelWait<ENV: Environment>: Debug {
+    /// Removes the task that started waiting with `ticket`, None if it was already woken
+    fn cancel_wait(&self, ticket: WaitTicket) -> Option<Task<ENV>>;
+}
+
+/// Where a parked task waits, so a signal can pull it back out
+#[derive(Debug)]
+pub enum WaitOn<ENV: Environment> {
+    /// A kernel object such as a pipe, a channel or a process timer
+    Object(Arc<dyn CancelWait<ENV>>),
+    /// The futex queue of a physical address
+    Futex(usize),
+    /// A child of the process exiting
+    Child(ProcessId),
+    /// Another thread of the process exiting
+    Join(TaskId),
+    /// The end of a `sleep`
+    Sleep,
+}
+
+/// A wait a task has been parked in, recorded on its thread until it returns to user mode
+#[derive(Debug)]
+pub struct Wait<ENV: Environment> {
+    pub on: WaitOn<ENV>,
+    pub ticket: WaitTicket,
+    /// The syscall runs again once the task is woken, otherwise an interrupted wait fails
+    /// with `EINTR`
+    pub restarts: bool,
+}
+
+/// Tasks blocked on some kernel object, woken in the order they started waiting
+#[derive(Debug)]
+pub struct WaitQueue<ENV: Environment> {
+    tasks: VecDeque<(WaitTicket, Task<ENV>)>,
+}
+
+impl<ENV: Environment> WaitQueue<ENV> {
+    pub const fn new() -> Self {
+        Self {
+            tasks: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, task: Task<ENV>) -> WaitTicket {
+        let ticket = WaitTicket::allocate();
+        self.tasks.push_back((ticket, task));
+        ticket
+    }
+
+    /// Removes the longest waiting task without waking it
+    pub fn pop(&mut self) -> Option<Task<ENV>> {
+        self.tasks.pop_front().map(|(_, task)| task)
+    }
+
+    /// Removes the task that started waiting with `ticket`, if it is still waiting
+    pub fn remove(&mut self, ticket: WaitTicket) -> Option<Task<ENV>> {
+        let index = self.tasks.iter().position(|(t, _)| *t == ticket)?;
+        self.tasks.remove(index).map(|(_, task)| task)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Moves the longest waiting task back onto the run queue, `f` gets to set up the frame
+    /// the task resumes with. Returns false if no task was waiting
+    pub fn wake_one<F: FnOnce(&mut Task<ENV>)>(&mut self, scheduler: &mut Scheduler<ENV>, f: F) -> bool {
+        match self.pop() {
+            Some(mut task) => {
+                f(&mut task);
+                scheduler.add_task(task);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves every waiting task back onto the run queue, returning how many were woken
+    pub fn wake_all<F: FnMut(&mut Task<ENV>)>(&mut self, scheduler: &mut Scheduler<ENV>, mut f: F) -> usize {
+        let count = self.tasks.len();
+        while self.wake_one(scheduler, &mut f) {}
+        count
+    }
+}
+
+impl<ENV: Environment> Default for WaitQueue<ENV> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
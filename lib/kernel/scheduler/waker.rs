@@ -0,0 +1,73 @@
+//! Wakers that put a task parked in kernel mode back on the run queue.
+//!
+//! `Kernel::block_on` hands one to the future it polls, so drivers can finish a request
+//! from an interrupt handler without knowing about tasks or the scheduler.
+
+extern crate alloc;
+
+use alloc::{sync::Arc, task::Wake};
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::{
+        environment::Environment,
+        scheduler::{Scheduler, Task},
+    },
+};
+
+#[derive(Debug)]
+enum WakerState<ENV: Environment> {
+    /// The task is running, or about to park
+    Running,
+    /// Woken before it parked, so it must not park
+    Woken,
+    Parked(Task<ENV>),
+}
+
+/// Wakes the task parked on it. It takes the scheduler lock, so it must be woken with
+/// interrupts off
+#[derive(Debug)]
+pub struct TaskWaker<ENV: Environment> {
+    scheduler: Arc<Mutex<Scheduler<ENV>>>,
+    state: Mutex<WakerState<ENV>>,
+}
+
+// SAFETY: The task is only reached through the lock, the same way the run queue holds it
+unsafe impl<ENV: Environment> Send for TaskWaker<ENV> {}
+unsafe impl<ENV: Environment> Sync for TaskWaker<ENV> {}
+
+impl<ENV: Environment> TaskWaker<ENV> {
+    pub fn new(scheduler: Arc<Mutex<Scheduler<ENV>>>) -> Self {
+        Self {
+            scheduler,
+            state: Mutex::new(WakerState::Running),
+        }
+    }
+
+    /// Keeps `task` off the run queue until the waker is woken. A wake that came first sends
+    /// the task straight back to the run queue
+    pub fn park(&self, task: Task<ENV>) {
+        let mut state = self.state.lock();
+        match *state {
+            WakerState::Woken => {
+                *state = WakerState::Running;
+                self.scheduler.lock().add_task(task);
+            }
+            _ => *state = WakerState::Parked(task),
+        }
+    }
+}
+
+impl<ENV: Environment> Wake for TaskWaker<ENV> {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let mut state = self.state.lock();
+        match core::mem::replace(&mut *state, WakerState::Running) {
+            WakerState::Parked(task) => self.scheduler.lock().add_task(task),
+            _ => *state = WakerState::Woken,
+        }
+    }
+}
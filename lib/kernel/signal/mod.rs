@@ -0,0 +1,431 @@
+//! POSIX-like signals. Signals are queued on a process and acted on by the first of its
+//! threads that returns to user mode without blocking them.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::{
+        environment::{Dispatch, Environment, Frame},
+        process::Process,
+        scheduler::{Scheduler, TaskId, Wait, WaitOn, WaitQueue},
+        trap::syscall::Errno,
+        Kernel,
+    },
+};
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+pub const SIGTTIN: usize = 21;
+pub const SIGTTOU: usize = 22;
+pub const SIGURG: usize = 23;
+pub const SIGWINCH: usize = 28;
+
+/// Signal numbers run from 1 to `NSIG - 1`, matching the Linux numbering
+pub const NSIG: usize = 32;
+
+/// `SigAction::handler` value for the default action of the signal
+pub const SIG_DFL: usize = 0;
+/// `SigAction::handler` value for discarding the signal
+pub const SIG_IGN: usize = 1;
+
+/// Signals that can not be caught, ignored or blocked
+const UNBLOCKABLE: u32 = bit(SIGKILL) | bit(SIGSTOP);
+const STOP_SIGNALS: u32 = bit(SIGSTOP) | bit(SIGTSTP) | bit(SIGTTIN) | bit(SIGTTOU);
+
+/// Exit code of a thread terminated by a signal, as reported by shells
+pub const fn exit_code(signal: usize) -> usize {
+    128 + signal
+}
+
+const fn bit(signal: usize) -> u32 {
+    1 << signal
+}
+
+/// Layout of the struct user space passes to `sigaction`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SigAction {
+    /// `SIG_DFL`, `SIG_IGN` or the address of `fn(signal: usize)`
+    pub handler: usize,
+    /// Signals blocked while the handler runs, on top of the signal itself
+    pub mask: u32,
+    /// Reserved, must be zero
+    pub flags: u32,
+    /// Where the handler returns to, must call `sigreturn`
+    pub restorer: usize,
+}
+
+impl SigAction {
+    pub const DEFAULT: SigAction = SigAction {
+        handler: SIG_DFL,
+        mask: 0,
+        flags: 0,
+        restorer: 0,
+    };
+}
+
+/// What happens to a signal whose handler is `SIG_DFL`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+pub fn default_action(signal: usize) -> DefaultAction {
+    match signal {
+        SIGCHLD | SIGURG | SIGWINCH => DefaultAction::Ignore,
+        SIGCONT => DefaultAction::Continue,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => DefaultAction::Stop,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+/// Pushed on the user stack when a handler is called and restored by `sigreturn`
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct SignalFrame<F: Frame> {
+    /// The frame the thread was interrupted at
+    pub frame: F,
+    /// Blocked signals of the thread before the handler was called
+    pub blocked: u32,
+}
+
+/// Signal handling state of one process
+#[derive(Debug)]
+pub struct SignalState<ENV: Environment> {
+    actions: [SigAction; NSIG],
+    pending: u32,
+    /// Set by a stop signal. Threads park in `stopped_tasks` when they return to user mode
+    /// until the process is continued
+    stopped: bool,
+    stopped_tasks: WaitQueue<ENV>,
+    /// Signal the process has been terminated by. Every thread exits when it next returns
+    /// to user mode, so threads blocked in the kernel exit once they are woken
+    terminated_by: Option<usize>,
+}
+
+/// What the thread acting on a signal does with it
+pub enum Disposition {
+    Handle(usize, SigAction),
+    Terminate(usize),
+    Stop,
+}
+
+impl<ENV: Environment> SignalState<ENV> {
+    pub fn new() -> Self {
+        Self {
+            actions: [SigAction::DEFAULT; NSIG],
+            pending: 0,
+            stopped: false,
+            stopped_tasks: WaitQueue::new(),
+            terminated_by: None,
+        }
+    }
+
+    /// State of a forked child, which keeps the actions but none of the pending signals
+    pub fn forked(&self) -> Self {
+        Self {
+            actions: self.actions,
+            ..Self::new()
+        }
+    }
+
+    pub fn is_valid(signal: usize) -> bool {
+        signal > 0 && signal < NSIG
+    }
+
+    /// Replaces the action of `signal` and returns the old one, None if the signal can not
+    /// take that action
+    pub fn set_action(&mut self, signal: usize, action: SigAction) -> Option<SigAction> {
+        if !Self::is_valid(signal) || bit(signal) & UNBLOCKABLE != 0 || action.flags != 0 {
+            return None;
+        }
+        let old = core::mem::replace(&mut self.actions[signal], action);
+        // Pending signals that are now ignored are discarded, as if they were never sent
+        if self.is_ignored(signal) {
+            self.pending &= !bit(signal);
+        }
+        Some(old)
+    }
+
+    pub fn action(&self, signal: usize) -> Option<SigAction> {
+        Self::is_valid(signal).then(|| self.actions[signal])
+    }
+
+    fn is_ignored(&self, signal: usize) -> bool {
+        match self.actions[signal].handler {
+            SIG_IGN => true,
+            SIG_DFL => default_action(signal) == DefaultAction::Ignore,
+            _ => false,
+        }
+    }
+
+    /// Queues `signal` on the process. Stopping and continuing take effect right away, the
+    /// rest is acted on by the next thread returning to user mode
+    pub fn send(&mut self, signal: usize, scheduler: &mut Scheduler<ENV>) {
+        match signal {
+            SIGKILL => {
+                self.terminated_by.get_or_insert(SIGKILL);
+                self.resume_stopped(scheduler);
+                return;
+            }
+            SIGCONT => {
+                self.pending &= !STOP_SIGNALS;
+                self.resume_stopped(scheduler);
+            }
+            _ if bit(signal) & STOP_SIGNALS != 0 => self.pending &= !bit(SIGCONT),
+            _ => {}
+        }
+
+        if !self.is_ignored(signal) {
+            self.pending |= bit(signal);
+        }
+    }
+
+    /// Queues a signal raised by a fault of the running thread. A fault the thread can not
+    /// handle terminates the process, so blocking or ignoring it is undone
+    pub fn force(&mut self, signal: usize, blocked: &mut u32) {
+        if *blocked & bit(signal) != 0 || self.actions[signal].handler == SIG_IGN {
+            self.actions[signal] = SigAction::DEFAULT;
+            *blocked &= !bit(signal);
+        }
+        self.pending |= bit(signal);
+    }
+
+    /// Terminates the process without going through the pending signals
+    pub fn terminate(&mut self, signal: usize) {
+        self.terminated_by.get_or_insert(signal);
+    }
+
+    /// Whether a thread blocking `blocked` has a signal to handle or is terminated, so a
+    /// wait it is parked in has to be cut short. Stops take effect once it is woken anyway
+    pub fn interrupts(&self, blocked: u32) -> bool {
+        if self.terminated_by.is_some() {
+            return true;
+        }
+        let deliverable = self.pending & !(blocked & !UNBLOCKABLE);
+        (1..NSIG)
+            .filter(|&signal| deliverable & bit(signal) != 0)
+            .any(|signal| {
+                self.actions[signal].handler != SIG_DFL
+                    || default_action(signal) == DefaultAction::Terminate
+            })
+    }
+
+    fn resume_stopped(&mut self, scheduler: &mut Scheduler<ENV>) {
+        self.stopped = false;
+        self.stopped_tasks.wake_all(scheduler, |_| {});
+    }
+
+    /// Takes the next signal a thread blocking `blocked` has to act on
+    pub fn next(&mut self, blocked: u32) -> Option<Disposition> {
+        loop {
+            if let Some(signal) = self.terminated_by {
+                return Some(Disposition::Terminate(signal));
+            }
+            if self.stopped {
+                return Some(Disposition::Stop);
+            }
+
+            let deliverable = self.pending & !(blocked & !UNBLOCKABLE);
+            if deliverable == 0 {
+                return None;
+            }
+            let signal = deliverable.trailing_zeros() as usize;
+            self.pending &= !bit(signal);
+
+            let action = self.actions[signal];
+            match action.handler {
+                SIG_IGN => {}
+                SIG_DFL => match default_action(signal) {
+                    DefaultAction::Terminate => self.terminated_by = Some(signal),
+                    DefaultAction::Stop => self.stopped = true,
+                    DefaultAction::Ignore | DefaultAction::Continue => {}
+                },
+                _ => return Some(Disposition::Handle(signal, action)),
+            }
+        }
+    }
+}
+
+impl<ENV: Environment> Default for SignalState<ENV> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<ENV: Environment> Kernel<ENV> {
+    /// Acts on the signals of the running task before it returns to user mode at `frame`.
+    /// Calling a handler rewrites `frame`, terminating or stopping the task does not return.
+    /// Must be called with interrupts disabled and the task's page table active
+    pub(crate) fn deliver_signals(&self, frame: &mut ENV::Frame) {
+        if !frame.is_user_mode() {
+            return;
+        }
+
+        'deliver: loop {
+            let disposition = 'locked: {
+                let running_task = self.current_running.borrow();
+                let running_task = running_task.as_ref().expect("No task running on this core");
+                let mut process = running_task.process.lock();
+                // Whatever the task waited in is over once it returns to user mode
+                let blocked = match process.threads.get_mut(&running_task.id) {
+                    Some(thread) => {
+                        thread.wait = None;
+                        thread.blocked
+                    }
+                    None => 0,
+                };
+                let Some(disposition) = process.signals.next(blocked) else {
+                    return;
+                };
+
+                let Disposition::Handle(signal, action) = disposition else {
+                    break 'locked disposition;
+                };
+                let size = core::mem::size_of::<SignalFrame<ENV::Frame>>();
+                // Keep the stack aligned the way the calling convention expects
+                let sp = frame.stack_pointer().wrapping_sub(size) & !15;
+                if !process.memory.contains(sp, size) {
+                    process.signals.terminate(SIGSEGV);
+                    continue 'deliver;
+                }
+
+                let signal_frame = SignalFrame {
+                    frame: frame.clone(),
+                    blocked,
+                };
+                // SAFETY: The range was checked to be user memory of the active page table
+                unsafe {
+                    core::ptr::write_unaligned(sp as *mut SignalFrame<ENV::Frame>, signal_frame)
+                };
+                if let Some(thread) = process.threads.get_mut(&running_task.id) {
+                    thread.blocked |= action.mask | bit(signal);
+                }
+
+                frame.set_pc(action.handler);
+                frame.set_argument(signal);
+                frame.set_stack_pointer(sp);
+                frame.set_return_address(action.restorer);
+                return;
+            };
+
+            match disposition {
+                Disposition::Terminate(signal) => self.exit_current(frame, exit_code(signal)),
+                Disposition::Stop => {
+                    let task = self.take_current(frame);
+                    let process = task.process.clone();
+                    let mut process_guard = process.lock();
+                    if process_guard.signals.stopped {
+                        process_guard.signals.stopped_tasks.push(task);
+                        drop(process_guard);
+                        drop(process);
+                        self.schedule_next();
+                    }
+                    // Continued before the task could park, so it carries on
+                    drop(process_guard);
+                    drop(process);
+                    self.current_running.replace(Some(task));
+                }
+                Disposition::Handle(..) => unreachable!(),
+            }
+        }
+    }
+
+    /// Records that thread `id` of `process` has been parked in `wait`, so a signal sent to
+    /// the process can interrupt it. A signal that arrived while the task was being parked
+    /// interrupts it right away
+    pub(crate) fn interruptible(&self, process: &Mutex<Process<ENV>>, id: TaskId, wait: Wait<ENV>) {
+        let mut process = process.lock();
+        let process = &mut *process;
+        let Some(thread) = process.threads.get_mut(&id) else {
+            return;
+        };
+        // The task may have been woken and parked again on another core already, tickets
+        // only grow so the newer wait is kept
+        if thread.wait.as_ref().is_some_and(|recorded| recorded.ticket > wait.ticket) {
+            return;
+        }
+        if process.signals.interrupts(thread.blocked) {
+            self.cancel_wait(process, wait);
+        } else {
+            thread.wait = Some(wait);
+        }
+    }
+
+    /// Pulls every thread of `process` that has to act on a pending signal out of the wait it
+    /// is parked in. Called after sending a signal, with no other lock held
+    pub(crate) fn interrupt_waits(&self, process: &mut Process<ENV>) {
+        let signals = &process.signals;
+        let waits: Vec<Wait<ENV>> = process
+            .threads
+            .values_mut()
+            .filter(|thread| signals.interrupts(thread.blocked))
+            .filter_map(|thread| thread.wait.take())
+            .collect();
+        for wait in waits {
+            self.cancel_wait(process, wait);
+        }
+    }
+
+    /// Puts the task parked in `wait` back on the run queue, unless it was woken already.
+    /// Object locks are taken before the scheduler lock, like everywhere else
+    fn cancel_wait(&self, process: &mut Process<ENV>, wait: Wait<ENV>) {
+        let ticket = wait.ticket;
+        let task = match wait.on {
+            WaitOn::Object(object) => object.cancel_wait(ticket),
+            WaitOn::Join(id) => process
+                .threads
+                .get_mut(&id)
+                .and_then(|thread| thread.joiners.remove(ticket)),
+            WaitOn::Futex(key) => self.scheduler.lock().futexes.remove(key, ticket),
+            WaitOn::Child(parent) => self.scheduler.lock().cancel_child_wait(parent, ticket),
+            WaitOn::Sleep => self.scheduler.lock().sleepers.remove(ticket),
+        };
+        if let Some(mut task) = task {
+            if !wait.restarts {
+                task.frame.set_error(Errno::EINTR.as_usize());
+            }
+            self.scheduler.lock().add_task(task);
+        }
+    }
+
+    /// Sends `signal` to the running task's process because of a fault at `frame` and returns
+    /// to user mode, where the signal is acted on
+    pub(crate) fn raise_fault(&self, frame: &ENV::Frame, signal: usize) -> ! {
+        ENV::Dispatch::deactivate_irq();
+        {
+            let running_task = self.current_running.borrow();
+            let running_task = running_task.as_ref().expect("No task running on this core");
+            let mut process = running_task.process.lock();
+            let process = &mut *process;
+            match process.threads.get_mut(&running_task.id) {
+                Some(thread) => process.signals.force(signal, &mut thread.blocked),
+                None => process.signals.force(signal, &mut 0),
+            }
+        }
+        self.resume(frame);
+    }
+}
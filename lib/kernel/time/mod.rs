@@ -0,0 +1,114 @@
+//! Kernel timekeeping and per-core timers.
+
+extern crate alloc;
+
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+use crate::kernel::{
+    environment::{Clock, Environment, Frame},
+    trap::syscall::Errno,
+    Kernel,
+};
+
+mod process_timer;
+mod timer_queue;
+pub use process_timer::*;
+pub use timer_queue::*;
+
+pub const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// How long a task may run before it is preempted
+pub const SCHEDULER_TICK_NANOS: u64 = 10_000_000;
+
+/// Used until `init` is called, matches the QEMU virt machine
+const DEFAULT_TIMEBASE_FREQUENCY: usize = 10_000_000;
+
+static TIMEBASE_FREQUENCY: AtomicUsize = AtomicUsize::new(DEFAULT_TIMEBASE_FREQUENCY);
+
+/// Sets the frequency in Hz of the clock returned by `Clock::now`
+pub fn init(timebase_frequency: usize) {
+    assert!(timebase_frequency > 0, "Timebase frequency can not be zero");
+    TIMEBASE_FREQUENCY.store(timebase_frequency, SeqCst);
+}
+
+pub fn timebase_frequency() -> u64 {
+    TIMEBASE_FREQUENCY.load(SeqCst) as u64
+}
+
+pub fn ticks_to_nanos(ticks: u64) -> u64 {
+    (ticks as u128 * NANOS_PER_SEC as u128 / timebase_frequency() as u128) as u64
+}
+
+/// Converts a duration to clock ticks, rounding up so a timer never fires early
+pub fn nanos_to_ticks(nanos: u64) -> u64 {
+    let ticks = (nanos as u128 * timebase_frequency() as u128).div_ceil(NANOS_PER_SEC as u128);
+    ticks.min(u64::MAX as u128) as u64
+}
+
+/// Nanoseconds since the clock started counting, usually since the machine was reset
+pub fn monotonic_nanos<ENV: Environment>() -> u64 {
+    ticks_to_nanos(ENV::Clock::now())
+}
+
+impl<ENV: Environment> Kernel<ENV> {
+    /// Queues `event` to run on this core once the clock reaches `deadline`.
+    /// Interrupts must be disabled, the timer interrupt handler borrows the same queue
+    pub fn add_timer(&self, deadline: u64, event: TimerEvent<ENV>) {
+        let mut timers = self.timers.borrow_mut();
+        timers.insert(deadline, event);
+        if let Some(next) = timers.next_deadline() {
+            ENV::Clock::set_deadline(next);
+        }
+    }
+
+    /// Runs every expired timer on this core and programs the clock for the next one.
+    /// Returns true if the running task has used up its time slice
+    pub fn run_timers(&self) -> bool {
+        let now = ENV::Clock::now();
+        let mut preempt = false;
+        let mut timers = self.timers.borrow_mut();
+
+        while let Some((deadline, event)) = timers.pop_expired(now) {
+            match event {
+                TimerEvent::SchedulerTick => {
+                    preempt = true;
+                    timers.insert(
+                        now.saturating_add(nanos_to_ticks(SCHEDULER_TICK_NANOS)),
+                        TimerEvent::SchedulerTick,
+                    );
+                }
+                TimerEvent::Wake(ticket) => {
+                    let mut scheduler = self.scheduler.lock();
+                    if let Some(task) = scheduler.sleepers.remove(ticket) {
+                        scheduler.add_task(task);
+                    }
+                }
+                TimerEvent::Process { timer, generation } => {
+                    // Object locks are always taken before the scheduler lock
+                    let next = {
+                        let mut timer_guard = timer.lock();
+                        timer_guard.fire(generation, deadline, now, &mut self.scheduler.lock())
+                    };
+                    if let Some(next) = next {
+                        timers.insert(next, TimerEvent::Process { timer, generation });
+                    }
+                }
+                TimerEvent::WaitTimeout { object, ticket } => {
+                    if let Some(task) = object.cancel_wait(ticket) {
+                        self.scheduler.lock().add_task(task);
+                    }
+                }
+                TimerEvent::FutexTimeout { key, ticket } => {
+                    let mut scheduler = self.scheduler.lock();
+                    if let Some(mut task) = scheduler.futexes.remove(key, ticket) {
+                        task.frame.set_error(Errno::ETIMEDOUT.as_usize());
+                        scheduler.add_task(task);
+                    }
+                }
+            }
+        }
+
+        ENV::Clock::set_deadline(timers.next_deadline().unwrap_or(u64::MAX));
+        preempt
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{
+    collections::mutex::Mutex,
+    kernel::{
+        environment::{Environment, Frame},
+        scheduler::{CancelWait, Scheduler, Task, WaitQueue, WaitTicket},
+        trap::syscall::Errno,
+    },
+};
+
+/// One-shot or periodic timer owned by a process, user space blocks on it with `timer_wait`
+#[derive(Debug)]
+pub struct ProcessTimer<ENV: Environment> {
+    /// Bumped every time the timer is re-armed or disarmed, which invalidates queued expiries
+    generation: usize,
+    armed: bool,
+    /// Ticks between expiries, zero for a one-shot timer
+    period: u64,
+    /// Expiries not yet reported to user space
+    expirations: usize,
+    waiters: WaitQueue<ENV>,
+}
+
+impl<ENV: Environment> ProcessTimer<ENV> {
+    pub fn new() -> Self {
+        Self {
+            generation: 0,
+            armed: false,
+            period: 0,
+            expirations: 0,
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    /// Arms the timer with a new period, dropping pending expiries. Returns the generation the
+    /// caller must queue the first expiry with
+    pub fn arm(&mut self, period: u64) -> usize {
+        self.generation = self.generation.wrapping_add(1);
+        self.armed = true;
+        self.period = period;
+        self.expirations = 0;
+        self.generation
+    }
+
+    /// Stops the timer, tasks blocked in `timer_wait` are woken with an error
+    pub fn disarm(&mut self, scheduler: &mut Scheduler<ENV>) {
+        self.generation = self.generation.wrapping_add(1);
+        self.armed = false;
+        self.expirations = 0;
+        self.waiters.wake_all(scheduler, |task| {
+            task.frame.set_error(Errno::EINVAL.as_usize());
+        });
+    }
+
+    /// Returns and clears the expiries since the last call
+    pub fn take_expirations(&mut self) -> usize {
+        core::mem::take(&mut self.expirations)
+    }
+
+    /// Parks `task` until the next expiry
+    pub fn wait(&mut self, task: Task<ENV>) -> WaitTicket {
+        self.waiters.push(task)
+    }
+
+    /// Handles an expiry queued for `deadline`. Returns the deadline of the next expiry if the
+    /// timer is periodic
+    pub fn fire(
+        &mut self,
+        generation: usize,
+        deadline: u64,
+        now: u64,
+        scheduler: &mut Scheduler<ENV>,
+    ) -> Option<u64> {
+        if !self.armed || generation != self.generation {
+            return None;
+        }
+
+        // A periodic timer that fell behind counts every period it missed
+        let missed = (now - deadline)
+            .checked_div(self.period)
+            .map_or(1, |behind| behind + 1);
+        self.expirations = self.expirations.saturating_add(missed as usize);
+
+        if !self.waiters.is_empty() {
+            let expirations = self.take_expirations();
+            self.waiters.wake_all(scheduler, |task| {
+                task.frame.set_success(expirations, 0);
+            });
+        }
+
+        if self.period == 0 {
+            self.armed = false;
+            None
+        } else {
+            Some(deadline.saturating_add(missed * self.period))
+        }
+    }
+}
+
+impl<ENV: Environment> Default for ProcessTimer<ENV> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<ENV: Environment> CancelWait<ENV> for Mutex<ProcessTimer<ENV>> {
+    fn cancel_wait(&self, ticket: WaitTicket) -> Option<Task<ENV>> {
+        self.lock().waiters.remove(ticket)
+    }
+}
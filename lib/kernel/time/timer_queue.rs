@@ -0,0 +1,108 @@
+extern crate alloc;
+
+use core::cmp::Ordering;
+
+use alloc::{collections::BinaryHeap, sync::Arc};
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::{
+        environment::Environment,
+        scheduler::{CancelWait, WaitTicket},
+        time::ProcessTimer,
+    },
+};
+
+#[derive(Debug)]
+pub enum TimerEvent<ENV: Environment> {
+    /// The running task has used up its time slice
+    SchedulerTick,
+    /// Puts a task in `sleep` back on the run queue, ignored if a signal woke it first
+    Wake(WaitTicket),
+    /// Expiry of a timer created by a process, ignored if the timer was re-armed or
+    /// deleted since this event was queued
+    Process {
+        timer: Arc<Mutex<ProcessTimer<ENV>>>,
+        generation: usize,
+    },
+    /// Ends a `futex_wait` with a timeout, ignored if the task was woken first
+    FutexTimeout { key: usize, ticket: WaitTicket },
+    /// Puts a task blocked on `object` back on the run queue, ignored if it was woken first.
+    /// The task restarts its syscall, which notices that its deadline has passed
+    WaitTimeout {
+        object: Arc<dyn CancelWait<ENV>>,
+        ticket: WaitTicket,
+    },
+}
+
+#[derive(Debug)]
+struct TimerEntry<ENV: Environment> {
+    deadline: u64,
+    /// Keeps timers with the same deadline in insertion order
+    sequence: usize,
+    event: TimerEvent<ENV>,
+}
+
+impl<ENV: Environment> PartialEq for TimerEntry<ENV> {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline && self.sequence == other.sequence
+    }
+}
+
+impl<ENV: Environment> Eq for TimerEntry<ENV> {}
+
+impl<ENV: Environment> PartialOrd for TimerEntry<ENV> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<ENV: Environment> Ord for TimerEntry<ENV> {
+    // Reversed so the binary heap, which is a max heap, pops the earliest deadline first
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.deadline, other.sequence).cmp(&(self.deadline, self.sequence))
+    }
+}
+
+/// Pending timers of one core, ordered by deadline
+#[derive(Debug)]
+pub struct TimerQueue<ENV: Environment> {
+    entries: BinaryHeap<TimerEntry<ENV>>,
+    sequence: usize,
+}
+
+impl<ENV: Environment> TimerQueue<ENV> {
+    pub fn new() -> Self {
+        Self {
+            entries: BinaryHeap::new(),
+            sequence: 0,
+        }
+    }
+
+    pub fn insert(&mut self, deadline: u64, event: TimerEvent<ENV>) {
+        self.sequence = self.sequence.wrapping_add(1);
+        self.entries.push(TimerEntry {
+            deadline,
+            sequence: self.sequence,
+            event,
+        });
+    }
+
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.entries.peek().map(|entry| entry.deadline)
+    }
+
+    /// Removes the earliest timer if its deadline is at or before `now`
+    pub fn pop_expired(&mut self, now: u64) -> Option<(u64, TimerEvent<ENV>)> {
+        if self.next_deadline()? > now {
+            return None;
+        }
+        self.entries.pop().map(|entry| (entry.deadline, entry.event))
+    }
+}
+
+impl<ENV: Environment> Default for TimerQueue<ENV> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
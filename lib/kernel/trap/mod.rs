@@ -1,12 +1,10 @@
-use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
-
 use crate::{
     arch::riscv::csr::Sstatus,
     kernel::{
         environment::{Dispatch, DispatchLevel, Environment, Frame, PageTable},
         process::ProcessId,
         scheduler::Pin,
-        Kernel,
+        signal, Kernel,
     },
 };
 
@@ -14,9 +12,13 @@ pub mod syscall;
 
 #[derive(Debug)]
 pub enum TrapReason {
-    SysCall(usize, usize, usize, usize),
+    /// Syscall number from a7 and the argument registers a0 to a5
+    SysCall(usize, [usize; 6]),
     SegFault { pc_addr: usize, addr: usize },
+    IllegalInstruction { pc_addr: usize },
     Timer,
+    /// A device raised an interrupt
+    External,
     KernelYield,
 }
 
@@ -25,31 +27,48 @@ pub struct TrapCtx<'a, ENV: Environment> {
     pub reason: TrapReason,
 }
 
-static TIMER_INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
-
 impl<ENV: Environment> Kernel<ENV> {
     pub fn trap(&self, frame: &mut ENV::Frame, reason: TrapReason) -> ! {
         let ctx = TrapCtx { frame, reason };
 
         match ctx.reason {
-            TrapReason::SysCall(r1, r2, r3, r4) => {
+            TrapReason::SysCall(number, args) => {
                 ENV::Dispatch::activate_irq();
-                let v = syscall::SystemCall::from_regs(r1, r2, r3, r4).unwrap();
-                syscall::trap_syscall(self, v, ctx)
+                match syscall::SystemCall::decode(number, args) {
+                    Ok(call) => syscall::trap_syscall(self, call, ctx),
+                    Err(errno) => syscall::complete(self, ctx.frame, Err(errno)),
+                }
             }
             TrapReason::SegFault { pc_addr, addr } => {
+                if ctx.frame.is_user_mode() {
+                    self.raise_fault(ctx.frame, signal::SIGSEGV);
+                }
                 panic!(
                     "Seg fault: frame {:#x?} 0x{:x} 0x{:x}, core: {}",
                     frame, pc_addr, addr, self.core
                 );
             }
+            TrapReason::IllegalInstruction { pc_addr } => {
+                if ctx.frame.is_user_mode() {
+                    self.raise_fault(ctx.frame, signal::SIGILL);
+                }
+                panic!(
+                    "Illegal instruction: frame {:#x?} 0x{:x}, core: {}",
+                    frame, pc_addr, self.core
+                );
+            }
+            TrapReason::External => {
+                crate::drivers::irq::handle(self.core);
+                // Tasks the handlers woke run once a core picks them
+                unsafe {
+                    ENV::Dispatch::dispatch(ctx.frame);
+                }
+            }
             TrapReason::KernelYield => {
                 self.context_switch(ctx.frame);
             }
             TrapReason::Timer => {
-                let old = TIMER_INTERRUPTS.fetch_add(1, SeqCst);
-                if old > 1000 {
-                    TIMER_INTERRUPTS.store(0, SeqCst);
+                if self.run_timers() {
                     if ctx.frame.is_user_mode() {
                         ENV::Dispatch::deactivate_irq();
                         {
@@ -61,20 +80,7 @@ impl<ENV: Environment> Kernel<ENV> {
                     } else {
                         ENV::Dispatch::deactivate_irq();
                         self.kernel_yield();
-                        {
-                            let running_task = self.current_running.borrow_mut();
-                            let running_task = running_task.as_ref().unwrap();
-                            let running_process = running_task.process.try_lock().unwrap();
-                            unsafe {
-                                ENV::PageTable::activate(&running_process.memory.page_table);
-                                self.stack
-                                    .get()
-                                    .write(running_task.stack.get_base().cast_mut());
-                            }
-                        };
-                        unsafe {
-                            ENV::Dispatch::dispatch(&ctx.frame);
-                        }
+                        self.resume(ctx.frame);
                     }
                 } else {
                     unsafe {
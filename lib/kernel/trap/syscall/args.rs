@@ -0,0 +1,79 @@
+use crate::kernel::{process::ProcessId, scheduler::TaskId, trap::syscall::Errno};
+
+/// The six argument registers of a syscall, consumed in order while decoding
+pub struct SyscallArgs {
+    regs: [usize; 6],
+    next: usize,
+}
+
+impl SyscallArgs {
+    pub fn new(regs: [usize; 6]) -> Self {
+        Self { regs, next: 0 }
+    }
+
+    /// Takes the next register, a call using more registers than there are is a bug in the
+    /// syscall table
+    pub fn take(&mut self) -> usize {
+        let value = self.regs[self.next];
+        self.next += 1;
+        value
+    }
+}
+
+/// A syscall argument type that can be decoded from the argument registers
+pub trait FromArgs: Sized {
+    fn from_args(args: &mut SyscallArgs) -> Result<Self, Errno>;
+}
+
+impl FromArgs for usize {
+    fn from_args(args: &mut SyscallArgs) -> Result<Self, Errno> {
+        Ok(args.take())
+    }
+}
+
+impl FromArgs for u32 {
+    fn from_args(args: &mut SyscallArgs) -> Result<Self, Errno> {
+        Ok(args.take() as u32)
+    }
+}
+
+/// 64 bit arguments are passed in two registers with the low word first
+impl FromArgs for u64 {
+    fn from_args(args: &mut SyscallArgs) -> Result<Self, Errno> {
+        let low = args.take() as u32 as u64;
+        let high = args.take() as u64;
+        Ok((high << 32) | low)
+    }
+}
+
+impl FromArgs for char {
+    fn from_args(args: &mut SyscallArgs) -> Result<Self, Errno> {
+        char::from_u32(args.take() as u32).ok_or(Errno::EINVAL)
+    }
+}
+
+/// User pointers are only decoded here, they are checked against the process memory by the
+/// handler using them
+impl<T> FromArgs for *const T {
+    fn from_args(args: &mut SyscallArgs) -> Result<Self, Errno> {
+        Ok(args.take() as *const T)
+    }
+}
+
+impl<T> FromArgs for *mut T {
+    fn from_args(args: &mut SyscallArgs) -> Result<Self, Errno> {
+        Ok(args.take() as *mut T)
+    }
+}
+
+impl FromArgs for TaskId {
+    fn from_args(args: &mut SyscallArgs) -> Result<Self, Errno> {
+        Ok(TaskId::from(args.take()))
+    }
+}
+
+impl FromArgs for ProcessId {
+    fn from_args(args: &mut SyscallArgs) -> Result<Self, Errno> {
+        Ok(ProcessId::from(args.take()))
+    }
+}
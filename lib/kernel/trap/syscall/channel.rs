@@ -0,0 +1,156 @@
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use crate::kernel::{
+    environment::{Clock, Dispatch, Environment, Frame},
+    fd::{ChannelEndpoint, FileError, Message, UserMessage},
+    time::{self, TimerEvent},
+    trap::syscall::{file_result, park, Errno, SyscallResult, SyscallReturn},
+    Kernel,
+};
+
+/// `channel_create` flag for a channel whose senders block until their message is received
+pub const CHANNEL_SYNCHRONOUS: usize = 1 << 0;
+
+/// Creates a channel, returns the descriptors of its two endpoints in a0 and a1
+pub(super) fn channel_create<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    flags: usize,
+) -> SyscallResult {
+    if flags & !CHANNEL_SYNCHRONOUS != 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    let synchronous = flags & CHANNEL_SYNCHRONOUS != 0;
+    let (a, b) = ChannelEndpoint::pair(synchronous, kernel.scheduler.clone());
+    let (a_fd, b_fd) = kernel.with_process(|process| {
+        let a_fd = process.files.insert(Arc::new(a))?;
+        match process.files.insert(Arc::new(b)) {
+            Ok(b_fd) => Ok((a_fd, b_fd)),
+            Err(e) => {
+                let _ = process.files.close(a_fd);
+                Err(Errno::from(e))
+            }
+        }
+    })?;
+    Ok(SyscallReturn::Pair(a_fd, b_fd))
+}
+
+pub(super) fn channel_send<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    frame: &mut ENV::Frame,
+    fd: usize,
+    message: *const UserMessage,
+) -> SyscallResult {
+    let prepared = kernel.with_process(|process| {
+        let file = process.files.get(fd)?;
+        // SAFETY: The page table of the running process is active during the syscall
+        let user = unsafe { process.memory.read(message) }.map_err(|_| FileError::BadAddress)?;
+        let handle = match user.handle {
+            UserMessage::NO_HANDLE => None,
+            handle => Some(process.files.get(handle as usize)?),
+        };
+        Ok((file, Message { data: user.data, handle }))
+    });
+
+    ENV::Dispatch::deactivate_irq();
+    file_result(prepared.and_then(|(file, message)| {
+        let endpoint = file.as_channel().ok_or(FileError::NotSupported)?;
+        if endpoint.is_synchronous() {
+            // The receiver sets the return value when it takes the message
+            park(kernel, frame, |sender| match endpoint.send(message, Some(sender)) {
+                Ok(wait) => Ok(wait.expect("a synchronous sender is parked")),
+                Err((e, sender)) => {
+                    let mut sender = sender.expect("a failed send hands the sender back");
+                    sender.frame.set_error(Errno::from(e).as_usize());
+                    Err(sender)
+                }
+            });
+            return Err(FileError::WouldBlock);
+        }
+
+        match endpoint.send(message, None) {
+            Ok(_) => Ok(0),
+            Err((FileError::WouldBlock, _)) => {
+                frame.restart_syscall();
+                park(kernel, frame, |task| endpoint.wait_sendable(task));
+                Err(FileError::WouldBlock)
+            }
+            Err((e, _)) => Err(e),
+        }
+    }))
+}
+
+/// Receives a message into `message`. `deadline` points to an absolute `CLOCK_MONOTONIC`
+/// time in nanoseconds, a null deadline waits forever
+pub(super) fn channel_recv<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    frame: &mut ENV::Frame,
+    fd: usize,
+    message: *mut UserMessage,
+    deadline: *const u64,
+) -> SyscallResult {
+    let prepared = kernel.with_process(|process| {
+        let file = process.files.get(fd)?;
+        if !process.memory.contains(message as usize, core::mem::size_of::<UserMessage>()) {
+            return Err(FileError::BadAddress);
+        }
+        let deadline = if deadline.is_null() {
+            None
+        } else {
+            // SAFETY: The page table of the running process is active during the syscall
+            let nanos =
+                unsafe { process.memory.read(deadline) }.map_err(|_| FileError::BadAddress)?;
+            Some(time::nanos_to_ticks(nanos))
+        };
+        Ok((file, deadline))
+    });
+
+    file_result(prepared.and_then(|(file, deadline)| {
+        let endpoint = file.as_channel().ok_or(FileError::NotSupported)?;
+
+        // The received handle needs a descriptor, so the message is only taken once a
+        // free one is guaranteed. No one else can open one while the process is locked
+        let received = kernel.with_process(|process| {
+            if process.files.is_full() {
+                return Err(FileError::TooManyFiles);
+            }
+            let received = endpoint.recv()?;
+            let handle = match received.handle {
+                Some(handle) => process.files.insert(handle)? as i32,
+                None => UserMessage::NO_HANDLE,
+            };
+            let user = UserMessage {
+                data: received.data,
+                handle,
+            };
+            // SAFETY: The range was checked to be user memory above
+            unsafe { core::ptr::write_unaligned(message, user) };
+            Ok(0)
+        });
+
+        ENV::Dispatch::deactivate_irq();
+        match received {
+            Err(FileError::WouldBlock) => {
+                // Deadlines are absolute, so a restarted receive keeps its deadline
+                if deadline.is_some_and(|deadline| ENV::Clock::now() >= deadline) {
+                    return Err(FileError::TimedOut);
+                }
+                frame.restart_syscall();
+                park(kernel, frame, |task| {
+                    let wait = endpoint.wait_receivable(task)?;
+                    if let Some(deadline) = deadline {
+                        let object = endpoint.receive_queue();
+                        let ticket = wait.ticket;
+                        kernel.add_timer(deadline, TimerEvent::WaitTimeout { object, ticket });
+                    }
+                    Ok(wait)
+                });
+                Err(FileError::WouldBlock)
+            }
+            result => result,
+        }
+    }))
+}
@@ -0,0 +1,157 @@
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::{
+        environment::{Clock, Dispatch, Environment, Frame},
+        scheduler::{Wait, WaitOn},
+        time::{self, ProcessTimer, TimerEvent},
+        trap::syscall::{park, Errno, SyscallResult, SyscallReturn},
+        Kernel,
+    },
+};
+
+/// Clock id accepted by `clock_gettime`, matches `CLOCK_MONOTONIC` on Linux
+pub const CLOCK_MONOTONIC: usize = 1;
+
+/// Layout of the struct user space passes to `timer_settime`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct TimerSpec {
+    /// Nanoseconds until the first expiry, zero disarms the timer
+    pub initial_nanos: u64,
+    /// Nanoseconds between expiries after the first, zero for a one-shot timer
+    pub period_nanos: u64,
+}
+
+pub(super) fn sleep<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    frame: &mut ENV::Frame,
+    nanos: u64,
+) -> SyscallResult {
+    frame.set_success(0, 0);
+    let deadline = ENV::Clock::now().saturating_add(time::nanos_to_ticks(nanos));
+    park(kernel, frame, |task| {
+        let ticket = kernel.scheduler.lock().sleepers.push(task);
+        kernel.add_timer(deadline, TimerEvent::Wake(ticket));
+        Ok(Wait {
+            on: WaitOn::Sleep,
+            ticket,
+            restarts: false,
+        })
+    });
+    Ok(SyscallReturn::Blocked)
+}
+
+/// Returns the time of `clock` in nanoseconds, the low word in a0 and the high word in a1
+pub(super) fn clock_gettime<ENV: Environment>(
+    _kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    clock: usize,
+) -> SyscallResult {
+    if clock != CLOCK_MONOTONIC {
+        return Err(Errno::EINVAL);
+    }
+    let nanos = time::monotonic_nanos::<ENV>();
+    Ok(SyscallReturn::Pair(nanos as u32 as usize, (nanos >> 32) as usize))
+}
+
+pub(super) fn timer_create<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+) -> SyscallResult {
+    let id = kernel.with_process(|process| {
+        let timer = Some(Arc::new(Mutex::new(ProcessTimer::new())));
+        match process.timers.iter().position(|t| t.is_none()) {
+            Some(id) => {
+                process.timers[id] = timer;
+                id
+            }
+            None => {
+                process.timers.push(timer);
+                process.timers.len() - 1
+            }
+        }
+    });
+    Ok(SyscallReturn::Value(id))
+}
+
+pub(super) fn timer_settime<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    id: usize,
+    spec: *const TimerSpec,
+) -> SyscallResult {
+    let (timer, spec) = kernel.with_process(|process| {
+        let timer = process.timer(id).ok_or(Errno::EINVAL)?;
+        // SAFETY: The page table of the running process is active during the syscall
+        let spec = unsafe { process.memory.read(spec) }.map_err(|_| Errno::EFAULT)?;
+        Ok::<_, Errno>((timer, spec))
+    })?;
+
+    ENV::Dispatch::deactivate_irq();
+    let mut timer_guard = timer.lock();
+    if spec.initial_nanos == 0 {
+        timer_guard.disarm(&mut kernel.scheduler.lock());
+    } else {
+        // Periods shorter than a tick would fire on every interrupt
+        let period = match spec.period_nanos {
+            0 => 0,
+            nanos => time::nanos_to_ticks(nanos).max(1),
+        };
+        let generation = timer_guard.arm(period);
+        let deadline =
+            ENV::Clock::now().saturating_add(time::nanos_to_ticks(spec.initial_nanos));
+        drop(timer_guard);
+        kernel.add_timer(deadline, TimerEvent::Process { timer, generation });
+    }
+    Ok(SyscallReturn::Value(0))
+}
+
+/// Returns the expiries since the last call, blocks until the next one if there are none
+pub(super) fn timer_wait<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    frame: &mut ENV::Frame,
+    id: usize,
+) -> SyscallResult {
+    let timer = kernel
+        .with_process(|process| process.timer(id))
+        .ok_or(Errno::EINVAL)?;
+
+    ENV::Dispatch::deactivate_irq();
+    let mut timer_guard = timer.lock();
+    let expirations = timer_guard.take_expirations();
+    if expirations > 0 {
+        return Ok(SyscallReturn::Value(expirations));
+    }
+    if !timer_guard.is_armed() {
+        return Err(Errno::EINVAL);
+    }
+    // The return value is filled in by whoever wakes the task
+    park(kernel, frame, |task| {
+        let ticket = timer_guard.wait(task);
+        drop(timer_guard);
+        Ok(Wait {
+            on: WaitOn::Object(timer.clone()),
+            ticket,
+            restarts: false,
+        })
+    });
+    Ok(SyscallReturn::Blocked)
+}
+
+pub(super) fn timer_delete<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    id: usize,
+) -> SyscallResult {
+    let timer = kernel
+        .with_process(|process| process.timers.get_mut(id).and_then(|t| t.take()))
+        .ok_or(Errno::EINVAL)?;
+
+    ENV::Dispatch::deactivate_irq();
+    timer.lock().disarm(&mut kernel.scheduler.lock());
+    Ok(SyscallReturn::Value(0))
+}
@@ -0,0 +1,123 @@
+use crate::kernel::{fd::FileError, process::ImageError, vfs::FsError};
+
+/// Error codes returned to user space in a0 when a syscall fails, numbered like Linux
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Errno {
+    /// The operation is not permitted
+    EPERM = 1,
+    /// No such file or directory
+    ENOENT = 2,
+    /// No such process or thread
+    ESRCH = 3,
+    /// The call was interrupted
+    EINTR = 4,
+    /// The device failed
+    EIO = 5,
+    /// The file is not a program that can be run
+    ENOEXEC = 8,
+    /// The descriptor is not open
+    EBADF = 9,
+    /// The process has no child to wait for
+    ECHILD = 10,
+    /// Try again, e.g. the futex word did not hold the expected value
+    EAGAIN = 11,
+    /// Out of memory
+    ENOMEM = 12,
+    /// A pointer is outside of the process memory
+    EFAULT = 14,
+    /// The object is in use
+    EBUSY = 16,
+    /// The object already exists
+    EEXIST = 17,
+    /// Renaming across filesystems
+    EXDEV = 18,
+    /// A path component is not a directory
+    ENOTDIR = 20,
+    /// The operation does not work on a directory
+    EISDIR = 21,
+    /// An argument is out of range
+    EINVAL = 22,
+    /// The process has run out of descriptors
+    EMFILE = 24,
+    /// No space left on the device
+    ENOSPC = 28,
+    /// Seeking on a pipe, channel or the console
+    ESPIPE = 29,
+    /// The filesystem is read only
+    EROFS = 30,
+    /// The other end of a pipe or channel has been closed
+    EPIPE = 32,
+    /// The result does not fit the buffer
+    ERANGE = 34,
+    /// The call would wait on itself, e.g. a thread joining itself
+    EDEADLK = 35,
+    /// A path or name is too long
+    ENAMETOOLONG = 36,
+    /// No syscall with this number
+    ENOSYS = 38,
+    /// The directory still has entries
+    ENOTEMPTY = 39,
+    /// A path leads through too many symbolic links
+    ELOOP = 40,
+    /// The object does not support the operation
+    EOPNOTSUPP = 95,
+    /// A blocking call reached its deadline
+    ETIMEDOUT = 110,
+}
+
+impl Errno {
+    pub fn as_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl From<FileError> for Errno {
+    fn from(error: FileError) -> Self {
+        match error {
+            FileError::BadDescriptor => Errno::EBADF,
+            FileError::NotSupported => Errno::EOPNOTSUPP,
+            FileError::BadAddress => Errno::EFAULT,
+            FileError::TooManyFiles => Errno::EMFILE,
+            FileError::Io => Errno::EIO,
+            FileError::WouldBlock => Errno::EAGAIN,
+            FileError::BrokenPipe => Errno::EPIPE,
+            FileError::TimedOut => Errno::ETIMEDOUT,
+            FileError::OutOfMemory => Errno::ENOMEM,
+            FileError::InvalidArgument => Errno::EINVAL,
+        }
+    }
+}
+
+impl From<ImageError> for Errno {
+    fn from(error: ImageError) -> Self {
+        match error {
+            ImageError::Invalid => Errno::ENOEXEC,
+            ImageError::OutOfMemory => Errno::ENOMEM,
+        }
+    }
+}
+
+impl From<FsError> for Errno {
+    fn from(error: FsError) -> Self {
+        match error {
+            FsError::NotFound => Errno::ENOENT,
+            FsError::NotDirectory => Errno::ENOTDIR,
+            FsError::IsDirectory => Errno::EISDIR,
+            FsError::AlreadyExists => Errno::EEXIST,
+            FsError::NotEmpty => Errno::ENOTEMPTY,
+            FsError::ReadOnly => Errno::EROFS,
+            FsError::NoSpace => Errno::ENOSPC,
+            FsError::Busy => Errno::EBUSY,
+            FsError::CrossDevice => Errno::EXDEV,
+            FsError::InvalidName | FsError::InvalidArgument | FsError::UnknownFormat => {
+                Errno::EINVAL
+            }
+            FsError::NameTooLong => Errno::ENAMETOOLONG,
+            FsError::TooManyLinks => Errno::ELOOP,
+            FsError::AccessMode => Errno::EBADF,
+            FsError::Corrupted | FsError::Io => Errno::EIO,
+            FsError::NotSupported => Errno::EOPNOTSUPP,
+        }
+    }
+}
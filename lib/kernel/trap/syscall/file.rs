@@ -0,0 +1,167 @@
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use crate::kernel::{
+    environment::{Dispatch, Environment, Frame},
+    fd::{FileError, Pipe},
+    trap::syscall::{file_result, fs, park, Errno, SyscallResult, SyscallReturn},
+    Kernel,
+};
+
+pub(super) fn uart_debug_print<ENV: Environment>(
+    _kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    c: char,
+) -> SyscallResult {
+    print!("{}", c);
+    Ok(SyscallReturn::Value(0))
+}
+
+pub(super) fn read<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    frame: &mut ENV::Frame,
+    fd: usize,
+    buf: *mut u8,
+    len: usize,
+) -> SyscallResult {
+    let file = kernel.with_process(|process| {
+        if !process.memory.contains(buf as usize, len) {
+            return Err(FileError::BadAddress);
+        }
+        process.files.get(fd)
+    });
+
+    if let Ok(Some(open)) = file.as_ref().map(|file| file.as_open_file()) {
+        return fs::read_file(kernel, open, buf, len);
+    }
+
+    let file = file?;
+    // The object is read into kernel pages and copied out with the process locked, so user
+    // memory can not be unmapped while it is written to
+    let mut bounce = fs::bounce_buffer(len)?;
+    // SAFETY: The pages belong to the buffer alone and outlive the slice
+    let staged = unsafe { core::slice::from_raw_parts_mut(bounce.start_mut(), bounce.size()) };
+    let staged = &mut staged[..len.min(bounce.size())];
+
+    // Objects are spin locked, so interrupts stay off while using them
+    ENV::Dispatch::deactivate_irq();
+    let count = match file.read(staged) {
+        Err(FileError::WouldBlock) => {
+            frame.restart_syscall();
+            park(kernel, frame, |task| file.wait_readable(task));
+            return Ok(SyscallReturn::Blocked);
+        }
+        result => result?,
+    };
+    kernel.with_process(|process| {
+        // SAFETY: Nothing else refers to the range while the process is locked
+        let user = unsafe { process.memory_mut().slice_mut(buf, count) }
+            .map_err(|_| Errno::EFAULT)?;
+        user.copy_from_slice(&staged[..count]);
+        Ok(SyscallReturn::Value(count))
+    })
+}
+
+pub(super) fn write<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    frame: &mut ENV::Frame,
+    fd: usize,
+    buf: *const u8,
+    len: usize,
+) -> SyscallResult {
+    let file = kernel.with_process(|process| {
+        if !process.memory.contains(buf as usize, len) {
+            return Err(FileError::BadAddress);
+        }
+        process.files.get(fd)
+    });
+
+    if let Ok(Some(open)) = file.as_ref().map(|file| file.as_open_file()) {
+        return fs::write_file(kernel, open, buf, len);
+    }
+
+    let file = file?;
+    // The data is copied to kernel pages with the process locked, so user memory can not be
+    // unmapped while it is read
+    let mut bounce = fs::bounce_buffer(len)?;
+    // SAFETY: The pages belong to the buffer alone and outlive the slice
+    let staged = unsafe { core::slice::from_raw_parts_mut(bounce.start_mut(), bounce.size()) };
+    let staged = &mut staged[..len.min(bounce.size())];
+    kernel.with_process(|process| {
+        // SAFETY: Nothing else refers to the range while the process is locked
+        let user = unsafe { process.memory.slice(buf as *mut u8, staged.len()) }
+            .map_err(|_| Errno::EFAULT)?;
+        staged.copy_from_slice(user);
+        Ok::<_, Errno>(())
+    })?;
+
+    ENV::Dispatch::deactivate_irq();
+    match file.write(staged) {
+        Err(FileError::WouldBlock) => {
+            frame.restart_syscall();
+            park(kernel, frame, |task| file.wait_writable(task));
+            Ok(SyscallReturn::Blocked)
+        }
+        result => file_result(result),
+    }
+}
+
+pub(super) fn close<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    fd: usize,
+) -> SyscallResult {
+    // The object itself may be dropped here, so keep it out of the process lock
+    let result = kernel.with_process(|process| {
+        let file = process.files.get(fd);
+        process.files.close(fd).and(file)
+    });
+    ENV::Dispatch::deactivate_irq();
+    file_result(result.map(|file| {
+        drop(file);
+        0
+    }))
+}
+
+pub(super) fn dup<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    fd: usize,
+) -> SyscallResult {
+    file_result(kernel.with_process(|process| process.files.dup(fd)))
+}
+
+pub(super) fn dup2<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    old_fd: usize,
+    new_fd: usize,
+) -> SyscallResult {
+    // The object `new_fd` referred to may be dropped here, so keep it out of the process lock
+    let result = kernel.with_process(|process| process.files.dup2(old_fd, new_fd));
+    ENV::Dispatch::deactivate_irq();
+    file_result(result.map(|replaced| {
+        drop(replaced);
+        new_fd
+    }))
+}
+
+/// Creates a pipe, returns the read descriptor in a0 and the write descriptor in a1
+pub(super) fn pipe<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+) -> SyscallResult {
+    let (reader, writer) = Pipe::pair(kernel.scheduler.clone());
+    let (read_fd, write_fd) = kernel.with_process(|process| {
+        let read_fd = process.files.insert(Arc::new(reader))?;
+        match process.files.insert(Arc::new(writer)) {
+            Ok(write_fd) => Ok((read_fd, write_fd)),
+            Err(e) => {
+                let _ = process.files.close(read_fd);
+                Err(Errno::from(e))
+            }
+        }
+    })?;
+    Ok(SyscallReturn::Pair(read_fd, write_fd))
+}
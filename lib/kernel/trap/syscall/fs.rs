@@ -0,0 +1,312 @@
+extern crate alloc;
+
+use alloc::{string::String, sync::Arc};
+
+use crate::kernel::{
+    environment::Environment,
+    mem::UserPages,
+    trap::syscall::{Errno, SyscallResult, SyscallReturn},
+    vfs::{self, Dentry, Metadata, OpenFile, OpenFlags, Whence, NAME_MAX, PATH_MAX},
+    Kernel,
+};
+
+/// Largest part of a read or write of an opened file staged in kernel memory at once
+const BOUNCE_PAGES: usize = 16;
+
+/// Layout of the struct `stat` fills in
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Stat {
+    pub inode: u64,
+    pub size: u64,
+    /// `FileType` as a number
+    pub kind: u32,
+    pub links: u32,
+}
+
+impl From<Metadata> for Stat {
+    fn from(metadata: Metadata) -> Self {
+        Self {
+            inode: metadata.inode,
+            size: metadata.size,
+            kind: metadata.kind as u32,
+            links: metadata.links,
+        }
+    }
+}
+
+/// Layout of the struct `readdir` fills in
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct UserDirEntry {
+    pub inode: u64,
+    /// `FileType` as a number
+    pub kind: u32,
+    /// Bytes of `name` in use
+    pub name_len: u32,
+    pub name: [u8; NAME_MAX + 1],
+}
+
+/// Copies the path of `len` bytes at `path` out of user memory
+fn user_path<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    path: *const u8,
+    len: usize,
+) -> Result<String, Errno> {
+    if len > PATH_MAX {
+        return Err(Errno::ENAMETOOLONG);
+    }
+    kernel.with_process(|process| {
+        // SAFETY: Nothing else refers to the path while the process is locked
+        let bytes =
+            unsafe { process.memory.slice(path as *mut u8, len) }.map_err(|_| Errno::EFAULT)?;
+        let path = core::str::from_utf8(bytes).map_err(|_| Errno::EINVAL)?;
+        Ok(String::from(path))
+    })
+}
+
+/// Working directory of the calling process
+fn cwd<ENV: Environment>(kernel: &Kernel<ENV>) -> Arc<Dentry> {
+    kernel
+        .with_process(|process| process.cwd.clone())
+        .unwrap_or_else(vfs::root)
+}
+
+/// Copies `value` to `ptr` in user memory
+fn write_user<ENV: Environment, T>(kernel: &Kernel<ENV>, ptr: *mut T, value: T) -> SyscallResult {
+    kernel.with_process(|process| {
+        if !process
+            .memory
+            .contains(ptr as usize, core::mem::size_of::<T>())
+        {
+            return Err(Errno::EFAULT);
+        }
+        // SAFETY: The range was checked to be user memory of the active page table
+        unsafe { core::ptr::write_unaligned(ptr, value) };
+        Ok(SyscallReturn::Value(0))
+    })
+}
+
+/// Kernel pages a read or write of `len` bytes of an opened file goes through. Another
+/// thread can unmap the user buffer while the filesystem waits for its device, so user
+/// memory is only touched with the process locked
+pub(super) fn bounce_buffer(len: usize) -> Result<UserPages, Errno> {
+    let pages = len.div_ceil(UserPages::PAGE_SIZE).clamp(1, BOUNCE_PAGES);
+    UserPages::try_zeroed(pages).ok_or(Errno::ENOMEM)
+}
+
+/// Reads up to `len` bytes of `file` to `buf` in user memory and returns how many were read
+pub(super) fn read_file<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    file: &OpenFile,
+    buf: *mut u8,
+    len: usize,
+) -> SyscallResult {
+    let mut bounce = bounce_buffer(len)?;
+    // SAFETY: The pages belong to the buffer alone and outlive the slice
+    let staged = unsafe { core::slice::from_raw_parts_mut(bounce.start_mut(), bounce.size()) };
+    let mut done = 0;
+    loop {
+        let chunk = (len - done).min(staged.len());
+        let count = match kernel.block_on(file.read(&mut staged[..chunk])) {
+            Ok(count) => count,
+            // What was read so far is returned instead of the error
+            Err(_) if done > 0 => break,
+            Err(err) => return Err(err.into()),
+        };
+        kernel.with_process(|process| {
+            // SAFETY: Nothing else refers to the range while the process is locked
+            let user = unsafe { process.memory_mut().slice_mut(buf.wrapping_add(done), count) }
+                .map_err(|_| Errno::EFAULT)?;
+            user.copy_from_slice(&staged[..count]);
+            Ok::<_, Errno>(())
+        })?;
+        done += count;
+        if count < chunk || done == len {
+            break;
+        }
+    }
+    Ok(SyscallReturn::Value(done))
+}
+
+/// Writes `len` bytes at `buf` in user memory to `file` and returns how many were written
+pub(super) fn write_file<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    file: &OpenFile,
+    buf: *const u8,
+    len: usize,
+) -> SyscallResult {
+    let mut bounce = bounce_buffer(len)?;
+    // SAFETY: The pages belong to the buffer alone and outlive the slice
+    let staged = unsafe { core::slice::from_raw_parts_mut(bounce.start_mut(), bounce.size()) };
+    let mut done = 0;
+    loop {
+        let chunk = (len - done).min(staged.len());
+        let copied = kernel.with_process(|process| {
+            // SAFETY: Nothing else refers to the range while the process is locked
+            let user = unsafe { process.memory.slice(buf.wrapping_add(done) as *mut u8, chunk) }
+                .map_err(|_| Errno::EFAULT)?;
+            staged[..chunk].copy_from_slice(user);
+            Ok::<_, Errno>(())
+        });
+        let written = copied.and_then(|()| {
+            kernel
+                .block_on(file.write(&staged[..chunk]))
+                .map_err(Errno::from)
+        });
+        let count = match written {
+            Ok(count) => count,
+            // What was written so far is returned instead of the error
+            Err(_) if done > 0 => break,
+            Err(err) => return Err(err),
+        };
+        done += count;
+        if count < chunk || done == len {
+            break;
+        }
+    }
+    Ok(SyscallReturn::Value(done))
+}
+
+/// Opens the file at `path` with `OpenFlags` and returns its descriptor
+pub(super) fn open<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    path: *const u8,
+    len: usize,
+    flags: usize,
+) -> SyscallResult {
+    let flags = OpenFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    let path = user_path(kernel, path, len)?;
+    let file = kernel.block_on(vfs::open(&cwd(kernel), &path, flags))?;
+    let fd = kernel.with_process(|process| process.files.insert(Arc::new(file)))?;
+    Ok(SyscallReturn::Value(fd))
+}
+
+/// Moves the position of `fd` and returns the new one, the low word in a0 and the high word
+/// in a1
+pub(super) fn lseek<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    fd: usize,
+    offset: u64,
+    whence: usize,
+) -> SyscallResult {
+    let whence = Whence::from_usize(whence).ok_or(Errno::EINVAL)?;
+    let file = kernel.with_process(|process| process.files.get(fd))?;
+    // Pipes, channels and the console have no position
+    let file = file.as_open_file().ok_or(Errno::ESPIPE)?;
+    let position = file.seek(offset as i64, whence)?;
+    Ok(SyscallReturn::Pair(
+        position as u32 as usize,
+        (position >> 32) as usize,
+    ))
+}
+
+pub(super) fn stat<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    path: *const u8,
+    len: usize,
+    stat: *mut Stat,
+) -> SyscallResult {
+    let path = user_path(kernel, path, len)?;
+    let metadata = kernel.block_on(vfs::stat(&cwd(kernel), &path))?;
+    write_user(kernel, stat, Stat::from(metadata))
+}
+
+pub(super) fn mkdir<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    path: *const u8,
+    len: usize,
+) -> SyscallResult {
+    let path = user_path(kernel, path, len)?;
+    kernel.block_on(vfs::mkdir(&cwd(kernel), &path))?;
+    Ok(SyscallReturn::Value(0))
+}
+
+/// Removes a file, or a directory once it is empty
+pub(super) fn unlink<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    path: *const u8,
+    len: usize,
+) -> SyscallResult {
+    let path = user_path(kernel, path, len)?;
+    kernel.block_on(vfs::unlink(&cwd(kernel), &path))?;
+    Ok(SyscallReturn::Value(0))
+}
+
+pub(super) fn rename<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    old: *const u8,
+    old_len: usize,
+    new: *const u8,
+    new_len: usize,
+) -> SyscallResult {
+    let old = user_path(kernel, old, old_len)?;
+    let new = user_path(kernel, new, new_len)?;
+    kernel.block_on(vfs::rename(&cwd(kernel), &old, &new))?;
+    Ok(SyscallReturn::Value(0))
+}
+
+/// Stores the next entry of the directory `fd` in `entry` and returns 1, or returns 0 after
+/// the last one
+pub(super) fn readdir<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    fd: usize,
+    entry: *mut UserDirEntry,
+) -> SyscallResult {
+    let file = kernel.with_process(|process| process.files.get(fd))?;
+    let file = file.as_open_file().ok_or(Errno::ENOTDIR)?;
+    let Some(next) = kernel.block_on(file.read_dir())? else {
+        return Ok(SyscallReturn::Value(0));
+    };
+    let mut name = [0; NAME_MAX + 1];
+    let len = next.name.len().min(NAME_MAX);
+    name[..len].copy_from_slice(&next.name.as_bytes()[..len]);
+    let user_entry = UserDirEntry {
+        inode: next.inode,
+        kind: next.kind as u32,
+        name_len: len as u32,
+        name,
+    };
+    write_user(kernel, entry, user_entry)?;
+    Ok(SyscallReturn::Value(1))
+}
+
+pub(super) fn chdir<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    path: *const u8,
+    len: usize,
+) -> SyscallResult {
+    let path = user_path(kernel, path, len)?;
+    let directory = kernel.block_on(vfs::directory(&cwd(kernel), &path))?;
+    kernel.with_process(|process| process.cwd = Some(directory));
+    Ok(SyscallReturn::Value(0))
+}
+
+/// Copies the path of the working directory to `buf` and returns its length
+pub(super) fn getcwd<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    buf: *mut u8,
+    len: usize,
+) -> SyscallResult {
+    let path = cwd(kernel).path();
+    if path.len() > len {
+        return Err(Errno::ERANGE);
+    }
+    kernel.with_process(|process| {
+        if !process.memory.contains(buf as usize, path.len()) {
+            return Err(Errno::EFAULT);
+        }
+        // SAFETY: The range was checked to be user memory of the active page table
+        unsafe { core::ptr::copy_nonoverlapping(path.as_ptr(), buf, path.len()) };
+        Ok(SyscallReturn::Value(path.len()))
+    })
+}
@@ -0,0 +1,99 @@
+use crate::kernel::{
+    environment::{Clock, Dispatch, Environment, Frame},
+    scheduler::{Wait, WaitOn},
+    time::{self, TimerEvent},
+    trap::syscall::{park, Errno, SyscallResult, SyscallReturn},
+    Kernel,
+};
+
+/// Blocks until `futex_wake` is called on `addr` if it still holds `expected`. A null
+/// `timeout` waits forever
+pub(super) fn futex_wait<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    frame: &mut ENV::Frame,
+    addr: usize,
+    expected: u32,
+    timeout: *const u64,
+) -> SyscallResult {
+    let (key, timeout) = kernel.with_process(|process| {
+        if !addr.is_multiple_of(core::mem::size_of::<u32>()) {
+            return Err(Errno::EINVAL);
+        }
+        // SAFETY: The page table of the running process is active during the syscall
+        let timeout = if timeout.is_null() {
+            None
+        } else {
+            Some(unsafe { process.memory.read(timeout) }.map_err(|_| Errno::EFAULT)?)
+        };
+        if !process.memory.contains(addr, core::mem::size_of::<u32>()) {
+            return Err(Errno::EFAULT);
+        }
+        let key = process.memory.physical_address(addr).map_err(|_| Errno::EFAULT)?;
+        Ok((key, timeout))
+    })?;
+
+    let process = kernel
+        .current_running
+        .borrow()
+        .as_ref()
+        .unwrap()
+        .process
+        .clone();
+
+    ENV::Dispatch::deactivate_irq();
+    let mut scheduler = {
+        // The word is read with the process locked, so it can not be unmapped meanwhile
+        let process = process.lock();
+        // Wakers take the scheduler lock, so a wake between this check and the task being
+        // queued can not be missed
+        let scheduler = kernel.scheduler.lock();
+        // SAFETY: The page table of the running process is active during the syscall
+        let value = unsafe { process.memory.read(addr as *const u32) }.map_err(|_| Errno::EFAULT)?;
+        if value != expected {
+            return Err(Errno::EAGAIN);
+        }
+        scheduler
+    };
+    drop(process);
+
+    // A timeout overwrites the result with `ETIMEDOUT`
+    frame.set_success(0, 0);
+    park(kernel, frame, |task| {
+        let ticket = scheduler.futexes.wait(key, task);
+        drop(scheduler);
+        if let Some(nanos) = timeout {
+            let deadline = ENV::Clock::now().saturating_add(time::nanos_to_ticks(nanos));
+            kernel.add_timer(deadline, TimerEvent::FutexTimeout { key, ticket });
+        }
+        Ok(Wait {
+            on: WaitOn::Futex(key),
+            ticket,
+            restarts: false,
+        })
+    });
+    Ok(SyscallReturn::Blocked)
+}
+
+/// Wakes up to `count` tasks blocked in `futex_wait` on `addr`, returns how many were woken
+pub(super) fn futex_wake<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    addr: usize,
+    count: usize,
+) -> SyscallResult {
+    let key = kernel
+        .with_process(|process| process.memory.physical_address(addr))
+        .map_err(|_| Errno::EFAULT)?;
+
+    ENV::Dispatch::deactivate_irq();
+    let mut scheduler = kernel.scheduler.lock();
+    let mut woken = 0;
+    while woken < count {
+        let Some(task) = scheduler.futexes.pop(key) else {
+            break;
+        };
+        scheduler.add_task(task);
+        woken += 1;
+    }
+    Ok(SyscallReturn::Value(woken))
+}
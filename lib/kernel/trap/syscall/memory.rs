@@ -0,0 +1,18 @@
+use crate::kernel::{
+    environment::Environment,
+    trap::syscall::{Errno, SyscallResult, SyscallReturn},
+    Kernel,
+};
+
+/// Grows the heap of the process by at least `increment` bytes and returns where the new
+/// memory starts. An increment of zero returns the current end of the heap
+pub(super) fn sbrk<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    increment: usize,
+) -> SyscallResult {
+    let start = kernel
+        .with_process(|process| process.memory_mut().sbrk(increment))
+        .map_err(|_| Errno::ENOMEM)?;
+    Ok(SyscallReturn::Value(start))
+}
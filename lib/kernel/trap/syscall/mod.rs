@@ -1,63 +1,181 @@
-extern crate alloc;
-
-use alloc::rc::Rc;
+//! System call numbers, argument decoding and the handlers they dispatch to.
+//!
+//! The number is passed in a7 and up to six arguments in a0 to a5. Handlers return a
+//! `SyscallResult` which is written back through `Frame::set_success` and `Frame::set_error`:
+//! on return a7 is 0 for success with the result in a0 and a secondary value in a1, or 1 for
+//! failure with an `Errno` in a0.
 
 use crate::kernel::{
-    environment::{Dispatch, DispatchLevel, Environment, Frame, PageTable},
-    mem::UserPages,
+    environment::{Environment, Frame},
+    fd::{FileError, UserMessage},
+    process::ProcessId,
+    scheduler::{Task, TaskId, Wait},
+    signal::SigAction,
     trap::TrapCtx,
     Kernel,
 };
 
-pub enum SystemCall {
-    Yield,
-    UartDebugPrint(char),
-}
+mod args;
+mod channel;
+mod clock;
+mod errno;
+mod file;
+mod fs;
+mod futex;
+mod memory;
+mod process;
+mod shm;
+mod signal;
+mod thread;
+
+pub use args::*;
+pub use channel::CHANNEL_SYNCHRONOUS;
+pub use clock::{TimerSpec, CLOCK_MONOTONIC};
+pub use errno::*;
+pub use fs::{Stat, UserDirEntry};
 
-impl SystemCall {
-    pub fn from_regs(r1: usize, r2: usize, r3: usize, r4: usize) -> Option<SystemCall> {
-        match r1 {
-            1 => Some(SystemCall::Yield),
-            0 => {
-                if let Some(c) = char::from_u32(r2 as u32) {
-                    Some(SystemCall::UartDebugPrint(c))
-                } else {
-                    None
+/// Declares every syscall as `number => Variant { args } => handler`. Arguments are decoded
+/// from the argument registers in order with `FromArgs`, and the handler is called with the
+/// kernel, the frame of the calling task and the decoded arguments. Its result is written back
+/// to the frame before the task is resumed
+macro_rules! syscall_table {
+    ($($number:literal => $name:ident { $($arg:ident: $ty:ty),* $(,)? } => $handler:path,)*) => {
+        pub enum SystemCall {
+            $($name { $($arg: $ty),* },)*
+        }
+
+        impl SystemCall {
+            /// Decodes syscall `number` from the argument registers a0 to a5
+            pub fn decode(number: usize, regs: [usize; 6]) -> Result<SystemCall, Errno> {
+                let mut args = SyscallArgs::new(regs);
+                match number {
+                    $($number => Ok(SystemCall::$name {
+                        $($arg: <$ty as FromArgs>::from_args(&mut args)?),*
+                    }),)*
+                    _ => Err(Errno::ENOSYS),
                 }
             }
-            _ => None,
         }
-    }
+
+        pub fn trap_syscall<ENV: Environment>(
+            kernel: &Kernel<ENV>,
+            call: SystemCall,
+            ctx: TrapCtx<'_, ENV>,
+        ) -> ! {
+            let result = match call {
+                $(SystemCall::$name { $($arg),* } => $handler(kernel, ctx.frame, $($arg),*),)*
+            };
+            complete(kernel, ctx.frame, result)
+        }
+    };
+}
+
+syscall_table! {
+    0 => UartDebugPrint { c: char } => file::uart_debug_print,
+    1 => Yield {} => thread::yield_now,
+    2 => Sleep { nanos: u64 } => clock::sleep,
+    3 => ClockGetTime { clock: usize } => clock::clock_gettime,
+    4 => TimerCreate {} => clock::timer_create,
+    5 => TimerSetTime { id: usize, spec: *const TimerSpec } => clock::timer_settime,
+    6 => TimerWait { id: usize } => clock::timer_wait,
+    7 => TimerDelete { id: usize } => clock::timer_delete,
+    8 => ThreadCreate { entry: usize, stack: usize, arg: usize } => thread::thread_create,
+    9 => ThreadExit { code: usize } => thread::thread_exit,
+    10 => ThreadJoin { id: TaskId } => thread::thread_join,
+    11 => FutexWait { addr: usize, expected: u32, timeout: *const u64 } => futex::futex_wait,
+    12 => FutexWake { addr: usize, count: usize } => futex::futex_wake,
+    13 => Read { fd: usize, buf: *mut u8, len: usize } => file::read,
+    14 => Write { fd: usize, buf: *const u8, len: usize } => file::write,
+    15 => Close { fd: usize } => file::close,
+    16 => Dup { fd: usize } => file::dup,
+    17 => Dup2 { old_fd: usize, new_fd: usize } => file::dup2,
+    18 => Pipe {} => file::pipe,
+    19 => ChannelCreate { flags: usize } => channel::channel_create,
+    20 => ChannelSend { fd: usize, message: *const UserMessage } => channel::channel_send,
+    21 => ChannelRecv {
+        fd: usize,
+        message: *mut UserMessage,
+        deadline: *const u64,
+    } => channel::channel_recv,
+    22 => ShmCreate { size: usize } => shm::shm_create,
+    23 => ShmMap { fd: usize, addr: usize } => shm::shm_map,
+    24 => ShmUnmap { addr: usize } => shm::shm_unmap,
+    25 => Kill { pid: ProcessId, signal: usize } => signal::kill,
+    26 => SigAction {
+        signal: usize,
+        action: *const SigAction,
+        old: *mut SigAction,
+    } => signal::sigaction,
+    27 => SigReturn {} => signal::sigreturn,
+    28 => Sbrk { increment: usize } => memory::sbrk,
+    29 => Spawn { path: *const u8, len: usize } => process::spawn,
+    30 => Wait { pid: usize } => process::wait,
+    31 => Open { path: *const u8, len: usize, flags: usize } => fs::open,
+    32 => Lseek { fd: usize, offset: u64, whence: usize } => fs::lseek,
+    33 => Stat { path: *const u8, len: usize, stat: *mut Stat } => fs::stat,
+    34 => Mkdir { path: *const u8, len: usize } => fs::mkdir,
+    35 => Unlink { path: *const u8, len: usize } => fs::unlink,
+    36 => Rename { old: *const u8, old_len: usize, new: *const u8, new_len: usize } => fs::rename,
+    37 => Readdir { fd: usize, entry: *mut UserDirEntry } => fs::readdir,
+    38 => Chdir { path: *const u8, len: usize } => fs::chdir,
+    39 => Getcwd { buf: *mut u8, len: usize } => fs::getcwd,
+}
+
+/// What a handler leaves the calling task with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallReturn {
+    /// The call completed with a value for a0
+    Value(usize),
+    /// The call completed with values for a0 and a1, e.g. the two ends of a pipe
+    Pair(usize, usize),
+    /// The task has been parked. Its return value was written before it was parked, is written
+    /// by whoever wakes it, or the syscall restarts when it runs again
+    Blocked,
+    /// The frame has been replaced as a whole, e.g. by `sigreturn`, and is left as it is
+    Restored,
 }
 
-pub fn trap_syscall<ENV: Environment>(
+pub type SyscallResult = Result<SyscallReturn, Errno>;
+
+/// Writes the result of a syscall back to the frame of the calling task and returns to it.
+/// A syscall that blocked has already given its task away, so the core moves on to the next
+/// task instead
+pub fn complete<ENV: Environment>(
     kernel: &Kernel<ENV>,
-    kind: SystemCall,
-    ctx: TrapCtx<'_, ENV>,
+    frame: &mut ENV::Frame,
+    result: SyscallResult,
 ) -> ! {
-    match kind {
-        SystemCall::Yield => {
-            unimplemented!();
-        }
-        SystemCall::UartDebugPrint(c) => {
-            print!("{}", c);
-            ENV::Dispatch::deactivate_irq();
-            {
-                let running_task = kernel.current_running.borrow_mut();
-                let running_task = running_task.as_ref().unwrap();
-                let running_process = running_task.process.try_lock().unwrap();
-                unsafe {
-                    ENV::PageTable::activate(&running_process.memory.page_table);
-                    kernel
-                        .stack
-                        .get()
-                        .write(running_task.stack.get_base().cast_mut());
-                }
-            }
+    match result {
+        Ok(SyscallReturn::Value(value)) => frame.set_success(value, 0),
+        Ok(SyscallReturn::Pair(value, secondary)) => frame.set_success(value, secondary),
+        Ok(SyscallReturn::Blocked) => kernel.schedule_next(),
+        Ok(SyscallReturn::Restored) => {}
+        Err(errno) => frame.set_error(errno.as_usize()),
+    }
+    kernel.resume(frame);
+}
 
-            unsafe {
-                ENV::Dispatch::dispatch(&ctx.frame);
-            }
-        }
+/// Takes the current task off the core at `frame` and hands it to `wait`, which parks it and
+/// returns where, so a signal can interrupt the wait. If `wait` hands the task back it is
+/// runnable right away. Locks `wait` holds must be released before it returns
+fn park<ENV: Environment, F>(kernel: &Kernel<ENV>, frame: &ENV::Frame, wait: F)
+where
+    F: FnOnce(Task<ENV>) -> Result<Wait<ENV>, Task<ENV>>,
+{
+    let task = kernel.take_current(frame);
+    let (process, id) = (task.process.clone(), task.id);
+    match wait(task) {
+        Ok(wait) => kernel.interruptible(&process, id, wait),
+        Err(task) => kernel.scheduler.lock().add_task(task),
+    }
+}
+
+/// Converts the result of a descriptor operation, where `FileError::WouldBlock` means the task
+/// has been parked
+fn file_result(result: Result<usize, FileError>) -> SyscallResult {
+    match result {
+        Ok(value) => Ok(SyscallReturn::Value(value)),
+        Err(FileError::WouldBlock) => Ok(SyscallReturn::Blocked),
+        Err(e) => Err(Errno::from(e)),
     }
 }
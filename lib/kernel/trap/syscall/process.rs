@@ -0,0 +1,90 @@
+extern crate alloc;
+
+use alloc::string::String;
+
+use crate::kernel::{
+    environment::{Dispatch, Environment, Frame},
+    mem::UserPages,
+    process::{Process, ProcessId},
+    scheduler::{NoChild, Stack, Wait, WaitOn},
+    trap::syscall::{park, Errno, SyscallResult, SyscallReturn},
+    vfs::{self, OpenFlags},
+    Kernel,
+};
+
+/// Starts the program at `path`, relative to the working directory of the calling process,
+/// as a child of it and returns the id of the child
+pub(super) fn spawn<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    path: *const u8,
+    len: usize,
+) -> SyscallResult {
+    let (parent, cwd, path) = kernel.with_process(|process| {
+        // SAFETY: Nothing else refers to the path while the process is locked
+        let bytes = unsafe { process.memory.slice(path as *mut u8, len) }
+            .map_err(|_| Errno::EFAULT)?;
+        let path = core::str::from_utf8(bytes).map_err(|_| Errno::EINVAL)?;
+        Ok::<_, Errno>((process.id, process.cwd.clone(), String::from(path)))
+    })?;
+
+    let start = cwd.clone().unwrap_or_else(vfs::root);
+    let file = kernel.block_on(vfs::open(&start, &path, OpenFlags::empty()))?;
+    let size = usize::try_from(file.dentry().inode().metadata().size)
+        .map_err(|_| Errno::ENOEXEC)?;
+    // Images can be large, so they are staged in pages instead of on the kernel heap
+    let mut pages = UserPages::try_zeroed(size.div_ceil(UserPages::PAGE_SIZE).max(1))
+        .ok_or(Errno::ENOMEM)?;
+    // SAFETY: The pages belong to the buffer alone and outlive the slice
+    let image = unsafe { core::slice::from_raw_parts_mut(pages.start_mut(), size) };
+    let mut read = 0;
+    while read < size {
+        match kernel.block_on(file.read(&mut image[read..]))? {
+            // The file shrank since its size was looked at
+            0 => break,
+            count => read += count,
+        }
+    }
+
+    let id = ProcessId::allocate();
+    let mut child = Process::from_image(id, &image[..read])?;
+    child.cwd = cwd;
+    let stack = Stack::try_new().ok_or(Errno::ENOMEM)?;
+
+    ENV::Dispatch::deactivate_irq();
+    kernel.scheduler.lock().add_process(child, Some(parent), stack);
+    Ok(SyscallReturn::Value(id.as_usize()))
+}
+
+/// Reaps an exited child and returns its id and exit code, blocking until one exits.
+/// `pid` zero waits for any child
+pub(super) fn wait<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    frame: &mut ENV::Frame,
+    pid: usize,
+) -> SyscallResult {
+    let parent = kernel.with_process(|process| process.id);
+    let child = (pid != 0).then(|| ProcessId::from(pid));
+
+    ENV::Dispatch::deactivate_irq();
+    let mut scheduler = kernel.scheduler.lock();
+    match scheduler.reap_child(parent, child) {
+        Ok(Some((id, code))) => Ok(SyscallReturn::Pair(id.as_usize(), code)),
+        Ok(None) => {
+            // Exiting children take the scheduler lock to wake waiters, so none can be
+            // missed between the check and the task being parked
+            frame.restart_syscall();
+            park(kernel, frame, |task| {
+                let ticket = scheduler.wait_for_child(parent, task)?;
+                drop(scheduler);
+                Ok(Wait {
+                    on: WaitOn::Child(parent),
+                    ticket,
+                    restarts: true,
+                })
+            });
+            Ok(SyscallReturn::Blocked)
+        }
+        Err(NoChild) => Err(Errno::ECHILD),
+    }
+}
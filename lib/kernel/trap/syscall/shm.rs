@@ -0,0 +1,51 @@
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use crate::kernel::{
+    environment::Environment,
+    fd::{FileError, SharedMemory},
+    trap::syscall::{file_result, Errno, SyscallResult, SyscallReturn},
+    Kernel,
+};
+
+/// Creates a shared memory region of at least `size` bytes and returns its descriptor
+pub(super) fn shm_create<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    size: usize,
+) -> SyscallResult {
+    file_result(SharedMemory::new(size).and_then(|region| {
+        kernel.with_process(|process| process.files.insert(Arc::new(region)))
+    }))
+}
+
+/// Maps the region behind `fd` at `addr` and returns where it was mapped. Address zero lets
+/// the kernel pick where the region goes
+pub(super) fn shm_map<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    fd: usize,
+    addr: usize,
+) -> SyscallResult {
+    let at = (addr != 0).then_some(addr);
+    file_result(kernel.with_process(|process| {
+        let file = process.files.get(fd)?;
+        let region = file.as_shared_memory().ok_or(FileError::NotSupported)?;
+        process
+            .memory_mut()
+            .map_shared(region.pages(), at)
+            .map_err(|_| FileError::BadAddress)
+    }))
+}
+
+pub(super) fn shm_unmap<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    addr: usize,
+) -> SyscallResult {
+    kernel
+        .with_process(|process| process.memory_mut().unmap_shared(addr))
+        .map_err(|_| Errno::EINVAL)?;
+    Ok(SyscallReturn::Value(0))
+}
@@ -0,0 +1,93 @@
+use crate::kernel::{
+    environment::{Dispatch, Environment, Frame},
+    process::ProcessId,
+    signal::{self, SigAction, SignalFrame, SignalState},
+    trap::syscall::{Errno, SyscallResult, SyscallReturn},
+    Kernel,
+};
+
+/// Sends `signal` to the process `pid`. Signal zero only checks that the process exists
+pub(super) fn kill<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    pid: ProcessId,
+    signal: usize,
+) -> SyscallResult {
+    if signal != 0 && !SignalState::<ENV>::is_valid(signal) {
+        return Err(Errno::EINVAL);
+    }
+
+    ENV::Dispatch::deactivate_irq();
+    let process = kernel.scheduler.lock().process(pid);
+    let process = process.ok_or(Errno::ESRCH)?;
+    if signal != 0 {
+        let mut process = process.lock();
+        process.signals.send(signal, &mut kernel.scheduler.lock());
+        // Threads blocked in the kernel only act on signals once they return to user mode
+        kernel.interrupt_waits(&mut process);
+    }
+    // A signal sent to the own process is acted on before returning to user mode
+    Ok(SyscallReturn::Value(0))
+}
+
+/// Sets the action of `signal` to `action` and stores the previous one in `old`, either
+/// may be null
+pub(super) fn sigaction<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    signal: usize,
+    action: *const SigAction,
+    old: *mut SigAction,
+) -> SyscallResult {
+    kernel.with_process(|process| {
+        let size = core::mem::size_of::<SigAction>();
+        if !old.is_null() && !process.memory.contains(old as usize, size) {
+            return Err(Errno::EFAULT);
+        }
+        let previous = process.signals.action(signal).ok_or(Errno::EINVAL)?;
+        if !action.is_null() {
+            // SAFETY: The page table of the running process is active during the syscall
+            let action = unsafe { process.memory.read(action) }.map_err(|_| Errno::EFAULT)?;
+            process
+                .signals
+                .set_action(signal, action)
+                .ok_or(Errno::EINVAL)?;
+        }
+        if !old.is_null() {
+            // SAFETY: The range was checked to be user memory above
+            unsafe { core::ptr::write_unaligned(old, previous) };
+        }
+        Ok(SyscallReturn::Value(0))
+    })
+}
+
+/// Returns from a signal handler to the frame it interrupted
+pub(super) fn sigreturn<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    frame: &mut ENV::Frame,
+) -> SyscallResult {
+    // The handler returned to its restorer, which left sp at the signal frame
+    let sp = frame.stack_pointer();
+    let restored = kernel.with_process(|process| {
+        let size = core::mem::size_of::<SignalFrame<ENV::Frame>>();
+        if !process.memory.contains(sp, size) {
+            process.signals.terminate(signal::SIGSEGV);
+            return None;
+        }
+        // SAFETY: The range was checked to be user memory of the active page table
+        let saved = unsafe { core::ptr::read_unaligned(sp as *const SignalFrame<ENV::Frame>) };
+        let id = kernel.current_running.borrow().as_ref().unwrap().id;
+        if let Some(thread) = process.threads.get_mut(&id) {
+            thread.blocked = saved.blocked;
+        }
+        Some(saved.frame)
+    });
+
+    if let Some(mut restored) = restored {
+        // The saved frame is user memory, it must not be able to enter kernel mode
+        restored.set_is_user_mode(true);
+        *frame = restored;
+    }
+    // A corrupt signal frame terminates the process on the way back to user mode
+    Ok(SyscallReturn::Restored)
+}
@@ -0,0 +1,110 @@
+use crate::kernel::{
+    environment::{Dispatch, Environment, Frame},
+    scheduler::{Pin, Stack, Task, TaskId, Wait, WaitOn},
+    trap::syscall::{park, Errno, SyscallResult, SyscallReturn},
+    Kernel,
+};
+
+/// Puts the calling task at the back of the run queue
+pub(super) fn yield_now<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    frame: &mut ENV::Frame,
+) -> SyscallResult {
+    frame.set_success(0, 0);
+    let task = kernel.take_current(frame);
+    kernel.scheduler.lock().add_task(task);
+    Ok(SyscallReturn::Blocked)
+}
+
+pub(super) fn thread_create<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    _frame: &mut ENV::Frame,
+    entry: usize,
+    stack: usize,
+    arg: usize,
+) -> SyscallResult {
+    let kernel_stack = Stack::try_new().ok_or(Errno::ENOMEM)?;
+    let id = kernel.with_process(|process| {
+        let memory = &process.memory;
+        let entry_valid = entry >= memory.user_start && entry < memory.user_end;
+        let stack_valid = stack > memory.user_start && stack <= memory.user_end;
+        if !entry_valid || !stack_valid {
+            return Err(Errno::EINVAL);
+        }
+
+        let id = TaskId::allocate();
+        process.add_thread(id);
+        Ok(id)
+    })?;
+
+    // The argument doubles as the initial thread pointer, so a runtime can pass the
+    // thread control block holding the thread's TLS. The thread may change tp freely
+    let mut frame = ENV::Frame::empty(entry);
+    frame.set_stack_pointer(stack);
+    frame.set_argument(arg);
+    frame.set_thread_pointer(arg);
+
+    let process = kernel
+        .current_running
+        .borrow()
+        .as_ref()
+        .unwrap()
+        .process
+        .clone();
+
+    ENV::Dispatch::deactivate_irq();
+    kernel.scheduler.lock().add_task(Task {
+        id,
+        pin: Pin::Unpinned,
+        frame,
+        stack: kernel_stack,
+        process,
+    });
+
+    Ok(SyscallReturn::Value(id.id()))
+}
+
+pub(super) fn thread_exit<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    frame: &mut ENV::Frame,
+    code: usize,
+) -> SyscallResult {
+    kernel.exit_current(frame, code);
+}
+
+/// Returns the exit code of thread `id`, blocks until it exits if it is still running
+pub(super) fn thread_join<ENV: Environment>(
+    kernel: &Kernel<ENV>,
+    frame: &mut ENV::Frame,
+    id: TaskId,
+) -> SyscallResult {
+    ENV::Dispatch::deactivate_irq();
+    let (process, self_id) = {
+        let running_task = kernel.current_running.borrow();
+        let running_task = running_task.as_ref().unwrap();
+        (running_task.process.clone(), running_task.id)
+    };
+    if id == self_id {
+        return Err(Errno::EDEADLK);
+    }
+
+    let mut locked = process.lock();
+    let thread = locked.threads.get_mut(&id).ok_or(Errno::ESRCH)?;
+    if let Some(code) = thread.exit_code {
+        locked.threads.remove(&id);
+        return Ok(SyscallReturn::Value(code));
+    }
+
+    // The exit code is filled in by the exiting thread
+    park(kernel, frame, |task| {
+        let joiners = &mut locked.threads.get_mut(&id).expect("checked above").joiners;
+        let ticket = joiners.push(task);
+        drop(locked);
+        Ok(Wait {
+            on: WaitOn::Join(id),
+            ticket,
+            restarts: false,
+        })
+    });
+    Ok(SyscallReturn::Blocked)
+}
@@ -0,0 +1,324 @@
+extern crate alloc;
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::vfs::{self, FileSystem, FileType, FsError, FsFuture, Inode, NAME_MAX},
+};
+
+/// How many symbolic links are followed while resolving one path
+const MAX_LINKS: usize = 8;
+
+/// A named inode in the tree of directories. Entries hold their parent, a directory only
+/// remembers the children someone else still holds, so unused entries and their inodes are
+/// dropped. Mount points are held by the mount table
+#[derive(Debug)]
+pub struct Dentry {
+    inode: Arc<dyn Inode>,
+    fs: Arc<dyn FileSystem>,
+    state: Mutex<DentryState>,
+}
+
+#[derive(Debug)]
+struct DentryState {
+    name: String,
+    /// None for the root of the tree. The root of a mounted filesystem has the parent of its
+    /// mount point, so `..` leaves the filesystem
+    parent: Option<Arc<Dentry>>,
+    /// Children looked up so far that are still in use
+    children: BTreeMap<String, Weak<Dentry>>,
+    /// Root of the filesystem mounted on this directory
+    mounted: Option<Arc<Dentry>>,
+}
+
+impl Dentry {
+    fn new(
+        name: String,
+        inode: Arc<dyn Inode>,
+        fs: Arc<dyn FileSystem>,
+        parent: Option<Arc<Dentry>>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            inode,
+            fs,
+            state: Mutex::new(DentryState {
+                name,
+                parent,
+                children: BTreeMap::new(),
+                mounted: None,
+            }),
+        })
+    }
+
+    /// The root of the tree, on the root of `fs`
+    pub(super) fn root(fs: Arc<dyn FileSystem>) -> Arc<Self> {
+        Self::new(String::new(), fs.root(), fs, None)
+    }
+
+    pub fn inode(&self) -> &Arc<dyn Inode> {
+        &self.inode
+    }
+
+    pub fn filesystem(&self) -> &Arc<dyn FileSystem> {
+        &self.fs
+    }
+
+    pub fn name(&self) -> String {
+        self.state.lock().name.clone()
+    }
+
+    /// The directory holding this one, None for the root of the tree
+    pub fn parent(&self) -> Option<Arc<Dentry>> {
+        self.state.lock().parent.clone()
+    }
+
+    /// Absolute path of the entry
+    pub fn path(self: &Arc<Self>) -> String {
+        let mut names = Vec::new();
+        let mut dentry = self.clone();
+        while let Some(parent) = dentry.parent() {
+            names.push(dentry.name());
+            dentry = parent;
+        }
+        let mut path = String::new();
+        for name in names.iter().rev() {
+            path.push('/');
+            path.push_str(name);
+        }
+        if path.is_empty() {
+            path.push('/');
+        }
+        path
+    }
+
+    /// Makes the root of `fs` take the place of this directory
+    pub(super) fn mount(self: &Arc<Self>, fs: Arc<dyn FileSystem>) -> Result<(), FsError> {
+        if self.inode.metadata().kind != FileType::Directory {
+            return Err(FsError::NotDirectory);
+        }
+        let mut state = self.state.lock();
+        // The root of the tree has nothing to be mounted over
+        if state.mounted.is_some() || state.parent.is_none() {
+            return Err(FsError::Busy);
+        }
+        let root = Self::new(state.name.clone(), fs.root(), fs, state.parent.clone());
+        state.mounted = Some(root);
+        Ok(())
+    }
+
+    /// The root of what is mounted here, or the entry itself
+    fn follow_mounts(self: Arc<Self>) -> Arc<Self> {
+        let mut dentry = self;
+        loop {
+            let mounted = dentry.state.lock().mounted.clone();
+            match mounted {
+                Some(mounted) => dentry = mounted,
+                None => return dentry,
+            }
+        }
+    }
+
+    fn is_mount_point(&self) -> bool {
+        self.state.lock().mounted.is_some()
+    }
+
+    /// The entry `name` of this directory as its filesystem has it, mount points not followed
+    async fn child(self: &Arc<Self>, name: &str) -> Result<Arc<Dentry>, FsError> {
+        let cached = self.state.lock().children.get(name).and_then(Weak::upgrade);
+        if let Some(child) = cached {
+            return Ok(child);
+        }
+        let inode = self.inode.lookup(name).await?;
+        Ok(self.insert_child(name, inode))
+    }
+
+    fn insert_child(self: &Arc<Self>, name: &str, inode: Arc<dyn Inode>) -> Arc<Dentry> {
+        let mut state = self.state.lock();
+        // Someone else may have looked it up in the meantime
+        if let Some(child) = state.children.get(name).and_then(Weak::upgrade) {
+            return child;
+        }
+        let child = Self::new(
+            String::from(name),
+            inode,
+            self.fs.clone(),
+            Some(self.clone()),
+        );
+        state
+            .children
+            .insert(String::from(name), Arc::downgrade(&child));
+        child
+    }
+
+    pub(super) async fn create(
+        self: &Arc<Self>,
+        name: &str,
+        kind: FileType,
+    ) -> Result<Arc<Dentry>, FsError> {
+        check_name(name)?;
+        let inode = self.inode.create(name, kind).await?;
+        Ok(self.insert_child(name, inode))
+    }
+
+    pub(super) async fn unlink(self: &Arc<Self>, name: &str) -> Result<(), FsError> {
+        check_name(name)?;
+        if self.child(name).await?.is_mount_point() {
+            return Err(FsError::Busy);
+        }
+        self.inode.unlink(name).await?;
+        self.state.lock().children.remove(name);
+        Ok(())
+    }
+
+    pub(super) async fn rename(
+        old_parent: &Arc<Self>,
+        old_name: &str,
+        new_parent: &Arc<Self>,
+        new_name: &str,
+    ) -> Result<(), FsError> {
+        check_name(old_name)?;
+        check_name(new_name)?;
+        if !Arc::ptr_eq(&old_parent.fs, &new_parent.fs) {
+            return Err(FsError::CrossDevice);
+        }
+        let moved = old_parent.child(old_name).await?;
+        if moved.is_mount_point() {
+            return Err(FsError::Busy);
+        }
+        if let Ok(replaced) = new_parent.child(new_name).await {
+            if replaced.is_mount_point() {
+                return Err(FsError::Busy);
+            }
+        }
+        // A directory can not be moved into itself
+        let mut ancestor = Some(new_parent.clone());
+        while let Some(dentry) = ancestor {
+            if Arc::ptr_eq(&dentry, &moved) {
+                return Err(FsError::InvalidArgument);
+            }
+            ancestor = dentry.parent();
+        }
+
+        old_parent
+            .inode
+            .rename(old_name, new_parent.inode.as_ref(), new_name)
+            .await?;
+
+        old_parent.state.lock().children.remove(old_name);
+        {
+            let mut state = moved.state.lock();
+            state.name = String::from(new_name);
+            state.parent = Some(new_parent.clone());
+        }
+        new_parent
+            .state
+            .lock()
+            .children
+            .insert(String::from(new_name), Arc::downgrade(&moved));
+        Ok(())
+    }
+}
+
+impl Drop for Dentry {
+    fn drop(&mut self) {
+        let state = self.state.lock();
+        let Some(parent) = &state.parent else {
+            return;
+        };
+        // A later lookup of the same name may have put a new entry in its place
+        let mut parent_state = parent.state.lock();
+        if parent_state
+            .children
+            .get(&state.name)
+            .is_some_and(|child| child.strong_count() == 0)
+        {
+            parent_state.children.remove(&state.name);
+        }
+    }
+}
+
+/// Rejects names that can not be given to a new entry
+fn check_name(name: &str) -> Result<(), FsError> {
+    match name {
+        "" | "." | ".." => Err(FsError::InvalidName),
+        name if name.contains('/') => Err(FsError::InvalidName),
+        name if name.len() > NAME_MAX => Err(FsError::NameTooLong),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the entry at `path`, relative to `cwd` unless it starts with `/`
+pub async fn resolve(cwd: &Arc<Dentry>, path: &str) -> Result<Arc<Dentry>, FsError> {
+    if path.is_empty() {
+        return Err(FsError::NotFound);
+    }
+    walk(cwd.clone(), path, 0).await
+}
+
+/// Returns the directory holding the entry at `path` and the name of the entry, which need
+/// not exist
+pub async fn resolve_parent<'p>(
+    cwd: &Arc<Dentry>,
+    path: &'p str,
+) -> Result<(Arc<Dentry>, &'p str), FsError> {
+    let trimmed = path.trim_end_matches('/');
+    let (directory, name) = match trimmed.rfind('/') {
+        Some(end) => (&trimmed[..end + 1], &trimmed[end + 1..]),
+        None => ("", trimmed),
+    };
+    if path.is_empty() {
+        return Err(FsError::NotFound);
+    }
+    check_name(name)?;
+    let parent = walk(cwd.clone(), directory, 0).await?;
+    if parent.inode.metadata().kind != FileType::Directory {
+        return Err(FsError::NotDirectory);
+    }
+    Ok((parent, name))
+}
+
+/// Resolves `path` from `start` one component at a time, `depth` counts the symbolic links
+/// followed to get here
+fn walk<'a>(start: Arc<Dentry>, path: &'a str, depth: usize) -> FsFuture<'a, Arc<Dentry>> {
+    Box::pin(async move {
+        let mut current = match path.starts_with('/') {
+            true => vfs::root(),
+            false => start,
+        };
+        for name in path.split('/') {
+            match name {
+                "" | "." => continue,
+                // The root is its own parent
+                ".." => {
+                    current = current.parent().unwrap_or(current);
+                    continue;
+                }
+                _ => {}
+            }
+            if current.inode.metadata().kind != FileType::Directory {
+                return Err(FsError::NotDirectory);
+            }
+            if name.len() > NAME_MAX {
+                return Err(FsError::NameTooLong);
+            }
+            let child = current.child(name).await?.follow_mounts();
+            if child.inode.metadata().kind == FileType::Symlink {
+                if depth >= MAX_LINKS {
+                    return Err(FsError::TooManyLinks);
+                }
+                let target = child.inode.read_link().await?;
+                current = walk(current, &target, depth + 1).await?;
+                continue;
+            }
+            current = child;
+        }
+        Ok(current)
+    })
+}
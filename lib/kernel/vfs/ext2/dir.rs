@@ -0,0 +1,93 @@
+//! Entries of directories. They follow each other through the blocks of a directory, each
+//! with the length of its record, which may be longer than the entry to cover free space after
+//! it. No entry crosses a block, removed ones are merged into the one before them or, first in
+//! their block, get inode zero.
+
+use crate::kernel::vfs::FileType;
+
+pub(super) const HEADER_SIZE: usize = 8;
+pub(super) const NAME_MAX: usize = 255;
+
+/// Types of the inodes of entries with `INCOMPAT_FILETYPE`, the others are device files,
+/// pipes and sockets
+const TYPE_REGULAR: u8 = 1;
+const TYPE_DIRECTORY: u8 = 2;
+const TYPE_SYMLINK: u8 = 7;
+
+#[derive(Debug, Clone)]
+pub(super) struct Entry {
+    /// Zero for empty entries
+    pub(super) inode: u32,
+    pub(super) record_len: u64,
+    pub(super) name_len: usize,
+    pub(super) file_type: u8,
+    /// Byte of the entry within the directory
+    pub(super) position: u64,
+    /// Byte address of the entry on the device
+    pub(super) address: u64,
+    /// Byte address and record length of the entry before in the same block
+    pub(super) previous: Option<(u64, u64)>,
+}
+
+impl Entry {
+    pub(super) fn parse(
+        header: &[u8; HEADER_SIZE],
+        position: u64,
+        address: u64,
+        previous: Option<(u64, u64)>,
+        filetype: bool,
+    ) -> Self {
+        // Without file types the name length has two bytes
+        let (name_len, file_type) = match filetype {
+            true => (header[6] as usize, header[7]),
+            false => (u16::from_le_bytes([header[6], header[7]]) as usize, 0),
+        };
+        Self {
+            inode: u32::from_le_bytes(header[..4].try_into().unwrap()),
+            record_len: u16::from_le_bytes([header[4], header[5]]) as u64,
+            name_len: name_len.min(NAME_MAX),
+            file_type,
+            position,
+            address,
+            previous,
+        }
+    }
+}
+
+/// Bytes an entry with a name of `name_len` bytes takes at least
+pub(super) fn record_len(name_len: usize) -> u64 {
+    (HEADER_SIZE + name_len).next_multiple_of(4) as u64
+}
+
+pub(super) fn header(inode: u32, record_len: u64, name_len: usize, file_type: u8) -> [u8; 8] {
+    let mut header = [0; HEADER_SIZE];
+    header[..4].copy_from_slice(&inode.to_le_bytes());
+    header[4..6].copy_from_slice(&(record_len as u16).to_le_bytes());
+    header[6] = name_len as u8;
+    header[7] = file_type;
+    header
+}
+
+/// The file type entries with `INCOMPAT_FILETYPE` store for inodes of `kind`
+pub(super) fn file_type(kind: FileType) -> u8 {
+    match kind {
+        FileType::Regular => TYPE_REGULAR,
+        FileType::Directory => TYPE_DIRECTORY,
+        FileType::Symlink => TYPE_SYMLINK,
+    }
+}
+
+/// The kind of the inodes of entries with `file_type`, None for those this driver does not
+/// open
+pub(super) fn kind(file_type: u8) -> Option<FileType> {
+    match file_type {
+        TYPE_REGULAR => Some(FileType::Regular),
+        TYPE_DIRECTORY => Some(FileType::Directory),
+        TYPE_SYMLINK => Some(FileType::Symlink),
+        _ => None,
+    }
+}
+
+pub(super) fn is_dot(name: &[u8]) -> bool {
+    name == b"." || name == b".."
+}
@@ -0,0 +1,755 @@
+extern crate alloc;
+
+use core::{any::Any, ops::ControlFlow};
+
+use alloc::{
+    boxed::Box,
+    string::String,
+    sync::{Arc, Weak},
+    vec,
+};
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::vfs::{
+        ext2::{
+            dir::{self, Entry},
+            read_u16, read_u32, Transfer, Volume, BLOCK_POINTERS, GOOD_OLD_INODE_SIZE,
+        },
+        DirEntry, FileType, FsError, FsFuture, Inode, Metadata, PATH_MAX,
+    },
+};
+
+/// Bits of the mode giving the type of the inode
+const MODE_TYPE: u16 = 0o170000;
+const MODE_REGULAR: u16 = 0o100000;
+const MODE_DIRECTORY: u16 = 0o040000;
+const MODE_SYMLINK: u16 = 0o120000;
+/// Permissions of new files and directories
+const FILE_PERMISSIONS: u16 = 0o644;
+const DIRECTORY_PERMISSIONS: u16 = 0o755;
+
+/// The directory has a hashed index besides its entries, which this driver does not keep
+const INDEX_FLAG: u32 = 0x1000;
+/// Where the block numbers are, short symbolic links keep their target there instead
+const BLOCKS_OFFSET: usize = 40;
+
+/// The first `GOOD_OLD_INODE_SIZE` bytes of an inode as on the device
+#[derive(Debug, Clone)]
+pub(super) struct RawInode(pub(super) [u8; GOOD_OLD_INODE_SIZE]);
+
+/// A file, directory or symbolic link of an ext2 volume
+#[derive(Debug)]
+pub struct Ext2Inode {
+    volume: Arc<Volume>,
+    number: u32,
+    kind: FileType,
+    state: Mutex<InodeState>,
+}
+
+#[derive(Debug)]
+struct InodeState {
+    raw: RawInode,
+    /// The last link is gone and the inode freed
+    removed: bool,
+}
+
+impl RawInode {
+    fn set_u16(&mut self, offset: usize, value: u16) {
+        self.0[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn set_u32(&mut self, offset: usize, value: u32) {
+        self.0[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn mode(&self) -> u16 {
+        read_u16(&self.0, 0)
+    }
+
+    fn set_mode(&mut self, mode: u16) {
+        self.set_u16(0, mode);
+    }
+
+    /// None for device files, pipes and sockets
+    fn kind(&self) -> Option<FileType> {
+        match self.mode() & MODE_TYPE {
+            MODE_REGULAR => Some(FileType::Regular),
+            MODE_DIRECTORY => Some(FileType::Directory),
+            MODE_SYMLINK => Some(FileType::Symlink),
+            _ => None,
+        }
+    }
+
+    /// Regular files keep the high half of their size where directories have other fields
+    pub(super) fn size(&self) -> u64 {
+        let low = read_u32(&self.0, 4) as u64;
+        match self.mode() & MODE_TYPE {
+            MODE_REGULAR => low | (read_u32(&self.0, 108) as u64) << 32,
+            _ => low,
+        }
+    }
+
+    fn set_size(&mut self, size: u64) {
+        self.set_u32(4, size as u32);
+        if self.mode() & MODE_TYPE == MODE_REGULAR {
+            self.set_u32(108, (size >> 32) as u32);
+        }
+    }
+
+    fn links(&self) -> u16 {
+        read_u16(&self.0, 26)
+    }
+
+    fn set_links(&mut self, links: u16) {
+        self.set_u16(26, links);
+    }
+
+    /// Blocks in use, data, indirect and attribute ones, in units of 512 bytes
+    pub(super) fn sectors(&self) -> u32 {
+        read_u32(&self.0, 28)
+    }
+
+    pub(super) fn set_sectors(&mut self, sectors: u32) {
+        self.set_u32(28, sectors);
+    }
+
+    fn flags(&self) -> u32 {
+        read_u32(&self.0, 32)
+    }
+
+    fn set_flags(&mut self, flags: u32) {
+        self.set_u32(32, flags);
+    }
+
+    /// Block number `slot` of the `BLOCK_POINTERS` of the inode
+    pub(super) fn block(&self, slot: usize) -> u32 {
+        read_u32(&self.0, BLOCKS_OFFSET + slot * 4)
+    }
+
+    pub(super) fn set_block(&mut self, slot: usize, block: u32) {
+        self.set_u32(BLOCKS_OFFSET + slot * 4, block);
+    }
+
+    /// Block of extended attributes, zero for none
+    fn file_acl(&self) -> u32 {
+        read_u32(&self.0, 104)
+    }
+}
+
+impl Ext2Inode {
+    /// Inode `number`, the same one as long as anyone holds it
+    pub(super) async fn get(volume: &Arc<Volume>, number: u32) -> Result<Arc<Self>, FsError> {
+        if let Some(inode) = volume
+            .state
+            .lock()
+            .inodes
+            .get(&number)
+            .and_then(Weak::upgrade)
+        {
+            return Ok(inode);
+        }
+        let raw = volume.read_inode(number).await?;
+        let kind = raw.kind().ok_or(FsError::NotSupported)?;
+        // An entry names an inode that was deleted
+        if raw.links() == 0 {
+            return Err(FsError::Corrupted);
+        }
+        let inode = Arc::new(Self {
+            volume: volume.clone(),
+            number,
+            kind,
+            state: Mutex::new(InodeState {
+                raw,
+                removed: false,
+            }),
+        });
+        volume
+            .state
+            .lock()
+            .inodes
+            .insert(number, Arc::downgrade(&inode));
+        Ok(inode)
+    }
+
+    fn raw(&self) -> RawInode {
+        self.state.lock().raw.clone()
+    }
+
+    /// Takes `raw` as the inode and writes it to the device
+    async fn store(&self, raw: RawInode) -> Result<(), FsError> {
+        self.volume.write_inode(self.number, &raw).await?;
+        self.state.lock().raw = raw;
+        Ok(())
+    }
+
+    /// Group to allocate blocks and inodes for this inode from
+    fn group(&self) -> u32 {
+        self.volume.inode_group(self.number)
+    }
+
+    fn check_directory(&self) -> Result<(), FsError> {
+        match self.kind {
+            FileType::Directory => Ok(()),
+            _ => Err(FsError::NotDirectory),
+        }
+    }
+
+    /// Fails for read only volumes and for inodes that were freed
+    fn check_writable(&self) -> Result<(), FsError> {
+        self.volume.check_writable()?;
+        match self.state.lock().removed {
+            true => Err(FsError::NotFound),
+            false => Ok(()),
+        }
+    }
+
+    fn check_file(&self) -> Result<(), FsError> {
+        match self.kind {
+            FileType::Regular => Ok(()),
+            FileType::Directory => Err(FsError::IsDirectory),
+            FileType::Symlink => Err(FsError::InvalidArgument),
+        }
+    }
+
+    /// Short symbolic links have no blocks but those of their extended attributes
+    fn is_fast_symlink(&self, raw: &RawInode) -> bool {
+        let attribute_sectors = match raw.file_acl() {
+            0 => 0,
+            _ => (self.volume.block_size / 512) as u32,
+        };
+        self.kind == FileType::Symlink && raw.sectors() == attribute_sectors
+    }
+
+    async fn add_links(&self, delta: i16) -> Result<(), FsError> {
+        let mut raw = self.raw();
+        raw.set_links(raw.links().wrapping_add_signed(delta));
+        self.store(raw).await
+    }
+
+    /// Reads the bytes from `offset` of the file of `raw` into `buf`, which must be within
+    /// its size. Holes read as zeroes
+    async fn read(&self, raw: &RawInode, offset: u64, buf: &mut [u8]) -> Result<(), FsError> {
+        let block_size = self.volume.block_size;
+        let mut done = 0;
+        while done < buf.len() {
+            let position = offset + done as u64;
+            let in_block = position % block_size;
+            let count = ((block_size - in_block) as usize).min(buf.len() - done);
+            let part = &mut buf[done..done + count];
+            match self.volume.block_at(raw, position / block_size).await? {
+                Some(block) => {
+                    let address = self.volume.block_address(block) + in_block;
+                    self.volume.transfer(address, Transfer::Read(part)).await?
+                }
+                None => part.fill(0),
+            }
+            done += count;
+        }
+        Ok(())
+    }
+
+    /// Zeroes what follows the end of a file of `size` bytes in its last block, before the
+    /// file grows over it
+    async fn zero_tail(&self, raw: &RawInode, size: u64) -> Result<(), FsError> {
+        let block_size = self.volume.block_size;
+        let in_block = size % block_size;
+        if in_block == 0 {
+            return Ok(());
+        }
+        if let Some(block) = self.volume.block_at(raw, size / block_size).await? {
+            let address = self.volume.block_address(block) + in_block;
+            let len = (block_size - in_block) as usize;
+            self.volume.transfer(address, Transfer::Zero(len)).await?;
+        }
+        Ok(())
+    }
+
+    /// Allocates an inode of `kind` in the group of this directory. A directory gets its
+    /// first block with `.` and `..`, the entry naming it is left to the caller
+    async fn new_inode(&self, kind: FileType) -> Result<Arc<Ext2Inode>, FsError> {
+        let directory = kind == FileType::Directory;
+        let number = self.volume.allocate_inode(self.group(), directory).await?;
+        let mut raw = RawInode([0; GOOD_OLD_INODE_SIZE]);
+        match kind {
+            FileType::Directory => {
+                raw.set_mode(MODE_DIRECTORY | DIRECTORY_PERMISSIONS);
+                raw.set_links(2);
+            }
+            _ => {
+                raw.set_mode(MODE_REGULAR | FILE_PERMISSIONS);
+                raw.set_links(1);
+            }
+        }
+        let result = async {
+            // A previous inode may have left data past the fields written here
+            self.volume.clear_inode(number).await?;
+            self.volume.write_inode(number, &raw).await
+        }
+        .await;
+        if let Err(err) = result {
+            self.volume.free_inode(number, directory).await?;
+            return Err(err);
+        }
+
+        let inode = Ext2Inode::get(&self.volume, number).await?;
+        if directory {
+            if let Err(err) = inode.init_directory(self.number).await {
+                inode.unlink_inode().await?;
+                return Err(err);
+            }
+        }
+        Ok(inode)
+    }
+
+    /// Gives this new directory a block with `.` and `..`, the latter naming `parent`
+    async fn init_directory(&self, parent: u32) -> Result<(), FsError> {
+        let mut raw = self.raw();
+        let block = self.volume.map_block(&mut raw, 0, self.group()).await?;
+        let address = self.volume.block_address(block);
+        let dot_len = dir::record_len(1);
+        self.volume
+            .write_entry(address, dot_len, ".", self.number, FileType::Directory)
+            .await?;
+        self.volume
+            .write_entry(
+                address + dot_len,
+                self.volume.block_size - dot_len,
+                "..",
+                parent,
+                FileType::Directory,
+            )
+            .await?;
+        raw.set_size(self.volume.block_size);
+        self.store(raw).await
+    }
+
+    /// Adds an entry `name` for inode `number` of `kind` to this directory, in the space an
+    /// entry does not use or in a new block
+    async fn add_entry(&self, name: &str, number: u32, kind: FileType) -> Result<(), FsError> {
+        let mut raw = self.raw();
+        let needed = dir::record_len(name.len());
+        let found = self
+            .volume
+            .entries(&raw, 0, |entry, _| {
+                let used = match entry.inode {
+                    0 => 0,
+                    _ => dir::record_len(entry.name_len),
+                };
+                match entry.record_len - used >= needed {
+                    true => ControlFlow::Break((entry.clone(), used)),
+                    false => ControlFlow::Continue(()),
+                }
+            })
+            .await?;
+        let (address, record_len) = match found {
+            Some((entry, 0)) => (entry.address, entry.record_len),
+            Some((entry, used)) => {
+                self.volume
+                    .write_u16(entry.address + 4, used as u16)
+                    .await?;
+                (entry.address + used, entry.record_len - used)
+            }
+            None => {
+                let size = raw.size();
+                let block_size = self.volume.block_size;
+                let block = match self
+                    .volume
+                    .map_block(&mut raw, size / block_size, self.group())
+                    .await
+                {
+                    Ok(block) => block,
+                    Err(err) => {
+                        // An indirect block may have been taken on the way
+                        self.volume
+                            .free_blocks_from(&mut raw, size / block_size)
+                            .await?;
+                        return Err(err);
+                    }
+                };
+                raw.set_size(size + block_size);
+                (self.volume.block_address(block), block_size)
+            }
+        };
+        self.volume
+            .write_entry(address, record_len, name, number, kind)
+            .await?;
+        // The index would miss the entry, the entries themselves are enough
+        raw.set_flags(raw.flags() & !INDEX_FLAG);
+        self.store(raw).await
+    }
+
+    /// Removes `entry` from this directory
+    async fn remove_entry(&self, entry: &Entry) -> Result<(), FsError> {
+        match entry.previous {
+            Some((address, record_len)) => {
+                let merged = (record_len + entry.record_len) as u16;
+                self.volume.write_u16(address + 4, merged).await?;
+            }
+            None => self.volume.write_u32(entry.address, 0).await?,
+        }
+        let mut raw = self.raw();
+        if raw.flags() & INDEX_FLAG != 0 {
+            raw.set_flags(raw.flags() & !INDEX_FLAG);
+            self.store(raw).await?;
+        }
+        Ok(())
+    }
+
+    /// Drops a link to this inode, directories lose both of theirs. With the last one the
+    /// inode and its blocks are freed, it stays usable for those holding it but is empty and
+    /// can not be written
+    async fn unlink_inode(&self) -> Result<(), FsError> {
+        let mut raw = self.raw();
+        let links = match self.kind {
+            FileType::Directory => 0,
+            _ => raw.links().saturating_sub(1),
+        };
+        if links > 0 {
+            raw.set_links(links);
+            return self.store(raw).await;
+        }
+
+        if !self.is_fast_symlink(&raw) {
+            self.volume.free_blocks_from(&mut raw, 0).await?;
+        }
+        if raw.file_acl() != 0 {
+            self.volume.release_xattr(raw.file_acl()).await?;
+        }
+        // There is no clock for the deletion time, e2fsck takes a cleared inode as deleted
+        // as well
+        self.volume.clear_inode(self.number).await?;
+        self.volume
+            .free_inode(self.number, self.kind == FileType::Directory)
+            .await?;
+        self.volume.state.lock().inodes.remove(&self.number);
+        raw.set_size(0);
+        raw.set_links(0);
+        let mut state = self.state.lock();
+        state.raw = raw;
+        state.removed = true;
+        Ok(())
+    }
+}
+
+impl Inode for Ext2Inode {
+    fn metadata(&self) -> Metadata {
+        let state = self.state.lock();
+        Metadata {
+            inode: self.number as u64,
+            kind: self.kind,
+            size: state.raw.size(),
+            links: state.raw.links() as u32,
+        }
+    }
+
+    fn read_at<'a>(&'a self, offset: u64, buf: &'a mut [u8]) -> FsFuture<'a, usize> {
+        Box::pin(async move {
+            self.check_file()?;
+            let _guard = self.volume.lock.lock().await;
+            let raw = self.raw();
+            let size = raw.size();
+            if offset >= size {
+                return Ok(0);
+            }
+            let len = buf.len().min((size - offset) as usize);
+            self.read(&raw, offset, &mut buf[..len]).await?;
+            Ok(len)
+        })
+    }
+
+    fn write_at<'a>(&'a self, offset: u64, buf: &'a [u8]) -> FsFuture<'a, usize> {
+        Box::pin(async move {
+            self.check_file()?;
+            let _guard = self.volume.lock.lock().await;
+            self.check_writable()?;
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            offset
+                .checked_add(buf.len() as u64)
+                .filter(|&end| end <= self.volume.max_size())
+                .ok_or(FsError::NoSpace)?;
+            let mut raw = self.raw();
+            let size = raw.size();
+            if offset > size {
+                self.zero_tail(&raw, size).await?;
+            }
+
+            let block_size = self.volume.block_size;
+            let mut done = 0;
+            let result = async {
+                while done < buf.len() {
+                    let position = offset + done as u64;
+                    let in_block = position % block_size;
+                    let count = ((block_size - in_block) as usize).min(buf.len() - done);
+                    let block = self
+                        .volume
+                        .map_block(&mut raw, position / block_size, self.group())
+                        .await?;
+                    let address = self.volume.block_address(block) + in_block;
+                    self.volume
+                        .transfer(address, Transfer::Write(&buf[done..done + count]))
+                        .await?;
+                    done += count;
+                }
+                Ok(())
+            }
+            .await;
+            raw.set_size(size.max(offset + done as u64));
+            if result.is_err() {
+                // Blocks taken past the end for what could not be written are given back
+                let blocks = raw.size().div_ceil(block_size);
+                self.volume.free_blocks_from(&mut raw, blocks).await?;
+            }
+            self.store(raw).await?;
+            match result {
+                Ok(()) => Ok(done),
+                // Short of space part of the way, what fit is written
+                Err(_) if done > 0 => Ok(done),
+                Err(err) => Err(err),
+            }
+        })
+    }
+
+    fn truncate(&self, size: u64) -> FsFuture<'_, ()> {
+        Box::pin(async move {
+            self.check_file()?;
+            let _guard = self.volume.lock.lock().await;
+            self.check_writable()?;
+            if size > self.volume.max_size() {
+                return Err(FsError::NoSpace);
+            }
+            let mut raw = self.raw();
+            let old_size = raw.size();
+            let result = match size < old_size {
+                true => {
+                    let blocks = size.div_ceil(self.volume.block_size);
+                    self.volume.free_blocks_from(&mut raw, blocks).await
+                }
+                false => self.zero_tail(&raw, old_size).await,
+            };
+            if result.is_ok() {
+                raw.set_size(size);
+            }
+            self.store(raw).await?;
+            result
+        })
+    }
+
+    fn lookup<'a>(&'a self, name: &'a str) -> FsFuture<'a, Arc<dyn Inode>> {
+        Box::pin(async move {
+            self.check_directory()?;
+            let _guard = self.volume.lock.lock().await;
+            let entry = self
+                .volume
+                .find(&self.raw(), name)
+                .await?
+                .ok_or(FsError::NotFound)?;
+            Ok(Ext2Inode::get(&self.volume, entry.inode).await? as Arc<dyn Inode>)
+        })
+    }
+
+    fn create<'a>(&'a self, name: &'a str, kind: FileType) -> FsFuture<'a, Arc<dyn Inode>> {
+        Box::pin(async move {
+            self.check_directory()?;
+            if kind == FileType::Symlink {
+                return Err(FsError::NotSupported);
+            }
+            let _guard = self.volume.lock.lock().await;
+            self.check_writable()?;
+            if self.volume.find(&self.raw(), name).await?.is_some() {
+                return Err(FsError::AlreadyExists);
+            }
+
+            let inode = self.new_inode(kind).await?;
+            if let Err(err) = self.add_entry(name, inode.number, kind).await {
+                inode.unlink_inode().await?;
+                return Err(err);
+            }
+            // The `..` of a new directory links here
+            if kind == FileType::Directory {
+                self.add_links(1).await?;
+            }
+            Ok(inode as Arc<dyn Inode>)
+        })
+    }
+
+    fn unlink<'a>(&'a self, name: &'a str) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            self.check_directory()?;
+            let _guard = self.volume.lock.lock().await;
+            self.check_writable()?;
+            let entry = self
+                .volume
+                .find(&self.raw(), name)
+                .await?
+                .ok_or(FsError::NotFound)?;
+            let inode = Ext2Inode::get(&self.volume, entry.inode).await?;
+            let directory = inode.kind == FileType::Directory;
+            if directory && !self.volume.is_empty(&inode.raw()).await? {
+                return Err(FsError::NotEmpty);
+            }
+            self.remove_entry(&entry).await?;
+            inode.unlink_inode().await?;
+            if directory {
+                self.add_links(-1).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn rename<'a>(
+        &'a self,
+        old_name: &'a str,
+        new_parent: &'a dyn Inode,
+        new_name: &'a str,
+    ) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            let new_parent = new_parent
+                .as_any()
+                .downcast_ref::<Ext2Inode>()
+                .filter(|parent| Arc::ptr_eq(&parent.volume, &self.volume))
+                .ok_or(FsError::CrossDevice)?;
+            self.check_directory()?;
+            new_parent.check_directory()?;
+            let _guard = self.volume.lock.lock().await;
+            self.check_writable()?;
+            new_parent.check_writable()?;
+
+            let moved = self
+                .volume
+                .find(&self.raw(), old_name)
+                .await?
+                .ok_or(FsError::NotFound)?;
+            let replaced = self.volume.find(&new_parent.raw(), new_name).await?;
+            // Both names are links to the same inode already
+            if replaced
+                .as_ref()
+                .is_some_and(|replaced| replaced.inode == moved.inode)
+            {
+                return Ok(());
+            }
+            let inode = Ext2Inode::get(&self.volume, moved.inode).await?;
+            let directory = inode.kind == FileType::Directory;
+
+            match replaced {
+                Some(replaced) => {
+                    let old = Ext2Inode::get(&self.volume, replaced.inode).await?;
+                    match (directory, old.kind == FileType::Directory) {
+                        (true, true) if !self.volume.is_empty(&old.raw()).await? => {
+                            return Err(FsError::NotEmpty)
+                        }
+                        (true, false) => return Err(FsError::NotDirectory),
+                        (false, true) => return Err(FsError::IsDirectory),
+                        _ => {}
+                    }
+                    self.volume
+                        .set_entry(&replaced, moved.inode, inode.kind)
+                        .await?;
+                    old.unlink_inode().await?;
+                    if old.kind == FileType::Directory {
+                        new_parent.add_links(-1).await?;
+                    }
+                }
+                None => {
+                    new_parent
+                        .add_entry(new_name, moved.inode, inode.kind)
+                        .await?
+                }
+            }
+
+            // Adding may have split the entries around the old one
+            let moved = self
+                .volume
+                .find(&self.raw(), old_name)
+                .await?
+                .ok_or(FsError::Corrupted)?;
+            self.remove_entry(&moved).await?;
+            if directory && self.number != new_parent.number {
+                let dot_dot = self
+                    .volume
+                    .find(&inode.raw(), "..")
+                    .await?
+                    .ok_or(FsError::Corrupted)?;
+                self.volume
+                    .set_entry(&dot_dot, new_parent.number, FileType::Directory)
+                    .await?;
+                self.add_links(-1).await?;
+                new_parent.add_links(1).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn read_dir(&self, position: u64) -> FsFuture<'_, Option<(DirEntry, u64)>> {
+        Box::pin(async move {
+            self.check_directory()?;
+            let _guard = self.volume.lock.lock().await;
+            let raw = self.raw();
+            let mut position = position;
+            loop {
+                let found = self
+                    .volume
+                    .entries(&raw, position, |entry, name| {
+                        match entry.inode == 0 || dir::is_dot(name) {
+                            true => ControlFlow::Continue(()),
+                            false => ControlFlow::Break((
+                                entry.clone(),
+                                String::from_utf8_lossy(name).into_owned(),
+                            )),
+                        }
+                    })
+                    .await?;
+                let Some((entry, name)) = found else {
+                    return Ok(None);
+                };
+                let kind = match self.volume.filetype {
+                    true => dir::kind(entry.file_type),
+                    false => self.volume.read_inode(entry.inode).await?.kind(),
+                };
+                position = entry.position + entry.record_len;
+                // Device files, pipes and sockets can not be opened here, so they are left
+                // out
+                if let Some(kind) = kind {
+                    let entry = DirEntry {
+                        inode: entry.inode as u64,
+                        kind,
+                        name,
+                    };
+                    return Ok(Some((entry, position)));
+                }
+            }
+        })
+    }
+
+    fn read_link(&self) -> FsFuture<'_, String> {
+        Box::pin(async move {
+            if self.kind != FileType::Symlink {
+                return Err(FsError::InvalidArgument);
+            }
+            let _guard = self.volume.lock.lock().await;
+            let raw = self.raw();
+            let size = raw.size() as usize;
+            let target = match self.is_fast_symlink(&raw) {
+                true => {
+                    let end = BLOCKS_OFFSET + size.min(BLOCK_POINTERS * 4);
+                    raw.0[BLOCKS_OFFSET..end].to_vec()
+                }
+                false => {
+                    if size > PATH_MAX {
+                        return Err(FsError::Corrupted);
+                    }
+                    let mut target = vec![0; size];
+                    self.read(&raw, 0, &mut target).await?;
+                    target
+                }
+            };
+            String::from_utf8(target).map_err(|_| FsError::Corrupted)
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,846 @@
+//! ext2, as made by `mke2fs` on Linux.
+//!
+//! The blocks after the superblock are split into groups, each with a bitmap of its used
+//! blocks, a bitmap of its used inodes and a table of inodes. The group descriptors, in the
+//! block after the superblock, say where these are. An inode maps the blocks of its file with
+//! twelve direct block numbers, then blocks of block numbers one, two and three levels deep.
+//! Directories are files of entries, each naming an inode.
+//!
+//! The device is read and written by byte address through the block cache, so blocks of the
+//! filesystem need not be the size of those of the device. Features that change the layout
+//! refuse the mount, unknown ones that only matter for writing make it read only. Operations
+//! on a volume run one at a time, as most of them touch several blocks that must agree.
+
+extern crate alloc;
+
+use core::ops::ControlFlow;
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::{
+        block::{cache, BlockDevice},
+        vfs::{
+            lock::OperationLock, FileSystem, FileSystemType, FileType, FsError, FsFuture, Inode,
+        },
+    },
+};
+
+mod dir;
+mod inode;
+
+use dir::Entry;
+pub use inode::Ext2Inode;
+use inode::RawInode;
+
+/// The superblock is at this byte of the device whatever the block size
+const SUPERBLOCK_OFFSET: u64 = 1024;
+/// Bytes of the superblock this driver reads
+const SUPERBLOCK_SIZE: usize = 128;
+const MAGIC: u16 = 0xEF53;
+/// Block sizes are `1024 << n` for n up to this, as Linux has them no larger than a page
+const MAX_LOG_BLOCK_SIZE: u32 = 2;
+const DESCRIPTOR_SIZE: u64 = 32;
+
+/// Inode size and first inode for files of revision 0, later revisions say in the superblock
+const GOOD_OLD_INODE_SIZE: usize = 128;
+const GOOD_OLD_FIRST_INODE: u32 = 11;
+const ROOT_INODE: u32 = 2;
+
+/// Directory entries carry the type of their inode
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+/// Only some groups keep a copy of the superblock
+const RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+/// Files may be larger than 2 GiB
+const RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+const RO_COMPAT_SUPPORTED: u32 = RO_COMPAT_SPARSE_SUPER | RO_COMPAT_LARGE_FILE;
+
+/// Inodes map this many blocks directly, the next three block numbers are for the indirect
+/// blocks of one, two and three levels
+const DIRECT_BLOCKS: u64 = 12;
+const BLOCK_POINTERS: usize = 15;
+
+/// Extended attribute blocks, which inodes may share
+const XATTR_MAGIC: u32 = 0xEA02_0000;
+
+pub const FILESYSTEM_TYPE: FileSystemType = FileSystemType {
+    name: "ext2",
+    mount,
+};
+
+fn mount(device: Arc<dyn BlockDevice>) -> FsFuture<'static, Arc<dyn FileSystem>> {
+    Box::pin(async move { Ok(Arc::new(Ext2Fs::new(device).await?) as Arc<dyn FileSystem>) })
+}
+
+#[derive(Debug)]
+pub struct Ext2Fs {
+    volume: Arc<Volume>,
+    root: Arc<Ext2Inode>,
+}
+
+#[derive(Debug)]
+struct Volume {
+    device: Arc<dyn BlockDevice>,
+    /// In bytes
+    block_size: u64,
+    block_count: u32,
+    /// Block of the superblock, the groups start there
+    first_data_block: u32,
+    blocks_per_group: u32,
+    inode_count: u32,
+    inodes_per_group: u32,
+    /// In bytes, of which the first `GOOD_OLD_INODE_SIZE` are used
+    inode_size: u64,
+    /// Inodes before this one are reserved
+    first_inode: u32,
+    groups: Vec<Group>,
+    filetype: bool,
+    large_file: bool,
+    read_only: bool,
+    lock: OperationLock,
+    state: Mutex<VolumeState>,
+}
+
+#[derive(Debug)]
+struct Group {
+    block_bitmap: u32,
+    inode_bitmap: u32,
+    inode_table: u32,
+}
+
+#[derive(Debug)]
+struct VolumeState {
+    /// Counts of the superblock
+    free_blocks: u32,
+    free_inodes: u32,
+    /// The counts changed since they were last written
+    superblock_dirty: bool,
+    /// Inodes in use by number, so an inode is in memory once
+    inodes: BTreeMap<u32, Weak<Ext2Inode>>,
+}
+
+/// What `transfer` does with the bytes at an address
+enum Transfer<'a> {
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+    Zero(usize),
+}
+
+impl Ext2Fs {
+    /// Reads the superblock of `device`, fails with `FsError::UnknownFormat` unless it
+    /// describes an ext2 volume
+    pub async fn new(device: Arc<dyn BlockDevice>) -> Result<Self, FsError> {
+        let device_size = device.block_count() * device.block_size() as u64;
+        if device_size < SUPERBLOCK_OFFSET + SUPERBLOCK_SIZE as u64 {
+            return Err(FsError::UnknownFormat);
+        }
+        let mut superblock = [0; SUPERBLOCK_SIZE];
+        transfer(&device, SUPERBLOCK_OFFSET, Transfer::Read(&mut superblock)).await?;
+        let mut volume = Volume::parse(device, &superblock)?;
+        volume.read_groups().await?;
+
+        let volume = Arc::new(volume);
+        let root = Ext2Inode::get(&volume, ROOT_INODE).await?;
+        if root.metadata().kind != FileType::Directory {
+            return Err(FsError::Corrupted);
+        }
+        Ok(Self { volume, root })
+    }
+}
+
+impl FileSystem for Ext2Fs {
+    fn name(&self) -> &str {
+        "ext2"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+
+    fn sync(&self) -> FsFuture<'_, ()> {
+        Box::pin(async move {
+            let _guard = self.volume.lock.lock().await;
+            self.volume.sync().await
+        })
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Reads, writes or zeroes the bytes from byte `address` of `device` on
+async fn transfer(
+    device: &Arc<dyn BlockDevice>,
+    address: u64,
+    mut transfer: Transfer<'_>,
+) -> Result<(), FsError> {
+    let len = match &transfer {
+        Transfer::Read(buf) => buf.len(),
+        Transfer::Write(buf) => buf.len(),
+        Transfer::Zero(len) => *len,
+    };
+    let block_size = device.block_size() as u64;
+    let mut done = 0;
+    while done < len {
+        let position = address + done as u64;
+        let start = (position % block_size) as usize;
+        let count = (block_size as usize - start).min(len - done);
+        let range = start..start + count;
+        let buffer = cache::get(device, position / block_size).await?;
+        match &mut transfer {
+            Transfer::Read(buf) => {
+                buffer
+                    .read(|data| buf[done..done + count].copy_from_slice(&data[range]))
+                    .await
+            }
+            Transfer::Write(buf) => {
+                buffer
+                    .write(|data| data[range].copy_from_slice(&buf[done..done + count]))
+                    .await
+            }
+            Transfer::Zero(_) => buffer.write(|data| data[range].fill(0)).await,
+        }
+        done += count;
+    }
+    Ok(())
+}
+
+impl Volume {
+    fn parse(device: Arc<dyn BlockDevice>, superblock: &[u8]) -> Result<Self, FsError> {
+        if read_u16(superblock, 56) != MAGIC {
+            return Err(FsError::UnknownFormat);
+        }
+        let inode_count = read_u32(superblock, 0);
+        let block_count = read_u32(superblock, 4);
+        let free_blocks = read_u32(superblock, 12);
+        let free_inodes = read_u32(superblock, 16);
+        let first_data_block = read_u32(superblock, 20);
+        let log_block_size = read_u32(superblock, 24);
+        let blocks_per_group = read_u32(superblock, 32);
+        let inodes_per_group = read_u32(superblock, 40);
+        let revision = read_u32(superblock, 76);
+        let (first_inode, inode_size) = match revision {
+            0 => (GOOD_OLD_FIRST_INODE, GOOD_OLD_INODE_SIZE as u64),
+            _ => (read_u32(superblock, 84), read_u16(superblock, 88) as u64),
+        };
+        let incompat = read_u32(superblock, 96);
+        let ro_compat = read_u32(superblock, 100);
+
+        // ext3 needing recovery and ext4 lay files out differently
+        if log_block_size > MAX_LOG_BLOCK_SIZE || incompat & !INCOMPAT_FILETYPE != 0 {
+            return Err(FsError::NotSupported);
+        }
+        let block_size = 1024 << log_block_size;
+        if blocks_per_group == 0
+            || blocks_per_group as u64 > block_size * 8
+            || inodes_per_group == 0
+            || inodes_per_group as u64 > block_size * 8
+            || !inode_size.is_power_of_two()
+            || !(GOOD_OLD_INODE_SIZE as u64..=block_size).contains(&inode_size)
+            || first_inode <= ROOT_INODE
+            || first_data_block >= block_count
+        {
+            return Err(FsError::Corrupted);
+        }
+        let device_size = device.block_count() * device.block_size() as u64;
+        if block_count as u64 * block_size > device_size {
+            return Err(FsError::Corrupted);
+        }
+        let group_count = (block_count - first_data_block).div_ceil(blocks_per_group);
+        if inode_count as u64 > group_count as u64 * inodes_per_group as u64
+            || first_inode > inode_count
+        {
+            return Err(FsError::Corrupted);
+        }
+
+        let read_only = device.is_read_only() || ro_compat & !RO_COMPAT_SUPPORTED != 0;
+        Ok(Self {
+            device,
+            block_size,
+            block_count,
+            first_data_block,
+            blocks_per_group,
+            inode_count,
+            inodes_per_group,
+            inode_size,
+            first_inode,
+            groups: Vec::with_capacity(group_count as usize),
+            filetype: incompat & INCOMPAT_FILETYPE != 0,
+            large_file: ro_compat & RO_COMPAT_LARGE_FILE != 0,
+            read_only,
+            lock: OperationLock::new(),
+            state: Mutex::new(VolumeState {
+                free_blocks,
+                free_inodes,
+                superblock_dirty: false,
+                inodes: BTreeMap::new(),
+            }),
+        })
+    }
+
+    /// Reads the descriptors of the groups
+    async fn read_groups(&mut self) -> Result<(), FsError> {
+        let group_count =
+            (self.block_count - self.first_data_block).div_ceil(self.blocks_per_group);
+        let inode_table_blocks =
+            (self.inodes_per_group as u64 * self.inode_size).div_ceil(self.block_size);
+        for group in 0..group_count {
+            let mut descriptor = [0; DESCRIPTOR_SIZE as usize];
+            transfer(
+                &self.device,
+                self.descriptor_address(group),
+                Transfer::Read(&mut descriptor),
+            )
+            .await?;
+            let group = Group {
+                block_bitmap: read_u32(&descriptor, 0),
+                inode_bitmap: read_u32(&descriptor, 4),
+                inode_table: read_u32(&descriptor, 8),
+            };
+            if !self.is_valid(group.block_bitmap)
+                || !self.is_valid(group.inode_bitmap)
+                || group.inode_table as u64 + inode_table_blocks > self.block_count as u64
+                || !self.is_valid(group.inode_table)
+            {
+                return Err(FsError::Corrupted);
+            }
+            self.groups.push(group);
+        }
+        Ok(())
+    }
+
+    fn is_valid(&self, block: u32) -> bool {
+        (self.first_data_block..self.block_count).contains(&block)
+    }
+
+    fn check_block(&self, block: u32) -> Result<u32, FsError> {
+        match self.is_valid(block) {
+            true => Ok(block),
+            false => Err(FsError::Corrupted),
+        }
+    }
+
+    fn check_writable(&self) -> Result<(), FsError> {
+        match self.read_only {
+            true => Err(FsError::ReadOnly),
+            false => Ok(()),
+        }
+    }
+
+    fn block_address(&self, block: u32) -> u64 {
+        block as u64 * self.block_size
+    }
+
+    /// Byte address of the descriptor of `group`, in the blocks after the superblock
+    fn descriptor_address(&self, group: u32) -> u64 {
+        self.block_address(self.first_data_block + 1) + group as u64 * DESCRIPTOR_SIZE
+    }
+
+    /// Number of blocks of `group`, the last one may have fewer
+    fn blocks_in_group(&self, group: u32) -> u32 {
+        let start = self.first_data_block + group * self.blocks_per_group;
+        (self.block_count - start).min(self.blocks_per_group)
+    }
+
+    /// Block numbers an indirect block holds
+    fn pointers_per_block(&self) -> u64 {
+        self.block_size / 4
+    }
+
+    /// Largest size of a file
+    fn max_size(&self) -> u64 {
+        let pointers = self.pointers_per_block();
+        let blocks = DIRECT_BLOCKS + pointers + pointers.pow(2) + pointers.pow(3);
+        match self.large_file {
+            true => blocks * self.block_size,
+            false => i32::MAX as u64,
+        }
+    }
+
+    async fn transfer(&self, address: u64, data: Transfer<'_>) -> Result<(), FsError> {
+        transfer(&self.device, address, data).await
+    }
+
+    async fn read_u32(&self, address: u64) -> Result<u32, FsError> {
+        let mut data = [0; 4];
+        self.transfer(address, Transfer::Read(&mut data)).await?;
+        Ok(u32::from_le_bytes(data))
+    }
+
+    async fn write_u32(&self, address: u64, value: u32) -> Result<(), FsError> {
+        self.transfer(address, Transfer::Write(&value.to_le_bytes()))
+            .await
+    }
+
+    async fn read_u16(&self, address: u64) -> Result<u16, FsError> {
+        let mut data = [0; 2];
+        self.transfer(address, Transfer::Read(&mut data)).await?;
+        Ok(u16::from_le_bytes(data))
+    }
+
+    async fn write_u16(&self, address: u64, value: u16) -> Result<(), FsError> {
+        self.transfer(address, Transfer::Write(&value.to_le_bytes()))
+            .await
+    }
+
+    /// Adds to the counts of free blocks, free inodes and directories of `group`, and to
+    /// those of the superblock
+    async fn count(
+        &self,
+        group: u32,
+        blocks: i32,
+        inodes: i32,
+        directories: i32,
+    ) -> Result<(), FsError> {
+        let address = self.descriptor_address(group) + 12;
+        let mut counts = [0; 6];
+        self.transfer(address, Transfer::Read(&mut counts)).await?;
+        for (index, delta) in [blocks, inodes, directories].into_iter().enumerate() {
+            let count = read_u16(&counts, index * 2).wrapping_add_signed(delta as i16);
+            counts[index * 2..index * 2 + 2].copy_from_slice(&count.to_le_bytes());
+        }
+        self.transfer(address, Transfer::Write(&counts)).await?;
+
+        let mut state = self.state.lock();
+        state.free_blocks = state.free_blocks.wrapping_add_signed(blocks);
+        state.free_inodes = state.free_inodes.wrapping_add_signed(inodes);
+        state.superblock_dirty = true;
+        Ok(())
+    }
+
+    /// Sets the first clear bit from `start` on among the first `count` of the bitmap in
+    /// `bitmap` and returns its index
+    async fn take_bit(&self, bitmap: u32, start: u32, count: u32) -> Result<Option<u32>, FsError> {
+        let address = self.block_address(bitmap);
+        let device_block = self.device.block_size() as u64;
+        let bytes = count.div_ceil(8);
+        let mut byte = start / 8;
+        while byte < bytes {
+            let position = address + byte as u64;
+            let offset = (position % device_block) as usize;
+            // Up to the end of the device block or of the bitmap
+            let len = (device_block as usize - offset).min((bytes - byte) as usize) as u32;
+            let buffer = cache::get(&self.device, position / device_block).await?;
+            let first = byte * 8;
+            let index = |bit: u32| offset + ((bit - first) / 8) as usize;
+            let found = buffer
+                .read(|data| {
+                    (first.max(start)..(first + len * 8).min(count))
+                        .find(|&bit| data[index(bit)] & 1 << (bit % 8) == 0)
+                })
+                .await;
+            if let Some(bit) = found {
+                buffer
+                    .write(|data| data[index(bit)] |= 1 << (bit % 8))
+                    .await;
+                return Ok(Some(bit));
+            }
+            byte += len;
+        }
+        Ok(None)
+    }
+
+    /// Clears `bit` of the bitmap in `bitmap`, it must be set
+    async fn clear_bit(&self, bitmap: u32, bit: u32) -> Result<(), FsError> {
+        let address = self.block_address(bitmap) + bit as u64 / 8;
+        let mut byte = [0];
+        self.transfer(address, Transfer::Read(&mut byte)).await?;
+        if byte[0] & 1 << (bit % 8) == 0 {
+            return Err(FsError::Corrupted);
+        }
+        byte[0] &= !(1 << (bit % 8));
+        self.transfer(address, Transfer::Write(&byte)).await
+    }
+
+    /// Takes a free block, zero filled, preferably from `goal`, the group of the inode it is
+    /// for
+    async fn allocate_block(&self, goal: u32) -> Result<u32, FsError> {
+        let group_count = self.groups.len() as u32;
+        for group in (0..group_count).map(|offset| (goal + offset) % group_count) {
+            let free = self.read_u16(self.descriptor_address(group) + 12).await?;
+            if free == 0 {
+                continue;
+            }
+            let bitmap = self.groups[group as usize].block_bitmap;
+            let Some(bit) = self
+                .take_bit(bitmap, 0, self.blocks_in_group(group))
+                .await?
+            else {
+                continue;
+            };
+            self.count(group, -1, 0, 0).await?;
+            let block = self.first_data_block + group * self.blocks_per_group + bit;
+            self.transfer(
+                self.block_address(block),
+                Transfer::Zero(self.block_size as usize),
+            )
+            .await?;
+            return Ok(block);
+        }
+        Err(FsError::NoSpace)
+    }
+
+    async fn free_block(&self, block: u32) -> Result<(), FsError> {
+        self.check_block(block)?;
+        let group = (block - self.first_data_block) / self.blocks_per_group;
+        let bit = (block - self.first_data_block) % self.blocks_per_group;
+        self.clear_bit(self.groups[group as usize].block_bitmap, bit)
+            .await?;
+        self.count(group, 1, 0, 0).await
+    }
+
+    /// Takes a free inode, preferably from `goal`, the group of the directory it goes into
+    async fn allocate_inode(&self, goal: u32, directory: bool) -> Result<u32, FsError> {
+        let group_count = self.groups.len() as u32;
+        for group in (0..group_count).map(|offset| (goal + offset) % group_count) {
+            let free = self.read_u16(self.descriptor_address(group) + 14).await?;
+            if free == 0 {
+                continue;
+            }
+            let first = group * self.inodes_per_group + 1;
+            let start = self.first_inode.saturating_sub(first);
+            let count = (self.inode_count - first + 1).min(self.inodes_per_group);
+            let bitmap = self.groups[group as usize].inode_bitmap;
+            let Some(bit) = self.take_bit(bitmap, start, count).await? else {
+                continue;
+            };
+            self.count(group, 0, -1, directory as i32).await?;
+            return Ok(first + bit);
+        }
+        Err(FsError::NoSpace)
+    }
+
+    async fn free_inode(&self, number: u32, directory: bool) -> Result<(), FsError> {
+        let group = (number - 1) / self.inodes_per_group;
+        let bit = (number - 1) % self.inodes_per_group;
+        self.clear_bit(self.groups[group as usize].inode_bitmap, bit)
+            .await?;
+        self.count(group, 0, 1, -(directory as i32)).await
+    }
+
+    /// Group of inode `number`
+    fn inode_group(&self, number: u32) -> u32 {
+        (number - 1) / self.inodes_per_group
+    }
+
+    fn inode_address(&self, number: u32) -> Result<u64, FsError> {
+        if !(1..=self.inode_count).contains(&number) {
+            return Err(FsError::Corrupted);
+        }
+        let group = &self.groups[self.inode_group(number) as usize];
+        let index = (number - 1) % self.inodes_per_group;
+        Ok(self.block_address(group.inode_table) + index as u64 * self.inode_size)
+    }
+
+    async fn read_inode(&self, number: u32) -> Result<RawInode, FsError> {
+        let mut raw = RawInode([0; GOOD_OLD_INODE_SIZE]);
+        self.transfer(self.inode_address(number)?, Transfer::Read(&mut raw.0))
+            .await?;
+        Ok(raw)
+    }
+
+    async fn write_inode(&self, number: u32, raw: &RawInode) -> Result<(), FsError> {
+        self.transfer(self.inode_address(number)?, Transfer::Write(&raw.0))
+            .await
+    }
+
+    /// Zeroes all of the record of inode `number`, including what is past the fields this
+    /// driver knows
+    async fn clear_inode(&self, number: u32) -> Result<(), FsError> {
+        self.transfer(
+            self.inode_address(number)?,
+            Transfer::Zero(self.inode_size as usize),
+        )
+        .await
+    }
+
+    /// Where the number of block `index` of a file is kept: the slot of the inode holding
+    /// the first block on the way, and the index into each indirect block after it
+    fn block_path(&self, index: u64) -> Result<(usize, [u64; 3], usize), FsError> {
+        if index < DIRECT_BLOCKS {
+            return Ok((index as usize, [0; 3], 0));
+        }
+        let pointers = self.pointers_per_block();
+        let mut rest = index - DIRECT_BLOCKS;
+        let mut span = pointers;
+        for depth in 1..=3 {
+            if rest < span {
+                let mut offsets = [0; 3];
+                for offset in offsets[..depth].iter_mut().rev() {
+                    *offset = rest % pointers;
+                    rest /= pointers;
+                }
+                return Ok((DIRECT_BLOCKS as usize + depth - 1, offsets, depth));
+            }
+            rest -= span;
+            span *= pointers;
+        }
+        Err(FsError::NoSpace)
+    }
+
+    /// The block holding block `index` of the file of `raw`, None for holes
+    async fn block_at(&self, raw: &RawInode, index: u64) -> Result<Option<u32>, FsError> {
+        let (slot, offsets, depth) = self.block_path(index)?;
+        let mut block = raw.block(slot);
+        for &offset in &offsets[..depth] {
+            if block == 0 {
+                return Ok(None);
+            }
+            let address = self.block_address(self.check_block(block)?) + offset * 4;
+            block = self.read_u32(address).await?;
+        }
+        match block {
+            0 => Ok(None),
+            block => self.check_block(block).map(Some),
+        }
+    }
+
+    /// The block holding block `index` of the file of `raw`, allocated in `goal` along with
+    /// the indirect blocks leading to it if it is a hole
+    async fn map_block(&self, raw: &mut RawInode, index: u64, goal: u32) -> Result<u32, FsError> {
+        let sectors = (self.block_size / 512) as u32;
+        let (slot, offsets, depth) = self.block_path(index)?;
+        let mut block = raw.block(slot);
+        if block == 0 {
+            block = self.allocate_block(goal).await?;
+            raw.set_block(slot, block);
+            raw.set_sectors(raw.sectors() + sectors);
+        }
+        for &offset in &offsets[..depth] {
+            let address = self.block_address(self.check_block(block)?) + offset * 4;
+            block = match self.read_u32(address).await? {
+                0 => {
+                    let new = self.allocate_block(goal).await?;
+                    self.write_u32(address, new).await?;
+                    raw.set_sectors(raw.sectors() + sectors);
+                    new
+                }
+                next => next,
+            };
+        }
+        self.check_block(block)
+    }
+
+    /// Frees the blocks of the file of `raw` from block `first` on, and the indirect blocks
+    /// left without any
+    async fn free_blocks_from(&self, raw: &mut RawInode, first: u64) -> Result<(), FsError> {
+        let mut freed = 0;
+        let result = async {
+            for slot in first.min(DIRECT_BLOCKS)..DIRECT_BLOCKS {
+                let block = raw.block(slot as usize);
+                if block != 0 {
+                    self.free_block(block).await?;
+                    raw.set_block(slot as usize, 0);
+                    freed += 1;
+                }
+            }
+            let mut start = DIRECT_BLOCKS;
+            let mut span = 1;
+            for depth in 1..=3 {
+                span *= self.pointers_per_block();
+                let slot = DIRECT_BLOCKS as usize + depth - 1;
+                let block = raw.block(slot);
+                if block != 0 && first < start + span {
+                    let from = first.saturating_sub(start);
+                    freed += self.free_tree(block, depth as u32, from).await?;
+                    if from == 0 {
+                        raw.set_block(slot, 0);
+                    }
+                }
+                start += span;
+            }
+            Ok(())
+        }
+        .await;
+        let sectors = freed * (self.block_size / 512) as u32;
+        raw.set_sectors(raw.sectors().saturating_sub(sectors));
+        result
+    }
+
+    /// Frees the blocks mapped by the indirect `block` of `depth` levels from index `from`
+    /// on, and `block` itself if that is all of them. Returns how many were freed
+    fn free_tree(&self, block: u32, depth: u32, from: u64) -> FsFuture<'_, u32> {
+        Box::pin(async move {
+            let address = self.block_address(self.check_block(block)?);
+            let span = self.pointers_per_block().pow(depth - 1);
+            let mut freed = 0;
+            for index in from / span..self.pointers_per_block() {
+                let child = self.read_u32(address + index * 4).await?;
+                if child == 0 {
+                    continue;
+                }
+                let child_from = match index == from / span {
+                    true => from % span,
+                    false => 0,
+                };
+                freed += match depth {
+                    1 => {
+                        self.free_block(child).await?;
+                        1
+                    }
+                    _ => self.free_tree(child, depth - 1, child_from).await?,
+                };
+                if child_from == 0 && from != 0 {
+                    self.write_u32(address + index * 4, 0).await?;
+                }
+            }
+            if from == 0 {
+                self.free_block(block).await?;
+                freed += 1;
+            }
+            Ok(freed)
+        })
+    }
+
+    /// Calls `f` with every entry of the directory of `raw` and its name, from the one at
+    /// byte `start` on, until `f` breaks. Empty entries are included
+    async fn entries<R>(
+        &self,
+        raw: &RawInode,
+        start: u64,
+        mut f: impl FnMut(&Entry, &[u8]) -> ControlFlow<R>,
+    ) -> Result<Option<R>, FsError> {
+        let size = raw.size();
+        // Entries may have been merged since `start` was handed out, so the block is read
+        // from its first entry on
+        let mut position = start - start % self.block_size;
+        let mut previous = None;
+        let mut name = [0; dir::NAME_MAX];
+        while position < size {
+            let in_block = position % self.block_size;
+            if in_block == 0 {
+                previous = None;
+            }
+            let block = self
+                .block_at(raw, position / self.block_size)
+                .await?
+                .ok_or(FsError::Corrupted)?;
+            let address = self.block_address(block) + in_block;
+            let mut header = [0; dir::HEADER_SIZE];
+            self.transfer(address, Transfer::Read(&mut header)).await?;
+            let entry = Entry::parse(&header, position, address, previous, self.filetype);
+            if entry.record_len < dir::record_len(entry.name_len)
+                || !entry.record_len.is_multiple_of(4)
+                || in_block + entry.record_len > self.block_size
+            {
+                return Err(FsError::Corrupted);
+            }
+            let name = &mut name[..entry.name_len];
+            if entry.inode != 0 {
+                self.transfer(
+                    address + dir::HEADER_SIZE as u64,
+                    Transfer::Read(&mut *name),
+                )
+                .await?;
+            }
+            if position >= start {
+                if let ControlFlow::Break(result) = f(&entry, name) {
+                    return Ok(Some(result));
+                }
+            }
+            previous = Some((address, entry.record_len));
+            position += entry.record_len;
+        }
+        Ok(None)
+    }
+
+    /// The entry `name` of the directory of `raw`
+    async fn find(&self, raw: &RawInode, name: &str) -> Result<Option<Entry>, FsError> {
+        self.entries(raw, 0, |entry, entry_name| {
+            match entry.inode != 0 && entry_name == name.as_bytes() {
+                true => ControlFlow::Break(entry.clone()),
+                false => ControlFlow::Continue(()),
+            }
+        })
+        .await
+    }
+
+    /// The directory of `raw` has no entries besides `.` and `..`
+    async fn is_empty(&self, raw: &RawInode) -> Result<bool, FsError> {
+        let found = self
+            .entries(raw, 0, |entry, name| {
+                match entry.inode == 0 || dir::is_dot(name) {
+                    true => ControlFlow::Continue(()),
+                    false => ControlFlow::Break(()),
+                }
+            })
+            .await?;
+        Ok(found.is_none())
+    }
+
+    /// What entries store in place of the file type for inodes of `kind`. Without
+    /// `INCOMPAT_FILETYPE` it is the high byte of the name length, zero as names are shorter
+    /// than 256 bytes
+    fn entry_type(&self, kind: FileType) -> u8 {
+        match self.filetype {
+            true => dir::file_type(kind),
+            false => 0,
+        }
+    }
+
+    /// Writes an entry `name` for inode `number` of `kind` at byte `address`
+    async fn write_entry(
+        &self,
+        address: u64,
+        record_len: u64,
+        name: &str,
+        number: u32,
+        kind: FileType,
+    ) -> Result<(), FsError> {
+        let header = dir::header(number, record_len, name.len(), self.entry_type(kind));
+        self.transfer(address, Transfer::Write(&header)).await?;
+        self.transfer(
+            address + dir::HEADER_SIZE as u64,
+            Transfer::Write(name.as_bytes()),
+        )
+        .await
+    }
+
+    /// Points `entry` to inode `number` of `kind`
+    async fn set_entry(&self, entry: &Entry, number: u32, kind: FileType) -> Result<(), FsError> {
+        self.write_u32(entry.address, number).await?;
+        if self.filetype {
+            let file_type = [dir::file_type(kind)];
+            self.transfer(entry.address + 7, Transfer::Write(&file_type))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Drops a reference to the extended attribute block `block`, freeing it with the last
+    async fn release_xattr(&self, block: u32) -> Result<(), FsError> {
+        let address = self.block_address(self.check_block(block)?);
+        if self.read_u32(address).await? != XATTR_MAGIC {
+            return Err(FsError::Corrupted);
+        }
+        match self.read_u32(address + 4).await? {
+            0 | 1 => self.free_block(block).await,
+            references => self.write_u32(address + 4, references - 1).await,
+        }
+    }
+
+    /// Writes the counts of the superblock and every dirty block back to the device
+    async fn sync(&self) -> Result<(), FsError> {
+        let counts = {
+            let mut state = self.state.lock();
+            let dirty = core::mem::take(&mut state.superblock_dirty);
+            dirty.then_some((state.free_blocks, state.free_inodes))
+        };
+        if let Some((free_blocks, free_inodes)) = counts {
+            self.write_u32(SUPERBLOCK_OFFSET + 12, free_blocks).await?;
+            self.write_u32(SUPERBLOCK_OFFSET + 16, free_inodes).await?;
+        }
+        Ok(cache::sync_device(&self.device).await?)
+    }
+}
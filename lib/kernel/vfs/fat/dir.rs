@@ -0,0 +1,395 @@
+//! On-disk format of directory entries and long file names.
+//!
+//! A directory is an array of 32 byte slots. Every entry ends in a short entry with an 8.3
+//! name, the cluster and the size; a long name is spread over the slots right before it, last
+//! part first, each carrying a checksum of the short name.
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+use crate::kernel::vfs::{FileType, FsError};
+
+pub const ENTRY_SIZE: usize = 32;
+
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_HIDDEN: u8 = 0x02;
+pub const ATTR_SYSTEM: u8 = 0x04;
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+/// Attributes of a slot holding part of a long name
+const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+/// First byte of the slot after the last entry
+pub const END: u8 = 0x00;
+/// First byte of a removed entry
+pub const DELETED: u8 = 0xE5;
+/// Stands in for a name starting with 0xE5, which would read as removed
+const ESCAPED_DELETED: u8 = 0x05;
+
+/// Set on the sequence number of the last part of a long name, which comes first
+const LAST_PART: u8 = 0x40;
+/// UTF-16 units per long name slot
+const PART_UNITS: usize = 13;
+/// Where the units of a part are in its slot
+const PART_OFFSETS: [usize; PART_UNITS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// Longest long name in UTF-16 units
+const LONG_NAME_MAX: usize = 255;
+const MAX_PARTS: usize = LONG_NAME_MAX.div_ceil(PART_UNITS);
+
+/// Flags in byte 12 saying the base or the extension of the short name is lower case, which
+/// Linux and Windows use instead of a long name when that is all that differs
+const LOWER_BASE: u8 = 0x08;
+const LOWER_EXTENSION: u8 = 0x10;
+
+/// There is no wall clock, entries are made on 1980-01-01, the earliest date FAT can store
+const DEFAULT_DATE: u16 = 1 << 5 | 1;
+
+/// Characters allowed in short names besides letters and digits
+const SHORT_NAME_SPECIAL: &str = "!#$%&'()-@^_`{}~";
+/// Characters never allowed in names
+const FORBIDDEN: &str = "\"*/:<>?\\|";
+
+/// A short entry and the long name before it
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// The long name, or the short name if there is none
+    pub name: String,
+    pub raw: [u8; ENTRY_SIZE],
+    /// Slot of the first part of the long name, or of the short entry without one
+    pub first_slot: u64,
+    /// Slot of the short entry
+    pub slot: u64,
+    /// Byte address of the short entry on the device
+    pub position: u64,
+}
+
+impl Entry {
+    pub fn short_name(&self) -> &[u8] {
+        &self.raw[..11]
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.raw[11] & ATTR_DIRECTORY != 0
+    }
+
+    pub fn kind(&self) -> FileType {
+        match self.is_directory() {
+            true => FileType::Directory,
+            false => FileType::Regular,
+        }
+    }
+
+    pub fn first_cluster(&self) -> u32 {
+        first_cluster(&self.raw)
+    }
+
+    pub fn size(&self) -> u32 {
+        u32::from_le_bytes(self.raw[28..32].try_into().unwrap())
+    }
+
+    /// `.` or `..` at the start of every directory but the root
+    pub fn is_dot(&self) -> bool {
+        self.name == "." || self.name == ".."
+    }
+
+    /// Compares with `name` the way FAT does, ignoring case, and also by the short name
+    pub fn matches(&self, name: &str) -> bool {
+        fn upper(name: &str) -> impl Iterator<Item = char> + '_ {
+            name.chars().flat_map(char::to_uppercase)
+        }
+        upper(&self.name).eq(upper(name))
+            || exact_short_name(name).is_some_and(|(short, _)| short == self.short_name())
+    }
+}
+
+pub fn first_cluster(raw: &[u8]) -> u32 {
+    let high = u16::from_le_bytes([raw[20], raw[21]]) as u32;
+    let low = u16::from_le_bytes([raw[26], raw[27]]) as u32;
+    high << 16 | low
+}
+
+pub fn set_first_cluster(raw: &mut [u8], cluster: u32) {
+    raw[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    raw[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+}
+
+pub fn set_size(raw: &mut [u8], size: u32) {
+    raw[28..32].copy_from_slice(&size.to_le_bytes());
+}
+
+/// A short entry named `short` with `attributes`, dated `DEFAULT_DATE`
+pub fn short_entry(short: [u8; 11], attributes: u8, cluster: u32) -> [u8; ENTRY_SIZE] {
+    let mut raw = [0; ENTRY_SIZE];
+    raw[..11].copy_from_slice(&short);
+    raw[11] = attributes;
+    // Creation, access and modification date
+    for offset in [16, 18, 24] {
+        raw[offset..offset + 2].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+    }
+    set_first_cluster(&mut raw, cluster);
+    raw
+}
+
+/// Short names of the `.` and `..` entries
+pub const DOT: [u8; 11] = *b".          ";
+pub const DOT_DOT: [u8; 11] = *b"..         ";
+
+/// Collects the parts of long names slot by slot and hands out the entries they belong to
+#[derive(Debug)]
+pub struct Parser {
+    units: [u16; MAX_PARTS * PART_UNITS],
+    /// Number of parts of the name being collected, zero if none is
+    parts: usize,
+    /// Sequence number of the part seen last
+    next: usize,
+    checksum: u8,
+    first_slot: u64,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self {
+            units: [0; MAX_PARTS * PART_UNITS],
+            parts: 0,
+            next: 0,
+            checksum: 0,
+            first_slot: 0,
+        }
+    }
+
+    /// Forgets the long name collected so far, e.g. at a free slot
+    pub fn reset(&mut self) {
+        self.parts = 0;
+    }
+
+    /// Takes the used slot at `slot`, returns the entry it completes. Volume labels and
+    /// parts of long names complete none
+    pub fn feed(&mut self, slot: u64, position: u64, raw: &[u8; ENTRY_SIZE]) -> Option<Entry> {
+        if raw[11] & 0x3F == ATTR_LONG_NAME {
+            self.feed_part(slot, raw);
+            return None;
+        }
+        if raw[11] & (ATTR_VOLUME_ID | ATTR_DIRECTORY) == ATTR_VOLUME_ID {
+            self.reset();
+            return None;
+        }
+
+        // A long name only counts if all its parts were there and belong to this entry
+        let long = self.parts > 0 && self.next == 1 && self.checksum == checksum(&raw[..11]);
+        let (name, first_slot) = match long {
+            true => (self.long_name(), self.first_slot),
+            false => (short_name(raw), slot),
+        };
+        self.reset();
+        Some(Entry {
+            name,
+            raw: *raw,
+            first_slot,
+            slot,
+            position,
+        })
+    }
+
+    fn feed_part(&mut self, slot: u64, raw: &[u8; ENTRY_SIZE]) {
+        let sequence = (raw[0] & !LAST_PART) as usize;
+        if raw[0] & LAST_PART != 0 {
+            if sequence == 0 || sequence > MAX_PARTS {
+                self.reset();
+                return;
+            }
+            self.parts = sequence;
+            self.checksum = raw[13];
+            self.first_slot = slot;
+        } else if self.parts == 0
+            || sequence == 0
+            || sequence + 1 != self.next
+            || raw[13] != self.checksum
+        {
+            self.reset();
+            return;
+        }
+        self.next = sequence;
+        let start = (sequence - 1) * PART_UNITS;
+        for (i, offset) in PART_OFFSETS.into_iter().enumerate() {
+            self.units[start + i] = u16::from_le_bytes([raw[offset], raw[offset + 1]]);
+        }
+    }
+
+    fn long_name(&self) -> String {
+        let units = &self.units[..self.parts * PART_UNITS];
+        let len = units
+            .iter()
+            .position(|&unit| unit == 0)
+            .unwrap_or(units.len());
+        char::decode_utf16(units[..len].iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+}
+
+/// The name a short entry stands for, lower cased where its flags say so
+fn short_name(raw: &[u8; ENTRY_SIZE]) -> String {
+    let mut base = [0; 8];
+    base.copy_from_slice(&raw[..8]);
+    if base[0] == ESCAPED_DELETED {
+        base[0] = DELETED;
+    }
+    let part = |bytes: &[u8], lower: bool| {
+        let len = bytes
+            .iter()
+            .rposition(|&b| b != b' ')
+            .map_or(0, |end| end + 1);
+        bytes[..len]
+            .iter()
+            .map(move |&b| match lower {
+                true => b.to_ascii_lowercase() as char,
+                // Bytes past ASCII are in a code page, read as Latin-1
+                false => b as char,
+            })
+            .collect::<String>()
+    };
+    let mut name = part(&base, raw[12] & LOWER_BASE != 0);
+    let extension = part(&raw[8..11], raw[12] & LOWER_EXTENSION != 0);
+    if !extension.is_empty() {
+        name.push('.');
+        name.push_str(&extension);
+    }
+    name
+}
+
+/// Checksum of a short name, repeated in every part of its long name
+pub fn checksum(short: &[u8]) -> u8 {
+    short
+        .iter()
+        .fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+}
+
+/// Rejects names FAT can not store
+pub fn check_name(name: &str) -> Result<(), FsError> {
+    if name.encode_utf16().count() > LONG_NAME_MAX {
+        return Err(FsError::NameTooLong);
+    }
+    // Windows drops trailing dots and spaces, which would make a different name
+    if name.ends_with(['.', ' ']) || name.chars().any(|c| c < ' ' || FORBIDDEN.contains(c)) {
+        return Err(FsError::InvalidName);
+    }
+    Ok(())
+}
+
+fn is_short_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || SHORT_NAME_SPECIAL.contains(c)
+}
+
+/// The short name `name` is stored as if it fits 8.3 as it is, with the case flags for byte
+/// 12. Either part may be lower case, but not mixed
+pub fn exact_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, extension) = match name.rsplit_once('.') {
+        Some((base, extension)) if !extension.is_empty() => (base, extension),
+        Some(_) => return None,
+        None => (name, ""),
+    };
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 {
+        return None;
+    }
+    let mut short = [b' '; 11];
+    let mut case = 0;
+    for (part, range, flag) in [
+        (base, 0..8, LOWER_BASE),
+        (extension, 8..11, LOWER_EXTENSION),
+    ] {
+        if !part.chars().all(is_short_name_char) {
+            return None;
+        }
+        let lower = part.chars().any(|c| c.is_ascii_lowercase());
+        let upper = part.chars().any(|c| c.is_ascii_uppercase());
+        match (lower, upper) {
+            (true, true) => return None,
+            (true, false) => case |= flag,
+            _ => {}
+        }
+        for (slot, b) in short[range].iter_mut().zip(part.bytes()) {
+            *slot = b.to_ascii_uppercase();
+        }
+    }
+    Some((short, case))
+}
+
+/// Makes up a short name of the form `BASE~N.EXT` for a name that needs a long one, taking
+/// the first number none of `taken` uses
+pub fn generate_short_name(name: &str, taken: &[[u8; 11]]) -> Result<[u8; 11], FsError> {
+    let (base, extension) = match name.rsplit_once('.') {
+        Some((base, extension)) if !base.is_empty() => (base, extension),
+        _ => (name, ""),
+    };
+    let convert = |part: &str, out: &mut [u8]| {
+        let chars = part.chars().filter(|&c| c != ' ' && c != '.');
+        let mut len = 0;
+        for (slot, c) in out.iter_mut().zip(chars) {
+            *slot = match is_short_name_char(c) {
+                true => c.to_ascii_uppercase() as u8,
+                false => b'_',
+            };
+            len += 1;
+        }
+        len
+    };
+    let mut short = [b' '; 11];
+    let mut basis = [b' '; 8];
+    let base_len = convert(base, &mut basis).max(1);
+    if basis[0] == b' ' {
+        basis[0] = b'_';
+    }
+    convert(extension, &mut short[8..]);
+
+    for number in 1..1_000_000u32 {
+        let mut tail = [0; 7];
+        let mut digits = 0;
+        let mut n = number;
+        while n > 0 {
+            tail[6 - digits] = b'0' + (n % 10) as u8;
+            n /= 10;
+            digits += 1;
+        }
+        tail[6 - digits] = b'~';
+        let tail = &tail[6 - digits..];
+        let keep = base_len.min(8 - tail.len());
+        short[..8].fill(b' ');
+        short[..keep].copy_from_slice(&basis[..keep]);
+        short[keep..keep + tail.len()].copy_from_slice(tail);
+        if !taken.contains(&short) {
+            return Ok(short);
+        }
+    }
+    Err(FsError::NoSpace)
+}
+
+/// Slots storing `name` for the short entry `raw`: the parts of the long name, last first,
+/// followed by the short entry
+pub fn encode(name: &str, raw: [u8; ENTRY_SIZE], long: bool) -> Vec<[u8; ENTRY_SIZE]> {
+    let mut slots = Vec::new();
+    if long {
+        let units: Vec<u16> = name.encode_utf16().collect();
+        let parts = units.len().div_ceil(PART_UNITS);
+        let checksum = checksum(&raw[..11]);
+        for sequence in (1..=parts).rev() {
+            let mut slot = [0; ENTRY_SIZE];
+            slot[0] = sequence as u8 | if sequence == parts { LAST_PART } else { 0 };
+            slot[11] = ATTR_LONG_NAME;
+            slot[13] = checksum;
+            for (i, offset) in PART_OFFSETS.into_iter().enumerate() {
+                // The name ends with a zero unit if there is room, the rest is padding
+                let unit = match ((sequence - 1) * PART_UNITS + i).cmp(&units.len()) {
+                    core::cmp::Ordering::Less => units[(sequence - 1) * PART_UNITS + i],
+                    core::cmp::Ordering::Equal => 0,
+                    core::cmp::Ordering::Greater => 0xFFFF,
+                };
+                slot[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            slots.push(slot);
+        }
+    }
+    slots.push(raw);
+    slots
+}
@@ -0,0 +1,598 @@
+extern crate alloc;
+
+use core::{any::Any, ops::ControlFlow};
+
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::vfs::{
+        fat::{
+            dir::{self, Entry, ENTRY_SIZE},
+            ChainPosition, Transfer, Volume, MAX_DIRECTORY_SLOTS,
+        },
+        DirEntry, FileType, FsError, FsFuture, Inode, Metadata,
+    },
+};
+
+/// Number of the root directory, which has no entry. Entries are never at this address
+const ROOT_INODE: u64 = 1;
+
+/// A file or directory of a FAT volume
+#[derive(Debug)]
+pub struct FatInode {
+    volume: Arc<Volume>,
+    number: u64,
+    kind: FileType,
+    state: Mutex<InodeState>,
+}
+
+#[derive(Debug)]
+struct InodeState {
+    /// Zero for empty files
+    first_cluster: u32,
+    /// Always zero for directories
+    size: u32,
+    /// Byte address of the short entry on the device, None for the root and once the entry
+    /// is removed
+    entry: Option<u64>,
+    /// Where the last transfer ended in the chain, so reading or writing on from there does
+    /// not walk it from the start again. Cleared whenever the chain is cut
+    position: Option<ChainPosition>,
+}
+
+impl FatInode {
+    pub(super) fn root(volume: Arc<Volume>) -> Arc<Self> {
+        let first_cluster = volume.root_cluster;
+        Arc::new(Self {
+            volume,
+            number: ROOT_INODE,
+            kind: FileType::Directory,
+            state: Mutex::new(InodeState {
+                first_cluster,
+                size: 0,
+                entry: None,
+                position: None,
+            }),
+        })
+    }
+
+    pub(super) fn number(&self) -> u64 {
+        self.number
+    }
+
+    /// The inode of `entry` of this directory, the same one as long as anyone holds it
+    fn inode_of(&self, entry: &Entry) -> Arc<FatInode> {
+        let mut state = self.volume.state.lock();
+        if let Some(inode) = state
+            .inodes
+            .get(&entry.position)
+            .and_then(|weak| weak.upgrade())
+        {
+            return inode;
+        }
+        let inode = Arc::new(FatInode {
+            volume: self.volume.clone(),
+            number: entry.position,
+            kind: entry.kind(),
+            state: Mutex::new(InodeState {
+                first_cluster: entry.first_cluster(),
+                size: match entry.kind() {
+                    FileType::Directory => 0,
+                    _ => entry.size(),
+                },
+                entry: Some(entry.position),
+                position: None,
+            }),
+        });
+        state.inodes.insert(entry.position, Arc::downgrade(&inode));
+        inode
+    }
+
+    fn first_cluster(&self) -> u32 {
+        self.state.lock().first_cluster
+    }
+
+    /// What `..` entries of subdirectories point to, zero for the root
+    fn dot_dot_cluster(&self) -> u32 {
+        match self.number {
+            ROOT_INODE => 0,
+            _ => self.first_cluster(),
+        }
+    }
+
+    fn check_directory(&self) -> Result<(), FsError> {
+        match self.kind {
+            FileType::Directory => Ok(()),
+            _ => Err(FsError::NotDirectory),
+        }
+    }
+
+    /// Fails for read only volumes and for files whose entry is gone
+    fn check_writable(&self) -> Result<(), FsError> {
+        self.volume.check_writable()?;
+        match self.number == ROOT_INODE || self.state.lock().entry.is_some() {
+            true => Ok(()),
+            false => Err(FsError::NotFound),
+        }
+    }
+
+    /// Writes the first cluster and the size to the entry
+    async fn update_entry(&self) -> Result<(), FsError> {
+        let (entry, first_cluster, size) = {
+            let state = self.state.lock();
+            (state.entry, state.first_cluster, state.size)
+        };
+        let Some(address) = entry else {
+            return Ok(());
+        };
+        let is_file = self.kind == FileType::Regular;
+        self.volume
+            .update_slot(address, |raw| {
+                dir::set_first_cluster(raw, first_cluster);
+                if is_file {
+                    dir::set_size(raw, size);
+                    raw[11] |= dir::ATTR_ARCHIVE;
+                }
+            })
+            .await
+    }
+
+    /// Grows the chain to hold `size` bytes and returns its first cluster
+    async fn reserve(&self, size: u64) -> Result<u32, FsError> {
+        let (mut first_cluster, position) = {
+            let state = self.state.lock();
+            (state.first_cluster, state.position)
+        };
+        let cluster_size = self.volume.cluster_size as u64;
+        let result = self
+            .volume
+            .grow(&mut first_cluster, size.div_ceil(cluster_size), position)
+            .await;
+        // Clusters past the end of a file that could not grow are given back, directories
+        // keep theirs as free slots. The error of growing is the one reported
+        if result.is_err() && self.kind == FileType::Regular {
+            let kept = (self.state.lock().size as u64).div_ceil(cluster_size);
+            match self.volume.shrink(first_cluster, kept).await {
+                Ok(first) => first_cluster = first,
+                // The chain may be partly freed, what is left of it is lost
+                Err(_) if kept == 0 => first_cluster = 0,
+                // The chain only stays longer than the file
+                Err(_) => {}
+            }
+        }
+        // A new first cluster is recorded even if a later one could not be taken
+        {
+            let mut state = self.state.lock();
+            if result.is_err() {
+                state.position = None;
+            }
+            state.first_cluster = first_cluster;
+        }
+        self.update_entry().await?;
+        result.map(|()| first_cluster)
+    }
+
+    /// Allocates the cluster of a new subdirectory and writes its `.` and `..` entries
+    async fn new_directory(&self) -> Result<u32, FsError> {
+        let cluster = self.volume.allocate(None).await?;
+        let dot = dir::short_entry(dir::DOT, dir::ATTR_DIRECTORY, cluster);
+        let dot_dot = dir::short_entry(dir::DOT_DOT, dir::ATTR_DIRECTORY, self.dot_dot_cluster());
+        let buffer = self
+            .volume
+            .block(self.volume.cluster_sector(cluster))
+            .await?;
+        buffer
+            .write(|data| {
+                data[..ENTRY_SIZE].copy_from_slice(&dot);
+                data[ENTRY_SIZE..2 * ENTRY_SIZE].copy_from_slice(&dot_dot);
+            })
+            .await;
+        Ok(cluster)
+    }
+
+    /// Stores the short entry `raw` under `name` in free slots of this directory, growing it
+    /// if there are not enough
+    async fn add_entry(&self, name: &str, mut raw: [u8; ENTRY_SIZE]) -> Result<Entry, FsError> {
+        let first_cluster = self.first_cluster();
+        let long = match dir::exact_short_name(name) {
+            Some((short, case)) => {
+                raw[..11].copy_from_slice(&short);
+                raw[12] = case;
+                false
+            }
+            None => {
+                let mut taken = Vec::new();
+                self.volume
+                    .entries(first_cluster, 0, |entry| {
+                        taken.push(entry.raw[..11].try_into().unwrap());
+                        ControlFlow::<()>::Continue(())
+                    })
+                    .await?;
+                raw[..11].copy_from_slice(&dir::generate_short_name(name, &taken)?);
+                raw[12] = 0;
+                true
+            }
+        };
+        let slots = dir::encode(name, raw, long);
+        let count = slots.len() as u64;
+
+        // The first run of free slots that is long enough. Everything from the end marker on
+        // is free, even past the last cluster
+        let (mut run, mut total) = (0, 0);
+        let found = self
+            .volume
+            .for_each_slot(first_cluster, 0, |slot, _, raw| {
+                total = slot + 1;
+                match raw[0] {
+                    dir::END => return ControlFlow::Break(slot - run),
+                    dir::DELETED => run += 1,
+                    _ => run = 0,
+                }
+                match run == count {
+                    true => ControlFlow::Break(slot + 1 - count),
+                    false => ControlFlow::Continue(()),
+                }
+            })
+            .await?;
+        let start = found.unwrap_or(total - run);
+        if start + count > MAX_DIRECTORY_SLOTS {
+            return Err(FsError::NoSpace);
+        }
+        self.reserve((start + count) * ENTRY_SIZE as u64).await?;
+
+        let mut address = 0;
+        for (slot, contents) in (start..).zip(&slots) {
+            address = self.volume.slot_address(first_cluster, slot).await?;
+            self.volume
+                .update_slot(address, |raw| raw.copy_from_slice(contents))
+                .await?;
+        }
+        Ok(Entry {
+            name: String::from(name),
+            raw,
+            first_slot: start,
+            slot: start + count - 1,
+            position: address,
+        })
+    }
+
+    /// Marks the slots of `entry` of this directory free
+    async fn remove_slots(&self, entry: &Entry) -> Result<(), FsError> {
+        let first_cluster = self.first_cluster();
+        for slot in entry.first_slot..=entry.slot {
+            let address = self.volume.slot_address(first_cluster, slot).await?;
+            self.volume
+                .update_slot(address, |raw| raw[0] = dir::DELETED)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Removes `entry` of this directory and frees its clusters. Its inode stays usable for
+    /// those holding it, but is empty and can not be written
+    async fn remove(&self, entry: &Entry) -> Result<(), FsError> {
+        self.remove_slots(entry).await?;
+        self.discard(entry).await
+    }
+
+    /// Frees the clusters of `entry`, whose slots are gone or taken over by another entry,
+    /// and empties its inode
+    async fn discard(&self, entry: &Entry) -> Result<(), FsError> {
+        let inode = self
+            .volume
+            .state
+            .lock()
+            .inodes
+            .remove(&entry.position)
+            .and_then(|weak| weak.upgrade());
+        if let Some(inode) = inode {
+            let mut state = inode.state.lock();
+            state.first_cluster = 0;
+            state.size = 0;
+            state.entry = None;
+            state.position = None;
+        }
+        self.volume.free_chain(entry.first_cluster()).await
+    }
+}
+
+impl Inode for FatInode {
+    fn metadata(&self) -> Metadata {
+        let state = self.state.lock();
+        Metadata {
+            inode: self.number,
+            kind: self.kind,
+            size: state.size as u64,
+            // FAT keeps no link counts
+            links: match self.kind {
+                FileType::Directory => 2,
+                _ => 1,
+            },
+        }
+    }
+
+    fn read_at<'a>(&'a self, offset: u64, buf: &'a mut [u8]) -> FsFuture<'a, usize> {
+        Box::pin(async move {
+            if self.kind == FileType::Directory {
+                return Err(FsError::IsDirectory);
+            }
+            let _guard = self.volume.lock.lock().await;
+            let (first_cluster, size, mut position) = {
+                let state = self.state.lock();
+                (state.first_cluster, state.size as u64, state.position)
+            };
+            if offset >= size {
+                return Ok(0);
+            }
+            let len = buf.len().min((size - offset) as usize);
+            let read = Transfer::Read(&mut buf[..len]);
+            self.volume
+                .transfer(first_cluster, offset, read, &mut position)
+                .await?;
+            self.state.lock().position = position;
+            Ok(len)
+        })
+    }
+
+    fn write_at<'a>(&'a self, offset: u64, buf: &'a [u8]) -> FsFuture<'a, usize> {
+        Box::pin(async move {
+            if self.kind == FileType::Directory {
+                return Err(FsError::IsDirectory);
+            }
+            let _guard = self.volume.lock.lock().await;
+            self.check_writable()?;
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            // Sizes are 32 bits
+            let end = offset
+                .checked_add(buf.len() as u64)
+                .filter(|&end| end <= u32::MAX as u64)
+                .ok_or(FsError::NoSpace)?;
+            let size = self.state.lock().size as u64;
+            let first_cluster = self.reserve(end).await?;
+            let mut position = self.state.lock().position;
+            // What is left in the clusters past the end may be old data
+            if offset > size {
+                let zero = Transfer::Zero((offset - size) as usize);
+                self.volume
+                    .transfer(first_cluster, size, zero, &mut position)
+                    .await?;
+            }
+            self.volume
+                .transfer(first_cluster, offset, Transfer::Write(buf), &mut position)
+                .await?;
+            {
+                let mut state = self.state.lock();
+                state.size = end.max(size) as u32;
+                state.position = position;
+            }
+            self.update_entry().await?;
+            Ok(buf.len())
+        })
+    }
+
+    fn truncate(&self, size: u64) -> FsFuture<'_, ()> {
+        Box::pin(async move {
+            if self.kind == FileType::Directory {
+                return Err(FsError::IsDirectory);
+            }
+            let _guard = self.volume.lock.lock().await;
+            self.check_writable()?;
+            if size > u32::MAX as u64 {
+                return Err(FsError::NoSpace);
+            }
+            let (first_cluster, old_size) = {
+                let state = self.state.lock();
+                (state.first_cluster, state.size as u64)
+            };
+            if size > old_size {
+                let first_cluster = self.reserve(size).await?;
+                let mut position = self.state.lock().position;
+                let zero = Transfer::Zero((size - old_size) as usize);
+                self.volume
+                    .transfer(first_cluster, old_size, zero, &mut position)
+                    .await?;
+                self.state.lock().position = position;
+            } else {
+                let cluster_size = self.volume.cluster_size as u64;
+                // The position may be in the part that is cut off
+                self.state.lock().position = None;
+                let first_cluster = self
+                    .volume
+                    .shrink(first_cluster, size.div_ceil(cluster_size))
+                    .await?;
+                self.state.lock().first_cluster = first_cluster;
+            }
+            self.state.lock().size = size as u32;
+            self.update_entry().await
+        })
+    }
+
+    fn lookup<'a>(&'a self, name: &'a str) -> FsFuture<'a, Arc<dyn Inode>> {
+        Box::pin(async move {
+            self.check_directory()?;
+            let _guard = self.volume.lock.lock().await;
+            let entry = self
+                .volume
+                .find(self.first_cluster(), name)
+                .await?
+                .ok_or(FsError::NotFound)?;
+            Ok(self.inode_of(&entry) as Arc<dyn Inode>)
+        })
+    }
+
+    fn create<'a>(&'a self, name: &'a str, kind: FileType) -> FsFuture<'a, Arc<dyn Inode>> {
+        Box::pin(async move {
+            self.check_directory()?;
+            let attributes = match kind {
+                FileType::Regular => dir::ATTR_ARCHIVE,
+                FileType::Directory => dir::ATTR_DIRECTORY,
+                FileType::Symlink => return Err(FsError::NotSupported),
+            };
+            dir::check_name(name)?;
+            let _guard = self.volume.lock.lock().await;
+            self.check_writable()?;
+            if self
+                .volume
+                .find(self.first_cluster(), name)
+                .await?
+                .is_some()
+            {
+                return Err(FsError::AlreadyExists);
+            }
+
+            let cluster = match kind {
+                FileType::Directory => self.new_directory().await?,
+                _ => 0,
+            };
+            let raw = dir::short_entry([b' '; 11], attributes, cluster);
+            match self.add_entry(name, raw).await {
+                Ok(entry) => Ok(self.inode_of(&entry) as Arc<dyn Inode>),
+                Err(err) => {
+                    self.volume.free_chain(cluster).await?;
+                    Err(err)
+                }
+            }
+        })
+    }
+
+    fn unlink<'a>(&'a self, name: &'a str) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            self.check_directory()?;
+            let _guard = self.volume.lock.lock().await;
+            self.check_writable()?;
+            let entry = self
+                .volume
+                .find(self.first_cluster(), name)
+                .await?
+                .ok_or(FsError::NotFound)?;
+            if entry.is_directory() && !self.volume.is_empty(entry.first_cluster()).await? {
+                return Err(FsError::NotEmpty);
+            }
+            self.remove(&entry).await
+        })
+    }
+
+    fn rename<'a>(
+        &'a self,
+        old_name: &'a str,
+        new_parent: &'a dyn Inode,
+        new_name: &'a str,
+    ) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            let new_parent = new_parent
+                .as_any()
+                .downcast_ref::<FatInode>()
+                .filter(|parent| Arc::ptr_eq(&parent.volume, &self.volume))
+                .ok_or(FsError::CrossDevice)?;
+            self.check_directory()?;
+            new_parent.check_directory()?;
+            dir::check_name(new_name)?;
+            let _guard = self.volume.lock.lock().await;
+            self.check_writable()?;
+
+            let moved = self
+                .volume
+                .find(self.first_cluster(), old_name)
+                .await?
+                .ok_or(FsError::NotFound)?;
+            let replaced = self
+                .volume
+                .find(new_parent.first_cluster(), new_name)
+                .await?;
+            let replaced = match replaced {
+                // Only the case of the name changes, or nothing at all
+                Some(replaced) if replaced.position == moved.position => {
+                    if moved.name == new_name {
+                        return Ok(());
+                    }
+                    None
+                }
+                Some(replaced) => {
+                    match (moved.is_directory(), replaced.is_directory()) {
+                        (true, true) if !self.volume.is_empty(replaced.first_cluster()).await? => {
+                            return Err(FsError::NotEmpty)
+                        }
+                        (true, false) => return Err(FsError::NotDirectory),
+                        (false, true) => return Err(FsError::IsDirectory),
+                        _ => {}
+                    }
+                    Some(replaced)
+                }
+                None => None,
+            };
+
+            // The new entry keeps the attributes, dates, cluster and size. The replaced entry
+            // is only dropped once the moved one is in place, so a failed rename loses neither
+            let position = match &replaced {
+                // Slots spelling the same name are taken over, short name and case included
+                Some(replaced) if replaced.name == new_name => {
+                    self.volume
+                        .update_slot(replaced.position, |raw| {
+                            raw[11] = moved.raw[11];
+                            raw[13..].copy_from_slice(&moved.raw[13..]);
+                        })
+                        .await?;
+                    replaced.position
+                }
+                _ => new_parent.add_entry(new_name, moved.raw).await?.position,
+            };
+            match replaced {
+                Some(replaced) if replaced.position == position => {
+                    new_parent.discard(&replaced).await?
+                }
+                Some(replaced) => new_parent.remove(&replaced).await?,
+                None => {}
+            }
+            self.remove_slots(&moved).await?;
+            {
+                let mut state = self.volume.state.lock();
+                if let Some(inode) = state.inodes.remove(&moved.position) {
+                    if let Some(live) = inode.upgrade() {
+                        live.state.lock().entry = Some(position);
+                    }
+                    state.inodes.insert(position, inode);
+                }
+            }
+            if moved.is_directory() && !core::ptr::eq(self, new_parent) {
+                let address = self.volume.slot_address(moved.first_cluster(), 1).await?;
+                let parent_cluster = new_parent.dot_dot_cluster();
+                self.volume
+                    .update_slot(address, |raw| dir::set_first_cluster(raw, parent_cluster))
+                    .await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn read_dir(&self, position: u64) -> FsFuture<'_, Option<(DirEntry, u64)>> {
+        Box::pin(async move {
+            self.check_directory()?;
+            let _guard = self.volume.lock.lock().await;
+            let entry = self
+                .volume
+                .entries(self.first_cluster(), position, |entry| {
+                    match entry.is_dot() {
+                        true => ControlFlow::Continue(()),
+                        false => ControlFlow::Break(entry),
+                    }
+                })
+                .await?;
+            Ok(entry.map(|entry| {
+                let next = entry.slot + 1;
+                let entry = DirEntry {
+                    inode: self.volume.inode_number(&entry),
+                    kind: entry.kind(),
+                    name: entry.name,
+                };
+                (entry, next)
+            }))
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,720 @@
+//! FAT32, as made by `mkfs.vfat -F 32` on Linux or by Windows.
+//!
+//! The boot sector describes the volume: reserved sectors, the copies of the file allocation
+//! table (FAT), then the clusters holding files and directories. The FAT has an entry per
+//! cluster with the number of the next cluster of its chain, zero for free ones. Sectors are
+//! read and written through the block cache, so their size must be the block size of the
+//! device.
+//!
+//! FAT has no inodes, an inode here stands for a directory entry and is numbered by where the
+//! entry was when it was first looked up. Operations on a volume run one at a time, as most of
+//! them touch several sectors that must agree.
+
+extern crate alloc;
+
+use core::ops::{ControlFlow, Range};
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+};
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::{
+        block::{
+            cache::{self, BlockBuffer},
+            BlockDevice,
+        },
+        vfs::{lock::OperationLock, FileSystem, FileSystemType, FsError, FsFuture, Inode},
+    },
+};
+
+mod dir;
+mod inode;
+
+use dir::{Entry, Parser, ENTRY_SIZE};
+pub use inode::FatInode;
+
+/// The low 28 bits of a FAT entry, the rest are reserved and kept as they are
+const ENTRY_MASK: u32 = 0x0FFF_FFFF;
+const FREE: u32 = 0;
+/// Entries from here on end a chain
+const END_OF_CHAIN: u32 = 0x0FFF_FFF8;
+/// What ends the chains this driver writes
+const END_MARK: u32 = 0x0FFF_FFFF;
+/// Clusters are numbered from here, the first two FAT entries are reserved
+const FIRST_CLUSTER: u32 = 2;
+
+/// Signatures of the FSInfo sector, which keeps hints for allocating clusters
+const FS_INFO_LEAD: u32 = 0x4161_5252;
+const FS_INFO_STRUCT: u32 = 0x6141_7272;
+const FS_INFO_TRAIL: u32 = 0xAA55_0000;
+const FS_INFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+/// A directory holds at most this many slots
+const MAX_DIRECTORY_SLOTS: u64 = 1 << 16;
+
+pub const FILESYSTEM_TYPE: FileSystemType = FileSystemType {
+    name: "fat32",
+    mount,
+};
+
+fn mount(device: Arc<dyn BlockDevice>) -> FsFuture<'static, Arc<dyn FileSystem>> {
+    Box::pin(async move { Ok(Arc::new(FatFs::new(device).await?) as Arc<dyn FileSystem>) })
+}
+
+#[derive(Debug)]
+pub struct FatFs {
+    volume: Arc<Volume>,
+    root: Arc<FatInode>,
+}
+
+#[derive(Debug)]
+struct Volume {
+    device: Arc<dyn BlockDevice>,
+    sector_size: usize,
+    sectors_per_cluster: u64,
+    /// In bytes
+    cluster_size: usize,
+    /// First sector of the first FAT
+    fat_start: u64,
+    fat_sectors: u64,
+    /// The copies of the FAT that are kept up to date, every one unless mirroring is off.
+    /// Entries are read from the first
+    fats: Range<u64>,
+    /// Sector of the first cluster
+    data_start: u64,
+    cluster_count: u32,
+    root_cluster: u32,
+    fs_info: Option<u64>,
+    read_only: bool,
+    lock: OperationLock,
+    state: Mutex<VolumeState>,
+}
+
+#[derive(Debug)]
+struct VolumeState {
+    /// Hints of the FSInfo sector
+    free_clusters: Option<u32>,
+    next_free: u32,
+    /// The hints changed since they were last written
+    fs_info_dirty: bool,
+    /// Inodes in use by the position of their entry, so an entry has one inode at a time
+    inodes: BTreeMap<u64, Weak<FatInode>>,
+}
+
+/// What `Volume::transfer` does with the bytes of a chain
+enum Transfer<'a> {
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+    Zero(usize),
+}
+
+/// A cluster of a chain and its index in it, which walks along the chain can start from
+/// instead of its first cluster
+#[derive(Debug, Clone, Copy)]
+struct ChainPosition {
+    index: u64,
+    cluster: u32,
+}
+
+impl FatFs {
+    /// Reads the boot sector of `device`, fails with `FsError::UnknownFormat` unless it
+    /// describes a FAT32 volume
+    pub async fn new(device: Arc<dyn BlockDevice>) -> Result<Self, FsError> {
+        if device.block_size() < 512 || device.block_count() == 0 {
+            return Err(FsError::UnknownFormat);
+        }
+        let boot = cache::get(&device, 0).await?;
+        let mut volume = boot
+            .read(|sector| Volume::parse(device.clone(), sector))
+            .await?;
+
+        if let Some(sector) = volume.fs_info {
+            let fs_info = cache::get(&device, sector).await?;
+            let hints = fs_info.read(FsInfo::parse).await;
+            match hints {
+                Some(hints) => {
+                    let mut state = volume.state.lock();
+                    state.free_clusters =
+                        Some(hints.free_clusters).filter(|&free| free <= volume.cluster_count);
+                    if volume.is_valid(hints.next_free) {
+                        state.next_free = hints.next_free;
+                    }
+                }
+                None => volume.fs_info = None,
+            }
+        }
+
+        let volume = Arc::new(volume);
+        let root = FatInode::root(volume.clone());
+        Ok(Self { volume, root })
+    }
+}
+
+impl FileSystem for FatFs {
+    fn name(&self) -> &str {
+        "fat32"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+
+    fn sync(&self) -> FsFuture<'_, ()> {
+        Box::pin(async move {
+            let _guard = self.volume.lock.lock().await;
+            self.volume.sync().await
+        })
+    }
+}
+
+struct FsInfo {
+    free_clusters: u32,
+    next_free: u32,
+}
+
+impl FsInfo {
+    fn parse(sector: &[u8]) -> Option<Self> {
+        let signatures = [
+            (0, FS_INFO_LEAD),
+            (484, FS_INFO_STRUCT),
+            (508, FS_INFO_TRAIL),
+        ];
+        if signatures
+            .into_iter()
+            .any(|(offset, signature)| read_u32(sector, offset) != signature)
+        {
+            return None;
+        }
+        Some(Self {
+            free_clusters: read_u32(sector, 488),
+            next_free: read_u32(sector, 492),
+        })
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+impl Volume {
+    fn parse(device: Arc<dyn BlockDevice>, boot: &[u8]) -> Result<Self, FsError> {
+        if boot[510..512] != [0x55, 0xAA] || !matches!(boot[0], 0xEB | 0xE9) {
+            return Err(FsError::UnknownFormat);
+        }
+        let sector_size = read_u16(boot, 11) as u64;
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved = read_u16(boot, 14) as u64;
+        let fat_count = boot[16] as u64;
+        let root_entries = read_u16(boot, 17);
+        let total_16 = read_u16(boot, 19) as u64;
+        let fat_sectors_16 = read_u16(boot, 22);
+        let total_32 = read_u32(boot, 32) as u64;
+        let fat_sectors = read_u32(boot, 36) as u64;
+        let flags = read_u16(boot, 40);
+        let root_cluster = read_u32(boot, 44);
+        let fs_info = read_u16(boot, 48) as u64;
+
+        if !sector_size.is_power_of_two()
+            || !(512..=4096).contains(&sector_size)
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved == 0
+            || fat_count == 0
+        {
+            return Err(FsError::UnknownFormat);
+        }
+        // FAT12 and FAT16 have a root directory of fixed size and a 16 bit FAT size
+        if root_entries != 0 || fat_sectors_16 != 0 || fat_sectors == 0 {
+            return Err(FsError::UnknownFormat);
+        }
+        if sector_size as usize != device.block_size() {
+            return Err(FsError::NotSupported);
+        }
+
+        let total = match total_16 {
+            0 => total_32,
+            total => total,
+        };
+        let data_start = reserved + fat_count * fat_sectors;
+        if total > device.block_count() || total <= data_start {
+            return Err(FsError::Corrupted);
+        }
+        let cluster_count = (total - data_start) / sectors_per_cluster;
+        let fat_entries = fat_sectors * sector_size / 4;
+        if cluster_count + FIRST_CLUSTER as u64 > fat_entries.min(END_OF_CHAIN as u64 - 1) {
+            return Err(FsError::Corrupted);
+        }
+
+        // Bit 7 turns mirroring off, the low bits then pick the FAT in use
+        let fats = match flags & 0x80 {
+            0 => 0..fat_count,
+            _ => {
+                let active = (flags & 0x0F) as u64;
+                if active >= fat_count {
+                    return Err(FsError::Corrupted);
+                }
+                active..active + 1
+            }
+        };
+
+        let read_only = device.is_read_only();
+        let volume = Self {
+            device,
+            sector_size: sector_size as usize,
+            sectors_per_cluster,
+            cluster_size: (sector_size * sectors_per_cluster) as usize,
+            fat_start: reserved,
+            fat_sectors,
+            fats,
+            data_start,
+            cluster_count: cluster_count as u32,
+            root_cluster,
+            fs_info: Some(fs_info).filter(|&sector| sector != 0 && sector < reserved),
+            read_only,
+            lock: OperationLock::new(),
+            state: Mutex::new(VolumeState {
+                free_clusters: None,
+                next_free: FIRST_CLUSTER,
+                fs_info_dirty: false,
+                inodes: BTreeMap::new(),
+            }),
+        };
+        if !volume.is_valid(root_cluster) {
+            return Err(FsError::Corrupted);
+        }
+        Ok(volume)
+    }
+
+    fn is_valid(&self, cluster: u32) -> bool {
+        (FIRST_CLUSTER..self.cluster_count + FIRST_CLUSTER).contains(&cluster)
+    }
+
+    fn check_writable(&self) -> Result<(), FsError> {
+        match self.read_only {
+            true => Err(FsError::ReadOnly),
+            false => Ok(()),
+        }
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster - FIRST_CLUSTER) as u64 * self.sectors_per_cluster
+    }
+
+    async fn block(&self, sector: u64) -> Result<Arc<BlockBuffer>, FsError> {
+        Ok(cache::get(&self.device, sector).await?)
+    }
+
+    /// Sector of the entry of `cluster` within a FAT, and its offset in the sector
+    fn fat_location(&self, cluster: u32) -> (u64, usize) {
+        let offset = cluster as u64 * 4;
+        let sector_size = self.sector_size as u64;
+        (offset / sector_size, (offset % sector_size) as usize)
+    }
+
+    async fn fat_entry(&self, cluster: u32) -> Result<u32, FsError> {
+        let (sector, offset) = self.fat_location(cluster);
+        let buffer = self
+            .block(self.fat_start + self.fats.start * self.fat_sectors + sector)
+            .await?;
+        Ok(buffer.read(|data| read_u32(data, offset)).await & ENTRY_MASK)
+    }
+
+    async fn set_fat_entry(&self, cluster: u32, value: u32) -> Result<(), FsError> {
+        let (sector, offset) = self.fat_location(cluster);
+        for fat in self.fats.clone() {
+            let buffer = self
+                .block(self.fat_start + fat * self.fat_sectors + sector)
+                .await?;
+            buffer
+                .write(|data| {
+                    let entry = read_u32(data, offset) & !ENTRY_MASK | value;
+                    data[offset..offset + 4].copy_from_slice(&entry.to_le_bytes());
+                })
+                .await;
+        }
+        Ok(())
+    }
+
+    /// The cluster after `cluster` in its chain, None at the end of it
+    async fn next_cluster(&self, cluster: u32) -> Result<Option<u32>, FsError> {
+        match self.fat_entry(cluster).await? {
+            entry if entry >= END_OF_CHAIN => Ok(None),
+            entry if self.is_valid(entry) => Ok(Some(entry)),
+            // Free or bad clusters do not belong to chains
+            _ => Err(FsError::Corrupted),
+        }
+    }
+
+    /// The cluster at `index` of the chain starting at `first`, None past its end. Empty
+    /// files start at cluster zero
+    async fn cluster_at(&self, first: u32, index: u64) -> Result<Option<u32>, FsError> {
+        self.cluster_near(first, None, index).await
+    }
+
+    /// Like `cluster_at`, but walks from `near`, a position in the same chain, if it is not
+    /// past `index`
+    async fn cluster_near(
+        &self,
+        first: u32,
+        near: Option<ChainPosition>,
+        index: u64,
+    ) -> Result<Option<u32>, FsError> {
+        if first == 0 {
+            return Ok(None);
+        }
+        if !self.is_valid(first) {
+            return Err(FsError::Corrupted);
+        }
+        let start = near
+            .filter(|near| near.index <= index)
+            .unwrap_or(ChainPosition {
+                index: 0,
+                cluster: first,
+            });
+        let mut cluster = start.cluster;
+        for _ in start.index..index {
+            match self.next_cluster(cluster).await? {
+                Some(next) => cluster = next,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(cluster))
+    }
+
+    /// Takes a free cluster, zero filled, and appends it to the chain ending in `previous`
+    async fn allocate(&self, previous: Option<u32>) -> Result<u32, FsError> {
+        let per_sector = (self.sector_size / 4) as u32;
+        let end = self.cluster_count + FIRST_CLUSTER;
+        let mut cluster = self.state.lock().next_free;
+        let mut searched = 0;
+        while searched < self.cluster_count {
+            // Up to the end of the FAT sector holding `cluster`
+            let sector_end = ((cluster / per_sector + 1) * per_sector).min(end);
+            let (sector, offset) = self.fat_location(cluster);
+            let buffer = self
+                .block(self.fat_start + self.fats.start * self.fat_sectors + sector)
+                .await?;
+            let found = buffer
+                .read(|data| {
+                    (cluster..sector_end).find(|&candidate| {
+                        let entry = read_u32(data, offset + (candidate - cluster) as usize * 4);
+                        entry & ENTRY_MASK == FREE
+                    })
+                })
+                .await;
+
+            if let Some(found) = found {
+                self.set_fat_entry(found, END_MARK).await?;
+                if let Some(previous) = previous {
+                    self.set_fat_entry(previous, found).await?;
+                }
+                {
+                    let mut state = self.state.lock();
+                    state.next_free = match found + 1 < end {
+                        true => found + 1,
+                        false => FIRST_CLUSTER,
+                    };
+                    state.free_clusters = state.free_clusters.map(|free| free.saturating_sub(1));
+                    state.fs_info_dirty = true;
+                }
+                self.transfer(found, 0, Transfer::Zero(self.cluster_size), &mut None)
+                    .await?;
+                return Ok(found);
+            }
+            searched += sector_end - cluster;
+            cluster = match sector_end {
+                sector_end if sector_end == end => FIRST_CLUSTER,
+                sector_end => sector_end,
+            };
+        }
+        Err(FsError::NoSpace)
+    }
+
+    /// Marks every cluster of the chain starting at `first` free
+    async fn free_chain(&self, first: u32) -> Result<(), FsError> {
+        let mut next = Some(first).filter(|&first| first != 0);
+        let mut freed = 0;
+        let result = async {
+            while let Some(cluster) = next {
+                if !self.is_valid(cluster) || freed >= self.cluster_count {
+                    return Err(FsError::Corrupted);
+                }
+                next = self.next_cluster(cluster).await?;
+                self.set_fat_entry(cluster, FREE).await?;
+                freed += 1;
+            }
+            Ok(())
+        }
+        .await;
+
+        let mut state = self.state.lock();
+        state.free_clusters = state.free_clusters.map(|free| free + freed);
+        state.fs_info_dirty |= freed > 0;
+        result
+    }
+
+    /// Grows the chain starting at `first` to at least `count` clusters, walking it from
+    /// `near` if that is a position in it. An empty chain gets a first cluster, which `first`
+    /// is set to as soon as it is taken
+    async fn grow(
+        &self,
+        first: &mut u32,
+        count: u64,
+        near: Option<ChainPosition>,
+    ) -> Result<(), FsError> {
+        if count == 0 || self.cluster_near(*first, near, count - 1).await?.is_some() {
+            return Ok(());
+        }
+        let mut length = 0;
+        let mut last = None;
+        if *first != 0 {
+            let start = near.unwrap_or(ChainPosition {
+                index: 0,
+                cluster: *first,
+            });
+            let mut cluster = start.cluster;
+            length = start.index + 1;
+            while let Some(next) = self.next_cluster(cluster).await? {
+                cluster = next;
+                length += 1;
+            }
+            last = Some(cluster);
+        }
+        while length < count {
+            let cluster = self.allocate(last).await?;
+            if last.is_none() {
+                *first = cluster;
+            }
+            last = Some(cluster);
+            length += 1;
+        }
+        Ok(())
+    }
+
+    /// Cuts the chain starting at `first` down to `count` clusters and returns its first
+    /// cluster, zero if none are left
+    async fn shrink(&self, first: u32, count: u64) -> Result<u32, FsError> {
+        if count == 0 {
+            self.free_chain(first).await?;
+            return Ok(0);
+        }
+        let Some(last) = self.cluster_at(first, count - 1).await? else {
+            return Ok(first);
+        };
+        if let Some(rest) = self.next_cluster(last).await? {
+            self.set_fat_entry(last, END_MARK).await?;
+            self.free_chain(rest).await?;
+        }
+        Ok(first)
+    }
+
+    /// Reads or writes the bytes from `offset` on of the chain starting at `first`, which
+    /// must be long enough. The chain is walked from `position` if that is a position in it
+    /// before `offset`, and `position` is left at the last cluster transferred
+    async fn transfer(
+        &self,
+        first: u32,
+        offset: u64,
+        mut transfer: Transfer<'_>,
+        position: &mut Option<ChainPosition>,
+    ) -> Result<(), FsError> {
+        let len = match &transfer {
+            Transfer::Read(buf) => buf.len(),
+            Transfer::Write(buf) => buf.len(),
+            Transfer::Zero(len) => *len,
+        };
+        if len == 0 {
+            return Ok(());
+        }
+        let cluster_size = self.cluster_size as u64;
+        let mut index = offset / cluster_size;
+        let mut cluster = self
+            .cluster_near(first, *position, index)
+            .await?
+            .ok_or(FsError::Corrupted)?;
+        let mut done = 0;
+        while done < len {
+            let in_cluster = (offset + done as u64) % cluster_size;
+            if done > 0 && in_cluster == 0 {
+                cluster = self
+                    .next_cluster(cluster)
+                    .await?
+                    .ok_or(FsError::Corrupted)?;
+                index += 1;
+            }
+            let sector = self.cluster_sector(cluster) + in_cluster / self.sector_size as u64;
+            let start = (in_cluster % self.sector_size as u64) as usize;
+            let count = (self.sector_size - start).min(len - done);
+            let range = start..start + count;
+            let buffer = self.block(sector).await?;
+            match &mut transfer {
+                Transfer::Read(buf) => {
+                    buffer
+                        .read(|data| buf[done..done + count].copy_from_slice(&data[range]))
+                        .await
+                }
+                Transfer::Write(buf) => {
+                    buffer
+                        .write(|data| data[range].copy_from_slice(&buf[done..done + count]))
+                        .await
+                }
+                Transfer::Zero(_) => buffer.write(|data| data[range].fill(0)).await,
+            }
+            done += count;
+        }
+        *position = Some(ChainPosition { index, cluster });
+        Ok(())
+    }
+
+    /// Calls `f` with the index, the byte address on the device and the contents of every
+    /// slot of the directory starting at `first`, from slot `start` on, until `f` breaks
+    async fn for_each_slot<R>(
+        &self,
+        first: u32,
+        start: u64,
+        mut f: impl FnMut(u64, u64, &[u8; ENTRY_SIZE]) -> ControlFlow<R>,
+    ) -> Result<Option<R>, FsError> {
+        let per_sector = (self.sector_size / ENTRY_SIZE) as u64;
+        let per_cluster = per_sector * self.sectors_per_cluster;
+        let Some(mut cluster) = self.cluster_at(first, start / per_cluster).await? else {
+            return Ok(None);
+        };
+        let mut slot = start;
+        loop {
+            if slot >= MAX_DIRECTORY_SLOTS {
+                return Err(FsError::Corrupted);
+            }
+            let sector = self.cluster_sector(cluster) + slot % per_cluster / per_sector;
+            let address = sector * self.sector_size as u64;
+            let buffer = self.block(sector).await?;
+            let flow = buffer
+                .read(|data| {
+                    for index in (slot % per_sector) as usize..per_sector as usize {
+                        let offset = index * ENTRY_SIZE;
+                        let raw = data[offset..offset + ENTRY_SIZE].try_into().unwrap();
+                        f(slot, address + offset as u64, raw)?;
+                        slot += 1;
+                    }
+                    ControlFlow::Continue(())
+                })
+                .await;
+            if let ControlFlow::Break(result) = flow {
+                return Ok(Some(result));
+            }
+            if slot.is_multiple_of(per_cluster) {
+                match self.next_cluster(cluster).await? {
+                    Some(next) => cluster = next,
+                    None => return Ok(None),
+                }
+            }
+        }
+    }
+
+    /// Byte address on the device of `slot` of the directory starting at `first`
+    async fn slot_address(&self, first: u32, slot: u64) -> Result<u64, FsError> {
+        let per_sector = (self.sector_size / ENTRY_SIZE) as u64;
+        let per_cluster = per_sector * self.sectors_per_cluster;
+        let cluster = self
+            .cluster_at(first, slot / per_cluster)
+            .await?
+            .ok_or(FsError::Corrupted)?;
+        let sector = self.cluster_sector(cluster) + slot % per_cluster / per_sector;
+        Ok(sector * self.sector_size as u64 + slot % per_sector * ENTRY_SIZE as u64)
+    }
+
+    /// Runs `f` on the slot at byte `address` of the device
+    async fn update_slot(&self, address: u64, f: impl FnOnce(&mut [u8])) -> Result<(), FsError> {
+        let sector_size = self.sector_size as u64;
+        let offset = (address % sector_size) as usize;
+        let buffer = self.block(address / sector_size).await?;
+        buffer
+            .write(|data| f(&mut data[offset..offset + ENTRY_SIZE]))
+            .await;
+        Ok(())
+    }
+
+    /// Entries of the directory starting at `first`, from slot `start` on, until `f` breaks
+    async fn entries<R>(
+        &self,
+        first: u32,
+        start: u64,
+        mut f: impl FnMut(Entry) -> ControlFlow<R>,
+    ) -> Result<Option<R>, FsError> {
+        let mut parser = Parser::new();
+        let found = self
+            .for_each_slot(first, start, |slot, address, raw| match raw[0] {
+                dir::END => ControlFlow::Break(None),
+                dir::DELETED => {
+                    parser.reset();
+                    ControlFlow::Continue(())
+                }
+                _ => match parser.feed(slot, address, raw) {
+                    Some(entry) => match f(entry) {
+                        ControlFlow::Break(result) => ControlFlow::Break(Some(result)),
+                        ControlFlow::Continue(()) => ControlFlow::Continue(()),
+                    },
+                    None => ControlFlow::Continue(()),
+                },
+            })
+            .await?;
+        Ok(found.flatten())
+    }
+
+    /// The entry `name` of the directory starting at `first`
+    async fn find(&self, first: u32, name: &str) -> Result<Option<Entry>, FsError> {
+        self.entries(first, 0, |entry| {
+            match !entry.is_dot() && entry.matches(name) {
+                true => ControlFlow::Break(entry),
+                false => ControlFlow::Continue(()),
+            }
+        })
+        .await
+    }
+
+    /// The directory starting at `first` has no entries besides `.` and `..`
+    async fn is_empty(&self, first: u32) -> Result<bool, FsError> {
+        let found = self
+            .entries(first, 0, |entry| match entry.is_dot() {
+                true => ControlFlow::Continue(()),
+                false => ControlFlow::Break(()),
+            })
+            .await?;
+        Ok(found.is_none())
+    }
+
+    /// The number of the inode of `entry`, which it keeps after it is moved
+    fn inode_number(&self, entry: &Entry) -> u64 {
+        let state = self.state.lock();
+        match state.inodes.get(&entry.position).and_then(Weak::upgrade) {
+            Some(inode) => inode.number(),
+            None => entry.position,
+        }
+    }
+
+    /// Writes the FSInfo hints and every dirty sector back to the device
+    async fn sync(&self) -> Result<(), FsError> {
+        let hints = {
+            let mut state = self.state.lock();
+            let dirty = core::mem::take(&mut state.fs_info_dirty);
+            dirty.then_some((state.free_clusters, state.next_free))
+        };
+        if let (Some(sector), Some((free_clusters, next_free))) = (self.fs_info, hints) {
+            let buffer = self.block(sector).await?;
+            buffer
+                .write(|data| {
+                    let free_clusters = free_clusters.unwrap_or(FS_INFO_UNKNOWN);
+                    data[488..492].copy_from_slice(&free_clusters.to_le_bytes());
+                    data[492..496].copy_from_slice(&next_free.to_le_bytes());
+                })
+                .await;
+        }
+        Ok(cache::sync_device(&self.device).await?)
+    }
+}
@@ -0,0 +1,155 @@
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use bitflags::bitflags;
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::{
+        environment::Environment,
+        fd::File,
+        vfs::{Dentry, DirEntry, FileType, FsError},
+    },
+};
+
+bitflags! {
+    /// Flags of `open`, numbered like Linux. Without `WRITE_ONLY` or `READ_WRITE` the file is
+    /// opened for reading only
+    pub struct OpenFlags: usize {
+        const WRITE_ONLY = 0o1;
+        const READ_WRITE = 0o2;
+        const CREATE = 0o100;
+        /// With `CREATE`, fail if the file exists
+        const EXCLUSIVE = 0o200;
+        const TRUNCATE = 0o1000;
+        /// Every write goes to the end of the file
+        const APPEND = 0o2000;
+        /// Fail unless the path names a directory
+        const DIRECTORY = 0o200000;
+    }
+}
+
+impl OpenFlags {
+    pub fn is_readable(&self) -> bool {
+        !self.contains(OpenFlags::WRITE_ONLY)
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.intersects(OpenFlags::WRITE_ONLY | OpenFlags::READ_WRITE)
+    }
+}
+
+/// Where `seek` counts its offset from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Whence {
+    Start,
+    Current,
+    End,
+}
+
+impl Whence {
+    /// `SEEK_SET`, `SEEK_CUR` and `SEEK_END`
+    pub fn from_usize(whence: usize) -> Option<Self> {
+        match whence {
+            0 => Some(Whence::Start),
+            1 => Some(Whence::Current),
+            2 => Some(Whence::End),
+            _ => None,
+        }
+    }
+}
+
+/// A file opened with `open`, shared by the descriptors referring to it
+#[derive(Debug)]
+pub struct OpenFile {
+    dentry: Arc<Dentry>,
+    flags: OpenFlags,
+    /// Offset of the next read or write, or position of the next directory entry
+    position: Mutex<u64>,
+}
+
+impl OpenFile {
+    pub fn new(dentry: Arc<Dentry>, flags: OpenFlags) -> Self {
+        Self {
+            dentry,
+            flags,
+            position: Mutex::new(0),
+        }
+    }
+
+    pub fn dentry(&self) -> &Arc<Dentry> {
+        &self.dentry
+    }
+
+    pub fn flags(&self) -> OpenFlags {
+        self.flags
+    }
+
+    pub async fn read(&self, buf: &mut [u8]) -> Result<usize, FsError> {
+        if !self.flags.is_readable() {
+            return Err(FsError::AccessMode);
+        }
+        let position = *self.position.lock();
+        let read = self.dentry.inode().read_at(position, buf).await?;
+        *self.position.lock() = position + read as u64;
+        Ok(read)
+    }
+
+    pub async fn write(&self, buf: &[u8]) -> Result<usize, FsError> {
+        if !self.flags.is_writable() {
+            return Err(FsError::AccessMode);
+        }
+        let inode = self.dentry.inode();
+        let position = match self.flags.contains(OpenFlags::APPEND) {
+            true => inode.metadata().size,
+            false => *self.position.lock(),
+        };
+        let written = inode.write_at(position, buf).await?;
+        *self.position.lock() = position + written as u64;
+        Ok(written)
+    }
+
+    /// Moves the position to `offset` from `whence` and returns it
+    pub fn seek(&self, offset: i64, whence: Whence) -> Result<u64, FsError> {
+        let mut position = self.position.lock();
+        let base = match whence {
+            Whence::Start => 0,
+            Whence::Current => *position,
+            Whence::End => self.dentry.inode().metadata().size,
+        };
+        let target = (base as i64)
+            .checked_add(offset)
+            .filter(|&target| target >= 0)
+            .ok_or(FsError::InvalidArgument)?;
+        *position = target as u64;
+        Ok(*position)
+    }
+
+    /// Returns the next entry of the directory, None after the last. `.` and `..` are left
+    /// out
+    pub async fn read_dir(&self) -> Result<Option<DirEntry>, FsError> {
+        let inode = self.dentry.inode();
+        if inode.metadata().kind != FileType::Directory {
+            return Err(FsError::NotDirectory);
+        }
+        let mut position = *self.position.lock();
+        loop {
+            let Some((entry, next)) = inode.read_dir(position).await? else {
+                *self.position.lock() = position;
+                return Ok(None);
+            };
+            position = next;
+            if entry.name != "." && entry.name != ".." {
+                *self.position.lock() = position;
+                return Ok(Some(entry));
+            }
+        }
+    }
+}
+
+impl<ENV: Environment> File<ENV> for OpenFile {
+    fn as_open_file(&self) -> Option<&OpenFile> {
+        Some(self)
+    }
+}
@@ -0,0 +1,109 @@
+extern crate alloc;
+
+use core::{any::Any, fmt::Debug};
+
+use alloc::{string::String, sync::Arc};
+
+use crate::kernel::vfs::{ready, FsError, FsFuture};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum FileType {
+    Regular = 1,
+    Directory = 2,
+    Symlink = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    /// Number of the inode, unique within its filesystem
+    pub inode: u64,
+    pub kind: FileType,
+    /// Size in bytes
+    pub size: u64,
+    /// Number of directory entries naming the inode
+    pub links: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub inode: u64,
+    pub kind: FileType,
+    pub name: String,
+}
+
+/// A mounted filesystem
+pub trait FileSystem: Send + Sync + Debug {
+    /// Name of the kind of filesystem, e.g. `ramfs`
+    fn name(&self) -> &str;
+
+    fn root(&self) -> Arc<dyn Inode>;
+
+    /// Writes everything the filesystem caches back to its device
+    fn sync(&self) -> FsFuture<'_, ()> {
+        ready(Ok(()))
+    }
+}
+
+/// A file, directory or symbolic link of a filesystem. Names given to the directory
+/// operations are single components, never `.` or `..`
+pub trait Inode: Send + Sync + Debug {
+    fn metadata(&self) -> Metadata;
+
+    /// Reads from `offset` into `buf`, returns the amount read, zero at the end of the file
+    fn read_at<'a>(&'a self, _offset: u64, _buf: &'a mut [u8]) -> FsFuture<'a, usize> {
+        ready(Err(FsError::IsDirectory))
+    }
+
+    /// Writes `buf` at `offset`, growing the file if it ends before. Returns the amount
+    /// written
+    fn write_at<'a>(&'a self, _offset: u64, _buf: &'a [u8]) -> FsFuture<'a, usize> {
+        ready(Err(FsError::IsDirectory))
+    }
+
+    /// Cuts the file off or zero fills it to `size` bytes
+    fn truncate(&self, _size: u64) -> FsFuture<'_, ()> {
+        ready(Err(FsError::IsDirectory))
+    }
+
+    /// Returns the entry `name` of the directory
+    fn lookup<'a>(&'a self, _name: &'a str) -> FsFuture<'a, Arc<dyn Inode>> {
+        ready(Err(FsError::NotDirectory))
+    }
+
+    /// Adds an empty file or directory named `name` to the directory
+    fn create<'a>(&'a self, _name: &'a str, _kind: FileType) -> FsFuture<'a, Arc<dyn Inode>> {
+        ready(Err(FsError::NotDirectory))
+    }
+
+    /// Removes the entry `name` of the directory, a directory only once it is empty
+    fn unlink<'a>(&'a self, _name: &'a str) -> FsFuture<'a, ()> {
+        ready(Err(FsError::NotDirectory))
+    }
+
+    /// Moves the entry `old_name` of this directory to `new_name` in `new_parent`, a
+    /// directory of the same filesystem. An entry `new_name` is replaced, unless it is a
+    /// directory with entries
+    fn rename<'a>(
+        &'a self,
+        _old_name: &'a str,
+        _new_parent: &'a dyn Inode,
+        _new_name: &'a str,
+    ) -> FsFuture<'a, ()> {
+        ready(Err(FsError::NotDirectory))
+    }
+
+    /// Returns the entry at `position` of the directory and the position of the one after
+    /// it, None past the last. Listing starts at position zero
+    fn read_dir(&self, _position: u64) -> FsFuture<'_, Option<(DirEntry, u64)>> {
+        ready(Err(FsError::NotDirectory))
+    }
+
+    /// Returns the target of the symbolic link
+    fn read_link(&self) -> FsFuture<'_, String> {
+        ready(Err(FsError::InvalidArgument))
+    }
+
+    /// For `rename` to find its own type behind the other directory
+    fn as_any(&self) -> &dyn Any;
+}
@@ -0,0 +1,62 @@
+extern crate alloc;
+
+use core::{
+    future::poll_fn,
+    task::{Poll, Waker},
+};
+
+use alloc::vec::Vec;
+
+use crate::{collections::mutex::Mutex, drivers::irq::without_interrupts};
+
+/// Lets one operation at a time work on a volume, held across the waits for the device
+#[derive(Debug)]
+pub(super) struct OperationLock {
+    state: Mutex<LockState>,
+}
+
+#[derive(Debug)]
+struct LockState {
+    locked: bool,
+    waiters: Vec<Waker>,
+}
+
+pub(super) struct OperationGuard<'a> {
+    lock: &'a OperationLock,
+}
+
+impl OperationLock {
+    pub(super) fn new() -> Self {
+        Self {
+            state: Mutex::new(LockState {
+                locked: false,
+                waiters: Vec::new(),
+            }),
+        }
+    }
+
+    pub(super) async fn lock(&self) -> OperationGuard<'_> {
+        poll_fn(|cx| {
+            let mut state = self.state.lock();
+            if state.locked {
+                state.waiters.push(cx.waker().clone());
+                return Poll::Pending;
+            }
+            state.locked = true;
+            Poll::Ready(())
+        })
+        .await;
+        OperationGuard { lock: self }
+    }
+}
+
+impl Drop for OperationGuard<'_> {
+    fn drop(&mut self) {
+        let waiters = {
+            let mut state = self.lock.state.lock();
+            state.locked = false;
+            core::mem::take(&mut state.waiters)
+        };
+        without_interrupts(|| waiters.into_iter().for_each(Waker::wake));
+    }
+}
@@ -0,0 +1,283 @@
+//! Virtual filesystem, the single tree of directories every filesystem is mounted into.
+//!
+//! Filesystems implement `FileSystem` and `Inode`. The tree is made of `Dentry`s, each
+//! naming an inode and caching the children looked up so far while they are in use.
+//! Mounting a filesystem on a directory makes its root take the place of the directory.
+//! Descriptors of opened files refer to an `OpenFile`. Anything that may touch a device is a future, which syscalls
+//! wait for with `Kernel::block_on`.
+
+extern crate alloc;
+
+use core::{future::Future, pin::Pin};
+
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::block::{self, BlockDevice, BlockError},
+};
+
+mod dentry;
+pub mod ext2;
+pub mod fat;
+mod file;
+mod inode;
+mod lock;
+pub mod ramfs;
+
+pub use dentry::*;
+pub use file::*;
+pub use inode::*;
+
+/// Longest name of a directory entry in bytes
+pub const NAME_MAX: usize = 255;
+/// Longest path a syscall takes in bytes
+pub const PATH_MAX: usize = 4096;
+
+static ROOT: Mutex<Option<Arc<Dentry>>> = Mutex::new(None);
+static MOUNTS: Mutex<Vec<Mount>> = Mutex::new(Vec::new());
+static FILESYSTEM_TYPES: Mutex<Vec<FileSystemType>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    NotFound,
+    NotDirectory,
+    IsDirectory,
+    AlreadyExists,
+    /// Removing a directory that still has entries
+    NotEmpty,
+    ReadOnly,
+    NoSpace,
+    /// The entry is a mount point
+    Busy,
+    /// Renaming across filesystems
+    CrossDevice,
+    /// Empty names, `.` and `..` where a new name is expected, or a name the filesystem can
+    /// not store
+    InvalidName,
+    NameTooLong,
+    /// Resolving a path followed too many symbolic links
+    TooManyLinks,
+    /// The file was not opened for reading or writing
+    AccessMode,
+    /// Seeking before the start of a file, or reading a link that is none
+    InvalidArgument,
+    /// The device does not hold the kind of filesystem that was asked for
+    UnknownFormat,
+    /// The structures on the device contradict each other
+    Corrupted,
+    Io,
+    NotSupported,
+}
+
+impl From<BlockError> for FsError {
+    fn from(error: BlockError) -> Self {
+        match error {
+            BlockError::ReadOnly => FsError::ReadOnly,
+            // The filesystem points past its device
+            BlockError::OutOfRange => FsError::Corrupted,
+            BlockError::BadLength | BlockError::Io | BlockError::Unsupported => FsError::Io,
+        }
+    }
+}
+
+/// Operations of filesystems and inodes, which may wait for their device
+pub type FsFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, FsError>> + Send + 'a>>;
+
+/// A future that is done right away, for operations that need no device
+pub fn ready<'a, T: Send + 'a>(result: Result<T, FsError>) -> FsFuture<'a, T> {
+    Box::pin(core::future::ready(result))
+}
+
+/// Reads the filesystem on a device, fails with `FsError::UnknownFormat` if it holds another
+/// kind
+pub type MountFn = fn(Arc<dyn BlockDevice>) -> FsFuture<'static, Arc<dyn FileSystem>>;
+
+/// Kind of filesystem that can be mounted from a block device
+#[derive(Debug, Clone, Copy)]
+pub struct FileSystemType {
+    pub name: &'static str,
+    pub mount: MountFn,
+}
+
+/// A filesystem mounted into the tree
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub path: String,
+    pub fs: Arc<dyn FileSystem>,
+    /// The directory the filesystem is mounted on, which the tree only holds while it is in
+    /// use
+    pub point: Arc<Dentry>,
+}
+
+/// Makes filesystems of this type mountable with `mount_device`
+pub fn register_filesystem(fs_type: FileSystemType) {
+    FILESYSTEM_TYPES.lock().push(fs_type);
+}
+
+/// Registers the filesystem types built into the kernel
+pub fn register_filesystems() {
+    register_filesystem(fat::FILESYSTEM_TYPE);
+    register_filesystem(ext2::FILESYSTEM_TYPE);
+}
+
+/// Mounts `fs` as the root of the tree
+pub fn init(fs: Arc<dyn FileSystem>) {
+    let root = Dentry::root(fs.clone());
+    *ROOT.lock() = Some(root.clone());
+    MOUNTS.lock().push(Mount {
+        path: String::from("/"),
+        fs,
+        point: root,
+    });
+}
+
+/// The root of the tree, where absolute paths start
+pub fn root() -> Arc<Dentry> {
+    ROOT.lock().clone().expect("no root filesystem mounted")
+}
+
+/// Every mounted filesystem, the root first
+pub fn mounts() -> Vec<Mount> {
+    MOUNTS.lock().clone()
+}
+
+/// Mounts `fs` on the directory at `path`
+pub async fn mount(path: &str, fs: Arc<dyn FileSystem>) -> Result<(), FsError> {
+    let target = resolve(&root(), path).await?;
+    target.mount(fs.clone())?;
+    MOUNTS.lock().push(Mount {
+        path: target.path(),
+        fs,
+        point: target,
+    });
+    Ok(())
+}
+
+/// Reads the filesystem on `device` with the first registered type that recognises it.
+/// Returns the filesystem and the name of the type
+pub async fn probe(
+    device: Arc<dyn BlockDevice>,
+) -> Result<(Arc<dyn FileSystem>, &'static str), FsError> {
+    let fs_types = FILESYSTEM_TYPES.lock().clone();
+    for fs_type in fs_types {
+        match (fs_type.mount)(device.clone()).await {
+            Ok(fs) => return Ok((fs, fs_type.name)),
+            Err(FsError::UnknownFormat) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(FsError::UnknownFormat)
+}
+
+/// Mounts the filesystem on `device` on the directory at `path`. Returns the name of its
+/// type
+pub async fn mount_device(
+    path: &str,
+    device: Arc<dyn BlockDevice>,
+) -> Result<&'static str, FsError> {
+    let (fs, fs_type) = probe(device).await?;
+    mount(path, fs).await.map(|()| fs_type)
+}
+
+/// Mounts every block device holding a filesystem of a registered type on
+/// `/mnt/<device name>`, creating the directories
+pub async fn mount_devices() {
+    for device in block::devices() {
+        let (fs, fs_type) = match probe(device.clone()).await {
+            Ok(found) => found,
+            Err(FsError::UnknownFormat) => continue,
+            Err(err) => {
+                println!("vfs: {}: {:?}", device.name(), err);
+                continue;
+            }
+        };
+        let path = format!("/mnt/{}", device.name());
+        let result = async {
+            for directory in ["/mnt", path.as_str()] {
+                match mkdir(&root(), directory).await {
+                    Ok(()) | Err(FsError::AlreadyExists) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            mount(&path, fs).await
+        };
+        match result.await {
+            Ok(()) => {
+                println!("vfs: {} ({}) mounted on {}", device.name(), fs_type, path);
+            }
+            Err(err) => {
+                println!("vfs: {}: {:?}", device.name(), err);
+            }
+        }
+    }
+}
+
+/// Writes everything the mounted filesystems cache back to their devices
+pub async fn sync_all() -> Result<(), FsError> {
+    let mut result = Ok(());
+    for mount in mounts() {
+        if let Err(err) = mount.fs.sync().await {
+            result = result.and(Err(err));
+        }
+    }
+    result
+}
+
+/// Opens the file at `path`, creating it first if `flags` say so
+pub async fn open(cwd: &Arc<Dentry>, path: &str, flags: OpenFlags) -> Result<OpenFile, FsError> {
+    let dentry = match resolve(cwd, path).await {
+        Ok(_) if flags.contains(OpenFlags::CREATE | OpenFlags::EXCLUSIVE) => {
+            return Err(FsError::AlreadyExists)
+        }
+        Ok(dentry) => dentry,
+        Err(FsError::NotFound) if flags.contains(OpenFlags::CREATE) => {
+            let (parent, name) = resolve_parent(cwd, path).await?;
+            parent.create(name, FileType::Regular).await?
+        }
+        Err(err) => return Err(err),
+    };
+
+    let kind = dentry.inode().metadata().kind;
+    if flags.contains(OpenFlags::DIRECTORY) && kind != FileType::Directory {
+        return Err(FsError::NotDirectory);
+    }
+    if kind == FileType::Directory && flags.is_writable() {
+        return Err(FsError::IsDirectory);
+    }
+    if flags.contains(OpenFlags::TRUNCATE) && flags.is_writable() {
+        dentry.inode().truncate(0).await?;
+    }
+    Ok(OpenFile::new(dentry, flags))
+}
+
+pub async fn stat(cwd: &Arc<Dentry>, path: &str) -> Result<Metadata, FsError> {
+    Ok(resolve(cwd, path).await?.inode().metadata())
+}
+
+pub async fn mkdir(cwd: &Arc<Dentry>, path: &str) -> Result<(), FsError> {
+    let (parent, name) = resolve_parent(cwd, path).await?;
+    parent.create(name, FileType::Directory).await.map(drop)
+}
+
+/// Removes the entry at `path`, directories only once they are empty
+pub async fn unlink(cwd: &Arc<Dentry>, path: &str) -> Result<(), FsError> {
+    let (parent, name) = resolve_parent(cwd, path).await?;
+    parent.unlink(name).await
+}
+
+/// Moves the entry at `old` to `new`, replacing what `new` names
+pub async fn rename(cwd: &Arc<Dentry>, old: &str, new: &str) -> Result<(), FsError> {
+    let (old_parent, old_name) = resolve_parent(cwd, old).await?;
+    let (new_parent, new_name) = resolve_parent(cwd, new).await?;
+    Dentry::rename(&old_parent, old_name, &new_parent, new_name).await
+}
+
+/// Returns the directory at `path`, for a new working directory
+pub async fn directory(cwd: &Arc<Dentry>, path: &str) -> Result<Arc<Dentry>, FsError> {
+    let dentry = resolve(cwd, path).await?;
+    match dentry.inode().metadata().kind {
+        FileType::Directory => Ok(dentry),
+        _ => Err(FsError::NotDirectory),
+    }
+}
@@ -0,0 +1,358 @@
+//! Filesystem kept in kernel memory, the root of the tree.
+//!
+//! At boot it is filled with the contents of the initramfs, and everything written to it is
+//! lost on shutdown.
+
+extern crate alloc;
+
+use core::{
+    any::Any,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    collections::mutex::Mutex,
+    kernel::{
+        initramfs::{self, Initramfs},
+        vfs::{ready, DirEntry, FileSystem, FileType, FsError, FsFuture, Inode, Metadata},
+    },
+};
+
+/// Files live on the kernel heap, which is shared with everything else
+const MAX_FILE_SIZE: u64 = 1 << 20;
+
+#[derive(Debug)]
+pub struct RamFs {
+    root: Arc<RamInode>,
+}
+
+#[derive(Debug)]
+enum RamNode {
+    File(Vec<u8>),
+    Directory(BTreeMap<String, Arc<RamInode>>),
+    Symlink(String),
+}
+
+#[derive(Debug)]
+pub struct RamInode {
+    number: u64,
+    node: Mutex<RamNode>,
+    /// Hands out the numbers of the inodes of the filesystem
+    next_number: Arc<AtomicUsize>,
+}
+
+impl RamFs {
+    pub fn new() -> Self {
+        let next_number = Arc::new(AtomicUsize::new(1));
+        Self {
+            root: RamInode::new(RamNode::Directory(BTreeMap::new()), next_number),
+        }
+    }
+
+    /// Returns a filesystem holding every file, directory and link of `archive`, which are
+    /// moved over instead of copied
+    pub fn from_initramfs(archive: Initramfs) -> Self {
+        let fs = Self::new();
+        // Parents sort before their children
+        for (path, node) in archive.into_nodes() {
+            let Some((parent, name)) = path.rsplit_once('/') else {
+                continue;
+            };
+            if name.is_empty() {
+                continue;
+            }
+            let node = match node {
+                initramfs::Node::File(data) => RamNode::File(data),
+                initramfs::Node::Directory => RamNode::Directory(BTreeMap::new()),
+                initramfs::Node::Symlink(target) => RamNode::Symlink(target),
+            };
+            let Some(parent) = fs.root.find(parent) else {
+                continue;
+            };
+            let mut parent = parent.node.lock();
+            if let RamNode::Directory(entries) = &mut *parent {
+                let inode = RamInode::new(node, fs.root.next_number.clone());
+                entries.insert(String::from(name), inode);
+            }
+        }
+        fs
+    }
+}
+
+impl Default for RamFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for RamFs {
+    fn name(&self) -> &str {
+        "ramfs"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+impl RamInode {
+    fn new(node: RamNode, next_number: Arc<AtomicUsize>) -> Arc<Self> {
+        Arc::new(Self {
+            number: next_number.fetch_add(1, Ordering::Relaxed) as u64,
+            node: Mutex::new(node),
+            next_number,
+        })
+    }
+
+    /// Walks the directories of the absolute `path` down from this one
+    fn find(self: &Arc<Self>, path: &str) -> Option<Arc<RamInode>> {
+        let mut inode = self.clone();
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let child = match &*inode.node.lock() {
+                RamNode::Directory(entries) => entries.get(name)?.clone(),
+                _ => return None,
+            };
+            inode = child;
+        }
+        Some(inode)
+    }
+
+    fn kind(&self) -> FileType {
+        match &*self.node.lock() {
+            RamNode::File(_) => FileType::Regular,
+            RamNode::Directory(_) => FileType::Directory,
+            RamNode::Symlink(_) => FileType::Symlink,
+        }
+    }
+
+    fn is_empty_directory(&self) -> bool {
+        matches!(&*self.node.lock(), RamNode::Directory(entries) if entries.is_empty())
+    }
+
+    fn entry(&self, name: &str) -> Result<Arc<RamInode>, FsError> {
+        match &*self.node.lock() {
+            RamNode::Directory(entries) => entries.get(name).cloned().ok_or(FsError::NotFound),
+            _ => Err(FsError::NotDirectory),
+        }
+    }
+}
+
+/// Checks that `replaced` may be overwritten by an entry of kind `kind`
+fn check_replace(kind: FileType, replaced: &RamInode) -> Result<(), FsError> {
+    match (kind, replaced.kind()) {
+        (FileType::Directory, FileType::Directory) if !replaced.is_empty_directory() => {
+            Err(FsError::NotEmpty)
+        }
+        (FileType::Directory, FileType::Directory) => Ok(()),
+        (FileType::Directory, _) => Err(FsError::NotDirectory),
+        (_, FileType::Directory) => Err(FsError::IsDirectory),
+        _ => Ok(()),
+    }
+}
+
+impl Inode for RamInode {
+    fn metadata(&self) -> Metadata {
+        let node = self.node.lock();
+        let (kind, size, links) = match &*node {
+            RamNode::File(data) => (FileType::Regular, data.len() as u64, 1),
+            // Its entry in the parent, its own `.` and the `..` of every subdirectory
+            RamNode::Directory(entries) => {
+                let subdirectories = entries
+                    .values()
+                    .filter(|inode| inode.kind() == FileType::Directory)
+                    .count();
+                (
+                    FileType::Directory,
+                    entries.len() as u64,
+                    2 + subdirectories as u32,
+                )
+            }
+            RamNode::Symlink(target) => (FileType::Symlink, target.len() as u64, 1),
+        };
+        Metadata {
+            inode: self.number,
+            kind,
+            size,
+            links,
+        }
+    }
+
+    fn read_at<'a>(&'a self, offset: u64, buf: &'a mut [u8]) -> FsFuture<'a, usize> {
+        ready(match &*self.node.lock() {
+            RamNode::File(data) => {
+                // Compared before converting, so offsets past 4 GiB do not wrap around
+                let start = offset.min(data.len() as u64) as usize;
+                let len = buf.len().min(data.len() - start);
+                buf[..len].copy_from_slice(&data[start..start + len]);
+                Ok(len)
+            }
+            RamNode::Directory(_) => Err(FsError::IsDirectory),
+            RamNode::Symlink(_) => Err(FsError::InvalidArgument),
+        })
+    }
+
+    fn write_at<'a>(&'a self, offset: u64, buf: &'a [u8]) -> FsFuture<'a, usize> {
+        ready(match &mut *self.node.lock() {
+            RamNode::File(_) if offset.saturating_add(buf.len() as u64) > MAX_FILE_SIZE => {
+                Err(FsError::NoSpace)
+            }
+            RamNode::File(data) => {
+                // The arm above keeps the offset under `MAX_FILE_SIZE`, so it fits a usize
+                let (start, end) = (offset as usize, offset as usize + buf.len());
+                let grow = end.saturating_sub(data.len());
+                if data.try_reserve(grow).is_err() {
+                    Err(FsError::NoSpace)
+                } else {
+                    if end > data.len() {
+                        data.resize(end, 0);
+                    }
+                    data[start..end].copy_from_slice(buf);
+                    Ok(buf.len())
+                }
+            }
+            RamNode::Directory(_) => Err(FsError::IsDirectory),
+            RamNode::Symlink(_) => Err(FsError::InvalidArgument),
+        })
+    }
+
+    fn truncate(&self, size: u64) -> FsFuture<'_, ()> {
+        ready(match &mut *self.node.lock() {
+            RamNode::File(_) if size > MAX_FILE_SIZE => Err(FsError::NoSpace),
+            RamNode::File(data) => {
+                let grow = (size as usize).saturating_sub(data.len());
+                if data.try_reserve(grow).is_err() {
+                    Err(FsError::NoSpace)
+                } else {
+                    data.resize(size as usize, 0);
+                    Ok(())
+                }
+            }
+            RamNode::Directory(_) => Err(FsError::IsDirectory),
+            RamNode::Symlink(_) => Err(FsError::InvalidArgument),
+        })
+    }
+
+    fn lookup<'a>(&'a self, name: &'a str) -> FsFuture<'a, Arc<dyn Inode>> {
+        ready(self.entry(name).map(|inode| inode as Arc<dyn Inode>))
+    }
+
+    fn create<'a>(&'a self, name: &'a str, kind: FileType) -> FsFuture<'a, Arc<dyn Inode>> {
+        let node = match kind {
+            FileType::Regular => RamNode::File(Vec::new()),
+            FileType::Directory => RamNode::Directory(BTreeMap::new()),
+            // Links are only made from the initramfs
+            FileType::Symlink => return ready(Err(FsError::NotSupported)),
+        };
+        ready(match &mut *self.node.lock() {
+            RamNode::Directory(entries) if entries.contains_key(name) => {
+                Err(FsError::AlreadyExists)
+            }
+            RamNode::Directory(entries) => {
+                let inode = RamInode::new(node, self.next_number.clone());
+                entries.insert(String::from(name), inode.clone());
+                Ok(inode as Arc<dyn Inode>)
+            }
+            _ => Err(FsError::NotDirectory),
+        })
+    }
+
+    fn unlink<'a>(&'a self, name: &'a str) -> FsFuture<'a, ()> {
+        ready(match &mut *self.node.lock() {
+            RamNode::Directory(entries) => match entries.get(name) {
+                None => Err(FsError::NotFound),
+                Some(inode)
+                    if inode.kind() == FileType::Directory && !inode.is_empty_directory() =>
+                {
+                    Err(FsError::NotEmpty)
+                }
+                Some(_) => {
+                    entries.remove(name);
+                    Ok(())
+                }
+            },
+            _ => Err(FsError::NotDirectory),
+        })
+    }
+
+    fn rename<'a>(
+        &'a self,
+        old_name: &'a str,
+        new_parent: &'a dyn Inode,
+        new_name: &'a str,
+    ) -> FsFuture<'a, ()> {
+        let Some(new_parent) = new_parent.as_any().downcast_ref::<RamInode>() else {
+            return ready(Err(FsError::CrossDevice));
+        };
+        let moved = match self.entry(old_name) {
+            Ok(inode) => inode,
+            Err(err) => return ready(Err(err)),
+        };
+        if let Ok(replaced) = new_parent.entry(new_name) {
+            if Arc::ptr_eq(&moved, &replaced) {
+                return ready(Ok(()));
+            }
+            if let Err(err) = check_replace(moved.kind(), &replaced) {
+                return ready(Err(err));
+            }
+        }
+
+        // Both directories are locked, the one with the lower number first
+        let result = if self.number == new_parent.number {
+            match &mut *self.node.lock() {
+                RamNode::Directory(entries) => {
+                    let inode = entries.remove(old_name).ok_or(FsError::NotFound);
+                    inode.map(|inode| drop(entries.insert(String::from(new_name), inode)))
+                }
+                _ => Err(FsError::NotDirectory),
+            }
+        } else {
+            let (first, second) = match self.number < new_parent.number {
+                true => (self, new_parent),
+                false => (new_parent, self),
+            };
+            let mut first = first.node.lock();
+            let mut second = second.node.lock();
+            let (old, new) = match self.number < new_parent.number {
+                true => (&mut *first, &mut *second),
+                false => (&mut *second, &mut *first),
+            };
+            match (old, new) {
+                (RamNode::Directory(old), RamNode::Directory(new)) => {
+                    let inode = old.remove(old_name).ok_or(FsError::NotFound);
+                    inode.map(|inode| drop(new.insert(String::from(new_name), inode)))
+                }
+                _ => Err(FsError::NotDirectory),
+            }
+        };
+        ready(result)
+    }
+
+    fn read_dir(&self, position: u64) -> FsFuture<'_, Option<(DirEntry, u64)>> {
+        ready(match &*self.node.lock() {
+            RamNode::Directory(entries) => {
+                Ok(entries.iter().nth(position as usize).map(|(name, inode)| {
+                    let entry = DirEntry {
+                        inode: inode.number,
+                        kind: inode.kind(),
+                        name: name.clone(),
+                    };
+                    (entry, position + 1)
+                }))
+            }
+            _ => Err(FsError::NotDirectory),
+        })
+    }
+
+    fn read_link(&self) -> FsFuture<'_, String> {
+        ready(match &*self.node.lock() {
+            RamNode::Symlink(target) => Ok(target.clone()),
+            _ => Err(FsError::InvalidArgument),
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
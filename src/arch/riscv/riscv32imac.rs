@@ -1,7 +1,5 @@
 extern crate alloc;
 
-use alloc::boxed::Box;
-use alloc::rc::Rc;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
@@ -9,7 +7,7 @@ use pippopp::arch::riscv::environment::riscv32im::EnvironmentRiscv32im;
 use pippopp::arch::riscv::sbi;
 use pippopp::arch::riscv::trap::trap_set_kernel;
 use pippopp::collections::mutex::Mutex;
-use pippopp::kernel::scheduler::Scheduler;
+use pippopp::kernel::scheduler::{Scheduler, Stack};
 use pippopp::kernel::Kernel;
 
 use core::alloc::Layout;
@@ -21,82 +19,116 @@ use core::{
     sync::atomic::{fence, Ordering},
 };
 use pippopp::{
-    arch::riscv::{
-        csr::{Sstatus, Stvec},
-        frame::riscv32im::FrameRiscv32im,
-        kernel,
-    },
+    arch::riscv::csr::Sstatus,
     kernel::{
-        environment::Frame,
-        mem::UserPages,
+        fdt::Fdt,
+        initramfs::{self, Initramfs},
         process::{Process, ProcessId},
-        scheduler::{Stack, Task},
+        vfs::{self, ramfs::RamFs},
     },
 };
 
 global_asm!(include_str!("boot.S"));
 
-const A_PROGRAM: &[u8] = include_bytes!("./A_main.bin");
-// const PRIME_PROGRAM: &[u8] = include_bytes!("../../../exe/simple/main.bin");
-const B_PROGRAM: &[u8] = include_bytes!("./B_main.bin");
-
 static mut SCHEDULER: Option<Arc<Mutex<Scheduler<EnvironmentRiscv32im>>>> = None;
-static LOCK: Mutex<()> = Mutex::new(());
+
+/// Hart ids below this are probed for harts to bring up when there is no device tree
+const MAX_HART_ID: u32 = 32;
 
 #[no_mangle]
 pub extern "C" fn kernel_main(a0: u32, a1: u32, a2: u32) -> ! {
-    let entry_harth_id = a0;
-    unsafe { pippopp::kernel::mem::init() };
+    let boot_harth_id = a0;
+    let mut writer = sbi::debug_console::SbiWriter;
+
+    // SAFETY: The firmware passes the address of the device tree blob in a1
+    let fdt = unsafe { Fdt::from_ptr(a1 as *const u8) }.ok();
+
+    // Memory next, everything after it allocates
+    unsafe { pippopp::kernel::mem::init(fdt.as_ref()) };
+    writeln!(
+        writer,
+        "{} MiB of memory for pages",
+        pippopp::kernel::mem::page_allocator::available() / (1024 * 1024)
+    )
+    .unwrap();
+    match fdt.and_then(|fdt| fdt.timebase_frequency()) {
+        Some(frequency) => pippopp::kernel::time::init(frequency as usize),
+        None => {
+            writeln!(writer, "No timebase frequency in the device tree, using the default").unwrap();
+        }
+    }
 
     fence(Ordering::SeqCst);
 
+    if let Some(fdt) = fdt {
+        if !pippopp::drivers::plic::init(&fdt) {
+            writeln!(writer, "No interrupt controller, devices are polled").unwrap();
+        }
+        pippopp::drivers::virtio::register_drivers();
+        // SAFETY: The slots are taken from the device tree and nothing else drives them
+        unsafe { pippopp::drivers::virtio::probe(fdt.virtio_mmio_devices()) };
+        pippopp::kernel::block::poll_to_completion(
+            pippopp::kernel::block::partition::register_all(),
+        );
+    }
+
+    // An archive passed with `-initrd` replaces the one built into the kernel
+    let initrd = fdt.and_then(|fdt| fdt.initrd());
+    let archive = match initrd {
+        // SAFETY: The boot loader placed the initrd in this range and it is left untouched
+        // until it is released below
+        Some((start, end)) => unsafe {
+            core::slice::from_raw_parts(start as *const u8, end - start)
+        },
+        None => initramfs::EMBEDDED,
+    };
+    let root = Initramfs::unpack(archive).expect("Failed to unpack the initramfs");
+    let init = root.read("/init").expect("No /init in the initramfs");
+
+    // Init is the only process the kernel starts, it launches everything else
     let mut scheduler = Scheduler::<EnvironmentRiscv32im>::new();
-    scheduler.new_test_task(0, A_PROGRAM);
-    scheduler.new_test_task(1, B_PROGRAM);
-    // scheduler.new_test_task(2, B_PROGRAM);
-    // scheduler.new_test_task(3, B_PROGRAM);
-    // scheduler.new_test_task(4, A_PROGRAM);
-    // scheduler.new_test_task(5, A_PROGRAM);
-    // scheduler.new_test_task(6, B_PROGRAM);
-    // scheduler.new_test_task(7, B_PROGRAM);
-    // scheduler.new_test_task(2, PRIME_PROGRAM);
-    // scheduler.new_test_task(2, PRIME_PROGRAM);
+    let init = Process::from_image(ProcessId::INIT, init).expect("Failed to load /init");
+    scheduler.add_process(init, None, Stack::new());
+    vfs::init(Arc::new(RamFs::from_initramfs(root)));
+    vfs::register_filesystems();
+    pippopp::kernel::block::poll_to_completion(vfs::mount_devices());
+    // Everything was copied out of the archive while unpacking it
+    if let Some((start, end)) = initrd {
+        // SAFETY: Nothing refers to the initrd anymore
+        unsafe { pippopp::kernel::mem::release(start, end) };
+    }
 
     unsafe {
         SCHEDULER = Some(Arc::new(Mutex::new(scheduler)));
     }
 
-    let mut amount_started = 0;
-
-    for i in 0..4 {
-        use pippopp::arch::riscv as r32;
-        let mut writer = r32::sbi::debug_console::SbiWriter;
-        if i == entry_harth_id {
-            writeln!(writer, "Main harth, skipping").unwrap();
-            continue;
-        } else {
-            writeln!(writer, "Starting {}", i).unwrap();
-        }
+    // Without a device tree every id is probed, ids without a hart behind them report an
+    // error instead of a state
+    let harths: Vec<u32> = match fdt {
+        Some(fdt) => fdt.harts().map(|id| id as u32).collect(),
+        None => (0..MAX_HART_ID)
+            .filter(|&id| matches!(sbi::harth::status(id), Ok(sbi::harth::HART_STOPPED)))
+            .collect(),
+    };
+    for harth_id in harths.into_iter().filter(|&id| id != boot_harth_id) {
+        writeln!(writer, "Starting {}", harth_id).unwrap();
 
         let trampoline_stack = unsafe {
             let layout = Layout::from_size_align(0x1000, 0x1000).unwrap();
             let stack = alloc::alloc::alloc(layout) as *mut u8;
             stack.addr() + 0x1000 - 4
         };
-        writeln!(writer, "Trampoline stack {:x}", trampoline_stack).unwrap();
-        amount_started += 1;
         sbi::harth::start(
-            i,
+            harth_id,
             harth_entrypoint_trampoline as *const (),
             trampoline_stack as u32,
         )
         .unwrap();
-        if amount_started == 1 {
-            writeln!(writer, "Started all harths").unwrap();
-            break;
-        }
     }
-    loop {}
+    writeln!(writer, "Started all harths").unwrap();
+
+    // The boot hart runs tasks like the others
+    harth_entrypoint(boot_harth_id, 0)
 }
 
 #[no_mangle]
@@ -122,8 +154,6 @@ pub extern "C" fn harth_entrypoint(core: u32, opaque: u32) -> ! {
     sstatus.SIE = false;
     sstatus.store();
 
-    pippopp::arch::riscv::timer::init();
-
     let kernel = unsafe {
         alloc::alloc::alloc(Layout::new::<Kernel<EnvironmentRiscv32im>>())
             as *mut Kernel<EnvironmentRiscv32im>
@@ -0,0 +1,37 @@
+//! First user process, started from `/init` of the initramfs. Launches the programs the
+//! system runs and reaps every process that exits without a parent to wait for it
+
+#![no_std]
+#![no_main]
+
+use pippopp_user::{
+    entry, println,
+    syscall::{self, Errno},
+};
+
+entry!(main);
+
+/// Programs started at boot, in order
+const STARTUP: &[&str] = &["/bin/print-a", "/bin/print-b"];
+
+fn main() {
+    println!("init: started");
+    for path in STARTUP {
+        match syscall::spawn(path) {
+            Ok(pid) => println!("init: started {} as {}", path, pid),
+            Err(e) => println!("init: failed to start {}: {}", path, e),
+        }
+    }
+
+    // The first process must never exit
+    loop {
+        match syscall::wait(None) {
+            Ok((pid, code)) => println!("init: process {} exited with {}", pid, code),
+            // Orphans may still be handed over later
+            Err(Errno::ECHILD) => {
+                let _ = syscall::sleep(1_000_000_000);
+            }
+            Err(e) => println!("init: wait failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,18 @@
+//! Prints "A" forever with a busy wait in between, to watch the scheduler interleave it
+//! with other processes
+
+#![no_std]
+#![no_main]
+
+use pippopp_user::{entry, print};
+
+entry!(main);
+
+fn main() {
+    loop {
+        print!("A");
+        for _ in 0..1_000_000 {
+            core::hint::spin_loop();
+        }
+    }
+}
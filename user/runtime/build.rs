@@ -0,0 +1,9 @@
+use std::{env, fs, path::PathBuf};
+
+fn main() {
+    // Programs link with `-Tpippopp-user.ld`, which is found through the search path
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::copy("link.ld", out_dir.join("pippopp-user.ld")).unwrap();
+    println!("cargo:rustc-link-search={}", out_dir.display());
+    println!("cargo:rerun-if-changed=link.ld");
+}
@@ -0,0 +1,162 @@
+//! Global allocator over the `sbrk` syscall.
+//!
+//! Free memory is kept in a list of blocks sorted by address, which are merged with their
+//! neighbours when memory is freed. The heap only grows, memory is never given back.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::syscall;
+
+/// Every block is a multiple of this size and aligned to it, which leaves room for the
+/// header of a free block
+const BLOCK_ALIGN: usize = 8;
+/// Smallest amount the heap grows by at once
+const GROW_SIZE: usize = 64 * 1024;
+
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+struct FreeList {
+    head: *mut FreeBlock,
+}
+
+const fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+fn block_size(layout: Layout) -> usize {
+    align_up(layout.size().max(1), BLOCK_ALIGN)
+}
+
+impl FreeList {
+    /// Takes a block fitting `layout` out of the list, returning the parts of the free
+    /// block before and after it to the list
+    unsafe fn take(&mut self, layout: Layout) -> Option<*mut u8> {
+        let size = block_size(layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+
+        let mut link: *mut *mut FreeBlock = &mut self.head;
+        unsafe {
+            while !(*link).is_null() {
+                let block = *link;
+                let start = block as usize;
+                let end = start + (*block).size;
+                let aligned = align_up(start, align);
+                if aligned + size > end {
+                    link = &mut (*block).next;
+                    continue;
+                }
+
+                *link = (*block).next;
+                if aligned > start {
+                    self.insert(start, aligned - start);
+                }
+                if aligned + size < end {
+                    self.insert(aligned + size, end - aligned - size);
+                }
+                return Some(aligned as *mut u8);
+            }
+        }
+        None
+    }
+
+    /// Returns `size` bytes at `start` to the list, merging them with adjacent blocks
+    unsafe fn insert(&mut self, start: usize, size: usize) {
+        let mut previous: *mut FreeBlock = ptr::null_mut();
+        let mut next = self.head;
+        unsafe {
+            while !next.is_null() && (next as usize) < start {
+                previous = next;
+                next = (*next).next;
+            }
+
+            let block = start as *mut FreeBlock;
+            block.write(FreeBlock { size, next });
+            if !next.is_null() && start + size == next as usize {
+                (*block).size += (*next).size;
+                (*block).next = (*next).next;
+            }
+
+            if previous.is_null() {
+                self.head = block;
+            } else if previous as usize + (*previous).size == start {
+                (*previous).size += (*block).size;
+                (*previous).next = (*block).next;
+            } else {
+                (*previous).next = block;
+            }
+        }
+    }
+}
+
+/// Allocator handing out memory from the heap of the process
+pub struct Heap {
+    locked: AtomicBool,
+    free: core::cell::UnsafeCell<FreeList>,
+}
+
+// SAFETY: The free list is only accessed with `locked` held
+unsafe impl Sync for Heap {}
+
+impl Heap {
+    pub const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            free: core::cell::UnsafeCell::new(FreeList {
+                head: ptr::null_mut(),
+            }),
+        }
+    }
+
+    fn with_list<R>(&self, f: impl FnOnce(&mut FreeList) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            syscall::yield_now();
+        }
+        // SAFETY: The lock is held
+        let result = f(unsafe { &mut *self.free.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.with_list(|list| unsafe {
+            if let Some(block) = list.take(layout) {
+                return block;
+            }
+
+            // Enough for the block even if it has to be aligned within the new memory
+            let needed = block_size(layout) + layout.align().max(BLOCK_ALIGN);
+            let Ok(start) = syscall::sbrk(needed.max(GROW_SIZE)) else {
+                return ptr::null_mut();
+            };
+            // `sbrk` hands out whole pages, query where they end
+            let Ok(end) = syscall::sbrk(0) else {
+                return ptr::null_mut();
+            };
+            list.insert(start as usize, end as usize - start as usize);
+            list.take(layout).unwrap_or(ptr::null_mut())
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.with_list(|list| unsafe { list.insert(ptr as usize, block_size(layout)) })
+    }
+}
@@ -0,0 +1,59 @@
+//! Standard descriptors and the `print!` family of macros.
+
+use core::fmt;
+
+use crate::syscall::{self, Errno};
+
+pub const STDIN: usize = 0;
+pub const STDOUT: usize = 1;
+pub const STDERR: usize = 2;
+
+/// Formatting sink writing to a descriptor
+pub struct Fd(pub usize);
+
+impl Fd {
+    /// Writes all of `buf`, retrying short writes
+    pub fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Errno> {
+        while !buf.is_empty() {
+            match syscall::write(self.0, buf)? {
+                0 => return Err(Errno::EIO),
+                written => buf = &buf[written..],
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Write for Fd {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(fd: usize, args: fmt::Arguments) {
+    // Output that can not be written has nowhere else to go
+    let _ = fmt::Write::write_fmt(&mut Fd(fd), args);
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::io::_print($crate::io::STDOUT, format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::io::_print($crate::io::STDOUT, format_args!("{}\n", format_args!($($arg)*))));
+}
+
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => ($crate::io::_print($crate::io::STDERR, format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::eprint!("\n"));
+    ($($arg:tt)*) => ($crate::io::_print($crate::io::STDERR, format_args!("{}\n", format_args!($($arg)*))));
+}
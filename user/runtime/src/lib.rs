@@ -0,0 +1,114 @@
+//! Runtime for pippopp user programs.
+//!
+//! Provides the `_start` entry point, wrappers for every syscall, the `print!` family of
+//! macros and a global allocator, so `alloc` can be used right away. A program is a
+//! `#![no_std]` and `#![no_main]` binary linked with `-Tpippopp-user.ld` which names its
+//! main function with `entry!`:
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! use pippopp_user::{entry, println};
+//!
+//! entry!(main);
+//!
+//! fn main() {
+//!     println!("Hello from user space");
+//! }
+//! ```
+//!
+//! Returning from main exits the main thread with the code of the `Termination` the
+//! function returns, a panic exits the thread with code 101.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod heap;
+pub mod io;
+pub mod syscall;
+
+use core::{fmt::Debug, panic::PanicInfo};
+
+#[global_allocator]
+static HEAP: heap::Heap = heap::Heap::new();
+
+// Sets up the global pointer and the stack from the linker script and clears .bss, which
+// is not part of the image the kernel loads
+core::arch::global_asm!(
+    ".section .text._start",
+    ".global _start",
+    "_start:",
+    ".option push",
+    ".option norelax",
+    "la gp, __global_pointer$",
+    ".option pop",
+    "la sp, __stack_top",
+    "la t0, __bss_start",
+    "la t1, __bss_end",
+    "1:",
+    "bgeu t0, t1, 2f",
+    "sw zero, 0(t0)",
+    "addi t0, t0, 4",
+    "j 1b",
+    "2:",
+    "call __pippopp_start",
+);
+
+#[no_mangle]
+extern "C" fn __pippopp_start() -> ! {
+    extern "Rust" {
+        fn __pippopp_main() -> usize;
+    }
+    // SAFETY: Defined by `entry!` in the program
+    let code = unsafe { __pippopp_main() };
+    syscall::thread_exit(code)
+}
+
+/// Declares the main function of the program, which takes no arguments and returns a
+/// `Termination`
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        #[export_name = "__pippopp_main"]
+        fn __pippopp_main() -> usize {
+            $crate::Termination::report($main())
+        }
+    };
+}
+
+/// Return type of a main function, turned into the exit code of the main thread
+pub trait Termination {
+    fn report(self) -> usize;
+}
+
+impl Termination for () {
+    fn report(self) -> usize {
+        0
+    }
+}
+
+impl Termination for usize {
+    fn report(self) -> usize {
+        self
+    }
+}
+
+impl<T: Termination, E: Debug> Termination for Result<T, E> {
+    fn report(self) -> usize {
+        match self {
+            Ok(value) => value.report(),
+            Err(error) => {
+                eprintln!("Error: {:?}", error);
+                1
+            }
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    eprintln!("{}", info);
+    syscall::thread_exit(101)
+}
@@ -0,0 +1,604 @@
+//! Wrappers for every system call of the kernel.
+//!
+//! The number goes in a7 and up to six arguments in a0 to a5, 64-bit values take two
+//! registers with the low word first. On return a7 is 0 for success with the result in a0
+//! and a secondary value in a1, or 1 for failure with an `Errno` in a0.
+
+use core::{arch::asm, fmt, sync::atomic::AtomicU32};
+
+/// Syscall numbers, matching the kernel's `SystemCall` table
+pub mod nr {
+    pub const UART_DEBUG_PRINT: usize = 0;
+    pub const YIELD: usize = 1;
+    pub const SLEEP: usize = 2;
+    pub const CLOCK_GETTIME: usize = 3;
+    pub const TIMER_CREATE: usize = 4;
+    pub const TIMER_SETTIME: usize = 5;
+    pub const TIMER_WAIT: usize = 6;
+    pub const TIMER_DELETE: usize = 7;
+    pub const THREAD_CREATE: usize = 8;
+    pub const THREAD_EXIT: usize = 9;
+    pub const THREAD_JOIN: usize = 10;
+    pub const FUTEX_WAIT: usize = 11;
+    pub const FUTEX_WAKE: usize = 12;
+    pub const READ: usize = 13;
+    pub const WRITE: usize = 14;
+    pub const CLOSE: usize = 15;
+    pub const DUP: usize = 16;
+    pub const DUP2: usize = 17;
+    pub const PIPE: usize = 18;
+    pub const CHANNEL_CREATE: usize = 19;
+    pub const CHANNEL_SEND: usize = 20;
+    pub const CHANNEL_RECV: usize = 21;
+    pub const SHM_CREATE: usize = 22;
+    pub const SHM_MAP: usize = 23;
+    pub const SHM_UNMAP: usize = 24;
+    pub const KILL: usize = 25;
+    pub const SIGACTION: usize = 26;
+    pub const SIGRETURN: usize = 27;
+    pub const SBRK: usize = 28;
+    pub const SPAWN: usize = 29;
+    pub const WAIT: usize = 30;
+    pub const OPEN: usize = 31;
+    pub const LSEEK: usize = 32;
+    pub const STAT: usize = 33;
+    pub const MKDIR: usize = 34;
+    pub const UNLINK: usize = 35;
+    pub const RENAME: usize = 36;
+    pub const READDIR: usize = 37;
+    pub const CHDIR: usize = 38;
+    pub const GETCWD: usize = 39;
+}
+
+/// Error code of a failed syscall, numbered like Linux
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Errno(pub usize);
+
+impl Errno {
+    pub const EPERM: Errno = Errno(1);
+    pub const ENOENT: Errno = Errno(2);
+    pub const ESRCH: Errno = Errno(3);
+    pub const EINTR: Errno = Errno(4);
+    pub const EIO: Errno = Errno(5);
+    pub const ENOEXEC: Errno = Errno(8);
+    pub const EBADF: Errno = Errno(9);
+    pub const ECHILD: Errno = Errno(10);
+    pub const EAGAIN: Errno = Errno(11);
+    pub const ENOMEM: Errno = Errno(12);
+    pub const EFAULT: Errno = Errno(14);
+    pub const EBUSY: Errno = Errno(16);
+    pub const EEXIST: Errno = Errno(17);
+    pub const EXDEV: Errno = Errno(18);
+    pub const ENOTDIR: Errno = Errno(20);
+    pub const EISDIR: Errno = Errno(21);
+    pub const EINVAL: Errno = Errno(22);
+    pub const EMFILE: Errno = Errno(24);
+    pub const ENOSPC: Errno = Errno(28);
+    pub const ESPIPE: Errno = Errno(29);
+    pub const EROFS: Errno = Errno(30);
+    pub const EPIPE: Errno = Errno(32);
+    pub const ERANGE: Errno = Errno(34);
+    pub const EDEADLK: Errno = Errno(35);
+    pub const ENAMETOOLONG: Errno = Errno(36);
+    pub const ENOSYS: Errno = Errno(38);
+    pub const ENOTEMPTY: Errno = Errno(39);
+    pub const ELOOP: Errno = Errno(40);
+    pub const EOPNOTSUPP: Errno = Errno(95);
+    pub const ETIMEDOUT: Errno = Errno(110);
+
+    pub fn name(self) -> Option<&'static str> {
+        Some(match self {
+            Self::EPERM => "EPERM",
+            Self::ENOENT => "ENOENT",
+            Self::ESRCH => "ESRCH",
+            Self::EINTR => "EINTR",
+            Self::EIO => "EIO",
+            Self::ENOEXEC => "ENOEXEC",
+            Self::EBADF => "EBADF",
+            Self::ECHILD => "ECHILD",
+            Self::EAGAIN => "EAGAIN",
+            Self::ENOMEM => "ENOMEM",
+            Self::EFAULT => "EFAULT",
+            Self::EBUSY => "EBUSY",
+            Self::EEXIST => "EEXIST",
+            Self::EXDEV => "EXDEV",
+            Self::ENOTDIR => "ENOTDIR",
+            Self::EISDIR => "EISDIR",
+            Self::EINVAL => "EINVAL",
+            Self::EMFILE => "EMFILE",
+            Self::ENOSPC => "ENOSPC",
+            Self::ESPIPE => "ESPIPE",
+            Self::EROFS => "EROFS",
+            Self::EPIPE => "EPIPE",
+            Self::ERANGE => "ERANGE",
+            Self::EDEADLK => "EDEADLK",
+            Self::ENAMETOOLONG => "ENAMETOOLONG",
+            Self::ENOSYS => "ENOSYS",
+            Self::ENOTEMPTY => "ENOTEMPTY",
+            Self::ELOOP => "ELOOP",
+            Self::EOPNOTSUPP => "EOPNOTSUPP",
+            Self::ETIMEDOUT => "ETIMEDOUT",
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Debug for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "Errno({})", self.0),
+        }
+    }
+}
+
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Errno>;
+
+/// Clock of `clock_gettime` counting from boot
+pub const CLOCK_MONOTONIC: usize = 1;
+
+/// `channel_create` flag making senders block until their message is received
+pub const CHANNEL_SYNCHRONOUS: usize = 1 << 0;
+
+/// Bytes of data carried by a channel message
+pub const MESSAGE_SIZE: usize = 64;
+
+/// Layout of the struct passed to `timer_settime`
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct TimerSpec {
+    /// Nanoseconds until the first expiry, zero disarms the timer
+    pub initial_nanos: u64,
+    /// Nanoseconds between expiries after the first, zero for a one-shot timer
+    pub period_nanos: u64,
+}
+
+/// Layout of the message `channel_send` and `channel_recv` take
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Message {
+    pub data: [u8; MESSAGE_SIZE],
+    /// Descriptor sent along with the message, `Message::NO_HANDLE` for none
+    pub handle: i32,
+}
+
+impl Message {
+    pub const NO_HANDLE: i32 = -1;
+
+    pub const fn new(data: [u8; MESSAGE_SIZE]) -> Self {
+        Self {
+            data,
+            handle: Self::NO_HANDLE,
+        }
+    }
+}
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+
+/// `SigAction::handler` value for the default action of the signal
+pub const SIG_DFL: usize = 0;
+/// `SigAction::handler` value for discarding the signal
+pub const SIG_IGN: usize = 1;
+
+/// Layout of the struct `sigaction` takes
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SigAction {
+    /// `SIG_DFL`, `SIG_IGN` or the address of `extern "C" fn(signal: usize)`
+    pub handler: usize,
+    /// Signals blocked while the handler runs, on top of the signal itself
+    pub mask: u32,
+    /// Reserved, must be zero
+    pub flags: u32,
+    /// Where the handler returns to, must call `sigreturn`
+    pub restorer: usize,
+}
+
+/// Flags of `open`. Without `O_WRONLY` or `O_RDWR` the file is opened for reading only
+pub const O_RDONLY: usize = 0;
+pub const O_WRONLY: usize = 0o1;
+pub const O_RDWR: usize = 0o2;
+pub const O_CREAT: usize = 0o100;
+/// With `O_CREAT`, fail if the file exists
+pub const O_EXCL: usize = 0o200;
+pub const O_TRUNC: usize = 0o1000;
+pub const O_APPEND: usize = 0o2000;
+pub const O_DIRECTORY: usize = 0o200000;
+
+/// Where `lseek` counts its offset from
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+/// `Stat::kind` and `DirEntry::kind` values
+pub const FILE_REGULAR: u32 = 1;
+pub const FILE_DIRECTORY: u32 = 2;
+pub const FILE_SYMLINK: u32 = 3;
+
+/// Longest name of a directory entry in bytes
+pub const NAME_MAX: usize = 255;
+
+/// Layout of the struct `stat` fills in
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct Stat {
+    pub inode: u64,
+    pub size: u64,
+    /// `FILE_REGULAR`, `FILE_DIRECTORY` or `FILE_SYMLINK`
+    pub kind: u32,
+    pub links: u32,
+}
+
+/// Layout of the struct `readdir` fills in
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct DirEntry {
+    pub inode: u64,
+    /// `FILE_REGULAR`, `FILE_DIRECTORY` or `FILE_SYMLINK`
+    pub kind: u32,
+    /// Bytes of `name` in use
+    pub name_len: u32,
+    pub name: [u8; NAME_MAX + 1],
+}
+
+impl DirEntry {
+    /// The name of the entry, empty if it is not UTF-8
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len as usize]).unwrap_or("")
+    }
+}
+
+/// Makes syscall `number` with up to six arguments and returns a0 and a1
+///
+/// # Safety
+/// Pointers among the arguments must be valid for what the syscall does with them
+#[inline(always)]
+pub unsafe fn syscall(number: usize, args: [usize; 6]) -> Result<(usize, usize)> {
+    let value: usize;
+    let secondary: usize;
+    let failed: usize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") args[0] => value,
+            inlateout("a1") args[1] => secondary,
+            in("a2") args[2],
+            in("a3") args[3],
+            in("a4") args[4],
+            in("a5") args[5],
+            inlateout("a7") number => failed,
+        );
+    }
+    match failed {
+        0 => Ok((value, secondary)),
+        _ => Err(Errno(value)),
+    }
+}
+
+/// Makes a syscall that only takes plain values, filling the remaining registers with zero
+fn call(number: usize, args: &[usize]) -> Result<usize> {
+    let mut regs = [0; 6];
+    regs[..args.len()].copy_from_slice(args);
+    // SAFETY: None of the arguments are pointers
+    unsafe { syscall(number, regs) }.map(|(value, _)| value)
+}
+
+fn split(value: u64) -> [usize; 2] {
+    [value as u32 as usize, (value >> 32) as usize]
+}
+
+fn pointer_or_null<T>(value: Option<&T>) -> usize {
+    value.map_or(0, |value| value as *const T as usize)
+}
+
+pub fn uart_debug_print(c: char) -> Result<()> {
+    call(nr::UART_DEBUG_PRINT, &[c as usize]).map(drop)
+}
+
+pub fn yield_now() {
+    // Yielding can not fail
+    let _ = call(nr::YIELD, &[]);
+}
+
+pub fn sleep(nanos: u64) -> Result<()> {
+    call(nr::SLEEP, &split(nanos)).map(drop)
+}
+
+/// Returns the time of `clock` in nanoseconds
+pub fn clock_gettime(clock: usize) -> Result<u64> {
+    let (low, high) = unsafe { syscall(nr::CLOCK_GETTIME, [clock, 0, 0, 0, 0, 0]) }?;
+    Ok(((high as u64) << 32) | low as u64)
+}
+
+pub fn timer_create() -> Result<usize> {
+    call(nr::TIMER_CREATE, &[])
+}
+
+pub fn timer_settime(id: usize, spec: &TimerSpec) -> Result<()> {
+    let spec = spec as *const TimerSpec as usize;
+    unsafe { syscall(nr::TIMER_SETTIME, [id, spec, 0, 0, 0, 0]) }.map(drop)
+}
+
+/// Blocks until the timer expires and returns how many times it expired since the last wait
+pub fn timer_wait(id: usize) -> Result<usize> {
+    call(nr::TIMER_WAIT, &[id])
+}
+
+pub fn timer_delete(id: usize) -> Result<()> {
+    call(nr::TIMER_DELETE, &[id]).map(drop)
+}
+
+/// Starts a thread at `entry` with the stack pointer `stack` and `arg` in a0 and tp.
+/// Returns the id of the thread
+///
+/// # Safety
+/// `stack` must point past the end of memory that stays reserved for the thread until it
+/// exits, and `entry` must never return
+pub unsafe fn thread_create(
+    entry: extern "C" fn(arg: usize) -> !,
+    stack: *mut u8,
+    arg: usize,
+) -> Result<usize> {
+    call(nr::THREAD_CREATE, &[entry as usize, stack as usize, arg])
+}
+
+pub fn thread_exit(code: usize) -> ! {
+    let _ = call(nr::THREAD_EXIT, &[code]);
+    unreachable!("thread_exit returned");
+}
+
+/// Blocks until thread `id` exits and returns its exit code
+pub fn thread_join(id: usize) -> Result<usize> {
+    call(nr::THREAD_JOIN, &[id])
+}
+
+/// Blocks until `futex_wake` is called on `futex` if it still holds `expected`. Fails with
+/// `Errno::EAGAIN` if it does not and `Errno::ETIMEDOUT` once `timeout` nanoseconds passed
+pub fn futex_wait(futex: &AtomicU32, expected: u32, timeout: Option<u64>) -> Result<()> {
+    let timeout = pointer_or_null(timeout.as_ref());
+    let args = [futex.as_ptr() as usize, expected as usize, timeout, 0, 0, 0];
+    unsafe { syscall(nr::FUTEX_WAIT, args) }.map(drop)
+}
+
+/// Wakes up to `count` threads waiting on `futex` and returns how many were woken
+pub fn futex_wake(futex: &AtomicU32, count: usize) -> Result<usize> {
+    call(nr::FUTEX_WAKE, &[futex.as_ptr() as usize, count])
+}
+
+pub fn read(fd: usize, buf: &mut [u8]) -> Result<usize> {
+    let args = [fd, buf.as_mut_ptr() as usize, buf.len(), 0, 0, 0];
+    unsafe { syscall(nr::READ, args) }.map(|(read, _)| read)
+}
+
+pub fn write(fd: usize, buf: &[u8]) -> Result<usize> {
+    let args = [fd, buf.as_ptr() as usize, buf.len(), 0, 0, 0];
+    unsafe { syscall(nr::WRITE, args) }.map(|(written, _)| written)
+}
+
+pub fn close(fd: usize) -> Result<()> {
+    call(nr::CLOSE, &[fd]).map(drop)
+}
+
+pub fn dup(fd: usize) -> Result<usize> {
+    call(nr::DUP, &[fd])
+}
+
+pub fn dup2(old_fd: usize, new_fd: usize) -> Result<usize> {
+    call(nr::DUP2, &[old_fd, new_fd])
+}
+
+/// Returns the read and the write end of a new pipe
+pub fn pipe() -> Result<(usize, usize)> {
+    unsafe { syscall(nr::PIPE, [0; 6]) }
+}
+
+/// Returns both endpoints of a new channel
+pub fn channel_create(flags: usize) -> Result<(usize, usize)> {
+    unsafe { syscall(nr::CHANNEL_CREATE, [flags, 0, 0, 0, 0, 0]) }
+}
+
+pub fn channel_send(fd: usize, message: &Message) -> Result<()> {
+    let message = message as *const Message as usize;
+    unsafe { syscall(nr::CHANNEL_SEND, [fd, message, 0, 0, 0, 0]) }.map(drop)
+}
+
+/// Blocks until a message arrives. `deadline` is a time of `CLOCK_MONOTONIC` in nanoseconds
+pub fn channel_recv(fd: usize, message: &mut Message, deadline: Option<u64>) -> Result<()> {
+    let deadline = pointer_or_null(deadline.as_ref());
+    let message = message as *mut Message as usize;
+    unsafe { syscall(nr::CHANNEL_RECV, [fd, message, deadline, 0, 0, 0]) }.map(drop)
+}
+
+/// Creates a shared memory region of at least `size` bytes and returns its descriptor
+pub fn shm_create(size: usize) -> Result<usize> {
+    call(nr::SHM_CREATE, &[size])
+}
+
+/// Maps the region behind `fd` at `addr`, or where the kernel picks for None. Returns the
+/// address the region was mapped at
+pub fn shm_map(fd: usize, addr: Option<usize>) -> Result<*mut u8> {
+    call(nr::SHM_MAP, &[fd, addr.unwrap_or(0)]).map(|addr| addr as *mut u8)
+}
+
+/// # Safety
+/// No references into the region may be used after it is unmapped
+pub unsafe fn shm_unmap(addr: *mut u8) -> Result<()> {
+    call(nr::SHM_UNMAP, &[addr as usize]).map(drop)
+}
+
+pub fn kill(pid: usize, signal: usize) -> Result<()> {
+    call(nr::KILL, &[pid, signal]).map(drop)
+}
+
+/// Replaces the action of `signal` and returns the old one
+///
+/// # Safety
+/// The handler and the restorer of `action` must be functions fit to be called on signals
+pub unsafe fn sigaction(signal: usize, action: Option<&SigAction>) -> Result<SigAction> {
+    let mut old = SigAction {
+        handler: 0,
+        mask: 0,
+        flags: 0,
+        restorer: 0,
+    };
+    let args = [
+        signal,
+        pointer_or_null(action),
+        &mut old as *mut SigAction as usize,
+        0,
+        0,
+        0,
+    ];
+    unsafe { syscall(nr::SIGACTION, args) }?;
+    Ok(old)
+}
+
+/// Installs `handler` for `signal`, returning through `sigreturn` when it is done
+pub fn signal(signal: usize, handler: extern "C" fn(signal: usize)) -> Result<()> {
+    let action = SigAction {
+        handler: handler as usize,
+        mask: 0,
+        flags: 0,
+        restorer: sigreturn_restorer(),
+    };
+    // SAFETY: Both functions follow the calling convention the kernel calls them with
+    unsafe { sigaction(signal, Some(&action)) }.map(drop)
+}
+
+// `sigreturn` finds the interrupted frame at the stack pointer, so it can only be called
+// from the point a handler returns to, never from Rust code
+core::arch::global_asm!(
+    ".section .text.__pippopp_sigreturn",
+    ".global __pippopp_sigreturn",
+    "__pippopp_sigreturn:",
+    "li a7, {number}",
+    "ecall",
+    number = const nr::SIGRETURN,
+);
+
+extern "C" {
+    fn __pippopp_sigreturn() -> !;
+}
+
+/// Address of the restorer making the `sigreturn` syscall, for `SigAction::restorer`
+pub fn sigreturn_restorer() -> usize {
+    __pippopp_sigreturn as *const () as usize
+}
+
+/// Grows the heap by at least `increment` bytes and returns where the new memory starts
+pub fn sbrk(increment: usize) -> Result<*mut u8> {
+    call(nr::SBRK, &[increment]).map(|start| start as *mut u8)
+}
+
+/// Starts the program at `path`, relative to the working directory, as a child process and
+/// returns its id
+pub fn spawn(path: &str) -> Result<usize> {
+    let args = [path.as_ptr() as usize, path.len(), 0, 0, 0, 0];
+    unsafe { syscall(nr::SPAWN, args) }.map(|(pid, _)| pid)
+}
+
+/// Blocks until the child `pid`, or any child for None, exits and returns its id and exit
+/// code. Fails with `Errno::ECHILD` if there is no such child
+pub fn wait(pid: Option<usize>) -> Result<(usize, usize)> {
+    unsafe { syscall(nr::WAIT, [pid.unwrap_or(0), 0, 0, 0, 0, 0]) }
+}
+
+/// Opens the file at `path` with `O_*` flags and returns its descriptor
+pub fn open(path: &str, flags: usize) -> Result<usize> {
+    let args = [path.as_ptr() as usize, path.len(), flags, 0, 0, 0];
+    unsafe { syscall(nr::OPEN, args) }.map(|(fd, _)| fd)
+}
+
+/// Moves the position of `fd` to `offset` from `whence`, one of `SEEK_*`, and returns it
+pub fn lseek(fd: usize, offset: i64, whence: usize) -> Result<u64> {
+    let [low, high] = split(offset as u64);
+    let (low, high) = unsafe { syscall(nr::LSEEK, [fd, low, high, whence, 0, 0]) }?;
+    Ok(low as u64 | (high as u64) << 32)
+}
+
+pub fn stat(path: &str) -> Result<Stat> {
+    let mut stat = Stat::default();
+    let args = [
+        path.as_ptr() as usize,
+        path.len(),
+        &mut stat as *mut Stat as usize,
+        0,
+        0,
+        0,
+    ];
+    unsafe { syscall(nr::STAT, args) }?;
+    Ok(stat)
+}
+
+pub fn mkdir(path: &str) -> Result<()> {
+    let args = [path.as_ptr() as usize, path.len(), 0, 0, 0, 0];
+    unsafe { syscall(nr::MKDIR, args) }.map(drop)
+}
+
+/// Removes a file, or a directory once it is empty
+pub fn unlink(path: &str) -> Result<()> {
+    let args = [path.as_ptr() as usize, path.len(), 0, 0, 0, 0];
+    unsafe { syscall(nr::UNLINK, args) }.map(drop)
+}
+
+pub fn rename(old: &str, new: &str) -> Result<()> {
+    let args = [
+        old.as_ptr() as usize,
+        old.len(),
+        new.as_ptr() as usize,
+        new.len(),
+        0,
+        0,
+    ];
+    unsafe { syscall(nr::RENAME, args) }.map(drop)
+}
+
+/// Returns the next entry of the directory opened as `fd`, None after the last
+pub fn readdir(fd: usize) -> Result<Option<DirEntry>> {
+    let mut entry = DirEntry {
+        inode: 0,
+        kind: 0,
+        name_len: 0,
+        name: [0; NAME_MAX + 1],
+    };
+    let args = [fd, &mut entry as *mut DirEntry as usize, 0, 0, 0, 0];
+    let (more, _) = unsafe { syscall(nr::READDIR, args) }?;
+    Ok((more != 0).then_some(entry))
+}
+
+pub fn chdir(path: &str) -> Result<()> {
+    let args = [path.as_ptr() as usize, path.len(), 0, 0, 0, 0];
+    unsafe { syscall(nr::CHDIR, args) }.map(drop)
+}
+
+/// Copies the path of the working directory to `buf` and returns it. Fails with
+/// `Errno::ERANGE` if it does not fit
+pub fn getcwd(buf: &mut [u8]) -> Result<&str> {
+    let args = [buf.as_mut_ptr() as usize, buf.len(), 0, 0, 0, 0];
+    let (len, _) = unsafe { syscall(nr::GETCWD, args) }?;
+    core::str::from_utf8(&buf[..len]).map_err(|_| Errno::EINVAL)
+}